@@ -0,0 +1,176 @@
+use crate::ai_models::{CustomSystemPrompt, GenerationMode, MelodyRequest, MelodyResponse, TimeSignature};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Entries kept; the least recently used are removed beyond this
+const MAX_ENTRIES: usize = 500;
+
+/// Everything that determines a generation's output
+#[derive(Serialize)]
+struct CacheKey<'a> {
+    provider: &'a str,
+    model: Option<&'a str>,
+    prompt: &'a str,
+    scale: Option<(&'a str, &'a str, Option<u8>)>,
+    measures: u32,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    seed: Option<u64>,
+    mode: &'a GenerationMode,
+    chord_progression: Option<&'a str>,
+    time_signature: TimeSignature,
+    system_prompt: Option<&'a CustomSystemPrompt>,
+}
+
+/// On-disk cache of AI responses, so repeating an identical request is instant and free
+///
+/// Each response is a JSON file named after the SHA-256 of its request.
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+impl ResponseCache {
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let dir = app_data_dir.join("ai_cache");
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create AI cache directory: {}", e))?;
+        Ok(Self { dir })
+    }
+
+    fn entry_path(request: &MelodyRequest, model: Option<&str>) -> String {
+        let key = CacheKey {
+            provider: request.model_provider.as_str(),
+            model,
+            prompt: &request.prompt,
+            scale: request
+                .scale
+                .as_ref()
+                .map(|scale| (scale.root.as_str(), scale.mode.as_str(), scale.octave)),
+            measures: request.measures,
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            seed: request.seed,
+            mode: &request.mode,
+            chord_progression: request.chord_progression.as_deref(),
+            time_signature: request.time_signature,
+            system_prompt: request.system_prompt.as_ref(),
+        };
+        let key = serde_json::to_vec(&key).unwrap_or_default();
+        format!("{}.json", hex::encode(Sha256::digest(key)))
+    }
+
+    /// Cached response for a request to `model` (`None` for the provider's default)
+    ///
+    /// Notes get fresh ids, so a cached melody can be inserted more than once.
+    pub fn get(&self, request: &MelodyRequest, model: Option<&str>) -> Option<MelodyResponse> {
+        let path = self.dir.join(Self::entry_path(request, model));
+        let data = fs::read(&path).ok()?;
+        let mut response: MelodyResponse = serde_json::from_slice(&data).ok()?;
+
+        // Rewrite to refresh the modification time used for eviction
+        fs::write(&path, &data).ok();
+
+        for note in &mut response.notes {
+            note.id = uuid::Uuid::new_v4().to_string();
+        }
+        Some(response)
+    }
+
+    /// Store a response, evicting the oldest entries beyond `MAX_ENTRIES`
+    pub fn put(&self, request: &MelodyRequest, model: Option<&str>, response: &MelodyResponse) -> Result<(), String> {
+        let data = serde_json::to_vec(response)
+            .map_err(|e| format!("Failed to serialize cached response: {}", e))?;
+        fs::write(self.dir.join(Self::entry_path(request, model)), data)
+            .map_err(|e| format!("Failed to write AI cache: {}", e))?;
+
+        self.evict();
+        Ok(())
+    }
+
+    fn evict(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else { return };
+        let mut files: Vec<_> = entries
+            .flatten()
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect();
+        if files.len() <= MAX_ENTRIES {
+            return;
+        }
+
+        files.sort();
+        for (_, path) in &files[..files.len() - MAX_ENTRIES] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    /// Delete every cached response
+    pub fn clear(&self) -> Result<(), String> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| format!("Failed to read AI cache: {}", e))?;
+        for entry in entries.flatten() {
+            fs::remove_file(entry.path())
+                .map_err(|e| format!("Failed to clear AI cache: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_models::{AIProvider, GenerationMetadata, Note};
+    use std::env;
+
+    #[test]
+    fn test_cache_hit_requires_identical_request() {
+        let temp_dir = env::temp_dir().join("piano-app-test-ai-cache");
+        let cache = ResponseCache::new(temp_dir.clone()).unwrap();
+
+        let request = MelodyRequest {
+            prompt: "Happy melody".to_string(),
+            seed: Some(7),
+            ..MelodyRequest::default()
+        };
+        let response = MelodyResponse {
+            notes: vec![Note {
+                id: "n1".to_string(),
+                pitch: 60,
+                start_time: 0.0,
+                duration: 1.0,
+                velocity: 90,
+                track_id: "track_right_hand".to_string(),
+            }],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+
+        assert!(cache.get(&request, None).is_none());
+        cache.put(&request, None, &response).unwrap();
+
+        let cached = cache.get(&request, None).unwrap();
+        assert_eq!(cached.notes[0].pitch, 60);
+        assert_ne!(cached.notes[0].id, "n1");
+
+        assert!(cache.get(&request, Some("gpt-4o")).is_none());
+        assert!(cache.get(&MelodyRequest { seed: Some(8), ..request.clone() }, None).is_none());
+        // A lower token limit can cut the reply short, so it needs its own entry
+        assert!(cache.get(&MelodyRequest { max_tokens: Some(256), ..request.clone() }, None).is_none());
+
+        cache.clear().unwrap();
+        assert!(cache.get(&request, None).is_none());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
@@ -1,33 +1,67 @@
-use crate::ai_models::{AIProvider, GenerationMetadata, MelodyRequest, MelodyResponse, Note};
-use crate::ai_prompts::{build_system_prompt, build_user_prompt, build_retry_prompt};
+use crate::ai_models::{
+    self, AIProvider, ChordSymbol, GenerationMetadata, GenerationMode, GenerationPhase, Hairpin, HairpinShape, MelodyCritique,
+    MelodyRequest, MelodyResponse, Note, PartRole, Scale, SuggestedEdit,
+};
+use crate::ai_prompts::{AIPrompt, ChatRole, DEFAULT_MAX_TOKENS};
+use crate::ai_rate_limit::{self, RateLimited};
+use crate::ai_retry::{self, RetryPolicy, ServerError, Unauthorized};
+use crate::ai_schema;
+use crate::ai_settings::ProviderConfig;
+use crate::api_key_storage::{AwsCredentials, GoogleCredentials};
+use crate::google_auth;
+use crate::key_audit;
+use crate::local_model;
+use crate::aws_sigv4::{self, SigningParams};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use reqwest::header::HeaderValue;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use schemars::{schema_for, JsonSchema};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 #[async_trait]
 pub trait AIClient: Send + Sync {
-    async fn generate_melody(&self, request: &MelodyRequest, api_key: &str) -> Result<MelodyResponse>;
+    /// Send a prompt and return the provider's JSON reply
+    ///
+    /// Providers with structured outputs guarantee the reply matches
+    /// `prompt.schema`; for the others it is only asked for in the prompt.
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value>;
+
+    /// Model that answers, recorded in the generation metadata
+    fn model_name(&self) -> &str;
+
+    async fn generate_melody(&self, request: &MelodyRequest, api_key: &str) -> Result<MelodyResponse> {
+        let reply = self.complete(&AIPrompt::for_request(request, None), api_key).await?;
+        parse_reply(reply, self.model_name(), request)
+    }
 
     /// Generate melody with retry logic and comprehensive validation
     ///
-    /// This is the main entry point for melody generation. It implements a two-attempt
-    /// strategy with validation-driven retry:
-    ///
-    /// **Attempt 1**: Generate with standard prompt
-    /// - If validation passes → Return immediately (happy path)
-    /// - If validation fails → Proceed to retry
+    /// This is the main entry point for melody generation. Failures are
+    /// retried according to `policy`, with separate budgets per kind:
     ///
-    /// **Attempt 2**: Generate with adjusted prompt that includes error feedback
+    /// **Validation failures**: Generate again with an adjusted prompt
     /// - Build retry prompt with specific validation error message
     /// - AI model can learn from its mistake and correct it
-    /// - If this fails → Return error to user
+    /// - Up to `max_validation_retries` times, then return the error to the user
     ///
-    /// **Why only 1 retry?**
+    /// **Network failures** (connection errors, timeouts, 5xx responses):
+    /// - Resend the same request after an exponential backoff with jitter
+    /// - Up to `max_network_retries` times per attempt
+    /// - Other API errors (bad key, 4xx) fail immediately
+    ///
+    /// **Why bounded retries?**
     /// - Prevents infinite loops and excessive API usage
-    /// - If AI can't generate valid output in 2 attempts, user should adjust prompt
+    /// - If AI can't generate valid output in a few attempts, user should adjust prompt
     /// - Balances success rate with API cost
     ///
     /// **Validation checks**:
@@ -38,55 +72,303 @@ pub trait AIClient: Send + Sync {
     /// # Arguments
     /// * `request` - User's melody generation request
     /// * `api_key` - Decrypted API key for the provider
+    /// * `policy` - Retry counts and backoff delays
+    /// * `progress` - Called with each phase and attempt number as generation proceeds
+    /// * `cancel` - Aborts the in-flight request (or backoff wait) when cancelled
     ///
     /// # Returns
     /// A validated `MelodyResponse` that passed all checks
     ///
     /// # Errors
     /// - API communication errors
-    /// - Validation failures after retry
+    /// - Validation failures after the last retry
     /// - JSON parsing errors
-    async fn generate_melody_with_retry(&self, request: &MelodyRequest, api_key: &str) -> Result<MelodyResponse> {
-        // First attempt: Use standard prompt
-        let response = self.generate_melody(request, api_key).await?;
-
-        // Comprehensive validation (measure bounds + scale constraints + basic validity)
-        match response.validate_comprehensive(request.measures, request.scale.as_ref()) {
-            Ok(_) => return Ok(response), // Success! Return immediately
-            Err(validation_error) => {
-                // First attempt failed validation - provide feedback for debugging
-                eprintln!("⚠ First generation attempt failed validation: {}", validation_error);
-                eprintln!("→ Retrying with adjusted prompt...");
-
-                // Second attempt: Use retry prompt with error feedback
-                // This tells the AI what went wrong so it can correct the issue
-                let retry_response = self.generate_melody_retry(request, api_key, &validation_error).await?;
-
-                // Validate retry response (if this fails, we give up)
-                retry_response.validate_comprehensive(request.measures, request.scale.as_ref())
-                    .map_err(|e| anyhow::anyhow!("Retry also failed validation: {}", e))?;
-
-                Ok(retry_response)
+    /// - `GENERATION_CANCELLED` when cancelled
+    async fn generate_melody_with_retry(
+        &self,
+        request: &MelodyRequest,
+        api_key: &str,
+        policy: &RetryPolicy,
+        progress: &(dyn Fn(GenerationPhase, u32) + Send + Sync),
+        cancel: &CancellationToken,
+    ) -> Result<MelodyResponse> {
+        let attempts = policy.max_validation_retries + 1;
+        let mut validation_error: Option<String> = None;
+
+        for attempt in 1..=attempts {
+            // First attempt uses the standard prompt, later ones feed back the validation error
+            progress(if attempt == 1 { GenerationPhase::Sent } else { GenerationPhase::Retrying }, attempt);
+            let (received, mut receiving) = mpsc::unbounded_channel();
+            let call = RECEIVED.scope(
+                received,
+                self.generate_attempt(request, api_key, validation_error.as_deref(), policy, cancel),
+            );
+            tokio::pin!(call);
+            let response = loop {
+                tokio::select! {
+                    response = &mut call => break response?,
+                    Some(()) = receiving.recv() => progress(GenerationPhase::Receiving, attempt),
+                }
+            };
+
+            // Comprehensive validation (measure bounds + scale constraints + basic validity)
+            progress(GenerationPhase::Validating, attempt);
+            match response.validate_request(request) {
+                Ok(_) => return Ok(response), // Success! Return immediately
+                Err(error) => {
+                    eprintln!("⚠ Generation attempt {}/{} failed validation: {}", attempt, attempts, error);
+                    validation_error = Some(error);
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Generation failed validation after {} attempts: {}",
+            attempts,
+            validation_error.unwrap_or_default()
+        ))
+    }
+
+    /// One generation attempt, resent with backoff after transient network failures
+    async fn generate_attempt(
+        &self,
+        request: &MelodyRequest,
+        api_key: &str,
+        validation_error: Option<&str>,
+        policy: &RetryPolicy,
+        cancel: &CancellationToken,
+    ) -> Result<MelodyResponse> {
+        let mut failures = 0;
+        loop {
+            let call = async {
+                match validation_error {
+                    Some(error) => self.generate_melody_retry(request, api_key, error).await,
+                    None => self.generate_melody(request, api_key).await,
+                }
+            };
+
+            match cancellable(cancel, call).await {
+                Err(error) if failures < policy.max_network_retries && ai_retry::is_transient(&error) => {
+                    failures += 1;
+                    let delay = policy.backoff(failures);
+                    eprintln!("⚠ Request failed ({}); retrying in {} ms", error, delay.as_millis());
+                    cancellable(cancel, async {
+                        tokio::time::sleep(delay).await;
+                        Ok(())
+                    })
+                    .await?;
+                }
+                result => return result,
             }
         }
     }
 
     /// Generate melody for retry attempt with error feedback
-    async fn generate_melody_retry(&self, request: &MelodyRequest, api_key: &str, error: &str) -> Result<MelodyResponse>;
+    async fn generate_melody_retry(&self, request: &MelodyRequest, api_key: &str, error: &str) -> Result<MelodyResponse> {
+        let reply = self.complete(&AIPrompt::for_request(request, Some(error)), api_key).await?;
+        parse_reply(reply, self.model_name(), request)
+    }
+
+    /// Critique the user's `notes`, optionally focusing on one aspect
+    async fn analyze_melody(
+        &self,
+        notes: &[Note],
+        scale: Option<&Scale>,
+        focus: Option<&str>,
+        api_key: &str,
+    ) -> Result<MelodyCritique> {
+        let reply = self.complete(&AIPrompt::for_critique(notes, scale, focus), api_key).await?;
+        parse_critique(reply, notes)
+    }
+
+    /// Suggest up to `count` titles for a piece from its notes and the prompt it came from
+    async fn suggest_titles(
+        &self,
+        notes: &[Note],
+        scale: Option<&Scale>,
+        description: Option<&str>,
+        count: u32,
+        api_key: &str,
+    ) -> Result<Vec<String>> {
+        let reply = self.complete(&AIPrompt::for_titles(notes, scale, description, count), api_key).await?;
+        parse_titles(reply, count as usize)
+    }
+
+    /// Models the user can choose from (empty when the provider can't list them)
+    async fn list_models(&self, _api_key: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Default time to establish a connection
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Default time for a whole request, including generating the response
+const DEFAULT_TIMEOUT_SECS: u64 = 180;
+
+/// How long an idle pooled connection is kept for the next request
+const POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+
+/// Interval of TCP keepalive probes on pooled connections
+const TCP_KEEPALIVE_SECS: u64 = 60;
+
+tokio::task_local! {
+    /// Told when a provider's response headers arrive during `generate_melody_with_retry`
+    static RECEIVED: mpsc::UnboundedSender<()>;
+}
+
+/// Note that a provider answered: the request goes in the key audit log, and
+/// a generation in progress reports `GenerationPhase::Receiving`
+fn response_received(response: &reqwest::Response) {
+    key_audit::record_response(response);
+    let _ = RECEIVED.try_with(|received| received.send(()));
+}
+
+/// Pooled HTTP client with the provider's connect and total timeouts and proxy
+///
+/// Without a configured proxy, the system proxy environment variables apply
+/// (unless `no_proxy` is set).
+fn http_client(config: &ProviderConfig) -> Client {
+    let connect_timeout = config.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
+    let timeout = config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS);
+
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(connect_timeout))
+        .timeout(Duration::from_secs(timeout))
+        .pool_idle_timeout(Duration::from_secs(POOL_IDLE_TIMEOUT_SECS))
+        .tcp_keepalive(Duration::from_secs(TCP_KEEPALIVE_SECS))
+        .http2_adaptive_window(true);
+
+    if config.no_proxy {
+        builder = builder.no_proxy();
+    } else if let Some(proxy) = &config.proxy {
+        match reqwest::Proxy::all(&proxy.url) {
+            Ok(mut reqwest_proxy) => {
+                if let Some(username) = &proxy.username {
+                    let password = proxy.password.as_deref().map_or("", String::as_str);
+                    reqwest_proxy = reqwest_proxy.basic_auth(username, password);
+                }
+                builder = builder.proxy(reqwest_proxy);
+            }
+            Err(e) => eprintln!("⚠ Ignoring invalid proxy {}: {}", proxy.url, e),
+        }
+    }
+
+    builder.build().unwrap_or_else(|e| {
+        eprintln!("⚠ Failed to configure HTTP client, using defaults: {}", e);
+        Client::new()
+    })
+}
+
+/// HTTP clients of the AI providers, kept for the life of the app so
+/// connections (HTTP/2 where the server offers it) are pooled across requests
+///
+/// Each provider has one client, built on first use and replaced when the
+/// provider's network settings change. Settings are compared by a keyed hash,
+/// so no proxy password is kept here.
+#[derive(Default)]
+pub struct HttpClients {
+    clients: Mutex<HashMap<AIProvider, (u64, Client)>>,
+    hasher: RandomState,
+}
+
+impl HttpClients {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash of the settings a client is built from
+    fn fingerprint(&self, config: &ProviderConfig) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        config.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS).hash(&mut hasher);
+        config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS).hash(&mut hasher);
+        config.no_proxy.hash(&mut hasher);
+        if let Some(proxy) = config.proxy.as_ref().filter(|_| !config.no_proxy) {
+            (&proxy.url, &proxy.username, proxy.password.as_deref()).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Client of a provider with its current settings
+    pub fn get(&self, provider: &AIProvider, config: &ProviderConfig) -> Client {
+        let fingerprint = self.fingerprint(config);
+        let mut clients = self.clients.lock().unwrap();
+        match clients.get(provider) {
+            Some((built_from, client)) if *built_from == fingerprint => client.clone(),
+            _ => {
+                let client = http_client(config);
+                clients.insert(provider.clone(), (fingerprint, client.clone()));
+                client
+            }
+        }
+    }
+}
+
+/// Header carrying a credential, marked sensitive so it is left out of debug output
+fn secret_header(value: &str) -> Result<HeaderValue> {
+    let mut header = HeaderValue::from_str(value).context("API key contains characters not allowed in a header")?;
+    header.set_sensitive(true);
+    Ok(header)
+}
+
+/// Error for a failed API response
+///
+/// 429 responses become `RateLimited`, carrying the `Retry-After` delay,
+/// 401 responses `Unauthorized`, and 5xx responses `ServerError`, which are
+/// retried with backoff.
+async fn api_error(response: reqwest::Response, service: &str) -> anyhow::Error {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+    let message = format!("{} API error ({}): {}", service, status, error_text);
+
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = ai_rate_limit::retry_after_or_default(retry_after.as_deref());
+        return RateLimited { message, retry_after }.into();
+    }
+    if status == reqwest::StatusCode::UNAUTHORIZED {
+        return Unauthorized { message }.into();
+    }
+    if status.is_server_error() {
+        return ServerError { message }.into();
+    }
+    anyhow::anyhow!(message)
+}
+
+/// Error message of a generation aborted with `cancel_generation`
+pub const GENERATION_CANCELLED: &str = "Generation cancelled";
+
+/// Part of the error message of a generation whose provider couldn't be reached
+pub const PROVIDER_UNREACHABLE: &str = "Provider unreachable";
+
+/// Run a provider call unless `cancel` fires first; dropping the call aborts its HTTP request
+async fn cancellable<T>(cancel: &CancellationToken, call: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::select! {
+        _ = cancel.cancelled() => Err(anyhow::anyhow!(GENERATION_CANCELLED)),
+        result = call => result,
+    }
 }
 
 // ============================================================================
 // OpenAI Client
 // ============================================================================
 
+const OPENAI_DEFAULT_MODEL: &str = "gpt-4o-mini";
+
 pub struct OpenAIClient {
     client: Client,
+    model: String,
 }
 
 impl OpenAIClient {
-    pub fn new() -> Self {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
         Self {
-            client: Client::new(),
+            client,
+            model: config.model.clone().unwrap_or_else(|| OPENAI_DEFAULT_MODEL.to_string()),
         }
     }
 }
@@ -94,6 +376,14 @@ impl OpenAIClient {
 #[derive(Debug, Deserialize)]
 struct OpenAIResponse {
     choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -106,208 +396,238 @@ struct OpenAIMessage {
     content: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct AINotesResponse {
     notes: Vec<AINote>,
+    #[serde(default)]
+    chords: Vec<AIChord>,
+    #[serde(default)]
+    dynamics: Vec<AIHairpin>,
+    #[serde(default)]
+    pedal: Vec<AIPedal>,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct AINote {
     pitch: u8,
     #[serde(rename = "startTime")]
-    #[schemars(rename = "startTime")]
     start_time: f64,
     duration: f64,
     velocity: u8,
+    /// Part of an arrangement the note belongs to
+    #[serde(default)]
+    role: Option<PartRole>,
 }
 
-/// Generate JSON schema for structured outputs, stripping unsupported format fields
-fn generate_melody_schema() -> serde_json::Value {
-    let schema = schema_for!(AINotesResponse);
-    let mut schema_value = serde_json::to_value(schema).unwrap();
-
-    // Remove "format" fields that OpenAI doesn't support
-    remove_format_fields(&mut schema_value);
-
-    schema_value
-}
-
-/// Recursively remove "format" fields from schema (OpenAI doesn't support them)
-fn remove_format_fields(value: &mut serde_json::Value) {
-    if let Some(obj) = value.as_object_mut() {
-        obj.remove("format");
-        obj.remove("$schema");
-        for (_key, val) in obj.iter_mut() {
-            remove_format_fields(val);
-        }
-    } else if let Some(arr) = value.as_array_mut() {
-        for item in arr.iter_mut() {
-            remove_format_fields(item);
-        }
-    }
-}
-
-/// Generate a simplified schema for Gemini (removes $defs, $ref, additionalProperties)
-fn generate_gemini_schema() -> serde_json::Value {
-    // Manually create a simple inline schema without $ref or $defs
-    json!({
-        "type": "object",
-        "properties": {
-            "notes": {
-                "type": "array",
-                "items": {
-                    "type": "object",
-                    "properties": {
-                        "pitch": {
-                            "type": "integer",
-                            "minimum": 0,
-                            "maximum": 127
-                        },
-                        "startTime": {
-                            "type": "number",
-                            "minimum": 0.0
-                        },
-                        "duration": {
-                            "type": "number",
-                            "minimum": 0.01
-                        },
-                        "velocity": {
-                            "type": "integer",
-                            "minimum": 0,
-                            "maximum": 127
-                        }
-                    },
-                    "required": ["pitch", "startTime", "duration", "velocity"]
-                }
-            }
-        },
-        "required": ["notes"]
-    })
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+struct AIHairpin {
+    shape: HairpinShape,
+    start_time: f64,
+    duration: f64,
+    from_velocity: u8,
+    to_velocity: u8,
 }
 
-#[async_trait]
-impl AIClient for OpenAIClient {
-    async fn generate_melody(&self, request: &MelodyRequest, api_key: &str) -> Result<MelodyResponse> {
-        let system_prompt = build_system_prompt(request);
-        let user_prompt = build_user_prompt(request);
-        self.make_request(request, api_key, &system_prompt, &user_prompt).await
-    }
-
-    async fn generate_melody_retry(&self, request: &MelodyRequest, api_key: &str, error: &str) -> Result<MelodyResponse> {
-        let system_prompt = build_system_prompt(request);
-        let retry_prompt = build_retry_prompt(request, error);
-        self.make_request(request, api_key, &system_prompt, &retry_prompt).await
-    }
+/// A span the sustain pedal is held down
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AIPedal {
+    #[serde(rename = "startTime")]
+    start_time: f64,
+    duration: f64,
 }
 
-impl OpenAIClient {
-    async fn make_request(&self, request: &MelodyRequest, api_key: &str, system_prompt: &str, user_prompt: &str) -> Result<MelodyResponse> {
-        let schema = generate_melody_schema();
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AIChord {
+    symbol: String,
+    #[serde(rename = "startTime")]
+    start_time: f64,
+    duration: f64,
+}
 
-        let body = json!({
-            "model": "gpt-4o-mini",
-            "messages": [
-                {
-                    "role": "system",
-                    "content": system_prompt
-                },
-                {
-                    "role": "user",
-                    "content": user_prompt
-                }
-            ],
-            "temperature": request.temperature.unwrap_or(1.0),
-            "response_format": {
-                "type": "json_schema",
-                "json_schema": {
-                    "name": "melody_generation",
-                    "schema": schema,
-                    "strict": true
-                }
-            }
-        });
+#[async_trait]
+impl AIClient for OpenAIClient {
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
+        let body = openai_chat_body(Some(&self.model), prompt);
 
         let response = self
             .client
             .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", api_key))
+            .bearer_auth(api_key)
             .header("Content-Type", "application/json")
             .json(&body)
             .send()
             .await
             .context("Failed to send request to OpenAI")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!("OpenAI API error ({}): {}", status, error_text));
-        }
+        parse_openai_response(response, "OpenAI").await
+    }
 
-        let openai_response: OpenAIResponse = response
-            .json()
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    async fn list_models(&self, api_key: &str) -> Result<Vec<String>> {
+        let response = self
+            .client
+            .get("https://api.openai.com/v1/models")
+            .bearer_auth(api_key)
+            .send()
             .await
-            .context("Failed to parse OpenAI response")?;
+            .context("Failed to list OpenAI models")?;
 
-        let content = openai_response
-            .choices
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No choices in OpenAI response"))?
-            .message
-            .content
-            .clone();
+        // Only chat models can generate melodies
+        let models = parse_model_list(response, "OpenAI").await?;
+        Ok(models.into_iter().filter(|id| id.starts_with("gpt-") || id.starts_with('o')).collect())
+    }
+}
 
-        // Parse JSON directly (structured outputs guarantee valid JSON)
-        let ai_notes: AINotesResponse = serde_json::from_str(&content)
-            .context("Failed to parse notes JSON from structured output")?;
+/// Chat completions request body with structured output (OpenAI-compatible APIs)
+///
+/// `model` is left out for APIs that take it from the URL (Azure deployments).
+fn openai_chat_body(model: Option<&str>, prompt: &AIPrompt) -> serde_json::Value {
+    let mut body = json!({
+        "messages": chat_messages(Some(&prompt.system), prompt),
+        "temperature": prompt.temperature,
+        "response_format": {
+            "type": "json_schema",
+            "json_schema": {
+                "name": prompt.schema_name,
+                "schema": ai_schema::strict_schema(&prompt.schema),
+                "strict": true
+            }
+        }
+    });
+    if let Some(model) = model {
+        body["model"] = json!(model);
+    }
+    if let Some(seed) = prompt.seed {
+        body["seed"] = json!(seed);
+    }
+    if let Some(max_tokens) = prompt.max_tokens {
+        body["max_completion_tokens"] = json!(max_tokens);
+    }
+    body
+}
 
-        // Convert to our Note format
-        let notes: Vec<Note> = ai_notes
-            .notes
-            .into_iter()
-            .map(|n| Note {
-                id: uuid::Uuid::new_v4().to_string(),
-                pitch: n.pitch,
-                start_time: n.start_time,
-                duration: n.duration,
-                velocity: n.velocity,
-                track_id: "track_right_hand".to_string(), // Default track
-            })
-            .collect();
+/// Chat messages of a prompt in the common `{role, content}` form, after an optional system message
+fn chat_messages(system: Option<&str>, prompt: &AIPrompt) -> Vec<serde_json::Value> {
+    let system = system.map(|system| json!({ "role": "system", "content": system }));
+    system
+        .into_iter()
+        .chain(
+            prompt
+                .messages()
+                .into_iter()
+                .map(|message| json!({ "role": message.role.as_str(), "content": message.content })),
+        )
+        .collect()
+}
 
-        Ok(MelodyResponse {
-            notes,
-            metadata: GenerationMetadata {
-                provider: AIProvider::OpenAI,
-                timestamp: chrono::Utc::now().to_rfc3339(),
-                model_name: "gpt-4o-mini".to_string(),
-                temperature: request.temperature.unwrap_or(1.0),
-                scale: request.scale.clone(),
-            },
-        })
+/// Check the status of a chat completions response and parse its JSON content
+async fn parse_openai_response(response: reqwest::Response, service: &str) -> Result<serde_json::Value> {
+    response_received(&response);
+    if !response.status().is_success() {
+        return Err(api_error(response, service).await);
+    }
+
+    let openai_response: OpenAIResponse = response
+        .json()
+        .await
+        .with_context(|| format!("Failed to parse {} response", service))?;
+    if let Some(usage) = &openai_response.usage {
+        key_audit::record_usage(usage.prompt_tokens, usage.completion_tokens);
+    }
+
+    let content = openai_response
+        .choices
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No choices in {} response", service))?
+        .message
+        .content
+        .clone();
+
+    // Parse JSON directly (structured outputs guarantee valid JSON)
+    serde_json::from_str(&content).context("Failed to parse JSON from structured output")
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+/// Parse an OpenAI-style `{"data": [{"id": ...}]}` model list
+async fn parse_model_list(response: reqwest::Response, service: &str) -> Result<Vec<String>> {
+    response_received(&response);
+    if !response.status().is_success() {
+        return Err(api_error(response, service).await);
     }
+
+    let list: ModelList = response
+        .json()
+        .await
+        .with_context(|| format!("Failed to parse {} model list", service))?;
+
+    Ok(list.data.into_iter().map(|model| model.id).collect())
 }
 
 // ============================================================================
 // Gemini Client
 // ============================================================================
 
+const GEMINI_DEFAULT_MODEL: &str = "gemini-2.0-flash";
+
 pub struct GeminiClient {
     client: Client,
+    model: String,
 }
 
 impl GeminiClient {
-    pub fn new() -> Self {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
         Self {
-            client: Client::new(),
+            client,
+            model: config.model.clone().unwrap_or_else(|| GEMINI_DEFAULT_MODEL.to_string()),
         }
     }
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModelList {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModel {
+    name: String,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse {
     candidates: Vec<GeminiCandidate>,
+    #[serde(default)]
+    usage_metadata: Option<GeminiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    prompt_token_count: Option<u64>,
+    candidates_token_count: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -327,102 +647,188 @@ struct GeminiPart {
 
 #[async_trait]
 impl AIClient for GeminiClient {
-    async fn generate_melody(&self, request: &MelodyRequest, api_key: &str) -> Result<MelodyResponse> {
-        let system_prompt = build_system_prompt(request);
-        let user_prompt = build_user_prompt(request);
-        let combined_prompt = format!("{}\n\n{}", system_prompt, user_prompt);
-        self.make_request(request, api_key, &combined_prompt).await
-    }
-
-    async fn generate_melody_retry(&self, request: &MelodyRequest, api_key: &str, error: &str) -> Result<MelodyResponse> {
-        let system_prompt = build_system_prompt(request);
-        let retry_prompt = build_retry_prompt(request, error);
-        let combined_prompt = format!("{}\n\n{}", system_prompt, retry_prompt);
-        self.make_request(request, api_key, &combined_prompt).await
-    }
-}
-
-impl GeminiClient {
-    async fn make_request(&self, request: &MelodyRequest, api_key: &str, combined_prompt: &str) -> Result<MelodyResponse> {
-        let schema = generate_gemini_schema();
-
-        let body = json!({
-            "contents": [{
-                "parts": [{
-                    "text": combined_prompt
-                }]
-            }],
-            "generationConfig": {
-                "temperature": request.temperature.unwrap_or(1.0),
-                "responseMimeType": "application/json",
-                "responseSchema": schema
-            }
-        });
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
+        let body = gemini_body(prompt);
 
+        // The key goes in a header: request errors quote the URL
         let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-            api_key
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
+            self.model
         );
 
         let response = self
             .client
             .post(&url)
+            .header("x-goog-api-key", secret_header(api_key)?)
             .header("Content-Type", "application/json")
             .json(&body)
             .send()
             .await
             .context("Failed to send request to Gemini")?;
 
+        parse_gemini_response(response, "Gemini").await
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    async fn list_models(&self, api_key: &str) -> Result<Vec<String>> {
+        let response = self
+            .client
+            .get("https://generativelanguage.googleapis.com/v1beta/models")
+            .header("x-goog-api-key", secret_header(api_key)?)
+            .query(&[("pageSize", "1000")])
+            .send()
+            .await
+            .context("Failed to list Gemini models")?;
+
+        response_received(&response);
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!("Gemini API error ({}): {}", status, error_text));
+            return Err(api_error(response, "Gemini").await);
         }
 
-        let gemini_response: GeminiResponse = response
+        let list: GeminiModelList = response
             .json()
             .await
-            .context("Failed to parse Gemini response")?;
+            .context("Failed to parse Gemini model list")?;
 
-        let content = gemini_response
-            .candidates
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No candidates in Gemini response"))?
-            .content
-            .parts
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No parts in Gemini response"))?
-            .text
-            .clone();
-
-        // Parse JSON directly (structured outputs guarantee valid JSON)
-        let ai_notes: AINotesResponse = serde_json::from_str(&content)
-            .context("Failed to parse notes JSON from structured output")?;
-
-        // Convert to our Note format
-        let notes: Vec<Note> = ai_notes
-            .notes
+        Ok(list
+            .models
             .into_iter()
-            .map(|n| Note {
-                id: uuid::Uuid::new_v4().to_string(),
-                pitch: n.pitch,
-                start_time: n.start_time,
-                duration: n.duration,
-                velocity: n.velocity,
-                track_id: "track_right_hand".to_string(),
-            })
-            .collect();
+            .filter(|model| model.supported_generation_methods.iter().any(|m| m == "generateContent"))
+            .map(|model| model.name.trim_start_matches("models/").to_string())
+            .collect())
+    }
+}
 
-        Ok(MelodyResponse {
-            notes,
-            metadata: GenerationMetadata {
-                provider: AIProvider::Gemini,
-                timestamp: chrono::Utc::now().to_rfc3339(),
-                model_name: "gemini-2.5-flash".to_string(),
-                temperature: request.temperature.unwrap_or(1.0),
-                scale: request.scale.clone(),
-            },
+/// `generateContent` request body shared by Gemini and Vertex AI
+fn gemini_body(prompt: &AIPrompt) -> serde_json::Value {
+    // The system prompt goes at the top of the first user message
+    let contents: Vec<serde_json::Value> = prompt
+        .messages()
+        .into_iter()
+        .enumerate()
+        .map(|(i, message)| {
+            let text = if i == 0 { format!("{}\n\n{}", prompt.system, message.content) } else { message.content };
+            let role = match message.role {
+                ChatRole::User => "user",
+                ChatRole::Assistant => "model",
+            };
+            json!({ "role": role, "parts": [{ "text": text }] })
         })
+        .collect();
+
+    let mut body = json!({
+        "contents": contents,
+        "generationConfig": {
+            "temperature": prompt.temperature,
+            "responseMimeType": "application/json",
+            "responseSchema": prompt.schema
+        }
+    });
+    if let Some(seed) = prompt.seed {
+        body["generationConfig"]["seed"] = json!(seed);
+    }
+    if let Some(max_tokens) = prompt.max_tokens {
+        body["generationConfig"]["maxOutputTokens"] = json!(max_tokens);
+    }
+    body
+}
+
+/// Read the JSON reply from a `generateContent` response
+async fn parse_gemini_response(response: reqwest::Response, service: &str) -> Result<serde_json::Value> {
+    response_received(&response);
+    if !response.status().is_success() {
+        return Err(api_error(response, service).await);
+    }
+
+    let gemini_response: GeminiResponse = response
+        .json()
+        .await
+        .with_context(|| format!("Failed to parse {} response", service))?;
+    if let Some(usage) = &gemini_response.usage_metadata {
+        key_audit::record_usage(usage.prompt_token_count, usage.candidates_token_count);
+    }
+
+    let content = gemini_response
+        .candidates
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No candidates in {} response", service))?
+        .content
+        .parts
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No parts in {} response", service))?
+        .text
+        .clone();
+
+    // Parse JSON directly (structured outputs guarantee valid JSON)
+    serde_json::from_str(&content).context("Failed to parse JSON from structured output")
+}
+
+// ============================================================================
+// Vertex AI Client
+// ============================================================================
+
+const VERTEX_DEFAULT_REGION: &str = "us-central1";
+const VERTEX_DEFAULT_MODEL: &str = "gemini-2.0-flash";
+
+/// Gemini models through Google Cloud Vertex AI
+///
+/// The "API key" is the stored `GoogleCredentials` (service account key or
+/// OAuth access token). The project comes from the settings, falling back to
+/// the service account's project.
+pub struct VertexClient {
+    client: Client,
+    region: String,
+    project_id: Option<String>,
+    model: String,
+}
+
+impl VertexClient {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
+        Self {
+            client,
+            region: config.region.clone().unwrap_or_else(|| VERTEX_DEFAULT_REGION.to_string()),
+            project_id: config.project_id.clone(),
+            model: config.model.clone().unwrap_or_else(|| VERTEX_DEFAULT_MODEL.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl AIClient for VertexClient {
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
+        let credentials = GoogleCredentials::from_stored(api_key)?;
+        let project_id = self
+            .project_id
+            .as_deref()
+            .or(credentials.project_id())
+            .ok_or_else(|| anyhow::anyhow!("Vertex AI project ID is not configured"))?;
+        let access_token = google_auth::access_token(&self.client, &credentials).await?;
+
+        let url = format!(
+            "https://{region}-aiplatform.googleapis.com/v1/projects/{project}/locations/{region}/publishers/google/models/{model}:generateContent",
+            region = self.region,
+            project = project_id,
+            model = self.model
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&*access_token)
+            .header("Content-Type", "application/json")
+            .json(&gemini_body(prompt))
+            .send()
+            .await
+            .context("Failed to send request to Vertex AI")?;
+
+        parse_gemini_response(response, "Vertex AI").await
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
     }
 }
 
@@ -430,14 +836,18 @@ impl GeminiClient {
 // Anthropic Client
 // ============================================================================
 
+const ANTHROPIC_DEFAULT_MODEL: &str = "claude-3-5-haiku-20241022";
+
 pub struct AnthropicClient {
     client: Client,
+    model: String,
 }
 
 impl AnthropicClient {
-    pub fn new() -> Self {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
         Self {
-            client: Client::new(),
+            client,
+            model: config.model.clone().unwrap_or_else(|| ANTHROPIC_DEFAULT_MODEL.to_string()),
         }
     }
 }
@@ -445,6 +855,14 @@ impl AnthropicClient {
 #[derive(Debug, Deserialize)]
 struct AnthropicResponse {
     content: Vec<AnthropicContent>,
+    #[serde(default)]
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicUsage {
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -458,51 +876,30 @@ enum AnthropicContent {
 
 #[async_trait]
 impl AIClient for AnthropicClient {
-    async fn generate_melody(&self, request: &MelodyRequest, api_key: &str) -> Result<MelodyResponse> {
-        let system_prompt = build_system_prompt(request);
-        let user_prompt = build_user_prompt(request);
-        self.make_request(request, api_key, &system_prompt, &user_prompt).await
-    }
-
-    async fn generate_melody_retry(&self, request: &MelodyRequest, api_key: &str, error: &str) -> Result<MelodyResponse> {
-        let system_prompt = build_system_prompt(request);
-        let retry_prompt = build_retry_prompt(request, error);
-        self.make_request(request, api_key, &system_prompt, &retry_prompt).await
-    }
-}
-
-impl AnthropicClient {
-    async fn make_request(&self, request: &MelodyRequest, api_key: &str, system_prompt: &str, user_prompt: &str) -> Result<MelodyResponse> {
-        let schema = generate_melody_schema();
-
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
         let body = json!({
-            "model": "claude-3-5-haiku-20241022",
-            "max_tokens": 4096,
-            "system": system_prompt,
-            "messages": [
-                {
-                    "role": "user",
-                    "content": user_prompt
-                }
-            ],
-            "temperature": request.temperature.unwrap_or(1.0),
+            "model": self.model,
+            "max_tokens": prompt.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "system": prompt.system,
+            "messages": chat_messages(None, prompt),
+            "temperature": prompt.temperature,
             "tools": [
                 {
-                    "name": "generate_melody",
-                    "description": "Generate a musical melody with specified notes",
-                    "input_schema": schema
+                    "name": prompt.schema_name,
+                    "description": "Return the generated music in the required structure",
+                    "input_schema": prompt.schema
                 }
             ],
             "tool_choice": {
                 "type": "tool",
-                "name": "generate_melody"
+                "name": prompt.schema_name
             }
         });
 
         let response = self
             .client
             .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", api_key)
+            .header("x-api-key", secret_header(api_key)?)
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json")
             .json(&body)
@@ -510,55 +907,46 @@ impl AnthropicClient {
             .await
             .context("Failed to send request to Anthropic")?;
 
+        response_received(&response);
         if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!("Anthropic API error ({}): {}", status, error_text));
+            return Err(api_error(response, "Anthropic").await);
         }
 
         let anthropic_response: AnthropicResponse = response
             .json()
             .await
             .context("Failed to parse Anthropic response")?;
+        if let Some(usage) = &anthropic_response.usage {
+            key_audit::record_usage(usage.input_tokens, usage.output_tokens);
+        }
 
-        // Find the tool use in the response
-        let tool_use = anthropic_response
+        // Find the tool use in the response (structured outputs guarantee valid JSON)
+        anthropic_response
             .content
-            .iter()
+            .into_iter()
             .find_map(|content| match content {
                 AnthropicContent::ToolUse { input, .. } => Some(input),
                 _ => None,
             })
-            .ok_or_else(|| anyhow::anyhow!("No tool use found in Anthropic response"))?;
+            .ok_or_else(|| anyhow::anyhow!("No tool use found in Anthropic response"))
+    }
 
-        // Parse JSON directly from tool input (structured outputs guarantee valid JSON)
-        let ai_notes: AINotesResponse = serde_json::from_value(tool_use.clone())
-            .context("Failed to parse notes JSON from structured output")?;
+    fn model_name(&self) -> &str {
+        &self.model
+    }
 
-        // Convert to our Note format
-        let notes: Vec<Note> = ai_notes
-            .notes
-            .into_iter()
-            .map(|n| Note {
-                id: uuid::Uuid::new_v4().to_string(),
-                pitch: n.pitch,
-                start_time: n.start_time,
-                duration: n.duration,
-                velocity: n.velocity,
-                track_id: "track_right_hand".to_string(),
-            })
-            .collect();
+    async fn list_models(&self, api_key: &str) -> Result<Vec<String>> {
+        let response = self
+            .client
+            .get("https://api.anthropic.com/v1/models")
+            .query(&[("limit", "1000")])
+            .header("x-api-key", secret_header(api_key)?)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await
+            .context("Failed to list Anthropic models")?;
 
-        Ok(MelodyResponse {
-            notes,
-            metadata: GenerationMetadata {
-                provider: AIProvider::Anthropic,
-                timestamp: chrono::Utc::now().to_rfc3339(),
-                model_name: "claude-3-5-haiku-20241022".to_string(),
-                temperature: request.temperature.unwrap_or(1.0),
-                scale: request.scale.clone(),
-            },
-        })
+        parse_model_list(response, "Anthropic").await
     }
 }
 
@@ -572,21 +960,748 @@ pub struct CohereClient {
 }
 
 impl CohereClient {
-    pub fn new() -> Self {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
         Self {
-            client: Client::new(),
+            client,
         }
     }
 }
 
 #[async_trait]
 impl AIClient for CohereClient {
-    async fn generate_melody(&self, _request: &MelodyRequest, _api_key: &str) -> Result<MelodyResponse> {
+    async fn complete(&self, _prompt: &AIPrompt, _api_key: &str) -> Result<serde_json::Value> {
         Err(anyhow::anyhow!("Cohere client not yet implemented"))
     }
 
-    async fn generate_melody_retry(&self, _request: &MelodyRequest, _api_key: &str, _error: &str) -> Result<MelodyResponse> {
-        Err(anyhow::anyhow!("Cohere client not yet implemented"))
+    fn model_name(&self) -> &str {
+        "command-r"
+    }
+}
+
+// ============================================================================
+// Azure OpenAI Client
+// ============================================================================
+
+const AZURE_DEFAULT_API_VERSION: &str = "2024-10-21";
+
+/// OpenAI models deployed on an Azure resource
+///
+/// Requests go to `<endpoint>/openai/deployments/<deployment>/chat/completions`
+/// with the `api-key` header instead of a bearer token.
+pub struct AzureOpenAIClient {
+    client: Client,
+    endpoint: Option<String>,
+    deployment: Option<String>,
+    api_version: String,
+}
+
+impl AzureOpenAIClient {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
+        Self {
+            client,
+            endpoint: config.base_url.as_ref().map(|url| url.trim_end_matches('/').to_string()),
+            deployment: config.deployment.clone(),
+            api_version: config
+                .api_version
+                .clone()
+                .unwrap_or_else(|| AZURE_DEFAULT_API_VERSION.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl AIClient for AzureOpenAIClient {
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
+        let endpoint = self
+            .endpoint
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Azure OpenAI endpoint is not configured"))?;
+        let deployment = self
+            .deployment
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Azure OpenAI deployment name is not configured"))?;
+
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            endpoint, deployment, self.api_version
+        );
+        let body = openai_chat_body(None, prompt);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("api-key", secret_header(api_key)?)
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send request to Azure OpenAI")?;
+
+        parse_openai_response(response, "Azure OpenAI").await
+    }
+
+    /// The deployment stands in for the model
+    fn model_name(&self) -> &str {
+        self.deployment.as_deref().unwrap_or_default()
+    }
+
+    /// The data-plane API can't list deployments; the configured one is the only choice
+    async fn list_models(&self, _api_key: &str) -> Result<Vec<String>> {
+        Ok(self.deployment.iter().cloned().collect())
+    }
+}
+
+// ============================================================================
+// OpenAI-compatible Clients (Groq, custom endpoints)
+// ============================================================================
+
+const GROQ_URL: &str = "https://api.groq.com/openai/v1/chat/completions";
+const GROQ_DEFAULT_MODEL: &str = "llama-3.3-70b-versatile";
+/// LM Studio's default server address
+const CUSTOM_DEFAULT_BASE_URL: &str = "http://localhost:1234/v1";
+
+/// Client for services exposing the OpenAI chat completions API
+pub struct OpenAICompatibleClient {
+    client: Client,
+    service: &'static str,
+    url: String,
+    model: String,
+    /// Whether the service supports `json_schema` structured outputs; otherwise JSON mode is used
+    json_schema: bool,
+}
+
+impl OpenAICompatibleClient {
+    /// Groq: fast inference of open models (Llama, Mixtral)
+    pub fn groq(client: Client, config: &ProviderConfig) -> Self {
+        Self {
+            client,
+            service: "Groq",
+            url: GROQ_URL.to_string(),
+            model: config.model.clone().unwrap_or_else(|| GROQ_DEFAULT_MODEL.to_string()),
+            json_schema: false,
+        }
+    }
+
+    /// User-supplied endpoint (LM Studio, vLLM, LocalAI, llama.cpp server)
+    ///
+    /// `base_url` is the API root, e.g. "http://localhost:1234/v1".
+    pub fn custom(client: Client, config: &ProviderConfig) -> Self {
+        let base_url = config.base_url.as_deref().unwrap_or(CUSTOM_DEFAULT_BASE_URL);
+        Self {
+            client,
+            service: "custom endpoint",
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            model: config.model.clone().unwrap_or_default(),
+            json_schema: true,
+        }
+    }
+}
+
+#[async_trait]
+impl AIClient for OpenAICompatibleClient {
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
+        if self.model.is_empty() {
+            return Err(anyhow::anyhow!("No model configured for the {}", self.service));
+        }
+
+        let mut body = openai_chat_body(Some(&self.model), prompt);
+        if !self.json_schema {
+            // JSON mode only guarantees valid JSON, so the shape goes in the prompt
+            body["messages"][0]["content"] = json!(prompt.system_with_format());
+            body["response_format"] = json!({ "type": "json_object" });
+        }
+        // Not every compatible server knows the newer name of the limit
+        if let Some(max_tokens) = body.as_object_mut().and_then(|body| body.remove("max_completion_tokens")) {
+            body["max_tokens"] = max_tokens;
+        }
+
+        let mut http_request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .json(&body);
+        if !api_key.is_empty() {
+            http_request = http_request.bearer_auth(api_key);
+        }
+
+        let response = http_request
+            .send()
+            .await
+            .with_context(|| format!("Failed to send request to {}", self.service))?;
+
+        parse_openai_response(response, self.service).await
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    async fn list_models(&self, api_key: &str) -> Result<Vec<String>> {
+        let url = format!("{}/models", self.url.trim_end_matches("/chat/completions"));
+        let mut http_request = self.client.get(&url);
+        if !api_key.is_empty() {
+            http_request = http_request.bearer_auth(api_key);
+        }
+
+        let response = http_request
+            .send()
+            .await
+            .with_context(|| format!("Failed to list {} models", self.service))?;
+
+        parse_model_list(response, self.service).await
+    }
+}
+
+// ============================================================================
+// Hugging Face Client
+// ============================================================================
+
+const HUGGING_FACE_URL: &str = "https://router.huggingface.co/v1";
+const HUGGING_FACE_DEFAULT_MODEL: &str = "meta-llama/Llama-3.1-8B-Instruct";
+
+/// Open models through the Hugging Face Inference API (or a dedicated Inference Endpoint)
+///
+/// Open models don't reliably follow a response format, so replies are
+/// parsed leniently with `extract_json`.
+pub struct HuggingFaceClient {
+    client: Client,
+    base_url: String,
+    model: String,
+}
+
+impl HuggingFaceClient {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
+        Self {
+            client,
+            base_url: config
+                .base_url
+                .as_deref()
+                .unwrap_or(HUGGING_FACE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            model: config.model.clone().unwrap_or_else(|| HUGGING_FACE_DEFAULT_MODEL.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl AIClient for HuggingFaceClient {
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": chat_messages(Some(&prompt.system_with_format()), prompt),
+            "temperature": prompt.temperature,
+            "max_tokens": prompt.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)
+        });
+        if let Some(seed) = prompt.seed {
+            body["seed"] = json!(seed);
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(api_key)
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send request to Hugging Face")?;
+
+        response_received(&response);
+        if !response.status().is_success() {
+            return Err(api_error(response, "Hugging Face").await);
+        }
+
+        let hf_response: OpenAIResponse = response
+            .json()
+            .await
+            .context("Failed to parse Hugging Face response")?;
+        if let Some(usage) = &hf_response.usage {
+            key_audit::record_usage(usage.prompt_tokens, usage.completion_tokens);
+        }
+
+        let content = &hf_response
+            .choices
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No choices in Hugging Face response"))?
+            .message
+            .content;
+
+        parse_json_text(content)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    async fn list_models(&self, api_key: &str) -> Result<Vec<String>> {
+        let response = self
+            .client
+            .get(format!("{}/models", self.base_url))
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .context("Failed to list Hugging Face models")?;
+
+        parse_model_list(response, "Hugging Face").await
+    }
+}
+
+// ============================================================================
+// AWS Bedrock Client
+// ============================================================================
+
+const BEDROCK_DEFAULT_REGION: &str = "us-east-1";
+const BEDROCK_DEFAULT_MODEL: &str = "anthropic.claude-3-5-haiku-20241022-v1:0";
+
+/// Models hosted on AWS Bedrock (Claude, Titan, ...) through the Converse API
+///
+/// Requests are signed with SigV4; the "API key" is the stored `AwsCredentials` JSON.
+pub struct BedrockClient {
+    client: Client,
+    region: String,
+    model: String,
+}
+
+impl BedrockClient {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
+        Self {
+            client,
+            region: config.region.clone().unwrap_or_else(|| BEDROCK_DEFAULT_REGION.to_string()),
+            model: config.model.clone().unwrap_or_else(|| BEDROCK_DEFAULT_MODEL.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BedrockResponse {
+    output: BedrockOutput,
+    #[serde(default)]
+    usage: Option<BedrockUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockUsage {
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BedrockOutput {
+    message: BedrockMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct BedrockMessage {
+    content: Vec<BedrockContent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BedrockContent {
+    text: Option<String>,
+}
+
+#[async_trait]
+impl AIClient for BedrockClient {
+    async fn complete(&self, prompt: &AIPrompt, api_key: &str) -> Result<serde_json::Value> {
+        let credentials = AwsCredentials::from_stored(api_key)?;
+
+        // Converse has no portable structured output, so the JSON shape goes in the prompt
+        let messages: Vec<serde_json::Value> = prompt
+            .messages()
+            .into_iter()
+            .map(|message| json!({ "role": message.role.as_str(), "content": [{ "text": message.content }] }))
+            .collect();
+        let body = json!({
+            "system": [{ "text": prompt.system_with_format() }],
+            "messages": messages,
+            "inferenceConfig": {
+                "temperature": prompt.temperature.min(1.0),
+                "maxTokens": prompt.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)
+            }
+        });
+        let body = serde_json::to_vec(&body).context("Failed to serialize Bedrock request")?;
+
+        // Model ids contain ':' which must be encoded in the path, and encoded again for signing
+        let host = format!("bedrock-runtime.{}.amazonaws.com", self.region);
+        let model = aws_sigv4::uri_encode(&self.model);
+        let path = format!("/model/{}/converse", model);
+        let canonical_uri = format!("/model/{}/converse", aws_sigv4::uri_encode(&model));
+
+        let params = SigningParams {
+            access_key_id: &credentials.access_key_id,
+            secret_access_key: credentials.secret_access_key.as_str(),
+            session_token: credentials.session_token.as_ref().map(|token| token.as_str()),
+            region: &self.region,
+            service: "bedrock",
+        };
+        let content_type = ("content-type", "application/json");
+        let signed = aws_sigv4::sign_request("POST", &host, &canonical_uri, &[content_type], &body, &params, chrono::Utc::now());
+
+        let mut http_request = self
+            .client
+            .post(format!("https://{}{}", host, path))
+            .header("Content-Type", "application/json")
+            .body(body);
+        for (name, value) in signed {
+            http_request = http_request.header(name, value);
+        }
+
+        let response = http_request
+            .send()
+            .await
+            .context("Failed to send request to Bedrock")?;
+
+        response_received(&response);
+        if !response.status().is_success() {
+            return Err(api_error(response, "Bedrock").await);
+        }
+
+        let bedrock_response: BedrockResponse = response
+            .json()
+            .await
+            .context("Failed to parse Bedrock response")?;
+        if let Some(usage) = &bedrock_response.usage {
+            key_audit::record_usage(usage.input_tokens, usage.output_tokens);
+        }
+
+        let text: String = bedrock_response
+            .output
+            .message
+            .content
+            .into_iter()
+            .filter_map(|content| content.text)
+            .collect();
+
+        parse_json_text(&text)
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Find the JSON object in a free-text model reply (code fences, leading prose, ...)
+pub fn extract_json(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    (end > start).then(|| &text[start..=end])
+}
+
+/// Parse a model reply that should be JSON but may be wrapped in other text
+fn parse_json_text(text: &str) -> Result<serde_json::Value> {
+    if let Ok(value @ serde_json::Value::Object(_)) = serde_json::from_str::<serde_json::Value>(text.trim()) {
+        return Ok(value);
+    }
+
+    let json = extract_json(text).ok_or_else(|| anyhow::anyhow!("No JSON found in model response"))?;
+    serde_json::from_str(json).context("Failed to parse JSON from model response")
+}
+
+// ============================================================================
+// Ollama Client (local models)
+// ============================================================================
+
+const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434";
+const OLLAMA_DEFAULT_MODEL: &str = "llama3.1";
+
+pub struct OllamaClient {
+    client: Client,
+    base_url: String,
+    model: String,
+}
+
+impl OllamaClient {
+    pub fn new(client: Client, config: &ProviderConfig) -> Self {
+        Self {
+            client,
+            base_url: config
+                .base_url
+                .clone()
+                .unwrap_or_else(|| OLLAMA_DEFAULT_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            model: config.model.clone().unwrap_or_else(|| OLLAMA_DEFAULT_MODEL.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    message: OllamaMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+    content: String,
+}
+
+#[async_trait]
+impl AIClient for OllamaClient {
+    async fn complete(&self, prompt: &AIPrompt, _api_key: &str) -> Result<serde_json::Value> {
+        // Ollama accepts a JSON schema in "format" to constrain the output
+        let body = json!({
+            "model": self.model,
+            "messages": chat_messages(Some(&prompt.system), prompt),
+            "stream": false,
+            "format": prompt.schema,
+            "options": {
+                "temperature": prompt.temperature,
+                "seed": prompt.seed,
+                "num_predict": prompt.max_tokens
+            }
+        });
+
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&body)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Ollama at {} (is it running?)", self.base_url))?;
+
+        response_received(&response);
+        if !response.status().is_success() {
+            return Err(api_error(response, "Ollama").await);
+        }
+
+        let ollama_response: OllamaResponse = response
+            .json()
+            .await
+            .context("Failed to parse Ollama response")?;
+
+        serde_json::from_str(&ollama_response.message.content)
+            .context("Failed to parse JSON from structured output")
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+
+    /// Models pulled on the local server
+    async fn list_models(&self, _api_key: &str) -> Result<Vec<String>> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Ollama at {} (is it running?)", self.base_url))?;
+
+        response_received(&response);
+        if !response.status().is_success() {
+            return Err(api_error(response, "Ollama").await);
+        }
+
+        let tags: OllamaTags = response
+            .json()
+            .await
+            .context("Failed to parse Ollama model list")?;
+
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaModel {
+    name: String,
+}
+
+/// Parse a provider's JSON reply to a generation request
+fn parse_reply(reply: serde_json::Value, model_name: &str, request: &MelodyRequest) -> Result<MelodyResponse> {
+    let ai_notes: AINotesResponse = serde_json::from_value(reply)
+        .context("Failed to parse notes JSON from structured output")?;
+    Ok(build_response(ai_notes, model_name, request))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AICritique {
+    summary: String,
+    #[serde(default)]
+    phrasing: Vec<String>,
+    #[serde(default)]
+    harmony_issues: Vec<String>,
+    #[serde(default)]
+    suggested_edits: Vec<AISuggestedEdit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AISuggestedEdit {
+    notes: Vec<usize>,
+    suggestion: String,
+}
+
+/// Parse a critique reply, turning the note numbers of suggested edits into ids of `notes`
+///
+/// Numbers that don't match a note are dropped, and so are edits left without notes.
+fn parse_critique(reply: serde_json::Value, notes: &[Note]) -> Result<MelodyCritique> {
+    let critique: AICritique = serde_json::from_value(reply)
+        .context("Failed to parse critique JSON from structured output")?;
+
+    let suggested_edits = critique
+        .suggested_edits
+        .into_iter()
+        .filter_map(|edit| {
+            let note_ids: Vec<String> = edit
+                .notes
+                .iter()
+                .filter_map(|&number| notes.get(number.checked_sub(1)?))
+                .map(|note| note.id.clone())
+                .collect();
+            (!note_ids.is_empty()).then(|| SuggestedEdit { note_ids, suggestion: edit.suggestion })
+        })
+        .collect();
+
+    Ok(MelodyCritique {
+        summary: critique.summary,
+        phrasing: critique.phrasing,
+        harmony_issues: critique.harmony_issues,
+        suggested_edits,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct AITitles {
+    titles: Vec<String>,
+}
+
+/// Longest title kept from a suggestion reply, in characters
+pub const MAX_TITLE_CHARS: usize = 100;
+
+/// Titles from a suggestion reply: trimmed of quotes, without blanks or duplicates, at most `count`
+fn parse_titles(reply: serde_json::Value, count: usize) -> Result<Vec<String>> {
+    let reply: AITitles = serde_json::from_value(reply)
+        .context("Failed to parse titles JSON from structured output")?;
+
+    let mut titles: Vec<String> = Vec::new();
+    for title in reply.titles {
+        let title = title.trim().trim_matches(['"', '“', '”', '\'']).trim().to_string();
+        if !title.is_empty()
+            && title.chars().count() <= MAX_TITLE_CHARS
+            && !titles.iter().any(|t| t.eq_ignore_ascii_case(&title))
+        {
+            titles.push(title);
+        }
+    }
+    titles.truncate(count);
+    anyhow::ensure!(!titles.is_empty(), "The provider suggested no usable titles");
+    Ok(titles)
+}
+
+/// Convert parsed notes into a `MelodyResponse` with generation metadata
+///
+/// Notes go to the track of the request's mode.
+fn build_response(ai_notes: AINotesResponse, model_name: &str, request: &MelodyRequest) -> MelodyResponse {
+    let track_id = request.mode.default_track();
+    let notes = ai_notes
+        .notes
+        .into_iter()
+        .map(|n| Note {
+            id: uuid::Uuid::new_v4().to_string(),
+            pitch: n.pitch,
+            start_time: n.start_time,
+            duration: n.duration,
+            velocity: n.velocity,
+            track_id: n.role.map_or(track_id, |role| role.track_id()).to_string(),
+        })
+        .collect();
+
+    let chords = ai_notes
+        .chords
+        .into_iter()
+        .map(|c| ChordSymbol {
+            symbol: c.symbol.trim().to_string(),
+            start_time: c.start_time,
+            duration: c.duration,
+        })
+        .collect();
+
+    let dynamics = ai_notes
+        .dynamics
+        .into_iter()
+        .map(|h| Hairpin {
+            shape: h.shape,
+            start_time: h.start_time,
+            duration: h.duration,
+            from_velocity: h.from_velocity,
+            to_velocity: h.to_velocity,
+        })
+        .collect();
+    let spans: Vec<(f64, f64)> = ai_notes.pedal.iter().map(|p| (p.start_time, p.duration)).collect();
+
+    MelodyResponse {
+        notes,
+        chords,
+        dynamics,
+        pedal: ai_models::pedal_lane(&spans),
+        metadata: GenerationMetadata {
+            provider: request.model_provider.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            model_name: model_name.to_string(),
+            temperature: request.temperature.unwrap_or(1.0),
+            scale: request.scale.clone(),
+            seed: request.seed.filter(|_| request.model_provider.supports_seed()),
+        },
+    }
+}
+
+// ============================================================================
+// Local Model Client
+// ============================================================================
+
+/// Melodies from a neural model run on this machine (see `local_model`)
+///
+/// No network and no cost, but the model only writes melodies and can't
+/// follow the prompt's wording.
+pub struct LocalModelClient {
+    model_path: Option<PathBuf>,
+    model_name: String,
+}
+
+impl LocalModelClient {
+    pub fn new(config: &ProviderConfig) -> Self {
+        let model_path = config.model_path.as_ref().map(PathBuf::from);
+        let model_name = model_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or_else(|| "local".to_string(), |stem| stem.to_string_lossy().into_owned());
+        Self { model_path, model_name }
+    }
+}
+
+#[async_trait]
+impl AIClient for LocalModelClient {
+    async fn complete(&self, _prompt: &AIPrompt, _api_key: &str) -> Result<serde_json::Value> {
+        anyhow::bail!("The local model only generates melodies")
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    async fn generate_melody(&self, request: &MelodyRequest, _api_key: &str) -> Result<MelodyResponse> {
+        if !matches!(request.mode, GenerationMode::Melody) {
+            anyhow::bail!("The local model only generates melodies");
+        }
+        let path = self.model_path.clone().context("No model file configured for the local provider")?;
+        let model_name = self.model_name.clone();
+        let request = request.clone();
+
+        tokio::task::spawn_blocking(move || local_model::generate(&path, &model_name, &request))
+            .await
+            .context("Local model generation stopped")?
+    }
+
+    /// The model can't read the validation error; sampling again gives another melody
+    async fn generate_melody_retry(&self, request: &MelodyRequest, api_key: &str, _error: &str) -> Result<MelodyResponse> {
+        self.generate_melody(request, api_key).await
     }
 }
 
@@ -594,11 +1709,157 @@ impl AIClient for CohereClient {
 // Client Factory
 // ============================================================================
 
-pub fn create_client(provider: &AIProvider) -> Box<dyn AIClient> {
+pub fn create_client(provider: &AIProvider, config: &ProviderConfig, clients: &HttpClients) -> Box<dyn AIClient> {
+    if *provider == AIProvider::Local {
+        return Box::new(LocalModelClient::new(config));
+    }
+
+    let client = clients.get(provider, config);
     match provider {
-        AIProvider::OpenAI => Box::new(OpenAIClient::new()),
-        AIProvider::Gemini => Box::new(GeminiClient::new()),
-        AIProvider::Anthropic => Box::new(AnthropicClient::new()),
-        AIProvider::Cohere => Box::new(CohereClient::new()),
+        AIProvider::OpenAI => Box::new(OpenAIClient::new(client, config)),
+        AIProvider::Gemini => Box::new(GeminiClient::new(client, config)),
+        AIProvider::Anthropic => Box::new(AnthropicClient::new(client, config)),
+        AIProvider::Cohere => Box::new(CohereClient::new(client, config)),
+        AIProvider::Ollama => Box::new(OllamaClient::new(client, config)),
+        AIProvider::Azure => Box::new(AzureOpenAIClient::new(client, config)),
+        AIProvider::Groq => Box::new(OpenAICompatibleClient::groq(client, config)),
+        AIProvider::Bedrock => Box::new(BedrockClient::new(client, config)),
+        AIProvider::Custom => Box::new(OpenAICompatibleClient::custom(client, config)),
+        AIProvider::HuggingFace => Box::new(HuggingFaceClient::new(client, config)),
+        AIProvider::Vertex => Box::new(VertexClient::new(client, config)),
+        AIProvider::Local => Box::new(LocalModelClient::new(config)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_key_storage::Secret;
+
+    #[test]
+    fn test_parse_titles_cleans_up() {
+        let reply = json!({ "titles": ["\"Harbor Lights\"", "harbor lights", "  ", "Slow Tide", "Third"] });
+        assert_eq!(parse_titles(reply, 2).unwrap(), vec!["Harbor Lights", "Slow Tide"]);
+        assert!(parse_titles(json!({ "titles": [""] }), 3).is_err());
+    }
+
+    #[test]
+    fn test_extract_json_from_prose() {
+        let reply = "Here is your melody:\n```json\n{\"notes\": [{\"pitch\": 60, \"startTime\": 0.0, \"duration\": 1.0, \"velocity\": 90}]}\n```";
+        let reply = parse_json_text(reply).unwrap();
+        assert_eq!(parse_reply(reply, "test", &MelodyRequest::default()).unwrap().notes.len(), 1);
+        assert_eq!(extract_json("no json here"), None);
+    }
+
+    #[test]
+    fn test_chord_reply_goes_to_chord_track() {
+        let request = MelodyRequest {
+            mode: crate::ai_models::GenerationMode::Chords,
+            ..MelodyRequest::default()
+        };
+        let reply = json!({
+            "notes": [{ "pitch": 48, "startTime": 0.0, "duration": 4.0, "velocity": 70 }],
+            "chords": [{ "symbol": " Cmaj7 ", "startTime": 0.0, "duration": 4.0 }]
+        });
+
+        let response = parse_reply(reply, "test", &request).unwrap();
+        assert_eq!(response.notes[0].track_id, "track_chords");
+        assert_eq!(response.chords[0].symbol, "Cmaj7");
+    }
+
+    #[test]
+    fn test_seed_recorded_when_provider_uses_it() {
+        let request = MelodyRequest { seed: Some(42), ..MelodyRequest::default() };
+        let reply = json!({ "notes": [{ "pitch": 60, "startTime": 0.0, "duration": 1.0, "velocity": 80 }] });
+        assert_eq!(parse_reply(reply.clone(), "test", &request).unwrap().metadata.seed, Some(42));
+
+        let request = MelodyRequest { model_provider: AIProvider::Anthropic, ..request };
+        assert_eq!(parse_reply(reply, "test", &request).unwrap().metadata.seed, None);
+    }
+
+    #[test]
+    fn test_hairpins_and_pedal_spans() {
+        let reply = json!({
+            "notes": [
+                { "pitch": 60, "startTime": 0.0, "duration": 1.0, "velocity": 90 },
+                { "pitch": 62, "startTime": 2.0, "duration": 1.0, "velocity": 90 },
+                { "pitch": 64, "startTime": 4.0, "duration": 1.0, "velocity": 90 }
+            ],
+            "dynamics": [{ "shape": "crescendo", "startTime": 0.0, "duration": 4.0, "fromVelocity": 50, "toVelocity": 90 }],
+            "pedal": [{ "startTime": 0.0, "duration": 2.0 }, { "startTime": 1.0, "duration": 2.0 }]
+        });
+
+        let mut response = parse_reply(reply, "test", &MelodyRequest::default()).unwrap();
+        assert!(response.validate_request(&MelodyRequest::default()).is_ok());
+        response.apply_dynamics();
+        let velocities: Vec<u8> = response.notes.iter().map(|note| note.velocity).collect();
+        assert_eq!(velocities, vec![50, 70, 90]);
+
+        // Overlapping spans are one press
+        assert_eq!(response.pedal.len(), 2);
+        assert_eq!((response.pedal[1].time, response.pedal[1].down), (3.0, false));
+
+        response.dynamics[0].to_velocity = 40;
+        assert!(response.validate_request(&MelodyRequest::default()).unwrap_err().contains("must get louder"));
+    }
+
+    #[test]
+    fn test_critique_edits_refer_to_note_ids() {
+        let notes: Vec<Note> = ["a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(i, id)| Note {
+                id: id.to_string(),
+                pitch: 60 + i as u8,
+                start_time: i as f64,
+                duration: 1.0,
+                velocity: 80,
+                track_id: "track_right_hand".to_string(),
+            })
+            .collect();
+        let reply = json!({
+            "summary": "A clear opening.",
+            "phrasing": ["The phrase never breathes"],
+            "harmonyIssues": [],
+            "suggestedEdits": [
+                { "notes": [2, 3], "suggestion": "Raise to D4" },
+                { "notes": [0, 9], "suggestion": "Not a note" }
+            ]
+        });
+
+        let critique = parse_critique(reply, &notes).unwrap();
+        assert_eq!(critique.phrasing.len(), 1);
+        assert_eq!(critique.suggested_edits.len(), 1);
+        assert_eq!(critique.suggested_edits[0].note_ids, vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn test_http_clients_follow_provider_settings() {
+        let clients = HttpClients::new();
+        let openai = ProviderConfig { model: Some("gpt-4o".to_string()), ..ProviderConfig::default() };
+        assert_eq!(clients.fingerprint(&openai), clients.fingerprint(&ProviderConfig::default()));
+
+        let slow = ProviderConfig { timeout_secs: Some(600), ..ProviderConfig::default() };
+        assert_ne!(clients.fingerprint(&openai), clients.fingerprint(&slow));
+
+        // A proxy is ignored when proxies are off; its password changes the client otherwise
+        let proxy = crate::ai_settings::ProxyConfig {
+            url: "http://proxy.corp:8080".to_string(),
+            username: Some("ci".to_string()),
+            password: Some(Secret::new("first".to_string())),
+        };
+        let direct = ProviderConfig { no_proxy: true, proxy: Some(proxy.clone()), ..ProviderConfig::default() };
+        assert_eq!(clients.fingerprint(&direct), clients.fingerprint(&ProviderConfig { no_proxy: true, ..ProviderConfig::default() }));
+        let proxied = ProviderConfig { proxy: Some(proxy.clone()), ..ProviderConfig::default() };
+        let rotated = crate::ai_settings::ProxyConfig { password: Some(Secret::new("second".to_string())), ..proxy };
+        assert_ne!(clients.fingerprint(&proxied), clients.fingerprint(&ProviderConfig { proxy: Some(rotated), ..ProviderConfig::default() }));
+
+        // Changing a provider's settings replaces its client rather than adding one
+        clients.get(&AIProvider::OpenAI, &openai);
+        clients.get(&AIProvider::OpenAI, &slow);
+        clients.get(&AIProvider::Gemini, &openai);
+        let cached = clients.clients.lock().unwrap();
+        assert_eq!(cached.len(), 2);
+        assert_eq!(cached[&AIProvider::OpenAI].0, clients.fingerprint(&slow));
     }
 }
@@ -0,0 +1,116 @@
+use crate::ai_models::{measures_spanned, Note, RefineTurn};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Turns sent as context; older follow-ups are dropped, the original generation is always kept
+pub const MAX_TURNS: usize = 8;
+
+/// A generation being refined with follow-up instructions ("make bar 3 busier")
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    /// Beat the refined passage starts at in the project
+    pub start_beat: f64,
+    /// Length of the passage in measures
+    pub measures: u32,
+    /// The original generation, then each follow-up, with notes from beat 0
+    pub turns: Vec<RefineTurn>,
+}
+
+/// Open refinement conversations by id, for the current session
+#[derive(Default)]
+pub struct Conversations {
+    conversations: Mutex<HashMap<String, Conversation>>,
+}
+
+impl Conversations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start refining `notes`, generated from `prompt`; returns the conversation's id
+    ///
+    /// The passage starts at the measure holding the first note.
+    pub fn start(&self, prompt: String, notes: Vec<Note>, beats_per_measure: f64) -> String {
+        let first = notes.iter().map(|note| note.start_time).fold(f64::INFINITY, f64::min);
+        let start_beat = (first / beats_per_measure).floor().max(0.0) * beats_per_measure;
+        let notes: Vec<Note> = notes
+            .into_iter()
+            .map(|note| Note { start_time: note.start_time - start_beat, ..note })
+            .collect();
+        let measures = measures_spanned(&notes, beats_per_measure);
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let conversation = Conversation {
+            start_beat,
+            measures,
+            turns: vec![RefineTurn { instruction: prompt, notes }],
+        };
+        self.conversations.lock().unwrap().insert(id.clone(), conversation);
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Conversation> {
+        self.conversations.lock().unwrap().get(id).cloned()
+    }
+
+    /// Add a follow-up and the melody it produced (notes placed in the project, from `start_beat`)
+    pub fn record(&self, id: &str, instruction: String, notes: &[Note]) -> Result<(), String> {
+        let mut conversations = self.conversations.lock().unwrap();
+        let conversation = conversations
+            .get_mut(id)
+            .ok_or_else(|| format!("No refinement conversation with id {}", id))?;
+
+        let notes = notes
+            .iter()
+            .map(|note| Note { start_time: note.start_time - conversation.start_beat, ..note.clone() })
+            .collect();
+        conversation.turns.push(RefineTurn { instruction, notes });
+        if conversation.turns.len() > MAX_TURNS {
+            conversation.turns.remove(1);
+        }
+        Ok(())
+    }
+
+    pub fn end(&self, id: &str) {
+        self.conversations.lock().unwrap().remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(start_time: f64) -> Note {
+        Note {
+            id: start_time.to_string(),
+            pitch: 60,
+            start_time,
+            duration: 1.0,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_conversation_keeps_original_and_latest_turns() {
+        let conversations = Conversations::new();
+        let id = conversations.start("A waltz".to_string(), vec![note(17.0), note(22.0)], 4.0);
+
+        let conversation = conversations.get(&id).unwrap();
+        assert_eq!(conversation.start_beat, 16.0);
+        assert_eq!(conversation.measures, 2);
+        assert_eq!(conversation.turns[0].notes[0].start_time, 1.0);
+
+        for n in 0..MAX_TURNS {
+            conversations.record(&id, format!("edit {}", n), &[note(16.0)]).unwrap();
+        }
+        let turns = conversations.get(&id).unwrap().turns;
+        assert_eq!(turns.len(), MAX_TURNS);
+        assert_eq!(turns[0].instruction, "A waltz");
+        assert_eq!(turns[1].instruction, "edit 1");
+        assert_eq!(turns.last().unwrap().notes[0].start_time, 0.0);
+
+        conversations.end(&id);
+        assert!(conversations.record(&id, "more".to_string(), &[]).is_err());
+    }
+}
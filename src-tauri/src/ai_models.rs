@@ -1,5 +1,7 @@
+use crate::drum_kit::{drum_sound, DRUM_TRACK_ID, GM_DRUM_MAP};
+use crate::lyrics::Syllable;
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 /// Supported AI providers for melody generation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -9,36 +11,240 @@ pub enum AIProvider {
     Gemini,
     Anthropic,
     Cohere,
+    Ollama,
+    Azure,
+    Groq,
+    Bedrock,
+    Custom,
+    HuggingFace,
+    Vertex,
+    /// A neural model run on this machine from an ONNX file
+    Local,
 }
 
 impl AIProvider {
+    pub const ALL: [AIProvider; 12] = [
+        AIProvider::OpenAI,
+        AIProvider::Gemini,
+        AIProvider::Anthropic,
+        AIProvider::Cohere,
+        AIProvider::Ollama,
+        AIProvider::Azure,
+        AIProvider::Groq,
+        AIProvider::Bedrock,
+        AIProvider::Custom,
+        AIProvider::HuggingFace,
+        AIProvider::Vertex,
+        AIProvider::Local,
+    ];
+
     pub fn as_str(&self) -> &str {
         match self {
             AIProvider::OpenAI => "openai",
             AIProvider::Gemini => "gemini",
             AIProvider::Anthropic => "anthropic",
             AIProvider::Cohere => "cohere",
+            AIProvider::Ollama => "ollama",
+            AIProvider::Azure => "azure",
+            AIProvider::Groq => "groq",
+            AIProvider::Bedrock => "bedrock",
+            AIProvider::Custom => "custom",
+            AIProvider::HuggingFace => "huggingface",
+            AIProvider::Vertex => "vertex",
+            AIProvider::Local => "local",
+        }
+    }
+
+    /// Whether the provider takes a sampling seed, making generations reproducible
+    pub fn supports_seed(&self) -> bool {
+        !matches!(self, AIProvider::Anthropic | AIProvider::Cohere | AIProvider::Bedrock)
+    }
+
+    /// Whether the provider needs an API key (local servers don't)
+    pub fn requires_api_key(&self) -> bool {
+        !matches!(self, AIProvider::Ollama | AIProvider::Custom | AIProvider::Local)
+    }
+
+    /// Environment variable the API key is read from when none is saved
+    pub fn api_key_env_var(&self) -> Option<&'static str> {
+        match self {
+            AIProvider::OpenAI => Some("OPENAI_API_KEY"),
+            AIProvider::Gemini => Some("GEMINI_API_KEY"),
+            AIProvider::Anthropic => Some("ANTHROPIC_API_KEY"),
+            AIProvider::Cohere => Some("COHERE_API_KEY"),
+            AIProvider::Azure => Some("AZURE_OPENAI_API_KEY"),
+            AIProvider::Groq => Some("GROQ_API_KEY"),
+            AIProvider::HuggingFace => Some("HF_TOKEN"),
+            // Bedrock and Vertex take credentials rather than a key; local servers need none
+            AIProvider::Bedrock | AIProvider::Vertex | AIProvider::Ollama | AIProvider::Custom | AIProvider::Local => None,
         }
     }
 
+    /// Whether listing the provider's models checks the API key, so a key can be verified when saved
+    pub fn can_verify_api_key(&self) -> bool {
+        matches!(
+            self,
+            AIProvider::OpenAI | AIProvider::Gemini | AIProvider::Anthropic | AIProvider::Groq | AIProvider::HuggingFace
+        )
+    }
+
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "openai" => Some(AIProvider::OpenAI),
             "gemini" => Some(AIProvider::Gemini),
             "anthropic" => Some(AIProvider::Anthropic),
             "cohere" => Some(AIProvider::Cohere),
+            "ollama" => Some(AIProvider::Ollama),
+            "azure" => Some(AIProvider::Azure),
+            "groq" => Some(AIProvider::Groq),
+            "bedrock" => Some(AIProvider::Bedrock),
+            "custom" => Some(AIProvider::Custom),
+            "huggingface" => Some(AIProvider::HuggingFace),
+            "vertex" => Some(AIProvider::Vertex),
+            "local" => Some(AIProvider::Local),
             _ => None,
         }
     }
 }
 
+/// Scale or mode a key is built on
+///
+/// Parsed case-insensitively, with spaces or hyphens for underscores and the
+/// usual aliases ("ionian", "aeolian", "pentatonic"), so older projects and
+/// free-typed modes still load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", try_from = "String")]
+pub enum ScaleMode {
+    #[default]
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    HarmonicMinor,
+    MelodicMinor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 12] = [
+        ScaleMode::Major,
+        ScaleMode::Minor,
+        ScaleMode::Dorian,
+        ScaleMode::Phrygian,
+        ScaleMode::Lydian,
+        ScaleMode::Mixolydian,
+        ScaleMode::Locrian,
+        ScaleMode::HarmonicMinor,
+        ScaleMode::MelodicMinor,
+        ScaleMode::MajorPentatonic,
+        ScaleMode::MinorPentatonic,
+        ScaleMode::Blues,
+    ];
+
+    /// Semitones above the root of each scale degree
+    pub fn intervals(&self) -> &'static [i32] {
+        match self {
+            ScaleMode::Major => &[0, 2, 4, 5, 7, 9, 11],
+            ScaleMode::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            ScaleMode::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            ScaleMode::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            ScaleMode::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            ScaleMode::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            ScaleMode::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            ScaleMode::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            ScaleMode::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            ScaleMode::MajorPentatonic => &[0, 2, 4, 7, 9],
+            ScaleMode::MinorPentatonic => &[0, 3, 5, 7, 10],
+            ScaleMode::Blues => &[0, 3, 5, 6, 7, 10],
+        }
+    }
+
+    /// Identifier used in JSON ("harmonic_minor")
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScaleMode::Major => "major",
+            ScaleMode::Minor => "minor",
+            ScaleMode::Dorian => "dorian",
+            ScaleMode::Phrygian => "phrygian",
+            ScaleMode::Lydian => "lydian",
+            ScaleMode::Mixolydian => "mixolydian",
+            ScaleMode::Locrian => "locrian",
+            ScaleMode::HarmonicMinor => "harmonic_minor",
+            ScaleMode::MelodicMinor => "melodic_minor",
+            ScaleMode::MajorPentatonic => "major_pentatonic",
+            ScaleMode::MinorPentatonic => "minor_pentatonic",
+            ScaleMode::Blues => "blues",
+        }
+    }
+
+    /// Whether the scale has a minor third above the root
+    pub fn is_minor(&self) -> bool {
+        self.intervals().contains(&3)
+    }
+
+    /// What sets the mode apart, for prompts (None for plain major and minor)
+    pub fn character(&self) -> Option<&'static str> {
+        match self {
+            ScaleMode::Major | ScaleMode::Minor => None,
+            ScaleMode::Dorian => Some("minor with a raised 6th; soulful, folky, not too dark"),
+            ScaleMode::Phrygian => Some("minor with a flat 2nd; dark, Spanish or metal flavor"),
+            ScaleMode::Lydian => Some("major with a raised 4th; dreamy, floating, cinematic"),
+            ScaleMode::Mixolydian => Some("major with a flat 7th; bluesy, rock and folk, no leading tone"),
+            ScaleMode::Locrian => Some("diminished tonic chord; unstable and tense, rarely resolves"),
+            ScaleMode::HarmonicMinor => Some("minor with a raised 7th; exotic augmented 2nd, strong V-i cadence"),
+            ScaleMode::MelodicMinor => Some("minor with raised 6th and 7th; smooth ascending lines, jazzy"),
+            ScaleMode::MajorPentatonic => Some("five notes, no half steps; open, bright, folk and pop"),
+            ScaleMode::MinorPentatonic => Some("five notes, no half steps; bluesy, rock riffs"),
+            ScaleMode::Blues => Some("minor pentatonic plus the flat 5th blue note"),
+        }
+    }
+}
+
+impl std::fmt::Display for ScaleMode {
+    /// Name for prompts and messages ("harmonic minor")
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str().replace('_', " "))
+    }
+}
+
+impl std::str::FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase().replace([' ', '-'], "_");
+        let mode = match name.as_str() {
+            "ionian" => ScaleMode::Major,
+            "aeolian" | "natural_minor" => ScaleMode::Minor,
+            "pentatonic" => ScaleMode::MajorPentatonic,
+            _ => ScaleMode::ALL
+                .into_iter()
+                .find(|mode| mode.as_str() == name)
+                .ok_or_else(|| format!("Unknown scale mode: {}", s.trim()))?,
+        };
+        Ok(mode)
+    }
+}
+
+impl TryFrom<String> for ScaleMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
 /// Musical scale definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Scale {
     /// Root note (e.g., "C", "C#", "D", "Eb", etc.)
     pub root: String,
-    /// Scale mode (e.g., "major", "minor")
-    pub mode: String,
+    /// Scale mode (e.g., major, dorian, blues)
+    pub mode: ScaleMode,
     /// Root octave (1-7, default: 4 for C4)
     pub octave: Option<u8>,
 }
@@ -50,11 +256,7 @@ impl Scale {
     /// Otherwise, returns notes across all octaves (0-127)
     pub fn get_midi_notes(&self) -> Vec<u8> {
         let root_offset = Self::note_to_offset(&self.root);
-        let intervals = match self.mode.to_lowercase().as_str() {
-            "major" => vec![0, 2, 4, 5, 7, 9, 11],
-            "minor" => vec![0, 2, 3, 5, 7, 8, 10],
-            _ => vec![0, 2, 4, 5, 7, 9, 11], // Default to major
-        };
+        let intervals = self.mode.intervals();
 
         let mut notes = Vec::new();
 
@@ -65,7 +267,7 @@ impl Scale {
             let end_octave = start_octave + 3;
 
             for oct in start_octave..=end_octave {
-                for &interval in &intervals {
+                for &interval in intervals {
                     let midi_note = (oct * 12) + root_offset + interval;
                     if midi_note >= 0 && midi_note <= 127 {
                         notes.push(midi_note as u8);
@@ -75,7 +277,7 @@ impl Scale {
         } else {
             // No octave specified, use full range
             for octave in 0..11 {
-                for &interval in &intervals {
+                for &interval in intervals {
                     let midi_note = (octave * 12) + root_offset + interval;
                     if midi_note <= 127 {
                         notes.push(midi_note as u8);
@@ -106,6 +308,70 @@ impl Scale {
     }
 }
 
+/// Meter of the generated music (4/4 unless set)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    /// Beats per measure, counted in `denominator` notes (3 in 3/4, 6 in 6/8)
+    pub numerator: u8,
+    /// Note value of a beat: 2, 4, 8 or 16
+    pub denominator: u8,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { numerator: 4, denominator: 4 }
+    }
+}
+
+impl std::fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl TimeSignature {
+    /// Length of a measure in quarter notes, the unit of every startTime and duration
+    ///
+    /// 4 in 4/4, 3 in 3/4 and in 6/8.
+    pub fn beats_per_measure(&self) -> f64 {
+        self.numerator as f64 * 4.0 / self.denominator as f64
+    }
+
+    /// Offsets (in quarter notes) of the strong beats within a measure
+    ///
+    /// The downbeat, plus the middle of the measure when it splits into two
+    /// equal halves of at least two beats (beat 3 of 4/4, the second dotted
+    /// quarter of 6/8); 3/4 and 2/4 only stress the downbeat.
+    pub fn strong_beats(&self) -> Vec<f64> {
+        if self.numerator >= 4 && self.numerator % 2 == 0 {
+            vec![0.0, self.beats_per_measure() / 2.0]
+        } else {
+            vec![0.0]
+        }
+    }
+}
+
+/// Strong beats of a measure as counted by musicians ("beats 1 and 3", "beat 1")
+pub fn strong_beat_names(time_signature: TimeSignature) -> String {
+    let beat_length = 4.0 / time_signature.denominator as f64;
+    let names: Vec<String> = time_signature
+        .strong_beats()
+        .iter()
+        .map(|offset| ((offset / beat_length).round() as u32 + 1).to_string())
+        .collect();
+    match names.as_slice() {
+        [one] => format!("beat {}", one),
+        _ => format!("beats {}", names.join(" and ")),
+    }
+}
+
+pub fn validate_time_signature(time_signature: &TimeSignature) -> Result<(), ValidationError> {
+    if !(1..=16).contains(&time_signature.numerator) || ![2, 4, 8, 16].contains(&time_signature.denominator) {
+        return Err(ValidationError::new("invalid_time_signature"));
+    }
+    Ok(())
+}
+
 /// Request for AI melody generation
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct MelodyRequest {
@@ -126,6 +392,44 @@ pub struct MelodyRequest {
     /// Temperature for generation (0.0-2.0, default: 1.0)
     #[validate(range(min = 0.0, max = 2.0))]
     pub temperature: Option<f32>,
+
+    /// Sampling seed, for providers that support reproducible output
+    #[serde(default)]
+    pub seed: Option<u64>,
+
+    /// What to generate (a melody unless set)
+    #[serde(default)]
+    pub mode: GenerationMode,
+
+    /// Beat the generated notes are moved to after validation
+    ///
+    /// Models always write from beat 0 of the requested measures; modes that
+    /// work on part of a project place the result where it belongs.
+    #[serde(default)]
+    pub start_beat: f64,
+
+    /// Chords the notes must follow, one bar per measure: "Cmaj7 | Am7 | Dm7 G7"
+    ///
+    /// Chords sharing a bar split it evenly; the bars repeat when there are
+    /// fewer than measures.
+    #[validate(length(max = 500), custom(function = "validate_progression"))]
+    #[serde(default)]
+    pub chord_progression: Option<String>,
+
+    /// Meter the notes are written in
+    #[validate(custom(function = "validate_time_signature"))]
+    #[serde(default)]
+    pub time_signature: TimeSignature,
+
+    /// The user's own system prompt instructions, merged with the built-in ones
+    #[validate(nested)]
+    #[serde(default)]
+    pub system_prompt: Option<CustomSystemPrompt>,
+
+    /// Most tokens the reply may use (the provider's default if unset)
+    #[validate(range(min = 256, max = 32768))]
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
 }
 
 impl Default for MelodyRequest {
@@ -136,11 +440,98 @@ impl Default for MelodyRequest {
             measures: 4,
             model_provider: AIProvider::OpenAI,
             temperature: Some(1.0),
+            seed: None,
+            mode: GenerationMode::Melody,
+            start_beat: 0.0,
+            chord_progression: None,
+            time_signature: TimeSignature::default(),
+            system_prompt: None,
+            max_tokens: None,
+        }
+    }
+}
+
+/// How a custom system prompt is combined with the built-in one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptMode {
+    /// Extra instructions after the built-in prompt
+    #[default]
+    Append,
+    /// Instead of the built-in role and style guidance; the note format,
+    /// scale and timing requirements still apply
+    Replace,
+}
+
+/// A power user's own system prompt, per request or saved for every generation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+pub struct CustomSystemPrompt {
+    #[validate(length(min = 1, max = 4000))]
+    pub text: String,
+
+    #[serde(default)]
+    pub mode: SystemPromptMode,
+}
+
+fn validate_progression(text: &str) -> Result<(), ValidationError> {
+    parse_progression(text, 1, TimeSignature::default().beats_per_measure())
+        .map(|_| ())
+        .map_err(|_| ValidationError::new("invalid_chord_progression"))
+}
+
+/// Parse a chord progression ("Cmaj7 | Am7 | Dm7 G7") into chords over `measures` bars
+/// of `beats_per_measure` beats
+///
+/// Bars are separated by `|`; chords sharing a bar split it evenly. The
+/// bars repeat until `measures` are filled.
+pub fn parse_progression(text: &str, measures: u32, beats_per_measure: f64) -> Result<Vec<ChordSymbol>, String> {
+    let bars: Vec<Vec<&str>> = text
+        .split('|')
+        .map(|bar| bar.split_whitespace().collect::<Vec<_>>())
+        .collect();
+
+    for (i, bar) in bars.iter().enumerate() {
+        if bar.is_empty() {
+            return Err(format!("Bar {} of the chord progression is empty", i + 1));
+        }
+        if let Some(symbol) = bar.iter().find(|symbol| pitch_class(symbol).is_none()) {
+            return Err(format!("\"{}\" is not a chord symbol", symbol));
         }
     }
+
+    let mut chords = Vec::new();
+    for (measure, bar) in (0..measures).zip(bars.iter().cycle()) {
+        let length = beats_per_measure / bar.len() as f64;
+        for (i, symbol) in bar.iter().enumerate() {
+            chords.push(ChordSymbol {
+                symbol: symbol.to_string(),
+                start_time: measure as f64 * beats_per_measure + i as f64 * length,
+                duration: length,
+            });
+        }
+    }
+    Ok(chords)
+}
+
+/// The same progression starting at bar `bar` (zero-based, wrapping around)
+///
+/// Lets a passage starting mid-piece pick up the harmony where it is.
+pub fn progression_from_bar(text: &str, bar: u32) -> String {
+    let mut bars: Vec<&str> = text.split('|').map(str::trim).collect();
+    let shift = bar as usize % bars.len();
+    bars.rotate_left(shift);
+    bars.join(" | ")
 }
 
 impl MelodyRequest {
+    /// Chords of `chord_progression` across the requested measures (none if not set)
+    pub fn progression(&self) -> Vec<ChordSymbol> {
+        self.chord_progression
+            .as_deref()
+            .and_then(|text| parse_progression(text, self.measures, self.time_signature.beats_per_measure()).ok())
+            .unwrap_or_default()
+    }
+
     /// Sanitize the prompt to prevent injection attacks
     pub fn sanitize_prompt(&mut self) {
         // Remove control characters and null bytes
@@ -159,6 +550,439 @@ impl MelodyRequest {
     }
 }
 
+/// What a generation produces; each mode has its own prompt, reply schema and validation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GenerationMode {
+    /// A melody, optionally with accompanying chords
+    #[default]
+    Melody,
+    /// A chord progression: chord symbols with a voicing for each
+    Chords,
+    /// Chords or counterlines accompanying an existing melody
+    Harmonize { melody: Vec<Note> },
+    /// The next measures of a passage
+    ///
+    /// `context` holds the preceding notes with start times relative to
+    /// where the continuation begins (so negative).
+    Continue { context: Vec<Note> },
+    /// A rhythmic/melodic variation of a phrase that keeps its contour
+    ///
+    /// `amount` runs from 0.0 (ornament the phrase) to 1.0 (recompose it freely).
+    Variation { theme: Vec<Note>, amount: f32 },
+    /// The missing beats of a gap between existing material
+    ///
+    /// `before` and `after` hold the surrounding notes with start times
+    /// relative to the start of the gap, which lasts `length` beats.
+    Infill { before: Vec<Note>, after: Vec<Note>, length: f64 },
+    /// A left-hand part (Alberti bass, stride, block chords) for a right-hand melody
+    LeftHand { melody: Vec<Note> },
+    /// A bass line on chord roots and approach notes
+    ///
+    /// Follows `chords` when given; otherwise the harmony is inferred from
+    /// `context`, the notes of the other tracks.
+    Bass { chords: Vec<ChordSymbol>, context: Vec<Note> },
+    /// A drum pattern of General MIDI percussion notes (channel 10 semantics)
+    ///
+    /// `context` holds the other parts, for the pattern to lock in with.
+    Drums { context: Vec<Note> },
+    /// Melody, harmony and bass at once, each note tagged with its part's role
+    Arrange,
+    /// `source` re-rendered in the style of the prompt, keeping its contour and measures
+    Restyle { source: Vec<Note> },
+    /// A melody developing a short motif (sequence, inversion, augmentation, ...)
+    Motif { motif: Vec<Note> },
+    /// One labeled section of a song generated section by section
+    ///
+    /// `context` holds the end of the sections before, with start times
+    /// relative to this section (so negative); `reprise` the notes of the
+    /// earlier section this one returns to (A' restating A), from beat 0.
+    Section {
+        label: String,
+        structure: String,
+        context: Vec<Note>,
+        reprise: Vec<Note>,
+    },
+    /// A vocal melody setting a lyric, one note per syllable
+    Lyrics { syllables: Vec<Syllable> },
+    /// An edit of the latest melody of a conversation, following the prompt's instruction
+    ///
+    /// `history` holds the earlier turns, oldest first: the original
+    /// generation, then each follow-up with the melody it produced.
+    Refine { history: Vec<RefineTurn> },
+}
+
+/// One exchange of a refinement conversation: what was asked and the melody it produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefineTurn {
+    pub instruction: String,
+    /// Notes from beat 0 of the refined passage
+    pub notes: Vec<Note>,
+}
+
+impl GenerationMode {
+    /// What is generated, for prompts and error messages
+    pub fn description(&self) -> &'static str {
+        match self {
+            GenerationMode::Melody => "melody",
+            GenerationMode::Chords => "chord progression",
+            GenerationMode::Harmonize { .. } => "harmony",
+            GenerationMode::Continue { .. } => "continuation",
+            GenerationMode::Variation { .. } => "variation",
+            GenerationMode::Infill { .. } => "infill",
+            GenerationMode::LeftHand { .. } => "left-hand part",
+            GenerationMode::Bass { .. } => "bass line",
+            GenerationMode::Drums { .. } => "drum pattern",
+            GenerationMode::Arrange => "arrangement",
+            GenerationMode::Restyle { .. } => "restyled passage",
+            GenerationMode::Motif { .. } => "melody",
+            GenerationMode::Section { .. } => "song section",
+            GenerationMode::Lyrics { .. } => "vocal melody",
+            GenerationMode::Refine { .. } => "edited melody",
+        }
+    }
+
+    /// Whether notes are pitches, so a scale applies (drum notes pick sounds instead)
+    pub fn is_pitched(&self) -> bool {
+        !matches!(self, GenerationMode::Drums { .. })
+    }
+
+    /// Whether the reply may shape dynamics and pedal the sustain (piano parts only)
+    pub fn has_expression(&self) -> bool {
+        !matches!(self, GenerationMode::Drums { .. } | GenerationMode::Lyrics { .. })
+    }
+
+    /// Whether notes follow the request's chord progression (chord modes write their own harmony)
+    pub fn follows_progression(&self) -> bool {
+        self.is_pitched() && !matches!(self, GenerationMode::Chords)
+    }
+
+    /// Track the generated notes are placed on
+    pub fn default_track(&self) -> &str {
+        match self {
+            GenerationMode::Melody
+            | GenerationMode::Arrange
+            | GenerationMode::Motif { .. }
+            | GenerationMode::Section { .. }
+            | GenerationMode::Lyrics { .. } => "track_right_hand",
+            GenerationMode::Chords => "track_chords",
+            GenerationMode::Harmonize { .. } => "track_harmony",
+            GenerationMode::LeftHand { .. } => "track_left_hand",
+            GenerationMode::Bass { .. } => "track_bass",
+            GenerationMode::Drums { .. } => DRUM_TRACK_ID,
+            GenerationMode::Continue { context, .. } => context
+                .last()
+                .map_or("track_right_hand", |note| note.track_id.as_str()),
+            GenerationMode::Variation { theme: notes, .. } | GenerationMode::Restyle { source: notes } => notes
+                .first()
+                .map_or("track_right_hand", |note| note.track_id.as_str()),
+            GenerationMode::Infill { before, after, .. } => before
+                .last()
+                .or(after.first())
+                .map_or("track_right_hand", |note| note.track_id.as_str()),
+            GenerationMode::Refine { history } => history
+                .last()
+                .and_then(|turn| turn.notes.first())
+                .map_or("track_right_hand", |note| note.track_id.as_str()),
+        }
+    }
+}
+
+/// Lowest note of a left-hand part (A0)
+pub const LEFT_HAND_LOWEST: u8 = 21;
+
+/// Highest note of a left-hand part (G4)
+pub const LEFT_HAND_HIGHEST: u8 = 67;
+
+/// Lowest note of a bass line (E1)
+pub const BASS_LOWEST: u8 = 28;
+
+/// Highest note of a bass line (G3)
+pub const BASS_HIGHEST: u8 = 55;
+
+/// A part of an arrangement, from the top of the texture down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartRole {
+    Melody,
+    Harmony,
+    Bass,
+}
+
+impl PartRole {
+    /// Every role, highest part first
+    pub const ALL: [PartRole; 3] = [PartRole::Melody, PartRole::Harmony, PartRole::Bass];
+
+    /// Name of the role in replies and messages
+    pub fn name(self) -> &'static str {
+        match self {
+            PartRole::Melody => "melody",
+            PartRole::Harmony => "harmony",
+            PartRole::Bass => "bass",
+        }
+    }
+
+    /// Track the part's notes are placed on
+    pub fn track_id(self) -> &'static str {
+        match self {
+            PartRole::Melody => "track_right_hand",
+            PartRole::Harmony => "track_harmony",
+            PartRole::Bass => "track_bass",
+        }
+    }
+
+    /// Role of the part placed on `track_id`
+    pub fn of_track(track_id: &str) -> Option<PartRole> {
+        PartRole::ALL.into_iter().find(|role| role.track_id() == track_id)
+    }
+}
+
+/// Feedback on a user's melody from `analyze_melody`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MelodyCritique {
+    /// Overall impression in a few sentences
+    pub summary: String,
+    /// Remarks on phrase lengths, breathing points, cadences and contour
+    pub phrasing: Vec<String>,
+    /// Clashes, weak progressions or notes fighting the key
+    pub harmony_issues: Vec<String>,
+    /// Concrete changes, each on specific notes
+    pub suggested_edits: Vec<SuggestedEdit>,
+}
+
+/// A change the critique proposes for some of the user's notes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SuggestedEdit {
+    /// Ids of the notes to change
+    pub note_ids: Vec<String>,
+    /// What to do with them ("raise to E", "shorten to an eighth")
+    pub suggestion: String,
+}
+
+/// How regenerated notes differ from the notes they were made from, by note id
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NoteDiff {
+    /// Original notes found unchanged in the result
+    pub kept: Vec<String>,
+    /// Original notes found at the same pitch and time with a new duration or velocity
+    pub changed: Vec<String>,
+    /// Original notes missing from the result
+    pub removed: Vec<String>,
+    /// Notes of the result that aren't in the original
+    pub added: Vec<String>,
+}
+
+/// Compare regenerated notes with the originals
+///
+/// A result note matches an original of the same pitch starting at the same
+/// time; matched notes take the original's id, so the frontend can apply the
+/// result as edits to the existing notes.
+pub fn diff_notes(original: &[Note], result: &mut [Note]) -> NoteDiff {
+    const EPSILON: f64 = 0.01;
+    let mut matched = vec![false; original.len()];
+    let mut diff = NoteDiff::default();
+
+    for note in result.iter_mut() {
+        let found = (0..original.len()).find(|&i| {
+            let other = &original[i];
+            !matched[i] && other.pitch == note.pitch && (other.start_time - note.start_time).abs() < EPSILON
+        });
+        match found {
+            Some(i) => {
+                matched[i] = true;
+                let other = &original[i];
+                note.id = other.id.clone();
+                if (other.duration - note.duration).abs() < EPSILON && other.velocity == note.velocity {
+                    diff.kept.push(note.id.clone());
+                } else {
+                    diff.changed.push(note.id.clone());
+                }
+            }
+            None => diff.added.push(note.id.clone()),
+        }
+    }
+
+    diff.removed = original
+        .iter()
+        .zip(&matched)
+        .filter(|(_, found)| !**found)
+        .map(|(note, _)| note.id.clone())
+        .collect();
+    diff
+}
+
+/// Share of strong-beat notes that must be chord tones when following a chord progression
+pub const STRONG_BEAT_CHORD_TONES: f64 = 0.75;
+
+/// Times a motif must appear in a melody developing it
+pub const MOTIF_MIN_OCCURRENCES: usize = 2;
+
+/// Intervals (in semitones) between consecutive notes of a line, highest note of chords
+fn intervals(notes: &[Note]) -> Vec<i32> {
+    let mut line: Vec<&Note> = Vec::new();
+    let mut sorted: Vec<&Note> = notes.iter().collect();
+    sorted.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(b.pitch.cmp(&a.pitch)));
+    for note in sorted {
+        match line.last() {
+            Some(last) if (last.start_time - note.start_time).abs() < 0.01 => {}
+            _ => line.push(note),
+        }
+    }
+    line.windows(2).map(|pair| pair[1].pitch as i32 - pair[0].pitch as i32).collect()
+}
+
+/// Times the motif's interval pattern appears in the melody line of `notes`
+///
+/// Matches the motif transposed (sequence), inverted, in retrograde, and at
+/// any rhythm (augmentation, diminution), since only intervals are compared.
+pub fn motif_occurrences(motif: &[Note], notes: &[Note]) -> usize {
+    let pattern = intervals(motif);
+    if pattern.is_empty() {
+        return 0;
+    }
+    let inversion: Vec<i32> = pattern.iter().map(|step| -step).collect();
+    let retrograde: Vec<i32> = pattern.iter().rev().map(|step| -step).collect();
+
+    intervals(notes)
+        .windows(pattern.len())
+        .filter(|window| *window == pattern.as_slice() || *window == inversion.as_slice() || *window == retrograde.as_slice())
+        .count()
+}
+
+/// Share of stressed syllables a lyrics melody must start on a beat
+pub const STRESSED_ON_BEAT: f64 = 0.6;
+
+/// Widest leap (in semitones) from the end of the preceding music to a continuation's first note
+pub const MAX_SEAM_LEAP: i32 = 12;
+
+/// Share of the source's contour a restyled passage must follow
+pub const RESTYLE_CONTOUR: f64 = 0.5;
+
+/// Measures of `beats_per_measure` beats needed to hold all of `notes` (at least one)
+pub fn measures_spanned(notes: &[Note], beats_per_measure: f64) -> u32 {
+    let end = notes
+        .iter()
+        .map(|note| note.start_time + note.duration)
+        .fold(0.0, f64::max);
+    ((end / beats_per_measure).ceil() as u32).max(1)
+}
+
+/// Highest pitch sounding on each beat, holding the last pitch through rests
+fn contour(notes: &[Note], beats: u32) -> Vec<Option<i32>> {
+    let mut last = None;
+    (0..beats)
+        .map(|beat| {
+            let time = beat as f64;
+            let top = notes
+                .iter()
+                .filter(|note| note.start_time <= time && time < note.start_time + note.duration)
+                .map(|note| note.pitch as i32)
+                .max();
+            last = top.or(last);
+            last
+        })
+        .collect()
+}
+
+/// Share of the theme's beat-to-beat moves (up or down) that `notes` follow in the same direction
+///
+/// 1.0 when the theme doesn't move at all.
+pub fn contour_agreement(theme: &[Note], notes: &[Note], beats: u32) -> f64 {
+    let theme_contour = contour(theme, beats);
+    let contour = contour(notes, beats);
+
+    let mut moves = 0;
+    let mut followed = 0;
+    for (theme_step, step) in theme_contour.windows(2).zip(contour.windows(2)) {
+        let (Some(from), Some(to)) = (theme_step[0], theme_step[1]) else { continue };
+        let direction = (to - from).signum();
+        if direction == 0 {
+            continue;
+        }
+
+        moves += 1;
+        if let (Some(from), Some(to)) = (step[0], step[1]) {
+            if (to - from).signum() == direction {
+                followed += 1;
+            }
+        }
+    }
+
+    if moves == 0 {
+        1.0
+    } else {
+        followed as f64 / moves as f64
+    }
+}
+
+/// A labeled part of a song ("Intro", "A", "B", "A'") and its length
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SongSection {
+    #[validate(length(min = 1, max = 32))]
+    pub label: String,
+
+    #[validate(range(min = 1, max = 16))]
+    pub measures: u32,
+}
+
+impl SongSection {
+    /// Label of the section this one returns to: "A" for "A'" (None for new material)
+    pub fn reprise_of(&self) -> Option<&str> {
+        let base = self.label.trim_end_matches(['\'', '’']);
+        (base.len() < self.label.len() && !base.is_empty()).then_some(base)
+    }
+}
+
+/// Optional settings of a `generate_melody` call
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GenerationOptions {
+    /// Record the generation in this project's history
+    pub project_path: Option<String>,
+    /// Password of `project_path`, if the project is encrypted
+    pub project_password: Option<String>,
+
+    /// Id for progress events and `cancel_generation` (generated if not given)
+    pub request_id: Option<String>,
+
+    /// Sampling seed, for providers that support reproducible output
+    pub seed: Option<u64>,
+
+    /// Always ask the provider, even if an identical request is cached
+    #[serde(default)]
+    pub bypass_cache: bool,
+
+    /// Chord progression the notes follow ("Cmaj7 | Am7 | Dm7 | G7")
+    pub chord_progression: Option<String>,
+    /// Meter of the notes (4/4 if not given)
+    pub time_signature: Option<TimeSignature>,
+    /// System prompt instructions for this request, instead of the saved ones
+    pub system_prompt: Option<CustomSystemPrompt>,
+    /// Most tokens the reply may use (the provider's saved default if not given)
+    pub max_tokens: Option<u32>,
+    /// Fail instead of using the offline generator when the provider can't be used
+    #[serde(default)]
+    pub no_offline_fallback: bool,
+    /// What to do when the notes don't end on a cadence (nothing if not given)
+    pub ending: Option<EndingFix>,
+}
+
+/// How to fix a generated piece that doesn't end on an authentic or plagal cadence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndingFix {
+    /// Append a measure with a V7-I cadence
+    Append,
+    /// Ask the provider once more for a proper ending, appending a cadence if it still lacks one
+    Retry,
+}
+
+impl GenerationOptions {
+    /// Length of a measure in beats, in the requested time signature
+    pub fn beats_per_measure(&self) -> f64 {
+        self.time_signature.unwrap_or_default().beats_per_measure()
+    }
+}
+
 /// A single musical note
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct Note {
@@ -187,6 +1011,126 @@ pub struct Note {
     pub track_id: String,
 }
 
+/// A passage re-rendered in a new style, with how it differs from the original
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StyleTransferResponse {
+    #[serde(flatten)]
+    pub response: MelodyResponse,
+    pub diff: NoteDiff,
+}
+
+/// A chord symbol on the timeline, e.g. "Am7" from beat 4 for 2 beats
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChordSymbol {
+    pub symbol: String,
+
+    /// Start time in beats
+    #[serde(rename = "startTime")]
+    pub start_time: f64,
+
+    /// Duration in beats
+    pub duration: f64,
+}
+
+impl ChordSymbol {
+    /// Pitch classes (0-11) of the chord's tones, from its root and quality
+    ///
+    /// Covers triads, sixths, sevenths, suspensions and added ninths; the
+    /// bass note of a slash chord counts as a chord tone. Empty when the
+    /// symbol doesn't start with a note name.
+    pub fn pitch_classes(&self) -> Vec<i32> {
+        let Some((root, intervals, slash)) = self.structure() else {
+            return Vec::new();
+        };
+
+        let mut classes: Vec<i32> = intervals.iter().map(|interval| (root + interval) % 12).collect();
+        classes.extend(slash);
+        classes.sort_unstable();
+        classes.dedup();
+        classes
+    }
+
+    /// Root pitch class, the chord's intervals above the root in stacking
+    /// order (third, fifth, seventh or sixth, then the ninth), and the bass
+    /// pitch class of a slash chord
+    ///
+    /// None when the symbol doesn't start with a note name.
+    pub fn structure(&self) -> Option<(i32, Vec<i32>, Option<i32>)> {
+        let (chord, slash) = match self.symbol.split_once('/') {
+            Some((chord, slash)) => (chord.trim(), pitch_class(slash.trim())),
+            None => (self.symbol.trim(), None),
+        };
+        let root = pitch_class(chord)?;
+
+        let root_length = if chord[1..].starts_with(['#', 'b']) { 2 } else { 1 };
+        let quality = &chord[root_length..];
+        let minor = quality.starts_with('m') && !quality.starts_with("maj");
+
+        let mut intervals: Vec<i32> = if quality.contains("dim") || quality.contains('°') {
+            vec![0, 3, 6]
+        } else if quality.contains("m7b5") || quality.contains('ø') {
+            vec![0, 3, 6, 10]
+        } else if quality.contains("aug") || quality.contains('+') {
+            vec![0, 4, 8]
+        } else if quality.contains("sus2") {
+            vec![0, 2, 7]
+        } else if quality.contains("sus") {
+            vec![0, 5, 7]
+        } else if minor {
+            vec![0, 3, 7]
+        } else {
+            vec![0, 4, 7]
+        };
+
+        if quality.contains("maj7") || quality.contains("maj9") || quality.contains("M7") {
+            intervals.push(11);
+        } else if quality.contains("dim7") || quality.contains("°7") {
+            intervals.push(9);
+        } else if !quality.contains("add") && ["7", "9", "11", "13"].iter().any(|extension| quality.contains(extension)) {
+            intervals.push(10);
+        }
+        if quality.contains('6') {
+            intervals.push(9);
+        }
+        if quality.contains('9') || quality.contains("add2") {
+            intervals.push(14);
+        }
+
+        Some((root, intervals, slash))
+    }
+
+    /// Pitch classes (0-11) a bass plays for this chord: the root, and the
+    /// bass note of a slash chord ("C/E")
+    ///
+    /// Empty when the symbol doesn't start with a note name.
+    pub fn bass_pitch_classes(&self) -> Vec<i32> {
+        let (chord, slash) = match self.symbol.split_once('/') {
+            Some((chord, slash)) => (chord, Some(slash)),
+            None => (self.symbol.as_str(), None),
+        };
+        let mut classes: Vec<i32> = std::iter::once(chord)
+            .chain(slash)
+            .filter_map(|name| pitch_class(name.trim()))
+            .collect();
+        classes.dedup();
+        classes
+    }
+}
+
+/// Pitch class (0-11) of the note name a chord symbol starts with ("F#m7" is 6)
+pub fn pitch_class(symbol: &str) -> Option<i32> {
+    let mut chars = symbol.chars();
+    let letter = chars.next()?.to_ascii_uppercase();
+    if !('A'..='G').contains(&letter) {
+        return None;
+    }
+    let name = match chars.next() {
+        Some(accidental @ ('#' | 'b')) => format!("{}{}", letter, accidental),
+        _ => letter.to_string(),
+    };
+    Some(Scale::note_to_offset(&name))
+}
+
 /// Metadata about the generation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenerationMetadata {
@@ -204,37 +1148,149 @@ pub struct GenerationMetadata {
 
     /// Scale used (if any)
     pub scale: Option<Scale>,
+
+    /// Sampling seed the provider used, to reproduce the result (absent for providers without seeds)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 }
 
-/// Response from AI melody generation
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MelodyResponse {
-    /// Generated notes
-    pub notes: Vec<Note>,
+/// Stage of a melody generation, reported as it happens
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GenerationPhase {
+    /// Request sent, waiting for the provider
+    Sent,
+    /// The provider's response headers arrived; its body is being read
+    Receiving,
+    /// Checking the returned notes against the request
+    Validating,
+    /// Validation failed; asking again with the error as feedback
+    Retrying,
+    /// The provider answered 429; waiting for its `Retry-After` before trying again
+    RateLimited,
+    Done,
+    Failed,
+    Cancelled,
+    /// The provider couldn't be used; the notes came from the offline generator instead
+    Offline,
+}
 
-    /// Metadata about the generation
-    pub metadata: GenerationMetadata,
+/// Payload of the `generation-progress` event
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationProgressEvent {
+    pub request_id: String,
+    pub phase: GenerationPhase,
+    /// 1 for the first attempt, then counting retries; 0 once finished
+    pub attempt: u32,
 }
 
-impl MelodyResponse {
-    /// Validate all notes in the response
-    pub fn validate_notes(&self) -> Result<(), validator::ValidationErrors> {
-        for note in &self.notes {
-            note.validate()?;
+/// Direction of a hairpin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HairpinShape {
+    Crescendo,
+    Decrescendo,
+}
+
+/// A crescendo or decrescendo: notes starting within it ramp from `from_velocity` to `to_velocity`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hairpin {
+    pub shape: HairpinShape,
+    /// Start in beats
+    pub start_time: f64,
+    /// Length in beats
+    pub duration: f64,
+    pub from_velocity: u8,
+    pub to_velocity: u8,
+}
+
+impl Hairpin {
+    /// Velocity of the ramp at `time`, if the hairpin covers it
+    pub fn velocity_at(&self, time: f64) -> Option<u8> {
+        if time < self.start_time - 0.01 || time >= self.start_time + self.duration {
+            return None;
         }
-        Ok(())
+        let progress = ((time - self.start_time) / self.duration).clamp(0.0, 1.0);
+        let (from, to) = (self.from_velocity as f64, self.to_velocity as f64);
+        Some((from + (to - from) * progress).round() as u8)
     }
+}
 
-    /// Check if all notes are within the specified scale
-    #[allow(dead_code)]
+/// A change of the sustain pedal (MIDI CC64), as in a project's pedal lane
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PedalChange {
+    /// Time in beats
+    pub time: f64,
+    pub down: bool,
+}
+
+/// Pedal lane holding the pedal down over each of `spans` (start, length in beats)
+///
+/// Overlapping spans merge into one press; spans that only touch lift and
+/// press again on the same beat (a pedal change), clearing the harmony.
+pub fn pedal_lane(spans: &[(f64, f64)]) -> Vec<PedalChange> {
+    let mut spans: Vec<(f64, f64)> = spans
+        .iter()
+        .filter(|(_, length)| *length > 0.0)
+        .map(|&(start, length)| (start, start + length))
+        .collect();
+    spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut merged: Vec<(f64, f64)> = Vec::new();
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start < last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .flat_map(|(start, end)| [PedalChange { time: start, down: true }, PedalChange { time: end, down: false }])
+        .collect()
+}
+
+/// Response from AI melody generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MelodyResponse {
+    /// Generated notes
+    pub notes: Vec<Note>,
+
+    /// Chord symbols of a chord progression (empty for other modes)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chords: Vec<ChordSymbol>,
+
+    /// Crescendos and decrescendos, already applied to the notes' velocities
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dynamics: Vec<Hairpin>,
+
+    /// Sustain pedal lane (CC64)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pedal: Vec<PedalChange>,
+
+    /// Metadata about the generation
+    pub metadata: GenerationMetadata,
+}
+
+impl MelodyResponse {
+    /// Validate all notes in the response
+    pub fn validate_notes(&self) -> Result<(), validator::ValidationErrors> {
+        for note in &self.notes {
+            note.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Check if all notes are within the specified scale
+    #[allow(dead_code)]
     pub fn notes_in_scale(&self, scale: &Scale) -> bool {
         let allowed_notes = scale.get_midi_notes();
         self.notes.iter().all(|note| allowed_notes.contains(&note.pitch))
     }
 
-    /// Validate that all notes fit within the specified number of measures
-    pub fn validate_measure_bounds(&self, measures: u32) -> Result<(), String> {
-        let max_beats = (measures * 4) as f64;
+    /// Validate that all notes fit within the specified number of measures of `beats_per_measure` beats
+    pub fn validate_measure_bounds(&self, measures: u32, beats_per_measure: f64) -> Result<(), String> {
+        let max_beats = measures as f64 * beats_per_measure;
 
         for (i, note) in self.notes.iter().enumerate() {
             // Check if note starts within bounds
@@ -316,13 +1372,18 @@ impl MelodyResponse {
     }
 
     /// Comprehensive validation including measures and scale
-    pub fn validate_comprehensive(&self, measures: u32, scale: Option<&Scale>) -> Result<(), String> {
+    pub fn validate_comprehensive(
+        &self,
+        measures: u32,
+        time_signature: TimeSignature,
+        scale: Option<&Scale>,
+    ) -> Result<(), String> {
         // First validate basic note structure
         self.validate_notes()
             .map_err(|e| format!("Note validation failed: {}", e))?;
 
         // Validate measure bounds
-        self.validate_measure_bounds(measures)?;
+        self.validate_measure_bounds(measures, time_signature.beats_per_measure())?;
 
         // Validate scale constraints if specified
         if let Some(scale) = scale {
@@ -336,6 +1397,531 @@ impl MelodyResponse {
 
         Ok(())
     }
+
+    /// Move notes and chords by `beats`
+    pub fn shift(&mut self, beats: f64) {
+        for note in &mut self.notes {
+            note.start_time += beats;
+        }
+        for chord in &mut self.chords {
+            chord.start_time += beats;
+        }
+        for hairpin in &mut self.dynamics {
+            hairpin.start_time += beats;
+        }
+        for change in &mut self.pedal {
+            change.time += beats;
+        }
+    }
+
+    /// Turn the hairpins into velocity ramps on the notes they cover
+    ///
+    /// Setting velocities from the ramp (rather than scaling them) makes
+    /// this safe to repeat, e.g. on a cached response.
+    pub fn apply_dynamics(&mut self) {
+        for note in &mut self.notes {
+            if let Some(velocity) = self.dynamics.iter().find_map(|hairpin| hairpin.velocity_at(note.start_time)) {
+                note.velocity = velocity;
+            }
+        }
+    }
+
+    /// Check that hairpins and pedal changes fit the measures and hairpins go the way they say
+    fn validate_expression(&self, measures: u32, beats_per_measure: f64) -> Result<(), String> {
+        let max_beats = measures as f64 * beats_per_measure;
+
+        let mut hairpins: Vec<&Hairpin> = self.dynamics.iter().collect();
+        hairpins.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        for (i, hairpin) in hairpins.iter().enumerate() {
+            let end = hairpin.start_time + hairpin.duration;
+            if hairpin.start_time < 0.0 || hairpin.duration <= 0.0 || end > max_beats + 1e-6 {
+                return Err(format!(
+                    "The {:?} from beat {:.2} to {:.2} doesn't fit within {} measures ({} beats)",
+                    hairpin.shape, hairpin.start_time, end, measures, max_beats
+                ));
+            }
+            if !(1..=127).contains(&hairpin.from_velocity) || !(1..=127).contains(&hairpin.to_velocity) {
+                return Err(format!("The {:?} at beat {:.2} needs velocities from 1 to 127", hairpin.shape, hairpin.start_time));
+            }
+            let rising = hairpin.to_velocity > hairpin.from_velocity;
+            if rising != (hairpin.shape == HairpinShape::Crescendo) || hairpin.to_velocity == hairpin.from_velocity {
+                return Err(format!(
+                    "The {:?} at beat {:.2} goes from velocity {} to {}; a crescendo must get louder and a decrescendo softer",
+                    hairpin.shape, hairpin.start_time, hairpin.from_velocity, hairpin.to_velocity
+                ));
+            }
+            if let Some(next) = hairpins.get(i + 1) {
+                if next.start_time < end - 0.01 {
+                    return Err(format!("Hairpins at beats {:.2} and {:.2} overlap", hairpin.start_time, next.start_time));
+                }
+            }
+        }
+
+        if let Some(change) = self.pedal.iter().find(|change| change.time < 0.0 || change.time > max_beats + 1e-6) {
+            return Err(format!(
+                "The sustain pedal changes at beat {:.2}, outside {} measures ({} beats)",
+                change.time, measures, max_beats
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validate a response against everything its request asked for
+    pub fn validate_request(&self, request: &MelodyRequest) -> Result<(), String> {
+        let scale = request.scale.as_ref().filter(|_| request.mode.is_pitched());
+        let time_signature = request.time_signature;
+        self.validate_comprehensive(request.measures, time_signature, scale)?;
+
+        if request.mode.follows_progression() {
+            self.validate_strong_beats(&request.progression(), time_signature)?;
+        }
+
+        let beats = time_signature.beats_per_measure();
+        self.validate_expression(request.measures, beats)?;
+
+        match &request.mode {
+            GenerationMode::Melody => Ok(()),
+            GenerationMode::Chords => self.validate_chords(request.measures, beats),
+            GenerationMode::Harmonize { melody } => self.validate_no_clashes(melody),
+            GenerationMode::Continue { context } => self.validate_continuity(context, beats),
+            GenerationMode::Section { .. } => Ok(()),
+            GenerationMode::Lyrics { syllables } => self.validate_syllables(syllables),
+            GenerationMode::Refine { history } => match history.last() {
+                Some(turn) => self.validate_edit(&turn.notes),
+                None => Ok(()),
+            },
+            GenerationMode::Variation { theme, amount } => {
+                // The more freedom `amount` gives, the fewer of the theme's moves must be followed
+                let required = 0.6 - 0.4 * amount.clamp(0.0, 1.0) as f64;
+                self.validate_contour(theme, required, request.measures, beats)
+            }
+            GenerationMode::Motif { motif } => self.validate_motif(motif),
+            GenerationMode::Restyle { source } => {
+                self.validate_contour(source, RESTYLE_CONTOUR, request.measures, beats)?;
+                self.validate_measure_structure(source, request.measures, beats)
+            }
+            GenerationMode::Infill { length, .. } => self.validate_gap(*length),
+            GenerationMode::LeftHand { melody } => {
+                self.validate_register(LEFT_HAND_LOWEST, LEFT_HAND_HIGHEST, "left hand")?;
+                self.validate_no_clashes(melody)
+            }
+            GenerationMode::Bass { chords, context } => {
+                self.validate_register(BASS_LOWEST, BASS_HIGHEST, "bass")?;
+                self.validate_bass_roots(chords)?;
+                self.validate_no_clashes(context)
+            }
+            GenerationMode::Drums { .. } => self.validate_drum_map(),
+            GenerationMode::Arrange => self.validate_arrangement(),
+        }
+    }
+
+    /// Check that a continuation picks up where `context` leaves off
+    ///
+    /// Its first notes must come within the first measure, and one of them no
+    /// further than `MAX_SEAM_LEAP` from one of the last notes of the context
+    /// (so a new bass note doesn't count as a leap from the melody).
+    fn validate_continuity(&self, context: &[Note], beats_per_measure: f64) -> Result<(), String> {
+        let Some(end) = context.iter().map(|note| note.start_time).reduce(f64::max) else { return Ok(()) };
+        let Some(start) = self.notes.iter().map(|note| note.start_time).reduce(f64::min) else { return Ok(()) };
+
+        if start >= beats_per_measure {
+            return Err(format!(
+                "The continuation starts at beat {:.2}, leaving its first measure silent; \
+                pick up right where the preceding music ends",
+                start
+            ));
+        }
+        let last: Vec<&Note> = context.iter().filter(|note| (note.start_time - end).abs() < 0.01).collect();
+        let (leap, from, to) = self
+            .notes
+            .iter()
+            .filter(|note| (note.start_time - start).abs() < 0.01)
+            .flat_map(|to| last.iter().map(move |from| ((to.pitch as i32 - from.pitch as i32).abs(), from.pitch, to.pitch)))
+            .min()
+            .unwrap_or_default();
+        if leap > MAX_SEAM_LEAP {
+            return Err(format!(
+                "The continuation starts on MIDI {}, {} semitones from the last preceding note (MIDI {}); \
+                connect within an octave",
+                to, leap, from
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that the notes sing the lyric: a single line with one note per syllable,
+    /// stressed syllables mostly on the beat
+    fn validate_syllables(&self, syllables: &[Syllable]) -> Result<(), String> {
+        if self.notes.len() != syllables.len() {
+            return Err(format!(
+                "The lyric has {} syllables but the melody has {} notes; write exactly one note per syllable",
+                syllables.len(),
+                self.notes.len()
+            ));
+        }
+
+        let mut sung: Vec<&Note> = self.notes.iter().collect();
+        sung.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        for pair in sung.windows(2) {
+            if pair[1].start_time < pair[0].start_time + pair[0].duration - 0.01 {
+                return Err(format!(
+                    "The notes at beats {:.2} and {:.2} overlap; a voice sings one note at a time",
+                    pair[0].start_time, pair[1].start_time
+                ));
+            }
+        }
+
+        let stressed: Vec<&Note> = sung
+            .iter()
+            .zip(syllables)
+            .filter(|(_, syllable)| syllable.stressed)
+            .map(|(note, _)| *note)
+            .collect();
+        let on_beat = stressed
+            .iter()
+            .filter(|note| (note.start_time - note.start_time.round()).abs() < 0.01)
+            .count();
+        if on_beat < (stressed.len() as f64 * STRESSED_ON_BEAT).ceil() as usize {
+            return Err(format!(
+                "Only {} of the {} stressed syllables start on a beat; put stressed syllables on beats \
+                and unstressed ones between them",
+                on_beat,
+                stressed.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that infilled notes stay inside the gap of `length` beats
+    ///
+    /// The requested measures are rounded up to whole measures; the material
+    /// after the gap may start mid-measure.
+    fn validate_gap(&self, length: f64) -> Result<(), String> {
+        for (i, note) in self.notes.iter().enumerate() {
+            let note_end = note.start_time + note.duration;
+            if note_end > length + 1e-6 {
+                return Err(format!(
+                    "Note {} (starting at beat {:.2} with duration {:.2}) ends at beat {:.2}, \
+                    after the gap ends at beat {:.2}",
+                    i + 1,
+                    note.start_time,
+                    note.duration,
+                    note_end,
+                    length
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the notes follow at least `required` (0.0-1.0) of the theme's contour
+    fn validate_contour(&self, theme: &[Note], required: f64, measures: u32, beats_per_measure: f64) -> Result<(), String> {
+        let beats = (measures as f64 * beats_per_measure).ceil() as u32;
+        let agreement = contour_agreement(theme, &self.notes, beats);
+
+        if agreement < required {
+            return Err(format!(
+                "The notes follow only {:.0}% of the original phrase's rises and falls \
+                (at least {:.0}% needed); keep the overall contour",
+                agreement * 100.0,
+                required * 100.0
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that notes on strong beats (see `TimeSignature::strong_beats`) are mostly tones of the chord sounding there
+    ///
+    /// Up to a quarter may be non-chord tones (appoggiaturas, suspensions).
+    fn validate_strong_beats(&self, chords: &[ChordSymbol], time_signature: TimeSignature) -> Result<(), String> {
+        let measure = time_signature.beats_per_measure();
+        let strong_beats = time_signature.strong_beats();
+        let mut on_strong_beats = 0;
+        let mut outside = Vec::new();
+
+        for note in &self.notes {
+            let beat = note.start_time.rem_euclid(measure);
+            if !strong_beats.iter().any(|strong| (beat - strong).abs() < 0.01 || (measure - beat) < 0.01) {
+                continue;
+            }
+            let Some(chord) = chords
+                .iter()
+                .find(|chord| chord.start_time <= note.start_time + 0.01 && note.start_time + 0.01 < chord.start_time + chord.duration)
+            else {
+                continue;
+            };
+            let tones = chord.pitch_classes();
+            if tones.is_empty() {
+                continue;
+            }
+
+            on_strong_beats += 1;
+            if !tones.contains(&(note.pitch as i32 % 12)) {
+                outside.push(format!("MIDI {} at beat {:.2} ({})", note.pitch, note.start_time, chord.symbol));
+            }
+        }
+
+        if outside.len() as f64 > on_strong_beats as f64 * (1.0 - STRONG_BEAT_CHORD_TONES) {
+            return Err(format!(
+                "Too many notes on strong beats ({}) are not tones of the given chords: {}; \
+                start notes on strong beats on chord tones",
+                strong_beat_names(time_signature),
+                outside.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that the motif appears often enough, as is or transformed (see `motif_occurrences`)
+    fn validate_motif(&self, motif: &[Note]) -> Result<(), String> {
+        let found = motif_occurrences(motif, &self.notes);
+        if found < MOTIF_MIN_OCCURRENCES {
+            return Err(format!(
+                "The motif appears {} time(s) in the melody (at least {} needed); state it and \
+                develop it by sequence (transposed), inversion or augmentation",
+                found, MOTIF_MIN_OCCURRENCES
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that an edit changed something about the melody it was asked to edit
+    fn validate_edit(&self, previous: &[Note]) -> Result<(), String> {
+        let key = |note: &Note| (note.pitch, note.start_time.to_bits(), note.duration.to_bits(), note.velocity);
+        let mut before: Vec<_> = previous.iter().map(key).collect();
+        let mut after: Vec<_> = self.notes.iter().map(key).collect();
+        before.sort_unstable();
+        after.sort_unstable();
+        if before == after {
+            return Err("The edited melody is identical to the previous one; apply the requested change".to_string());
+        }
+        Ok(())
+    }
+
+    /// Check that every measure with notes in `source` still has notes, and no empty one gained any
+    fn validate_measure_structure(&self, source: &[Note], measures: u32, beats_per_measure: f64) -> Result<(), String> {
+        let occupied = |notes: &[Note], measure: u32| {
+            let (start, end) = (measure as f64 * beats_per_measure, (measure + 1) as f64 * beats_per_measure);
+            notes.iter().any(|note| note.start_time >= start && note.start_time < end)
+        };
+
+        for measure in 0..measures {
+            match (occupied(source, measure), occupied(&self.notes, measure)) {
+                (true, false) => {
+                    return Err(format!(
+                        "Measure {} is empty but has notes in the original; keep the measure structure",
+                        measure + 1
+                    ));
+                }
+                (false, true) => {
+                    return Err(format!(
+                        "Measure {} has notes but is a rest in the original; keep the measure structure",
+                        measure + 1
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the bass plays each chord's root (or slash bass note) where the chord starts
+    ///
+    /// Between chord changes, passing and approach notes are free.
+    fn validate_bass_roots(&self, chords: &[ChordSymbol]) -> Result<(), String> {
+        for chord in chords {
+            let allowed = chord.bass_pitch_classes();
+            if allowed.is_empty() {
+                continue;
+            }
+
+            let on_change = self
+                .notes
+                .iter()
+                .filter(|note| (note.start_time - chord.start_time).abs() < 0.01)
+                .map(|note| note.pitch as i32)
+                .min();
+            match on_change {
+                Some(pitch) if allowed.contains(&(pitch % 12)) => {}
+                Some(pitch) => {
+                    return Err(format!(
+                        "The bass plays MIDI {} on the {} chord at beat {:.2}; play the chord's root there",
+                        pitch, chord.symbol, chord.start_time
+                    ));
+                }
+                None => {
+                    return Err(format!(
+                        "No bass note starts with the {} chord at beat {:.2}; play the chord's root there",
+                        chord.symbol, chord.start_time
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check an arrangement: every part present, bass in its register, and parts
+    /// kept apart (a higher part never on or below a lower one while both sound)
+    fn validate_arrangement(&self) -> Result<(), String> {
+        for role in PartRole::ALL {
+            if !self.notes.iter().any(|note| PartRole::of_track(&note.track_id) == Some(role)) {
+                return Err(format!(
+                    "The arrangement has no {} notes; tag notes with the role \"{}\"",
+                    role.name(),
+                    role.name()
+                ));
+            }
+        }
+
+        for (i, note) in self.notes.iter().enumerate() {
+            let is_bass = PartRole::of_track(&note.track_id) == Some(PartRole::Bass);
+            if is_bass && !(BASS_LOWEST..=BASS_HIGHEST).contains(&note.pitch) {
+                return Err(format!(
+                    "Note {} (MIDI {}) is outside the bass register (MIDI {}-{})",
+                    i + 1,
+                    note.pitch,
+                    BASS_LOWEST,
+                    BASS_HIGHEST
+                ));
+            }
+        }
+
+        for note in &self.notes {
+            let Some(role) = PartRole::of_track(&note.track_id) else { continue };
+            for other in &self.notes {
+                let Some(other_role) = PartRole::of_track(&other.track_id) else { continue };
+                let overlaps = note.start_time < other.start_time + other.duration
+                    && other.start_time < note.start_time + note.duration;
+                if role < other_role && overlaps && note.pitch <= other.pitch {
+                    return Err(format!(
+                        "The {} note MIDI {} at beat {:.2} collides with the {} note MIDI {} at beat {:.2}; \
+                        keep the {} above the {}",
+                        role.name(),
+                        note.pitch,
+                        note.start_time,
+                        other_role.name(),
+                        other.pitch,
+                        other.start_time,
+                        role.name(),
+                        other_role.name()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every note triggers a sound of the General MIDI drum map
+    fn validate_drum_map(&self) -> Result<(), String> {
+        for (i, note) in self.notes.iter().enumerate() {
+            if drum_sound(note.pitch).is_none() {
+                let allowed = GM_DRUM_MAP
+                    .iter()
+                    .map(|sound| sound.pitch.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(format!(
+                    "Note {} (MIDI {}) is not a drum sound; use only these General MIDI drum notes: {}",
+                    i + 1,
+                    note.pitch,
+                    allowed
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every note lies between `lowest` and `highest` (MIDI, inclusive)
+    fn validate_register(&self, lowest: u8, highest: u8, part: &str) -> Result<(), String> {
+        for (i, note) in self.notes.iter().enumerate() {
+            if note.pitch < lowest || note.pitch > highest {
+                return Err(format!(
+                    "Note {} (MIDI {}) is outside the {} register (MIDI {}-{})",
+                    i + 1,
+                    note.pitch,
+                    part,
+                    lowest,
+                    highest
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that added notes don't clash with the notes they accompany
+    ///
+    /// Notes sounding together clash when on the same pitch or a minor second
+    /// apart (also a major seventh or minor ninth: the same pitch classes).
+    fn validate_no_clashes(&self, existing: &[Note]) -> Result<(), String> {
+        for (i, note) in self.notes.iter().enumerate() {
+            for other in existing {
+                let overlaps = note.start_time < other.start_time + other.duration
+                    && other.start_time < note.start_time + note.duration;
+                if !overlaps {
+                    continue;
+                }
+
+                let interval = (note.pitch as i32 - other.pitch as i32).abs();
+                if interval == 0 || interval % 12 == 1 || interval % 12 == 11 {
+                    return Err(format!(
+                        "Note {} (MIDI {} at beat {:.2}) clashes with the existing note MIDI {} at beat {:.2}; \
+                        avoid unisons and minor seconds against sounding notes",
+                        i + 1,
+                        note.pitch,
+                        note.start_time,
+                        other.pitch,
+                        other.start_time
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that chord symbols fit the measures and each comes with a voicing
+    fn validate_chords(&self, measures: u32, beats_per_measure: f64) -> Result<(), String> {
+        if self.chords.is_empty() {
+            return Err("No chord symbols were generated".to_string());
+        }
+
+        let max_beats = measures as f64 * beats_per_measure;
+        for (i, chord) in self.chords.iter().enumerate() {
+            if chord.symbol.trim().is_empty() {
+                return Err(format!("Chord {} has no symbol", i + 1));
+            }
+
+            let chord_end = chord.start_time + chord.duration;
+            if chord.start_time < 0.0 || chord.duration <= 0.0 || chord_end > max_beats {
+                return Err(format!(
+                    "Chord {} ({}) from beat {:.2} to {:.2} doesn't fit within {} measures ({} beats)",
+                    i + 1,
+                    chord.symbol,
+                    chord.start_time,
+                    chord_end,
+                    measures,
+                    max_beats
+                ));
+            }
+
+            // A voicing is at least two notes starting within the chord
+            let voicing = self
+                .notes
+                .iter()
+                .filter(|note| note.start_time >= chord.start_time - 0.01 && note.start_time < chord_end)
+                .count();
+            if voicing < 2 {
+                return Err(format!(
+                    "Chord {} ({}) at beat {:.2} has no voicing: add at least 2 notes starting within it",
+                    i + 1,
+                    chord.symbol,
+                    chord.start_time
+                ));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -346,7 +1932,7 @@ mod tests {
     fn test_scale_midi_notes() {
         let c_major = Scale {
             root: "C".to_string(),
-            mode: "major".to_string(),
+            mode: ScaleMode::Major,
             octave: None,
         };
         let notes = c_major.get_midi_notes();
@@ -364,6 +1950,22 @@ mod tests {
         assert!(!notes.contains(&1));
     }
 
+    #[test]
+    fn test_scale_modes() {
+        let mode: ScaleMode = serde_json::from_str("\"Harmonic Minor\"").unwrap();
+        assert_eq!(mode, ScaleMode::HarmonicMinor);
+        assert_eq!(serde_json::to_string(&mode).unwrap(), "\"harmonic_minor\"");
+        assert_eq!(mode.to_string(), "harmonic minor");
+        assert_eq!("aeolian".parse::<ScaleMode>(), Ok(ScaleMode::Minor));
+        assert!(serde_json::from_str::<ScaleMode>("\"bebop\"").is_err());
+
+        let d_dorian = Scale { root: "D".to_string(), mode: ScaleMode::Dorian, octave: Some(4) };
+        assert_eq!(&d_dorian.get_midi_notes()[..7], &[50, 52, 53, 55, 57, 59, 60]);
+        let a_blues = Scale { root: "A".to_string(), mode: ScaleMode::Blues, octave: Some(4) };
+        assert_eq!(&a_blues.get_midi_notes()[..6], &[57, 60, 62, 63, 64, 67]);
+        assert!(ScaleMode::Dorian.is_minor() && !ScaleMode::Lydian.is_minor());
+    }
+
     #[test]
     fn test_provider_conversion() {
         assert_eq!(AIProvider::from_str("openai"), Some(AIProvider::OpenAI));
@@ -371,4 +1973,535 @@ mod tests {
         assert_eq!(AIProvider::from_str("GEMINI"), Some(AIProvider::Gemini));
         assert_eq!(AIProvider::from_str("invalid"), None);
     }
+
+    #[test]
+    fn test_chords_need_voicings() {
+        let note = |pitch: u8, start_time: f64| Note {
+            id: pitch.to_string(),
+            pitch,
+            start_time,
+            duration: 2.0,
+            velocity: 80,
+            track_id: "track_chords".to_string(),
+        };
+        let chord = |symbol: &str, start_time: f64| ChordSymbol {
+            symbol: symbol.to_string(),
+            start_time,
+            duration: 2.0,
+        };
+        let request = MelodyRequest {
+            measures: 1,
+            mode: GenerationMode::Chords,
+            ..MelodyRequest::default()
+        };
+        let mut response = MelodyResponse {
+            notes: vec![note(48, 0.0), note(52, 0.0), note(55, 0.0)],
+            chords: vec![chord("C", 0.0), chord("G", 2.0)],
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+
+        assert!(response.validate_request(&request).unwrap_err().contains("(G)"));
+
+        response.notes.extend([note(43, 2.0), note(47, 2.0), note(50, 2.0)]);
+        assert!(response.validate_request(&request).is_ok());
+
+        response.chords.push(chord("C", 4.0));
+        assert!(response.validate_request(&request).is_err());
+    }
+
+    #[test]
+    fn test_harmony_must_not_clash() {
+        let note = |pitch: u8, start_time: f64| Note {
+            id: pitch.to_string(),
+            pitch,
+            start_time,
+            duration: 1.0,
+            velocity: 80,
+            track_id: "track_harmony".to_string(),
+        };
+        let melody = vec![note(72, 0.0), note(74, 1.0)];
+        assert_eq!(measures_spanned(&melody, 4.0), 1);
+
+        let request = MelodyRequest {
+            measures: 1,
+            mode: GenerationMode::Harmonize { melody },
+            ..MelodyRequest::default()
+        };
+        let mut response = MelodyResponse {
+            notes: vec![note(60, 0.0), note(67, 1.0)],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        // B3 against the C5 melody note is a minor ninth
+        response.notes.push(note(59, 0.5));
+        assert!(response.validate_request(&request).unwrap_err().contains("MIDI 59"));
+    }
+
+    #[test]
+    fn test_contour_agreement() {
+        let phrase = |pitches: &[u8]| -> Vec<Note> {
+            pitches
+                .iter()
+                .enumerate()
+                .map(|(i, &pitch)| Note {
+                    id: i.to_string(),
+                    pitch,
+                    start_time: i as f64,
+                    duration: 1.0,
+                    velocity: 80,
+                    track_id: "track_right_hand".to_string(),
+                })
+                .collect()
+        };
+        let theme = phrase(&[60, 62, 64, 62]);
+
+        assert_eq!(contour_agreement(&theme, &phrase(&[60, 64, 67, 65]), 4), 1.0);
+        assert_eq!(contour_agreement(&theme, &phrase(&[67, 65, 64, 65]), 4), 0.0);
+        assert_eq!(contour_agreement(&phrase(&[60, 60]), &phrase(&[72, 48]), 2), 1.0);
+    }
+
+    #[test]
+    fn test_infill_must_stay_in_gap() {
+        let note = |start_time: f64, duration: f64| Note {
+            id: start_time.to_string(),
+            pitch: 64,
+            start_time,
+            duration,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        };
+
+        // A gap of a measure and a half, rounded up to 2 measures
+        let request = MelodyRequest {
+            measures: 2,
+            mode: GenerationMode::Infill {
+                before: vec![note(-1.0, 1.0)],
+                after: vec![note(6.0, 2.0)],
+                length: 6.0,
+            },
+            ..MelodyRequest::default()
+        };
+        let mut response = MelodyResponse {
+            notes: vec![note(0.0, 2.0), note(4.0, 2.0)],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        // Within the 2 measures but overlapping the material after the gap
+        response.notes.push(note(5.0, 2.0));
+        assert!(response.validate_request(&request).unwrap_err().contains("after the gap ends"));
+    }
+
+    #[test]
+    fn test_bass_plays_chord_roots() {
+        let chord = |symbol: &str, start_time: f64| ChordSymbol {
+            symbol: symbol.to_string(),
+            start_time,
+            duration: 2.0,
+        };
+        assert_eq!(chord("F#m7", 0.0).bass_pitch_classes(), vec![6]);
+        assert_eq!(chord("C/E", 0.0).bass_pitch_classes(), vec![0, 4]);
+        assert!(chord("N.C.", 0.0).bass_pitch_classes().is_empty());
+
+        let note = |pitch: u8, start_time: f64| Note {
+            id: start_time.to_string(),
+            pitch,
+            start_time,
+            duration: 1.0,
+            velocity: 90,
+            track_id: "track_bass".to_string(),
+        };
+        let request = MelodyRequest {
+            measures: 1,
+            mode: GenerationMode::Bass {
+                chords: vec![chord("Am", 0.0), chord("C/E", 2.0)],
+                context: Vec::new(),
+            },
+            ..MelodyRequest::default()
+        };
+        // Root, approach note, slash bass, approach note
+        let mut response = MelodyResponse {
+            notes: vec![note(45, 0.0), note(47, 1.0), note(40, 2.0), note(43, 3.0)],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        response.notes[2].pitch = 41;
+        assert!(response.validate_request(&request).unwrap_err().contains("C/E"));
+
+        response.notes[2].pitch = 76;
+        assert!(response.validate_request(&request).unwrap_err().contains("bass register"));
+    }
+
+    #[test]
+    fn test_drums_use_drum_map_not_scale() {
+        let hit = |pitch: u8, start_time: f64| Note {
+            id: start_time.to_string(),
+            pitch,
+            start_time,
+            duration: 0.25,
+            velocity: 100,
+            track_id: DRUM_TRACK_ID.to_string(),
+        };
+        // The closed hi-hat (42) is an F#, outside the scale, which doesn't apply to drums
+        let request = MelodyRequest {
+            measures: 1,
+            scale: Some(Scale {
+                root: "C".to_string(),
+                mode: ScaleMode::Major,
+                octave: None,
+            }),
+            mode: GenerationMode::Drums { context: Vec::new() },
+            ..MelodyRequest::default()
+        };
+        let mut response = MelodyResponse {
+            notes: vec![hit(36, 0.0), hit(42, 0.0), hit(38, 1.0), hit(42, 1.0)],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        response.notes.push(hit(60, 2.0));
+        assert!(response.validate_request(&request).unwrap_err().contains("not a drum sound"));
+    }
+
+    #[test]
+    fn test_arrangement_keeps_parts_apart() {
+        let note = |role: PartRole, pitch: u8, start_time: f64| Note {
+            id: format!("{}-{}", role.name(), start_time),
+            pitch,
+            start_time,
+            duration: 2.0,
+            velocity: 80,
+            track_id: role.track_id().to_string(),
+        };
+        let request = MelodyRequest {
+            measures: 1,
+            mode: GenerationMode::Arrange,
+            ..MelodyRequest::default()
+        };
+        let mut response = MelodyResponse {
+            notes: vec![
+                note(PartRole::Melody, 72, 0.0),
+                note(PartRole::Melody, 76, 2.0),
+                note(PartRole::Harmony, 64, 0.0),
+                note(PartRole::Harmony, 67, 0.0),
+                note(PartRole::Bass, 48, 0.0),
+                note(PartRole::Bass, 43, 2.0),
+            ],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        // Harmony reaching above the melody while both sound
+        response.notes.push(note(PartRole::Harmony, 79, 2.0));
+        assert!(response.validate_request(&request).unwrap_err().contains("keep the melody above the harmony"));
+
+        response.notes.pop();
+        response.notes.retain(|n| n.track_id != PartRole::Bass.track_id());
+        assert!(response.validate_request(&request).unwrap_err().contains("no bass notes"));
+    }
+
+    #[test]
+    fn test_diff_notes_keeps_ids_of_matching_notes() {
+        let note = |id: &str, pitch: u8, start_time: f64, velocity: u8| Note {
+            id: id.to_string(),
+            pitch,
+            start_time,
+            duration: 1.0,
+            velocity,
+            track_id: "track_right_hand".to_string(),
+        };
+        let original = vec![note("a", 60, 0.0, 80), note("b", 62, 1.0, 80), note("c", 64, 2.0, 80)];
+        let mut result = vec![note("x", 60, 0.0, 80), note("y", 62, 1.0, 100), note("z", 65, 2.5, 80)];
+
+        let diff = diff_notes(&original, &mut result);
+        assert_eq!(diff.kept, vec!["a"]);
+        assert_eq!(diff.changed, vec!["b"]);
+        assert_eq!(diff.removed, vec!["c"]);
+        assert_eq!(diff.added, vec!["z"]);
+        assert_eq!(result[1].id, "b");
+    }
+
+    #[test]
+    fn test_motif_occurrences_include_transformations() {
+        let line = |pitches: &[u8], length: f64| -> Vec<Note> {
+            pitches
+                .iter()
+                .enumerate()
+                .map(|(i, &pitch)| Note {
+                    id: i.to_string(),
+                    pitch,
+                    start_time: i as f64 * length,
+                    duration: length,
+                    velocity: 80,
+                    track_id: "track_right_hand".to_string(),
+                })
+                .collect()
+        };
+        let motif = line(&[60, 62, 64, 60], 0.5);
+
+        // Stated, sequenced a step up in augmentation, then inverted
+        let melody = [line(&[60, 62, 64, 60], 0.5), line(&[62, 64, 66, 62], 1.0), line(&[67, 65, 63, 67], 0.5)];
+        let mut notes = Vec::new();
+        let mut start = 0.0;
+        for mut part in melody {
+            for note in &mut part {
+                note.start_time += start;
+            }
+            start = part.last().map_or(start, |n| n.start_time + n.duration);
+            notes.extend(part);
+        }
+        assert_eq!(motif_occurrences(&motif, &notes), 3);
+        assert_eq!(motif_occurrences(&motif, &line(&[60, 61, 62, 63], 1.0)), 0);
+    }
+
+    #[test]
+    fn test_chord_progression_parsing_and_tones() {
+        let chords = parse_progression("Cmaj7 | Am7 | Dm7 G7", 4, 4.0).unwrap();
+        let summary: Vec<(&str, f64, f64)> = chords
+            .iter()
+            .map(|chord| (chord.symbol.as_str(), chord.start_time, chord.duration))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Cmaj7", 0.0, 4.0),
+                ("Am7", 4.0, 4.0),
+                ("Dm7", 8.0, 2.0),
+                ("G7", 10.0, 2.0),
+                ("Cmaj7", 12.0, 4.0),
+            ]
+        );
+        assert!(parse_progression("C | | G", 4, 4.0).is_err());
+        assert!(parse_progression("C | H7", 4, 4.0).is_err());
+
+        assert_eq!(chords[0].pitch_classes(), vec![0, 4, 7, 11]);
+        assert_eq!(chords[1].pitch_classes(), vec![0, 4, 7, 9]);
+        assert_eq!(chords[3].pitch_classes(), vec![2, 5, 7, 11]);
+        let bdim = ChordSymbol { symbol: "Bdim".to_string(), start_time: 0.0, duration: 4.0 };
+        assert_eq!(bdim.pitch_classes(), vec![2, 5, 11]);
+    }
+
+    #[test]
+    fn test_strong_beats_follow_progression() {
+        let note = |pitch: u8, start_time: f64| Note {
+            id: start_time.to_string(),
+            pitch,
+            start_time,
+            duration: 1.0,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        };
+        let request = MelodyRequest {
+            measures: 1,
+            chord_progression: Some("C F".to_string()),
+            ..MelodyRequest::default()
+        };
+        assert!(request.validate().is_ok());
+
+        // E on C, passing D off the beat, A on F
+        let mut response = MelodyResponse {
+            notes: vec![note(64, 0.0), note(62, 1.0), note(69, 2.0), note(67, 3.0)],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        response.notes[2].pitch = 71;
+        assert!(response.validate_request(&request).unwrap_err().contains("MIDI 71 at beat 2.00 (F)"));
+    }
+
+    #[test]
+    fn test_time_signature_sets_measure_length() {
+        let waltz = TimeSignature { numerator: 3, denominator: 4 };
+        let jig = TimeSignature { numerator: 6, denominator: 8 };
+        assert_eq!(waltz.beats_per_measure(), 3.0);
+        assert_eq!(jig.beats_per_measure(), 3.0);
+        assert_eq!(strong_beat_names(waltz), "beat 1");
+        assert_eq!(strong_beat_names(jig), "beats 1 and 4");
+        assert_eq!(strong_beat_names(TimeSignature::default()), "beats 1 and 3");
+
+        let request = MelodyRequest { measures: 2, time_signature: waltz, ..MelodyRequest::default() };
+        assert!(request.validate().is_ok());
+        assert!(MelodyRequest { time_signature: TimeSignature { numerator: 3, denominator: 5 }, ..request.clone() }
+            .validate()
+            .is_err());
+
+        // Two measures of 3/4 end at beat 6
+        let mut response = MelodyResponse {
+            notes: vec![Note {
+                id: "n1".to_string(),
+                pitch: 60,
+                start_time: 4.0,
+                duration: 2.0,
+                velocity: 80,
+                track_id: "track_right_hand".to_string(),
+            }],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        response.notes[0].duration = 3.0;
+        assert!(response.validate_request(&request).unwrap_err().contains("2 measures (6 beats)"));
+
+        let chords = parse_progression("C | G", 2, jig.beats_per_measure()).unwrap();
+        assert_eq!(chords[1].start_time, 3.0);
+    }
+
+    #[test]
+    fn test_continuation_connects_to_context() {
+        let note = |pitch: u8, start_time: f64| Note {
+            id: start_time.to_string(),
+            pitch,
+            start_time,
+            duration: 1.0,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        };
+        let request = MelodyRequest {
+            mode: GenerationMode::Continue { context: vec![note(60, -2.0), note(67, -1.0)] },
+            ..MelodyRequest::default()
+        };
+        let mut response = MelodyResponse {
+            notes: vec![note(65, 0.0), note(64, 1.0)],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        response.notes[0].pitch = 84;
+        assert!(response.validate_request(&request).unwrap_err().contains("17 semitones"));
+
+        response.notes = vec![note(65, 4.5)];
+        assert!(response.validate_request(&request).unwrap_err().contains("first measure silent"));
+
+        assert_eq!(progression_from_bar("C | Am | F | G7", 6), "F | G7 | C | Am");
+    }
+
+    #[test]
+    fn test_lyrics_melody_sings_each_syllable() {
+        let note = |start_time: f64, duration: f64| Note {
+            id: start_time.to_string(),
+            pitch: 67,
+            start_time,
+            duration,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        };
+        // "a-GAIN and a-GAIN": stresses on beats 1 and 4
+        let request = MelodyRequest {
+            mode: GenerationMode::Lyrics { syllables: crate::lyrics::syllables("again and again") },
+            ..MelodyRequest::default()
+        };
+        let mut response = MelodyResponse {
+            notes: vec![note(0.5, 0.5), note(1.0, 1.0), note(2.0, 0.5), note(3.5, 0.5), note(4.0, 2.0)],
+            chords: Vec::new(),
+            dynamics: Vec::new(),
+            pedal: Vec::new(),
+            metadata: GenerationMetadata {
+                provider: AIProvider::OpenAI,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                model_name: "gpt-4o-mini".to_string(),
+                temperature: 1.0,
+                scale: None,
+                seed: None,
+            },
+        };
+        assert!(response.validate_request(&request).is_ok());
+
+        response.notes[1] = note(1.5, 0.5);
+        response.notes[4].start_time = 4.5;
+        assert!(response.validate_request(&request).unwrap_err().contains("stressed syllables"));
+
+        response.notes.pop();
+        assert!(response.validate_request(&request).unwrap_err().contains("5 syllables but the melody has 4 notes"));
+    }
 }
@@ -1,4 +1,11 @@
-use crate::ai_models::{MelodyRequest, Scale};
+use crate::ai_models::{
+    strong_beat_names, ChordSymbol, GenerationMode, MelodyRequest, Note, RefineTurn, Scale, SystemPromptMode,
+    TimeSignature, BASS_HIGHEST, BASS_LOWEST, LEFT_HAND_HIGHEST, LEFT_HAND_LOWEST, MOTIF_MIN_OCCURRENCES,
+};
+use crate::ai_schema;
+use crate::lyrics::Syllable;
+use crate::drum_kit::GM_DRUM_MAP;
+use crate::music_theory;
 
 /// Style information extracted from user prompt
 #[derive(Debug)]
@@ -125,7 +132,254 @@ fn analyze_prompt_style(prompt: &str) -> PromptStyle {
     PromptStyle { mood, dynamics, rhythm, genre, articulation, texture, direction }
 }
 
-/// Build the system prompt for AI melody generation
+/// Who sent a message of a multi-turn prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRole {
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    /// Role name in chat APIs
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+}
+
+/// A message of an earlier turn of a conversation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+/// Everything sent to a provider for one structured reply
+#[derive(Debug, Clone)]
+pub struct AIPrompt {
+    pub system: String,
+    /// Earlier turns of a conversation, oldest first (empty for single-turn prompts)
+    pub history: Vec<ChatMessage>,
+    pub user: String,
+    /// JSON schema the reply must match (see `ai_schema`)
+    pub schema: serde_json::Value,
+    /// Name of the structured output (the tool name for Anthropic)
+    pub schema_name: &'static str,
+    pub temperature: f32,
+    pub seed: Option<u64>,
+    /// Most tokens the reply may use (see `DEFAULT_MAX_TOKENS` for APIs that need a limit)
+    pub max_tokens: Option<u32>,
+}
+
+/// Reply token limit for APIs that need one when none is configured
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+impl AIPrompt {
+    /// Prompt for a generation request; `validation_error` feeds back why the last attempt failed
+    pub fn for_request(request: &MelodyRequest, validation_error: Option<&str>) -> Self {
+        Self {
+            system: build_system_prompt(request),
+            history: match &request.mode {
+                GenerationMode::Refine { history } => refine_history(history),
+                _ => Vec::new(),
+            },
+            user: match validation_error {
+                Some(error) => build_retry_prompt(request, error),
+                None => build_user_prompt(request),
+            },
+            schema: ai_schema::response_schema(request),
+            schema_name: ai_schema::schema_name(&request.mode),
+            temperature: request.temperature.unwrap_or(1.0),
+            seed: request.seed,
+            max_tokens: request.max_tokens,
+        }
+    }
+
+    /// Prompt asking for a critique of `notes`, which are numbered in the given order
+    ///
+    /// `focus` is what the user wants feedback on, if anything in particular.
+    pub fn for_critique(notes: &[Note], scale: Option<&Scale>, focus: Option<&str>) -> Self {
+        let focus = focus.map(str::trim).filter(|focus| !focus.is_empty());
+        Self {
+            system: build_critique_system_prompt(notes, scale),
+            history: Vec::new(),
+            user: match focus {
+                Some(focus) => format!("Critique this melody, focusing on: {}", sanitize_focus(focus)),
+                None => "Critique this melody.".to_string(),
+            },
+            schema: ai_schema::critique_schema(),
+            schema_name: ai_schema::CRITIQUE_SCHEMA_NAME,
+            temperature: CRITIQUE_TEMPERATURE,
+            seed: None,
+            max_tokens: None,
+        }
+    }
+
+    /// Prompt asking for `count` title ideas for a piece, from its notes and the prompt it came from
+    pub fn for_titles(notes: &[Note], scale: Option<&Scale>, description: Option<&str>, count: u32) -> Self {
+        Self {
+            system: build_title_system_prompt(notes, scale, description),
+            history: Vec::new(),
+            user: format!("Suggest {} titles for this piece.", count),
+            schema: ai_schema::title_schema(),
+            schema_name: ai_schema::TITLE_SCHEMA_NAME,
+            temperature: TITLE_TEMPERATURE,
+            seed: None,
+            max_tokens: None,
+        }
+    }
+
+    /// The conversation to send after the system prompt: the history, then the user's message
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        let mut messages = self.history.clone();
+        messages.push(ChatMessage { role: ChatRole::User, content: self.user.clone() });
+        messages
+    }
+
+    /// System prompt with the reply format spelled out, for providers
+    /// without structured outputs (JSON mode or plain text)
+    pub fn system_with_format(&self) -> String {
+        format!(
+            "{}\n\nOUTPUT FORMAT:\n\
+            Respond with only a JSON object, no other text, matching this JSON schema:\n{}",
+            self.system, self.schema
+        )
+    }
+}
+
+/// Build the system prompt for a generation request, by mode
+///
+/// A custom system prompt either follows the built-in one or, for modes that
+/// write from scratch, takes the place of its role and style guidance. Either
+/// way the note format, scale, timing and progression requirements stay, and
+/// the reply schema is enforced separately (see `ai_schema`).
+pub fn build_system_prompt(request: &MelodyRequest) -> String {
+    let instructions = request.system_prompt.as_ref().map(|custom| sanitize_instructions(&custom.text));
+    let replaces = request.system_prompt.as_ref().is_some_and(|custom| custom.mode == SystemPromptMode::Replace)
+        && matches!(request.mode, GenerationMode::Melody | GenerationMode::Chords | GenerationMode::Arrange);
+
+    let mut prompt = match &request.mode {
+        _ if replaces => replacement_system_prompt(request, instructions.as_deref().unwrap_or_default()),
+        GenerationMode::Melody => build_melody_system_prompt(request),
+        GenerationMode::Chords => build_chords_system_prompt(request),
+        GenerationMode::Harmonize { melody } => build_harmonize_system_prompt(request, melody),
+        GenerationMode::Continue { context } => build_continue_system_prompt(request, context),
+        GenerationMode::Variation { theme, amount } => build_variation_system_prompt(request, theme, *amount),
+        GenerationMode::Infill { before, after, length } => build_infill_system_prompt(request, before, after, *length),
+        GenerationMode::LeftHand { melody } => build_left_hand_system_prompt(request, melody),
+        GenerationMode::Bass { chords, context } => build_bass_system_prompt(request, chords, context),
+        GenerationMode::Drums { context } => build_drums_system_prompt(request, context),
+        GenerationMode::Arrange => build_arrange_system_prompt(request),
+        GenerationMode::Restyle { source } => build_restyle_system_prompt(request, source),
+        GenerationMode::Motif { motif } => build_motif_system_prompt(request, motif),
+        GenerationMode::Section { label, structure, context, reprise } => {
+            build_section_system_prompt(request, label, structure, context, reprise)
+        }
+        GenerationMode::Lyrics { syllables } => build_lyrics_system_prompt(request, syllables),
+        GenerationMode::Refine { .. } => build_refine_system_prompt(request),
+    };
+
+    if request.mode.follows_progression() {
+        prompt.push_str(&progression_requirements(&request.progression(), request.time_signature));
+    }
+    if request.mode.has_expression() {
+        prompt.push_str(EXPRESSION_REQUIREMENTS);
+    }
+    if let Some(instructions) = instructions.filter(|_| !replaces) {
+        prompt.push_str(&format!(
+            "ADDITIONAL INSTRUCTIONS (from the user):\n{}\n\n\
+            Follow these as long as they don't conflict with the requirements above, which always apply.\n\n",
+            instructions
+        ));
+    }
+    prompt
+}
+
+/// Longest custom system prompt kept, in characters
+const MAX_INSTRUCTION_CHARS: usize = 4000;
+
+/// Custom instructions without control characters (other than line breaks and tabs), truncated
+fn sanitize_instructions(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .take(MAX_INSTRUCTION_CHARS)
+        .collect()
+}
+
+/// System prompt built on the user's own instructions, keeping the requirements every reply must meet
+fn replacement_system_prompt(request: &MelodyRequest, instructions: &str) -> String {
+    let mut prompt = format!(
+        "{}\n\n\
+        REQUIRED NOTE FORMAT (always applies, whatever the instructions above say):\n\
+        - pitch: MIDI note number (0-127, where 60 is middle C)\n\
+        - startTime: Start time in beats (floating point)\n\
+        - duration: Note duration in beats (floating point, minimum 0.25)\n\
+        - velocity: Note loudness (0-127, where 64 is normal, 100 is forte)\n\n",
+        instructions
+    );
+    if let Some(scale) = &request.scale {
+        prompt.push_str(&format!(
+            "SCALE REQUIREMENTS:\n\
+            - Use only notes from the {} {} scale\n{}\
+            - Allowed MIDI notes: {:?}\n\n",
+            scale.root,
+            scale.mode,
+            mode_character(scale),
+            scale.get_midi_notes()
+        ));
+    }
+    prompt.push_str(&timing_constraints(request));
+    prompt
+}
+
+/// Expression section: hairpins and sustain pedal for piano parts
+const EXPRESSION_REQUIREMENTS: &str = "EXPRESSION (dynamics and pedal):\n\
+    - dynamics: Crescendos and decrescendos shaping the phrases, each with shape (\"crescendo\" or \
+    \"decrescendo\"), startTime, duration (beats) and fromVelocity/toVelocity (1-127); a crescendo gets \
+    louder, a decrescendo softer; hairpins don't overlap; notes starting within one take its velocities\n\
+    - pedal: Spans the sustain pedal is held down (startTime, duration); lift and press again when the \
+    harmony changes so chords don't blur; keep it up for dry or staccato passages\n\
+    - Both may be empty when the music calls for neither\n\n";
+
+/// Chord progression section: the chords by beat, and strong beats on chord tones
+fn progression_requirements(chords: &[ChordSymbol], time_signature: TimeSignature) -> String {
+    if chords.is_empty() {
+        return String::new();
+    }
+
+    let changes = chords
+        .iter()
+        .map(|chord| {
+            format!(
+                "- Beats {}-{}: {}",
+                chord.start_time,
+                chord.start_time + chord.duration,
+                chord.symbol
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let measure = time_signature.beats_per_measure();
+    let strong_times = (0..2)
+        .flat_map(|n| time_signature.strong_beats().into_iter().map(move |beat| n as f64 * measure + beat))
+        .map(|time| time.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "CHORD PROGRESSION:\n{}\n\
+        - Follow this harmony throughout\n\
+        - Notes starting on strong beats ({} of each measure: startTime {}, ...) \
+        must be tones of the chord sounding there; use other notes only in passing, off the strong beats\n\n",
+        changes,
+        strong_beat_names(time_signature),
+        strong_times
+    )
+}
+
+/// Build the system prompt for melody generation
 ///
 /// This is the core prompt engineering function that constructs detailed instructions
 /// for the AI model. The prompt includes:
@@ -145,7 +399,7 @@ fn analyze_prompt_style(prompt: &str) -> PromptStyle {
 ///
 /// # Returns
 /// A complete system prompt string to be sent to the AI provider
-pub fn build_system_prompt(request: &MelodyRequest) -> String {
+fn build_melody_system_prompt(request: &MelodyRequest) -> String {
     let mut prompt = String::from(
         "You are a professional melody composer. Generate musical melodies with these note properties:\n\
         - pitch: MIDI note number (0-127, where 60 is middle C)\n\
@@ -184,7 +438,7 @@ pub fn build_system_prompt(request: &MelodyRequest) -> String {
 
         prompt.push_str(&format!(
             "SCALE REQUIREMENTS:\n\
-            - Use only notes from the {} {} scale\n\
+            - Use only notes from the {} {} scale\n{}\
             - Allowed MIDI notes: {:?}\n\n\
             REGISTER GUIDELINES:\n\
             - Harmony (chords): Use octaves {} to {} - MIDI examples: {:?}\n\
@@ -197,7 +451,7 @@ pub fn build_system_prompt(request: &MelodyRequest) -> String {
             - Melody: Craft an expressive single-note line that stands out above the harmony\n\
             - For sparse or minimalist styles, chords are optional - focus on the melodic line\n\
             - Balance: Ensure the melody is distinct and the chords provide support without overwhelming\n\n",
-            scale.root, scale.mode, midi_notes,
+            scale.root, scale.mode, mode_character(scale), midi_notes,
             chord_octave_start, chord_octave_end,
             chord_example_notes,
             melody_octave_start, melody_octave_end,
@@ -208,14 +462,7 @@ pub fn build_system_prompt(request: &MelodyRequest) -> String {
     }
 
     // Add timing constraints
-    let total_beats = request.measures * 4; // Assuming 4/4 time signature
-    prompt.push_str(&format!(
-        "TIMING CONSTRAINTS:\n\
-        - Duration: {} measures ({} beats total in 4/4 time)\n\
-        - All notes must fit within this timeframe: startTime + duration <= {}\n\
-        - Note durations: 0.25 (16th), 0.5 (8th), 1.0 (quarter), 2.0 (half), 4.0 (whole)\n\n",
-        request.measures, total_beats, total_beats
-    ));
+    prompt.push_str(&timing_constraints(request));
 
     // Analyze prompt for style keywords and add specific guidance
     let style = analyze_prompt_style(&request.prompt);
@@ -295,13 +542,798 @@ pub fn build_system_prompt(request: &MelodyRequest) -> String {
     prompt
 }
 
+/// Timing section shared by every mode's system prompt
+fn timing_constraints(request: &MelodyRequest) -> String {
+    let time_signature = request.time_signature;
+    let measure = time_signature.beats_per_measure();
+    let total_beats = request.measures as f64 * measure;
+    format!(
+        "TIMING CONSTRAINTS:\n\
+        - Duration: {} measures ({} beats total in {} time)\n\
+        - A beat is a quarter note; each measure is {} beats long (measure n starts at startTime n * {})\n\
+        - All notes must fit within this timeframe: startTime + duration <= {}\n\
+        - Note durations: 0.25 (16th), 0.5 (8th), 1.0 (quarter), 2.0 (half), 4.0 (whole)\n\n",
+        request.measures, total_beats, time_signature, measure, measure, total_beats
+    )
+}
+
+/// Build the system prompt for chord progression generation
+///
+/// The reply holds chord symbols plus a voicing (the notes) for each, so the
+/// progression is both readable and playable on the chord track.
+fn build_chords_system_prompt(request: &MelodyRequest) -> String {
+    let mut prompt = String::from(
+        "You are a professional harmony arranger. Write chord progressions as chord symbols \
+        with a piano voicing for every chord.\n\n\
+        CHORD SYMBOLS (chords):\n\
+        - symbol: Root and quality, e.g. \"C\", \"Am7\", \"Fmaj7\", \"G7\", \"Bdim\", \"Dsus4\", \"C/E\"\n\
+        - startTime: Beat where the chord starts (floating point)\n\
+        - duration: Beats the chord lasts (floating point)\n\
+        - Chords follow each other without gaps or overlaps\n\n\
+        VOICINGS (notes):\n\
+        - For every chord, add 3-5 notes starting at the chord's startTime and lasting its duration\n\
+        - pitch: MIDI note number (0-127, where 60 is middle C); put the root (or the slash bass note) lowest\n\
+        - velocity: Note loudness (0-127), usually 60-85 for accompaniment\n\
+        - Voice lead smoothly: keep common tones and move the other voices by step\n\n"
+    );
+
+    if let Some(scale) = &request.scale {
+        let octave = scale.octave.unwrap_or(4);
+        prompt.push_str(&format!(
+            "SCALE REQUIREMENTS:\n\
+            - Stay in the key of {} {}: use chords built only from scale notes\n{}\
+            - Allowed MIDI notes: {:?}\n\
+            - Voice chords in octaves {} to {}\n\n",
+            scale.root,
+            scale.mode,
+            mode_character(scale),
+            scale.get_midi_notes(),
+            octave,
+            octave + 1
+        ));
+    } else {
+        prompt.push_str("REGISTER:\n- Voice chords between MIDI 48 and 72 (C3 to C5)\n\n");
+    }
+
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str(
+        "HARMONIC GUIDELINES:\n\
+        - Establish the key at the start and end on a cadence (e.g. V-I or IV-I)\n\
+        - Change chords once or twice per measure unless the description asks otherwise\n",
+    );
+    if let Some(mood) = style.mood {
+        prompt.push_str(&format!("- Mood: Choose harmonies that sound {}\n", mood));
+    }
+    if let Some(genre) = style.genre {
+        prompt.push_str(&format!("- Genre: Follow {} style conventions (chord extensions, progressions)\n", genre));
+    }
+    if let Some(rhythm) = style.rhythm {
+        prompt.push_str(&format!("- Harmonic rhythm: {}\n", rhythm));
+    }
+    prompt.push('\n');
+
+    prompt
+}
+
+/// Line describing the sound of a church mode or other non-major/minor scale, if any
+fn mode_character(scale: &Scale) -> String {
+    match scale.mode.character() {
+        Some(character) => format!("- Mode character: {}\n", character),
+        None => String::new(),
+    }
+}
+
+/// Notes as JSON lines in time order, to show the model existing material
+fn notes_context(notes: &[Note]) -> String {
+    let mut sorted: Vec<&Note> = notes.iter().collect();
+    sorted.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(a.pitch.cmp(&b.pitch)));
+
+    sorted
+        .iter()
+        .map(|note| {
+            format!(
+                "{{\"pitch\": {}, \"startTime\": {}, \"duration\": {}, \"velocity\": {}}}",
+                note.pitch, note.start_time, note.duration, note.velocity
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Scale section for modes adding to existing material
+fn scale_requirements(request: &MelodyRequest) -> String {
+    match &request.scale {
+        Some(scale) => format!(
+            "SCALE REQUIREMENTS:\n\
+            - Use only notes from the {} {} scale\n{}\
+            - Allowed MIDI notes: {:?}\n\n",
+            scale.root,
+            scale.mode,
+            mode_character(scale),
+            scale.get_midi_notes()
+        ),
+        None => String::new(),
+    }
+}
+
+/// Build the system prompt for harmonizing an existing melody
+///
+/// The melody is sent as context; the reply holds only the added notes,
+/// which must not clash with it.
+fn build_harmonize_system_prompt(request: &MelodyRequest, melody: &[Note]) -> String {
+    let lowest = melody.iter().map(|note| note.pitch).min().unwrap_or(60);
+
+    let mut prompt = format!(
+        "You are a professional arranger. Harmonize the user's melody with supporting chords \
+        and/or counterlines. Return only the new notes, not the melody.\n\n\
+        THE MELODY:\n{}\n\n\
+        HARMONY RULES:\n\
+        - Place the harmony below the melody: keep new notes under MIDI {} where possible\n\
+        - Never double a melody note's pitch while it sounds, and avoid minor seconds, \
+        major sevenths and minor ninths against sounding melody notes\n\
+        - Support the melody's strong beats with chord tones, and follow its phrasing\n\
+        - pitch: MIDI note number (0-127); velocity: 50-80 so the melody stays in front\n\n",
+        notes_context(melody),
+        lowest
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str("STYLE:\n");
+    if let Some(genre) = style.genre {
+        prompt.push_str(&format!("- Genre: Follow {} style conventions\n", genre));
+    }
+    if let Some(texture) = style.texture {
+        prompt.push_str(&format!("- Texture: Create {}\n", texture));
+    }
+    if let Some(mood) = style.mood {
+        prompt.push_str(&format!("- Mood: Keep the harmony {}\n", mood));
+    }
+    prompt.push_str("- Follow the user's description of the accompaniment style\n\n");
+
+    prompt
+}
+
+/// Left-hand pattern picked by style keywords; block chords when none match
+fn left_hand_pattern(style: &str) -> &'static str {
+    let style = style.to_lowercase();
+    if style.contains("alberti") || style.contains("classical") || style.contains("mozart") {
+        "Alberti bass: broken chords in the pattern low-high-middle-high, in steady 8th or 16th notes"
+    } else if style.contains("stride") || style.contains("ragtime") || style.contains("jazz") {
+        "stride: a low bass note (root or fifth, often an octave) on beats 1 and 3, \
+        a mid-register chord on beats 2 and 4"
+    } else if style.contains("arpeggi") || style.contains("broken") || style.contains("romantic") {
+        "arpeggios: chord tones spread over an octave or more, rising and falling in even notes"
+    } else if style.contains("waltz") {
+        "waltz: a bass note on the first beat, chords on the following beats"
+    } else {
+        "block chords: 3-4 note chords changing with the harmony, sustained or repeated on the beat"
+    }
+}
+
+/// Build the system prompt for a left-hand part under a right-hand melody
+///
+/// The accompaniment pattern is picked from the style keywords in the prompt.
+fn build_left_hand_system_prompt(request: &MelodyRequest, melody: &[Note]) -> String {
+    let mut prompt = format!(
+        "You are a professional pianist. Write the left-hand part for the user's right-hand \
+        melody. Return only the left-hand notes, not the melody.\n\n\
+        THE RIGHT-HAND MELODY:\n{}\n\n\
+        LEFT-HAND RULES:\n\
+        - Pattern: {}\n\
+        - Register: MIDI {}-{}, below the melody; keep the hand within reach (about an octave \
+        per beat for chords)\n\
+        - Follow the harmony implied by the melody, with the chord root in the bass on strong beats\n\
+        - Never double a melody note's pitch while it sounds, and avoid minor seconds against it\n\
+        - pitch: MIDI note number; velocity: 50-75 so the melody stays in front\n\n",
+        notes_context(melody),
+        left_hand_pattern(&request.prompt),
+        LEFT_HAND_LOWEST,
+        LEFT_HAND_HIGHEST
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+    prompt
+}
+
+/// Build the system prompt for a bass line
+///
+/// With chord symbols the bass follows them; otherwise it follows the harmony
+/// of the existing notes.
+fn build_bass_system_prompt(request: &MelodyRequest, chords: &[ChordSymbol], context: &[Note]) -> String {
+    let mut prompt = String::from(
+        "You are a professional bass player. Write a bass line for the user's music. \
+        Return only the bass notes.\n\n",
+    );
+
+    if !chords.is_empty() {
+        let changes = chords
+            .iter()
+            .map(|chord| format!("- {} at beat {} for {} beats", chord.symbol, chord.start_time, chord.duration))
+            .collect::<Vec<_>>()
+            .join("\n");
+        prompt.push_str(&format!("THE CHORDS:\n{}\n\n", changes));
+    }
+    if !context.is_empty() {
+        prompt.push_str(&format!("THE OTHER PARTS:\n{}\n\n", notes_context(context)));
+    }
+
+    let harmony = if chords.is_empty() {
+        "- Infer the chords from the other parts and play their roots on the chord changes"
+    } else {
+        "- On every chord's startTime, start a note on that chord's root (the note after the slash \
+        for slash chords like C/E)"
+    };
+    prompt.push_str(&format!(
+        "BASS RULES:\n\
+        {}\n\
+        - Between chord changes use chord tones, and approach the next root by step or half step\n\
+        - Register: MIDI {}-{} only\n\
+        - One note at a time; no chords\n\
+        - Avoid minor seconds against notes sounding in the other parts\n\
+        - pitch: MIDI note number; velocity: 80-100, accenting the chord changes\n\n",
+        harmony, BASS_LOWEST, BASS_HIGHEST
+    ));
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str("STYLE:\n");
+    if let Some(genre) = style.genre {
+        prompt.push_str(&format!("- Genre: Follow {} style conventions\n", genre));
+    }
+    if let Some(rhythm) = style.rhythm {
+        prompt.push_str(&format!("- Rhythm: Use {}\n", rhythm));
+    }
+    prompt.push_str("- Follow the user's description of the bass line\n\n");
+
+    prompt
+}
+
+/// Build the system prompt for a drum pattern
+///
+/// Notes are General MIDI percussion: the pitch picks the sound, and no scale applies.
+fn build_drums_system_prompt(request: &MelodyRequest, context: &[Note]) -> String {
+    let drum_map = GM_DRUM_MAP
+        .iter()
+        .map(|sound| format!("- {}: {}", sound.pitch, sound.name))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut prompt = format!(
+        "You are a professional drummer. Write a drum pattern as General MIDI percussion \
+        (channel 10), where each note's pitch selects a drum sound.\n\n\
+        DRUM MAP (use only these pitches):\n{}\n\n\
+        DRUM RULES:\n\
+        - Build the groove from kick (36), snare (38) and hi-hat (42 closed, 46 open); \
+        add toms and cymbals for fills and accents\n\
+        - Several sounds may hit at the same startTime (e.g. kick and hi-hat together)\n\
+        - duration: 0.25 for every hit (drums ring out by themselves)\n\
+        - velocity: accents 100-120, regular hits 70-100, ghost notes 30-50\n\
+        - Keep the pattern consistent and mark phrase ends (the last measure) with a fill\n\n",
+        drum_map
+    );
+
+    if !context.is_empty() {
+        prompt.push_str(&format!(
+            "THE OTHER PARTS (lock in with their rhythm):\n{}\n\n",
+            notes_context(context)
+        ));
+    }
+
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str("STYLE:\n");
+    if let Some(genre) = style.genre {
+        prompt.push_str(&format!("- Genre: Follow {} style conventions\n", genre));
+    }
+    if let Some(rhythm) = style.rhythm {
+        prompt.push_str(&format!("- Rhythm: Use {}\n", rhythm));
+    }
+    if let Some(dynamics) = style.dynamics {
+        prompt.push_str(&format!("- Dynamics: Use {}\n", dynamics));
+    }
+    prompt.push_str("- Follow the user's description of the groove\n\n");
+
+    prompt
+}
+
+/// Build the system prompt for a full arrangement: melody, harmony and bass in one reply
+///
+/// Every note carries a `role`, which places it on its part's track.
+fn build_arrange_system_prompt(request: &MelodyRequest) -> String {
+    let mut prompt = format!(
+        "You are a professional arranger. Write a complete short arrangement with three parts: \
+        a melody, a harmony (chords or inner voices) and a bass line.\n\n\
+        NOTE PROPERTIES:\n\
+        - pitch: MIDI note number (0-127, where 60 is middle C)\n\
+        - startTime: Start time in beats (floating point)\n\
+        - duration: Note duration in beats (floating point, minimum 0.25)\n\
+        - velocity: Note loudness (0-127)\n\
+        - role: The part the note belongs to: \"melody\", \"harmony\" or \"bass\"\n\n\
+        ARRANGEMENT RULES:\n\
+        - Melody: a singable line on top (about MIDI 60-84), velocity 80-110\n\
+        - Harmony: chords or inner voices below the melody (about MIDI 48-72), velocity 50-80\n\
+        - Bass: one note at a time, chord roots and approach notes, MIDI {}-{}, velocity 80-100\n\
+        - Keep the parts apart: while notes of two parts sound together, the melody must be above \
+        the harmony and the harmony above the bass (never the same pitch or crossing)\n\
+        - All three parts follow the same chord progression\n\n",
+        BASS_LOWEST, BASS_HIGHEST
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str("STYLE:\n");
+    if let Some(genre) = style.genre {
+        prompt.push_str(&format!("- Genre: Follow {} style conventions\n", genre));
+    }
+    if let Some(mood) = style.mood {
+        prompt.push_str(&format!("- Mood: Make the arrangement {}\n", mood));
+    }
+    if let Some(texture) = style.texture {
+        prompt.push_str(&format!("- Texture: Create {}\n", texture));
+    }
+    prompt.push_str("- Follow the user's description of the piece\n\n");
+
+    prompt
+}
+
+/// Build the system prompt for re-rendering a passage in another style
+///
+/// The target style is the user prompt; contour and measure structure are kept.
+fn build_restyle_system_prompt(request: &MelodyRequest, source: &[Note]) -> String {
+    let mut prompt = format!(
+        "You are a professional arranger. Re-render the user's passage in a new style.\n\n\
+        THE PASSAGE:\n{}\n\n\
+        STYLE TRANSFER RULES:\n\
+        - Change rhythm, articulation, ornamentation, voicing and dynamics to fit the target style\n\
+        - Preserve the contour: rise where the passage rises and fall where it falls\n\
+        - Preserve the measure structure: measures with notes keep notes, rests stay rests\n\
+        - Keep notes that already fit the style unchanged (same pitch and startTime)\n\
+        - Return the complete passage in the new style\n\n",
+        notes_context(source)
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str("TARGET STYLE:\n");
+    if let Some(genre) = style.genre {
+        prompt.push_str(&format!("- Genre: Follow {} style conventions\n", genre));
+    }
+    if let Some(rhythm) = style.rhythm {
+        prompt.push_str(&format!("- Rhythm: Use {}\n", rhythm));
+    }
+    if let Some(articulation) = style.articulation {
+        prompt.push_str(&format!("- Articulation: Use {}\n", articulation));
+    }
+    prompt.push_str("- Follow the user's description of the target style\n\n");
+
+    prompt
+}
+
+/// Build the system prompt for a melody developing the user's motif
+///
+/// The usual melody prompt, with the motif embedded and the development
+/// techniques spelled out.
+fn build_motif_system_prompt(request: &MelodyRequest, motif: &[Note]) -> String {
+    let mut prompt = build_melody_system_prompt(request);
+    prompt.push_str(&format!(
+        "MOTIF DEVELOPMENT:\n\
+        - Build the whole melody from this {}-note motif:\n{}\n\
+        - State the motif near the start, then develop it using sequence (the motif transposed \
+        up or down), inversion (its intervals turned upside down), augmentation or diminution \
+        (the same pitches in longer or shorter notes) and fragmentation\n\
+        - Keep the motif's exact interval pattern (or its inversion) in at least {} places so \
+        it stays recognizable\n\
+        - Connect the motif statements with short linking passages\n\n",
+        motif.len(),
+        notes_context(motif),
+        MOTIF_MIN_OCCURRENCES
+    ));
+    prompt
+}
+
+/// Lowest note of a vocal melody (G3)
+const VOCAL_LOWEST: u8 = 55;
+
+/// Highest note of a vocal melody (G5)
+const VOCAL_HIGHEST: u8 = 79;
+
+/// Build the system prompt for a vocal melody setting a lyric
+///
+/// The lyric is shown line by line with stressed syllables in capitals, and
+/// the syllables are numbered so the notes can follow them in order.
+fn build_lyrics_system_prompt(request: &MelodyRequest, syllables: &[Syllable]) -> String {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    for syllable in syllables {
+        line.push(if syllable.stressed { syllable.text.to_uppercase() } else { syllable.text.to_lowercase() });
+        if syllable.line_end {
+            lines.push(format!("- {} ({} syllables)", line.join(" "), line.len()));
+            line.clear();
+        }
+    }
+
+    let mut prompt = format!(
+        "You are a professional songwriter. Set the user's lyric to a singable vocal melody.\n\n\
+        THE LYRIC (syllables separated by spaces, STRESSED syllables in capitals):\n{}\n\n\
+        LYRIC SETTING RULES:\n\
+        - Write exactly {} notes: one note per syllable, in the order of the lyric\n\
+        - A single vocal line: no chords, no overlapping notes\n\
+        - Put stressed syllables on beats (whole-number startTime), preferably strong beats, \
+        and give them longer notes; unstressed syllables fall between beats or on weak beats\n\
+        - Follow the lyric's phrasing: end each line on a longer note or leave a short rest after it \
+        to breathe, and shape each line as a phrase\n\
+        - Keep it singable: pitch within MIDI {}-{}, mostly steps and small leaps\n\
+        - velocity: follow the words' emotion, usually 70-100\n\n",
+        lines.join("\n"),
+        syllables.len(),
+        VOCAL_LOWEST,
+        VOCAL_HIGHEST
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str("STYLE:\n- Follow the user's description of the song\n");
+    if let Some(mood) = style.mood {
+        prompt.push_str(&format!("- Mood: Make the melody sound {}\n", mood));
+    }
+    if let Some(genre) = style.genre {
+        prompt.push_str(&format!("- Genre: Follow {} style conventions\n", genre));
+    }
+    prompt.push('\n');
+
+    prompt
+}
+
+/// Build the system prompt for one section of a song
+///
+/// The usual melody prompt, plus the song's structure, the end of the
+/// previous section to connect to, and the earlier section a reprise restates.
+fn build_section_system_prompt(
+    request: &MelodyRequest,
+    label: &str,
+    structure: &str,
+    context: &[Note],
+    reprise: &[Note],
+) -> String {
+    let mut prompt = build_melody_system_prompt(request);
+    prompt.push_str(&format!(
+        "SONG SECTION:\n\
+        - The song's structure: {}\n\
+        - Write only section \"{}\", from beat 0\n",
+        structure, label
+    ));
+
+    if context.is_empty() {
+        prompt.push_str("- This section opens the song: establish the key, tempo feel and register\n");
+    } else {
+        prompt.push_str(&format!(
+            "- Connect seamlessly to the end of the previous section (startTime relative to this \
+            section, so negative):\n{}\n",
+            notes_context(context)
+        ));
+    }
+
+    if reprise.is_empty() {
+        prompt.push_str("- Give the section its own character, contrasting with the other sections\n\n");
+    } else {
+        prompt.push_str(&format!(
+            "- This section returns to an earlier one: restate it recognizably, with small changes \
+            (ornaments, a varied ending):\n{}\n\n",
+            notes_context(reprise)
+        ));
+    }
+    prompt
+}
+
+/// Roman-numeral outline of the preceding music, so continuations follow its harmony
+///
+/// Uses the request's scale as the key, or the detected one when it is clear;
+/// empty when neither is available or no chords are found.
+fn harmony_context(request: &MelodyRequest, context: &[Note]) -> String {
+    let key = request.scale.clone().or_else(|| {
+        music_theory::detect_key(context)
+            .first()
+            .filter(|key| key.score >= music_theory::KEY_CONFIDENCE)
+            .map(|key| key.scale())
+    });
+    let Some(key) = key.filter(|key| music_theory::check_root(&key.root).is_ok()) else {
+        return String::new();
+    };
+
+    // Context times are negative; analyze from the start of its first measure
+    let beats = request.time_signature.beats_per_measure();
+    let first = context.iter().map(|n| n.start_time).fold(0.0, f64::min);
+    let offset = (-first / beats).ceil() * beats;
+    let shifted: Vec<Note> = context.iter().map(|n| Note { start_time: n.start_time + offset, ..n.clone() }).collect();
+
+    let analysis = music_theory::analyze_harmony(&shifted, &key, beats);
+    if analysis.chords.is_empty() {
+        return String::new();
+    }
+    format!(
+        "HARMONY SO FAR (in {} {}):\n- {}\n- Continue the progression so it follows on naturally\n\n",
+        key.root,
+        key.mode,
+        music_theory::harmony_summary(&analysis)
+    )
+}
+
+/// Build the system prompt for continuing a passage
+///
+/// The preceding measures are sent with start times relative to where the
+/// continuation begins, so the model writes from beat 0 as usual.
+fn build_continue_system_prompt(request: &MelodyRequest, context: &[Note]) -> String {
+    let mut prompt = format!(
+        "You are a professional composer. Continue the user's piece with the next measures.\n\n\
+        THE PRECEDING MUSIC (startTime is relative to where your continuation begins, \
+        so these are negative):\n{}\n\n\
+        CONTINUATION RULES:\n\
+        - Start your notes at beat 0, right where the preceding music ends\n\
+        - Connect seamlessly: keep the key, register, tempo feel and texture\n\
+        - Begin within an octave of the last preceding notes; don't jump to a new register at the seam\n\
+        - Develop the existing motifs and rhythms instead of starting something unrelated\n\
+        - Complete any unfinished phrase before starting a new one\n\
+        - pitch: MIDI note number (0-127); velocity: continue the existing dynamics\n\n",
+        notes_context(context)
+    );
+
+    prompt.push_str(&harmony_context(request, context));
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+
+    let style = analyze_prompt_style(&request.prompt);
+    prompt.push_str("DIRECTION:\n- Follow the user's description of where the music should go\n");
+    if let Some(direction) = style.direction {
+        prompt.push_str(&format!("- Direction: Use {}\n", direction));
+    }
+    if let Some(dynamics) = style.dynamics {
+        prompt.push_str(&format!("- Dynamics: Move towards {}\n", dynamics));
+    }
+    prompt.push('\n');
+
+    prompt
+}
+
+/// Build the system prompt for editing a melody over a conversation
+///
+/// The melodies so far and the instructions that led to them are sent as
+/// earlier turns (see `refine_history`).
+fn build_refine_system_prompt(request: &MelodyRequest) -> String {
+    let measure = request.time_signature.beats_per_measure();
+    let mut prompt = format!(
+        "You are a professional composer revising a melody together with the user. The conversation \
+        so far holds the user's requests and the melodies you wrote for them.\n\n\
+        EDITING RULES:\n\
+        - Apply the user's latest request to your latest melody and reply with the whole edited melody\n\
+        - Change only what the request asks for; keep every other note as it is\n\
+        - Bars are measures counted from 1: bar n spans startTime {m} * (n - 1) to {m} * n\n\
+        - \"Busier\" means more and shorter notes, \"sparser\" fewer and longer; \"less jumpy\" means \
+        smaller leaps and more stepwise motion\n\
+        - pitch: MIDI note number (0-127); velocity: keep the existing dynamics unless asked\n\n",
+        m = measure
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+    prompt
+}
+
+/// Earlier turns of a refinement: each instruction, answered with the melody it produced
+fn refine_history(turns: &[RefineTurn]) -> Vec<ChatMessage> {
+    turns
+        .iter()
+        .enumerate()
+        .flat_map(|(i, turn)| {
+            let request = if i == 0 {
+                format!("Create a melody based on this description: {}", turn.instruction)
+            } else {
+                refine_instruction(&turn.instruction)
+            };
+            [
+                ChatMessage { role: ChatRole::User, content: request },
+                ChatMessage {
+                    role: ChatRole::Assistant,
+                    content: format!("{{\"notes\": [\n{}\n]}}", notes_context(&turn.notes).replace('\n', ",\n")),
+                },
+            ]
+        })
+        .collect()
+}
+
+/// User message asking to edit the latest melody
+fn refine_instruction(instruction: &str) -> String {
+    format!("Edit your latest melody: {}", instruction)
+}
+
+/// Build the system prompt for a variation of a phrase
+///
+/// `amount` (0.0-1.0) picks how far the variation may stray; the contour is kept either way.
+fn build_variation_system_prompt(request: &MelodyRequest, theme: &[Note], amount: f32) -> String {
+    let freedom = if amount < 0.34 {
+        "- Subtle variation: keep most notes, vary only ornaments, passing notes and a few rhythms\n"
+    } else if amount < 0.67 {
+        "- Moderate variation: rework rhythms and inner notes while keeping the phrase recognizable\n"
+    } else {
+        "- Free variation: recompose rhythm and notes freely, keeping only the phrase's shape\n"
+    };
+
+    let mut prompt = format!(
+        "You are a professional composer writing variations on a theme. Rewrite the user's \
+        phrase as a new version of itself.\n\n\
+        THE PHRASE:\n{}\n\n\
+        VARIATION RULES:\n\
+        - Preserve the contour: rise where the phrase rises and fall where it falls\n\
+        - Keep the same length and the phrase's start and end points in time\n\
+        {}\
+        - Techniques: rhythmic displacement, diminution, passing and neighbor tones, \
+        ornaments, register shifts of single notes\n\
+        - Return the complete varied phrase (all of its notes)\n\n",
+        notes_context(theme),
+        freedom
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&timing_constraints(request));
+    prompt
+}
+
+/// Build the system prompt for filling a gap between existing material
+///
+/// The gap need not be whole measures, so its timing replaces the usual
+/// measure-based constraints.
+fn build_infill_system_prompt(request: &MelodyRequest, before: &[Note], after: &[Note], length: f64) -> String {
+    let mut prompt = format!(
+        "You are a professional composer. Fill the gap in the user's piece so the music before \
+        and after it connects smoothly.\n\n\
+        THE MUSIC BEFORE THE GAP (startTime is relative to the start of the gap, so negative):\n{}\n\n\
+        THE MUSIC AFTER THE GAP (starting at beat {}):\n{}\n\n\
+        INFILL RULES:\n\
+        - Write only the missing notes, not the surrounding music\n\
+        - Pick up the phrase where the music before the gap leaves off\n\
+        - Lead into the first notes after the gap (approach their pitch by step where possible)\n\
+        - Keep the key, register, rhythmic feel and texture of the surrounding music\n\
+        - pitch: MIDI note number (0-127); velocity: match the surrounding dynamics\n\n",
+        notes_context(before),
+        length,
+        notes_context(after)
+    );
+
+    prompt.push_str(&scale_requirements(request));
+    prompt.push_str(&format!(
+        "TIMING CONSTRAINTS:\n\
+        - The gap lasts {} beats ({} time)\n\
+        - All notes must fit within the gap: startTime >= 0 and startTime + duration <= {}\n\
+        - Note durations: 0.25 (16th), 0.5 (8th), 1.0 (quarter), 2.0 (half), 4.0 (whole)\n\n",
+        length, request.time_signature, length
+    ));
+    prompt
+}
+
+/// Temperature of critiques: consistent feedback matters more than variety
+const CRITIQUE_TEMPERATURE: f32 = 0.3;
+
+/// Longest `focus` of a critique, in characters
+const MAX_FOCUS_CHARS: usize = 200;
+
+/// Trim a critique focus to `MAX_FOCUS_CHARS` and drop control characters
+fn sanitize_focus(focus: &str) -> String {
+    focus.chars().filter(|c| !c.is_control()).take(MAX_FOCUS_CHARS).collect()
+}
+
+/// Build the system prompt for critiquing the user's melody
+///
+/// Notes are numbered from 1 so suggested edits can point at them.
+fn build_critique_system_prompt(notes: &[Note], scale: Option<&Scale>) -> String {
+    let numbered = notes
+        .iter()
+        .enumerate()
+        .map(|(i, note)| {
+            format!(
+                "{{\"note\": {}, \"pitch\": {}, \"startTime\": {}, \"duration\": {}, \"velocity\": {}}}",
+                i + 1,
+                note.pitch,
+                note.start_time,
+                note.duration,
+                note.velocity
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let key = match scale {
+        Some(scale) => format!("The melody is in {} {}.\n\n", scale.root, scale.mode),
+        None => String::new(),
+    };
+
+    format!(
+        "You are an experienced composition teacher. Review the user's melody and give honest, \
+        specific, encouraging feedback.\n\n\
+        THE MELODY (pitch is a MIDI note number, 60 = middle C; times are in beats):\n{}\n\n{}\
+        FEEDBACK:\n\
+        - summary: Overall impression in 2-3 sentences, strengths first\n\
+        - phrasing: Phrase lengths, breathing points, cadences, contour and climax\n\
+        - harmonyIssues: Clashing simultaneous notes, notes fighting the key, weak implied progressions \
+        (empty if there are none)\n\
+        - suggestedEdits: Up to 8 concrete changes; list the numbers of the notes each applies to in notes \
+        and say exactly what to change (pitch, timing, length or loudness) in suggestion\n\
+        - Refer to notes by number or beat, and to pitches by name (e.g. \"E4\")\n\n",
+        numbered, key
+    )
+}
+
+/// Temperature of title suggestions: varied ideas matter more than consistency
+const TITLE_TEMPERATURE: f32 = 1.0;
+
+/// Name of a MIDI pitch, e.g. "C#4" for 61
+fn pitch_name(pitch: u8) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[(pitch % 12) as usize], (pitch / 12) as i32 - 1)
+}
+
+/// Build the system prompt for suggesting titles
+///
+/// The notes are summarized (length, range, pace, opening) rather than
+/// listed; a title doesn't need every note.
+fn build_title_system_prompt(notes: &[Note], scale: Option<&Scale>, description: Option<&str>) -> String {
+    let mut about = Vec::new();
+    if let Some(description) = description.map(str::trim).filter(|d| !d.is_empty()) {
+        about.push(format!("- Described as: {}", sanitize_focus(description)));
+    }
+    if let Some(scale) = scale {
+        about.push(format!("- Key: {} {}", scale.root, scale.mode));
+    }
+    if !notes.is_empty() {
+        let end = notes.iter().map(|n| n.start_time + n.duration).fold(0.0, f64::max);
+        let lowest = notes.iter().map(|n| n.pitch).min().unwrap_or_default();
+        let highest = notes.iter().map(|n| n.pitch).max().unwrap_or_default();
+        let velocity = notes.iter().map(|n| n.velocity as f64).sum::<f64>() / notes.len() as f64;
+        let mut ordered: Vec<&Note> = notes.iter().collect();
+        ordered.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        let opening: Vec<String> = ordered.iter().take(8).map(|n| pitch_name(n.pitch)).collect();
+
+        about.push(format!("- {} notes over {} beats ({:.1} notes per beat)", notes.len(), end, notes.len() as f64 / end.max(1.0)));
+        about.push(format!("- Range {} to {}", pitch_name(lowest), pitch_name(highest)));
+        about.push(format!("- Average velocity {:.0} of 127", velocity));
+        about.push(format!("- Opens with {}", opening.join(" ")));
+    }
+
+    format!(
+        "You name pieces of music. Suggest evocative, original titles that fit the character \
+        of the user's piece.\n\n\
+        THE PIECE:\n{}\n\n\
+        TITLES:\n\
+        - 1 to 6 words each, without quotes or numbering\n\
+        - Each title different in style (imagery, mood, place, wordplay)\n\
+        - No existing song titles\n",
+        about.join("\n")
+    )
+}
+
 /// Build the user prompt combining the system prompt with the user's request
 pub fn build_user_prompt(request: &MelodyRequest) -> String {
+    if let GenerationMode::Refine { .. } = request.mode {
+        return refine_instruction(&request.prompt);
+    }
     format!(
-        "Create a melody based on this description: {}\n\n\
+        "Create a {} based on this description: {}\n\n\
         Requirements:\n\
         - Measures: {}\n\
         - Scale: {}",
+        request.mode.description(),
         request.prompt,
         request.measures,
         if let Some(scale) = &request.scale {
@@ -319,9 +1351,10 @@ pub fn build_retry_prompt(request: &MelodyRequest, error_message: &str) -> Strin
         "{}\n\n\
         IMPORTANT: The previous attempt failed validation with this error:\n\
         \"{}\"\n\n\
-        Please carefully correct this issue and generate a valid melody that passes all constraints.",
+        Please carefully correct this issue and generate a valid {} that passes all constraints.",
         base_prompt,
-        error_message
+        error_message,
+        request.mode.description()
     )
 }
 
@@ -329,7 +1362,7 @@ pub fn build_retry_prompt(request: &MelodyRequest, error_message: &str) -> Strin
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ai_models::Scale;
+    use crate::ai_models::{Scale, ScaleMode};
 
     #[test]
     fn test_build_system_prompt_with_scale() {
@@ -337,12 +1370,19 @@ mod tests {
             prompt: "Happy melody".to_string(),
             scale: Some(Scale {
                 root: "C".to_string(),
-                mode: "major".to_string(),
+                mode: ScaleMode::Major,
                 octave: Some(4),
             }),
             measures: 4,
             model_provider: crate::ai_models::AIProvider::OpenAI,
             temperature: Some(1.0),
+            seed: None,
+            mode: crate::ai_models::GenerationMode::Melody,
+            start_beat: 0.0,
+            chord_progression: None,
+            time_signature: crate::ai_models::TimeSignature::default(),
+            system_prompt: None,
+            max_tokens: None,
         };
 
         let prompt = build_system_prompt(&request);
@@ -372,6 +1412,13 @@ mod tests {
             measures: 4,
             model_provider: crate::ai_models::AIProvider::OpenAI,
             temperature: Some(0.3),
+            seed: None,
+            mode: crate::ai_models::GenerationMode::Melody,
+            start_beat: 0.0,
+            chord_progression: None,
+            time_signature: crate::ai_models::TimeSignature::default(),
+            system_prompt: None,
+            max_tokens: None,
         };
 
         let prompt = build_system_prompt(&request);
@@ -387,10 +1434,149 @@ mod tests {
             measures: 4,
             model_provider: crate::ai_models::AIProvider::OpenAI,
             temperature: Some(1.8),
+            seed: None,
+            mode: crate::ai_models::GenerationMode::Melody,
+            start_beat: 0.0,
+            chord_progression: None,
+            time_signature: crate::ai_models::TimeSignature::default(),
+            system_prompt: None,
+            max_tokens: None,
         };
 
         let prompt = build_system_prompt(&request);
         assert!(prompt.contains("experiment"));
         assert!(prompt.contains("creative risks"));
     }
+
+    #[test]
+    fn test_continue_prompt_includes_context() {
+        let context = vec![Note {
+            id: "n1".to_string(),
+            pitch: 64,
+            start_time: -2.0,
+            duration: 1.5,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        }];
+        let request = MelodyRequest {
+            prompt: "Build to a climax".to_string(),
+            mode: GenerationMode::Continue { context },
+            ..MelodyRequest::default()
+        };
+
+        let prompt = build_system_prompt(&request);
+        assert!(prompt.contains("{\"pitch\": 64, \"startTime\": -2, \"duration\": 1.5, \"velocity\": 80}"));
+        assert!(build_user_prompt(&request).starts_with("Create a continuation"));
+    }
+
+    #[test]
+    fn test_progression_in_prompt() {
+        let request = MelodyRequest {
+            prompt: "A ballad".to_string(),
+            measures: 2,
+            chord_progression: Some("Cmaj7 | Am7".to_string()),
+            ..MelodyRequest::default()
+        };
+
+        let prompt = build_system_prompt(&request);
+        assert!(prompt.contains("- Beats 0-4: Cmaj7\n- Beats 4-8: Am7"));
+        assert!(!build_system_prompt(&MelodyRequest::default()).contains("CHORD PROGRESSION"));
+    }
+
+    #[test]
+    fn test_time_signature_in_prompt() {
+        let request = MelodyRequest {
+            prompt: "A waltz".to_string(),
+            measures: 4,
+            chord_progression: Some("C | G7".to_string()),
+            time_signature: TimeSignature { numerator: 3, denominator: 4 },
+            ..MelodyRequest::default()
+        };
+
+        let prompt = build_system_prompt(&request);
+        assert!(prompt.contains("4 measures (12 beats total in 3/4 time)"));
+        assert!(prompt.contains("- Beats 3-6: G7"));
+        assert!(prompt.contains("(beat 1 of each measure: startTime 0, 3, ...)"));
+    }
+
+    #[test]
+    fn test_custom_system_prompt_keeps_requirements() {
+        let custom = |mode| {
+            Some(crate::ai_models::CustomSystemPrompt { text: "You write film cues.\u{0}".to_string(), mode })
+        };
+        let request = MelodyRequest {
+            prompt: "A chase".to_string(),
+            chord_progression: Some("Am | F".to_string()),
+            system_prompt: custom(SystemPromptMode::Replace),
+            ..MelodyRequest::default()
+        };
+
+        let replaced = build_system_prompt(&request);
+        assert!(replaced.starts_with("You write film cues.\n\nREQUIRED NOTE FORMAT"));
+        assert!(!replaced.contains("professional melody composer"));
+        assert!(replaced.contains("TIMING CONSTRAINTS"));
+        assert!(replaced.contains("CHORD PROGRESSION"));
+
+        let appended = build_system_prompt(&MelodyRequest { system_prompt: custom(SystemPromptMode::Append), ..request.clone() });
+        assert!(appended.starts_with("You are a professional melody composer"));
+        assert!(appended.contains("ADDITIONAL INSTRUCTIONS (from the user):\nYou write film cues.\n"));
+
+        // Modes working on the user's notes keep their prompt, which carries those notes
+        let continued = MelodyRequest { mode: GenerationMode::Continue { context: Vec::new() }, ..request };
+        assert!(build_system_prompt(&continued).contains("ADDITIONAL INSTRUCTIONS"));
+    }
+
+    #[test]
+    fn test_refinement_sends_earlier_turns() {
+        let note = |pitch: u8| Note {
+            id: pitch.to_string(),
+            pitch,
+            start_time: 0.0,
+            duration: 1.0,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        };
+        let turn = |instruction: &str, pitch: u8| RefineTurn { instruction: instruction.to_string(), notes: vec![note(pitch)] };
+        let request = MelodyRequest {
+            prompt: "less jumpy".to_string(),
+            mode: GenerationMode::Refine { history: vec![turn("A hopeful tune", 60), turn("make bar 1 busier", 62)] },
+            ..MelodyRequest::default()
+        };
+
+        let prompt = AIPrompt::for_request(&request, None);
+        let messages = prompt.messages();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0].content, "Create a melody based on this description: A hopeful tune");
+        assert_eq!(messages[1].role, ChatRole::Assistant);
+        assert!(messages[3].content.contains("\"pitch\": 62"));
+        assert_eq!(messages[4].content, "Edit your latest melody: less jumpy");
+        assert!(prompt.system.contains("bar n spans startTime 4 * (n - 1) to 4 * n"));
+
+        assert!(AIPrompt::for_request(&MelodyRequest::default(), None).history.is_empty());
+    }
+
+    #[test]
+    fn test_critique_numbers_notes() {
+        let note = |id: &str, pitch: u8, start_time: f64| Note {
+            id: id.to_string(),
+            pitch,
+            start_time,
+            duration: 1.0,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        };
+        let notes = [note("a", 60, 0.0), note("b", 64, 1.0)];
+
+        let prompt = AIPrompt::for_critique(&notes, None, Some("  the ending\n "));
+        assert!(prompt.system.contains("{\"note\": 2, \"pitch\": 64, \"startTime\": 1,"));
+        assert_eq!(prompt.user, "Critique this melody, focusing on: the ending");
+        assert_eq!(AIPrompt::for_critique(&notes, None, Some("")).user, "Critique this melody.");
+    }
+
+    #[test]
+    fn test_left_hand_pattern_follows_style() {
+        assert!(left_hand_pattern("Classical, Alberti bass").starts_with("Alberti bass"));
+        assert!(left_hand_pattern("Jazzy STRIDE").starts_with("stride"));
+        assert!(left_hand_pattern("Something simple").starts_with("block chords"));
+    }
 }
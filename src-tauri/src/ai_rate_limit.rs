@@ -0,0 +1,219 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Minimum time between the starts of two requests to the same provider
+const MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Most generations in flight at once against the same provider
+const MAX_IN_FLIGHT: usize = 3;
+
+/// Wait used when a 429 response has no (valid) `Retry-After` header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Longest `Retry-After` honored; longer waits fail instead of leaving the user queued
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// A provider answered 429 Too Many Requests
+#[derive(Debug)]
+pub struct RateLimited {
+    pub message: String,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Parse a `Retry-After` header: delay in seconds or an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Default wait for a 429 response without a usable `Retry-After`
+pub fn retry_after_or_default(header: Option<&str>) -> Duration {
+    header.and_then(parse_retry_after).unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
+/// Payload of the `generation-queued` event
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationQueuedEvent {
+    pub request_id: String,
+    /// Generations ahead of this one for the same provider
+    pub position: usize,
+}
+
+/// Queue of one provider: up to `MAX_IN_FLIGHT` requests at once, their starts spaced by `MIN_INTERVAL`
+struct ProviderQueue {
+    /// Turns to have a request in flight
+    slots: Arc<Semaphore>,
+    /// Earliest time the next request may start
+    next_start: Mutex<Instant>,
+    /// Generations waiting or in flight
+    queued: AtomicUsize,
+}
+
+/// Place in a provider's queue, given up when dropped (even while still waiting for a slot)
+struct Ticket {
+    queue: Arc<ProviderQueue>,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.queue.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Turn to send requests to a provider; another generation may start once dropped
+pub struct Permit {
+    _slot: OwnedSemaphorePermit,
+    ticket: Ticket,
+}
+
+impl Permit {
+    /// Hold back every request to this provider until `retry_after` has passed
+    pub fn back_off(&mut self, retry_after: Duration) {
+        let mut next_start = self.ticket.queue.next_start.lock().unwrap();
+        *next_start = (*next_start).max(Instant::now() + retry_after);
+    }
+
+    /// Wait until the provider may be called, taking the next start time
+    pub async fn ready(&self) {
+        let start = {
+            let mut next_start = self.ticket.queue.next_start.lock().unwrap();
+            let start = (*next_start).max(Instant::now());
+            *next_start = start + MIN_INTERVAL;
+            start
+        };
+        tokio::time::sleep_until(start.into()).await;
+    }
+}
+
+/// Per-provider request queues, so rapid-fire generations don't trigger 429s
+///
+/// Up to `MAX_IN_FLIGHT` generations run against the same provider at once,
+/// the rest wait in arrival order (tokio's semaphore is fair); different
+/// providers don't wait for each other.
+pub struct RateLimiter {
+    queues: Mutex<HashMap<String, Arc<ProviderQueue>>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Join the queue of `provider`; `on_queued` gets the number of generations
+    /// that must finish first, if any
+    pub async fn acquire(&self, provider: &str, on_queued: impl FnOnce(usize)) -> Permit {
+        let queue = self
+            .queues
+            .lock()
+            .unwrap()
+            .entry(provider.to_string())
+            .or_insert_with(|| {
+                Arc::new(ProviderQueue {
+                    slots: Arc::new(Semaphore::new(MAX_IN_FLIGHT)),
+                    next_start: Mutex::new(Instant::now()),
+                    queued: AtomicUsize::new(0),
+                })
+            })
+            .clone();
+
+        let ahead = queue.queued.fetch_add(1, Ordering::SeqCst);
+        let ticket = Ticket { queue };
+        if ahead >= MAX_IN_FLIGHT {
+            on_queued(ahead + 1 - MAX_IN_FLIGHT);
+        }
+
+        let slot = ticket.queue.slots.clone().acquire_owned().await.expect("provider queues are never closed");
+        let permit = Permit { _slot: slot, ticket };
+        permit.ready().await;
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("12"), Some(Duration::from_secs(12)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(retry_after_or_default(None), DEFAULT_RETRY_AFTER);
+    }
+
+    #[tokio::test]
+    async fn test_queue_positions() {
+        let limiter = RateLimiter::new();
+        let started = Instant::now();
+        let mut in_flight = Vec::new();
+        for _ in 0..MAX_IN_FLIGHT {
+            in_flight.push(limiter.acquire("openai", |_| panic!("a free slot isn't queued")).await);
+        }
+        // Requests overlap, but their starts are still spaced
+        assert!(started.elapsed() >= MIN_INTERVAL * (MAX_IN_FLIGHT as u32 - 1) - Duration::from_millis(50));
+
+        let position = std::cell::Cell::new(None);
+        let waiting = limiter.acquire("openai", |ahead| position.set(Some(ahead)));
+        tokio::pin!(waiting);
+        assert!(futures_poll_once(waiting.as_mut()).await.is_none());
+        assert_eq!(position.get(), Some(1));
+
+        // Other providers have their own queue
+        let _other = limiter.acquire("gemini", |_| panic!("gemini isn't busy")).await;
+
+        in_flight.pop();
+        let _next = waiting.await;
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_wait_leaves_queue() {
+        let limiter = RateLimiter::new();
+        let mut in_flight = Vec::new();
+        for _ in 0..MAX_IN_FLIGHT {
+            in_flight.push(limiter.acquire("openai", |_| {}).await);
+        }
+
+        // A generation cancelled while queued gives up its place
+        {
+            let waiting = limiter.acquire("openai", |_| {});
+            tokio::pin!(waiting);
+            assert!(futures_poll_once(waiting.as_mut()).await.is_none());
+        }
+
+        let position = std::cell::Cell::new(None);
+        let waiting = limiter.acquire("openai", |ahead| position.set(Some(ahead)));
+        tokio::pin!(waiting);
+        assert!(futures_poll_once(waiting.as_mut()).await.is_none());
+        assert_eq!(position.get(), Some(1));
+    }
+
+    /// Poll a future once, returning its output if it's already ready
+    async fn futures_poll_once<F: std::future::Future + Unpin>(future: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            output = future => Some(output),
+            _ = std::future::ready(()) => None,
+        }
+    }
+}
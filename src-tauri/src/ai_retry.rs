@@ -0,0 +1,134 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use validator::Validate;
+
+/// How often a generation is retried, and how long to wait in between
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, PartialEq)]
+pub struct RetryPolicy {
+    /// Extra attempts, with the validation error as feedback, when the notes don't fit the request
+    #[validate(range(min = 0, max = 5))]
+    pub max_validation_retries: u32,
+
+    /// Extra attempts after network errors and 5xx responses
+    #[validate(range(min = 0, max = 10))]
+    pub max_network_retries: u32,
+
+    /// Wait before the first network retry; doubles with every further retry
+    #[validate(range(min = 100, max = 10000))]
+    pub base_delay_ms: u64,
+
+    /// Upper bound of the wait between network retries
+    #[validate(range(min = 100, max = 60000))]
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_validation_retries: 1,
+            max_network_retries: 2,
+            base_delay_ms: 500,
+            max_delay_ms: 8000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before network retry number `retry` (1-based): exponential with "equal jitter"
+    ///
+    /// Half the delay is fixed and half random, so clients that failed
+    /// together don't retry in lockstep.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self.base_delay_ms.saturating_mul(1 << retry.saturating_sub(1).min(16));
+        let capped = exponential.min(self.max_delay_ms);
+        let jitter = rand::thread_rng().gen_range(0..=capped / 2);
+        Duration::from_millis(capped - capped / 2 + jitter)
+    }
+}
+
+/// A provider answered with a 5xx status
+#[derive(Debug)]
+pub struct ServerError {
+    pub message: String,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// A provider answered 401 Unauthorized: the API key is wrong or revoked
+#[derive(Debug)]
+pub struct Unauthorized {
+    pub message: String,
+}
+
+impl fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Unauthorized {}
+
+/// Whether an error is worth retrying as is: connection problems, timeouts and server errors
+///
+/// Client errors (bad key, invalid request) and unparseable output fail the same way again.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if cause.is::<ServerError>() {
+            return true;
+        }
+        match cause.downcast_ref::<reqwest::Error>() {
+            Some(e) => e.is_connect() || e.is_timeout() || (e.is_request() && !e.is_builder()),
+            None => false,
+        }
+    })
+}
+
+/// Whether the provider turned the API key down: unauthorized (401) or rate limited (429)
+///
+/// Another of the provider's keys may get through (see `ProviderConfig::rotate_keys`).
+pub fn is_key_rejected(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<Unauthorized>() || cause.is::<crate::ai_rate_limit::RateLimited>())
+}
+
+/// Whether the provider couldn't be reached at all (offline, DNS failure, no answer in time)
+pub fn is_unreachable(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| match cause.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.is_connect() || e.is_timeout(),
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy { base_delay_ms: 1000, max_delay_ms: 3000, ..RetryPolicy::default() };
+
+        for _ in 0..20 {
+            let first = policy.backoff(1).as_millis();
+            assert!((500..=1000).contains(&first));
+            let second = policy.backoff(2).as_millis();
+            assert!((1000..=2000).contains(&second));
+            let capped = policy.backoff(10).as_millis();
+            assert!((1500..=3000).contains(&capped));
+        }
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        let server = anyhow::Error::new(ServerError { message: "OpenAI API error (503)".to_string() });
+        assert!(is_transient(&server));
+        assert!(is_transient(&server.context("Failed to generate")));
+        assert!(!is_transient(&anyhow::anyhow!("OpenAI API error (401): invalid key")));
+    }
+}
@@ -0,0 +1,311 @@
+use crate::ai_models::{GenerationMode, MelodyRequest, PartRole};
+use crate::drum_kit::GM_DRUM_MAP;
+use serde_json::{json, Map, Value};
+
+/// Schema of a note in a reply
+fn note_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "pitch": {
+                "type": "integer",
+                "minimum": 0,
+                "maximum": 127
+            },
+            "startTime": {
+                "type": "number",
+                "minimum": 0.0
+            },
+            "duration": {
+                "type": "number",
+                "minimum": 0.01
+            },
+            "velocity": {
+                "type": "integer",
+                "minimum": 0,
+                "maximum": 127
+            }
+        },
+        "required": ["pitch", "startTime", "duration", "velocity"]
+    })
+}
+
+/// Schema of a chord symbol in a reply
+fn chord_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "symbol": {
+                "type": "string"
+            },
+            "startTime": {
+                "type": "number",
+                "minimum": 0.0
+            },
+            "duration": {
+                "type": "number",
+                "minimum": 0.01
+            }
+        },
+        "required": ["symbol", "startTime", "duration"]
+    })
+}
+
+/// Schema of a drum hit: a note whose pitch is limited to the General MIDI drum map
+fn drum_note_schema() -> Value {
+    let lowest = GM_DRUM_MAP.iter().map(|sound| sound.pitch).min().unwrap_or(35);
+    let highest = GM_DRUM_MAP.iter().map(|sound| sound.pitch).max().unwrap_or(81);
+
+    let mut schema = note_schema();
+    schema["properties"]["pitch"] = json!({
+        "type": "integer",
+        "minimum": lowest,
+        "maximum": highest
+    });
+    schema
+}
+
+/// Schema of a note in an arrangement, tagged with the part it belongs to
+fn part_note_schema() -> Value {
+    let roles: Vec<&str> = PartRole::ALL.iter().map(|role| role.name()).collect();
+
+    let mut schema = note_schema();
+    schema["properties"]["role"] = json!({
+        "type": "string",
+        "enum": roles
+    });
+    schema["required"] = json!(["pitch", "startTime", "duration", "velocity", "role"]);
+    schema
+}
+
+/// Schema of a crescendo or decrescendo in a reply
+fn hairpin_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "shape": {
+                "type": "string",
+                "enum": ["crescendo", "decrescendo"]
+            },
+            "startTime": {
+                "type": "number",
+                "minimum": 0.0
+            },
+            "duration": {
+                "type": "number",
+                "minimum": 0.01
+            },
+            "fromVelocity": {
+                "type": "integer",
+                "minimum": 1,
+                "maximum": 127
+            },
+            "toVelocity": {
+                "type": "integer",
+                "minimum": 1,
+                "maximum": 127
+            }
+        },
+        "required": ["shape", "startTime", "duration", "fromVelocity", "toVelocity"]
+    })
+}
+
+/// Schema of a span the sustain pedal is held down in a reply
+fn pedal_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "startTime": {
+                "type": "number",
+                "minimum": 0.0
+            },
+            "duration": {
+                "type": "number",
+                "minimum": 0.01
+            }
+        },
+        "required": ["startTime", "duration"]
+    })
+}
+
+fn array_of(items: Value) -> Value {
+    json!({
+        "type": "array",
+        "items": items
+    })
+}
+
+/// JSON schema the reply to a request must match
+///
+/// Written in the subset every provider accepts (inline, without `$ref`,
+/// `format` or `additionalProperties`); `strict_schema` adapts it for
+/// OpenAI's strict structured outputs. Every property is required.
+pub fn response_schema(request: &MelodyRequest) -> Value {
+    let mut properties = Map::new();
+    let notes = match request.mode {
+        GenerationMode::Drums { .. } => drum_note_schema(),
+        GenerationMode::Arrange => part_note_schema(),
+        _ => note_schema(),
+    };
+    properties.insert("notes".to_string(), array_of(notes));
+
+    if let GenerationMode::Chords = request.mode {
+        properties.insert("chords".to_string(), array_of(chord_schema()));
+    }
+    if request.mode.has_expression() {
+        properties.insert("dynamics".to_string(), array_of(hairpin_schema()));
+        properties.insert("pedal".to_string(), array_of(pedal_schema()));
+    }
+
+    let required: Vec<String> = properties.keys().cloned().collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required
+    })
+}
+
+/// JSON schema of a critique reply (see `MelodyCritique`)
+///
+/// Suggested edits refer to notes by their number in the prompt.
+pub fn critique_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "summary": {
+                "type": "string"
+            },
+            "phrasing": array_of(json!({ "type": "string" })),
+            "harmonyIssues": array_of(json!({ "type": "string" })),
+            "suggestedEdits": array_of(json!({
+                "type": "object",
+                "properties": {
+                    "notes": array_of(json!({ "type": "integer", "minimum": 1 })),
+                    "suggestion": {
+                        "type": "string"
+                    }
+                },
+                "required": ["notes", "suggestion"]
+            }))
+        },
+        "required": ["summary", "phrasing", "harmonyIssues", "suggestedEdits"]
+    })
+}
+
+/// Name of the critique output (the tool name for Anthropic)
+pub const CRITIQUE_SCHEMA_NAME: &str = "melody_critique";
+
+/// JSON schema of a title suggestion reply
+pub fn title_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "titles": array_of(json!({ "type": "string" }))
+        },
+        "required": ["titles"]
+    })
+}
+
+/// Name of the title suggestion output (the tool name for Anthropic)
+pub const TITLE_SCHEMA_NAME: &str = "project_titles";
+
+/// Name of the structured output (the tool name for Anthropic)
+pub fn schema_name(mode: &GenerationMode) -> &'static str {
+    match mode {
+        GenerationMode::Melody => "melody_generation",
+        GenerationMode::Chords => "chord_progression",
+        GenerationMode::Harmonize { .. } => "harmonization",
+        GenerationMode::Continue { .. } => "continuation",
+        GenerationMode::Variation { .. } => "variation",
+        GenerationMode::Infill { .. } => "infill",
+        GenerationMode::LeftHand { .. } => "left_hand_accompaniment",
+        GenerationMode::Bass { .. } => "bass_line",
+        GenerationMode::Drums { .. } => "drum_pattern",
+        GenerationMode::Arrange => "arrangement",
+        GenerationMode::Restyle { .. } => "style_transfer",
+        GenerationMode::Motif { .. } => "motif_development",
+        GenerationMode::Section { .. } => "song_section",
+        GenerationMode::Lyrics { .. } => "lyrics_melody",
+        GenerationMode::Refine { .. } => "melody_edit",
+    }
+}
+
+/// Schema for OpenAI strict mode, which requires every object to be closed
+pub fn strict_schema(schema: &Value) -> Value {
+    let mut schema = schema.clone();
+    close_objects(&mut schema);
+    schema
+}
+
+fn close_objects(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            if object.get("type").and_then(Value::as_str) == Some("object") {
+                object.insert("additionalProperties".to_string(), json!(false));
+            }
+            for (_key, child) in object.iter_mut() {
+                close_objects(child);
+            }
+        }
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                close_objects(item);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chords_schema_requires_chords() {
+        let request = MelodyRequest {
+            mode: GenerationMode::Chords,
+            ..MelodyRequest::default()
+        };
+        let schema = response_schema(&request);
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&json!("chords")));
+        assert!(required.contains(&json!("notes")));
+
+        let melody = response_schema(&MelodyRequest::default());
+        assert!(!melody["required"].as_array().unwrap().contains(&json!("chords")));
+    }
+
+    #[test]
+    fn test_expression_only_for_piano_parts() {
+        let melody = response_schema(&MelodyRequest::default());
+        let required = melody["required"].as_array().unwrap();
+        assert!(required.contains(&json!("dynamics")));
+        assert!(required.contains(&json!("pedal")));
+        assert_eq!(melody["properties"]["dynamics"]["items"]["properties"]["shape"]["enum"], json!(["crescendo", "decrescendo"]));
+
+        let drums = MelodyRequest {
+            mode: GenerationMode::Drums { context: Vec::new() },
+            ..MelodyRequest::default()
+        };
+        assert_eq!(response_schema(&drums)["required"], json!(["notes"]));
+    }
+
+    #[test]
+    fn test_strict_schema_closes_every_object() {
+        let strict = strict_schema(&response_schema(&MelodyRequest::default()));
+        assert_eq!(strict["additionalProperties"], json!(false));
+        assert_eq!(strict["properties"]["notes"]["items"]["additionalProperties"], json!(false));
+        assert!(strict["properties"]["notes"].get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn test_arrangement_notes_require_role() {
+        let request = MelodyRequest {
+            mode: GenerationMode::Arrange,
+            ..MelodyRequest::default()
+        };
+        let note = &response_schema(&request)["properties"]["notes"]["items"];
+        assert_eq!(note["properties"]["role"]["enum"], json!(["melody", "harmony", "bass"]));
+        assert!(note["required"].as_array().unwrap().contains(&json!("role")));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::ai_models::Note;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Weights of the criteria in the overall score (they add up to 1)
+const CONTOUR_WEIGHT: f64 = 0.3;
+const RHYTHM_WEIGHT: f64 = 0.25;
+const CONSONANCE_WEIGHT: f64 = 0.3;
+const RANGE_WEIGHT: f64 = 0.15;
+
+/// Span in semitones that sounds like a complete melody, neither cramped nor sprawling
+const IDEAL_RANGE: std::ops::RangeInclusive<i32> = 7..=19;
+
+/// Span at which the range score drops to 0
+const MAX_RANGE: i32 = 36;
+
+/// How a melody rates on each criterion, from 0.0 to 1.0 (higher is better)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MelodyScore {
+    /// Mostly stepwise motion, few wide leaps, and a climax inside the phrase
+    pub contour: f64,
+    /// Mix of note lengths
+    pub rhythmic_variety: f64,
+    /// Share of simultaneous notes not forming a minor second, tritone or major seventh
+    pub consonance: f64,
+    /// Span of the melody compared to a comfortable octave and a half
+    pub range: f64,
+    /// Weighted mean of the criteria, to rank candidates by
+    pub overall: f64,
+}
+
+/// Rate a generated melody
+pub fn score(notes: &[Note]) -> MelodyScore {
+    if notes.is_empty() {
+        return MelodyScore { contour: 0.0, rhythmic_variety: 0.0, consonance: 0.0, range: 0.0, overall: 0.0 };
+    }
+
+    let contour = contour_score(&melody_line(notes));
+    let rhythmic_variety = rhythm_score(notes);
+    let consonance = consonance_score(notes);
+    let range = range_score(notes);
+    MelodyScore {
+        contour,
+        rhythmic_variety,
+        consonance,
+        range,
+        overall: contour * CONTOUR_WEIGHT
+            + rhythmic_variety * RHYTHM_WEIGHT
+            + consonance * CONSONANCE_WEIGHT
+            + range * RANGE_WEIGHT,
+    }
+}
+
+/// Pitches of the line heard on top: the highest note at each start time
+fn melody_line(notes: &[Note]) -> Vec<i32> {
+    let mut sorted: Vec<&Note> = notes.iter().collect();
+    sorted.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(b.pitch.cmp(&a.pitch)));
+
+    let mut line: Vec<&Note> = Vec::new();
+    for note in sorted {
+        match line.last() {
+            Some(last) if (last.start_time - note.start_time).abs() < 0.01 => {}
+            _ => line.push(note),
+        }
+    }
+    line.iter().map(|note| note.pitch as i32).collect()
+}
+
+fn contour_score(line: &[i32]) -> f64 {
+    if line.len() < 2 {
+        return 0.0;
+    }
+
+    let moves: Vec<i32> = line.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let steps = moves.iter().filter(|step| step.abs() <= 2).count() as f64;
+    let wide_leaps = moves.iter().filter(|step| step.abs() > 12).count() as f64;
+    let step_share = steps / moves.len() as f64;
+    let smoothness = 1.0 - wide_leaps / moves.len() as f64;
+
+    // A climax at the very start or end leaves the phrase without an arc
+    let highest = line.iter().max().copied().unwrap_or_default();
+    let climax = line.iter().position(|&pitch| pitch == highest).unwrap_or_default();
+    let arc = if climax > 0 && climax < line.len() - 1 { 1.0 } else { 0.5 };
+
+    0.5 * step_share + 0.3 * smoothness + 0.2 * arc
+}
+
+/// Normalized entropy of the note lengths (rounded to 16ths); 1.0 for an even mix of four or more
+fn rhythm_score(notes: &[Note]) -> f64 {
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for note in notes {
+        *counts.entry((note.duration * 4.0).round() as i64).or_default() += 1;
+    }
+
+    let total = notes.len() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.ln()
+        })
+        .sum();
+    (entropy / 4f64.ln()).min(1.0)
+}
+
+fn consonance_score(notes: &[Note]) -> f64 {
+    let mut pairs = 0;
+    let mut dissonant = 0;
+    for (i, note) in notes.iter().enumerate() {
+        for other in &notes[i + 1..] {
+            let overlaps = note.start_time < other.start_time + other.duration
+                && other.start_time < note.start_time + note.duration;
+            if !overlaps {
+                continue;
+            }
+
+            pairs += 1;
+            let interval = (note.pitch as i32 - other.pitch as i32).abs() % 12;
+            if matches!(interval, 1 | 6 | 11) {
+                dissonant += 1;
+            }
+        }
+    }
+
+    if pairs == 0 {
+        1.0
+    } else {
+        1.0 - dissonant as f64 / pairs as f64
+    }
+}
+
+fn range_score(notes: &[Note]) -> f64 {
+    let lowest = notes.iter().map(|note| note.pitch as i32).min().unwrap_or_default();
+    let highest = notes.iter().map(|note| note.pitch as i32).max().unwrap_or_default();
+    let span = highest - lowest;
+
+    if span < *IDEAL_RANGE.start() {
+        span as f64 / *IDEAL_RANGE.start() as f64
+    } else if span <= *IDEAL_RANGE.end() {
+        1.0
+    } else {
+        (1.0 - (span - IDEAL_RANGE.end()) as f64 / (MAX_RANGE - IDEAL_RANGE.end()) as f64).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(notes: &[(u8, f64)]) -> Vec<Note> {
+        let mut start = 0.0;
+        notes
+            .iter()
+            .enumerate()
+            .map(|(i, &(pitch, duration))| {
+                let note = Note {
+                    id: i.to_string(),
+                    pitch,
+                    start_time: start,
+                    duration,
+                    velocity: 80,
+                    track_id: "track_right_hand".to_string(),
+                };
+                start += duration;
+                note
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_singable_melody_beats_random_leaps() {
+        let singable = line(&[(60, 1.0), (62, 0.5), (64, 0.5), (67, 1.0), (65, 0.5), (64, 0.5), (62, 2.0), (60, 2.0)]);
+        let leaping = line(&[(40, 1.0), (90, 1.0), (41, 1.0), (89, 1.0), (42, 1.0), (88, 1.0)]);
+
+        let good = score(&singable);
+        let bad = score(&leaping);
+        assert!(good.contour > bad.contour);
+        assert!(good.rhythmic_variety > bad.rhythmic_variety);
+        assert_eq!(good.range, 1.0);
+        assert_eq!(bad.range, 0.0);
+        assert!(good.overall > bad.overall);
+    }
+
+    #[test]
+    fn test_clusters_lower_consonance() {
+        let mut chord = line(&[(60, 2.0)]);
+        chord.extend(line(&[(64, 2.0)]));
+        chord.extend(line(&[(67, 2.0)]));
+        assert_eq!(consonance_score(&chord), 1.0);
+
+        chord.extend(line(&[(61, 2.0)]));
+        // C# against C and G, but a minor third from E
+        assert!((consonance_score(&chord) - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(score(&[]).overall, 0.0);
+    }
+}
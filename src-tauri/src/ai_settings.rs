@@ -0,0 +1,317 @@
+use crate::ai_models::{AIProvider, CustomSystemPrompt};
+use crate::ai_retry::RetryPolicy;
+use crate::api_key_storage::Secret;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use validator::{Validate, ValidationError};
+
+/// Proxy for AI requests (HTTP, HTTPS or SOCKS5)
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, PartialEq)]
+pub struct ProxyConfig {
+    /// e.g. "http://proxy.corp:8080" or "socks5://127.0.0.1:1080"
+    #[validate(url, custom(function = "validate_proxy_scheme"))]
+    pub url: String,
+
+    #[validate(length(min = 1, max = 200))]
+    pub username: Option<String>,
+
+    /// Only passed in from the UI; kept encrypted by the API key manager, never in this file
+    #[serde(default, skip_serializing, deserialize_with = "deserialize_secret")]
+    pub password: Option<Secret>,
+}
+
+/// Read a password straight into a `Secret`, so no plain copy is left behind
+fn deserialize_secret<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Secret>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.map(Secret::new))
+}
+
+fn validate_proxy_scheme(url: &str) -> Result<(), ValidationError> {
+    let scheme = url.split("://").next().unwrap_or_default().to_lowercase();
+    match scheme.as_str() {
+        "http" | "https" | "socks5" | "socks5h" => Ok(()),
+        _ => Err(ValidationError::new("unsupported_proxy_scheme")),
+    }
+}
+
+/// Per-provider connection settings; unset fields use the client's defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct ProviderConfig {
+    /// Server URL (e.g. a local Ollama server or an Azure resource endpoint)
+    #[validate(url)]
+    pub base_url: Option<String>,
+
+    /// Model name (e.g. "llama3.1")
+    #[validate(length(min = 1, max = 200))]
+    pub model: Option<String>,
+
+    /// ONNX file of the local provider's model
+    #[validate(length(min = 1, max = 1000))]
+    pub model_path: Option<String>,
+
+    /// Azure OpenAI deployment name
+    #[validate(length(min = 1, max = 200))]
+    pub deployment: Option<String>,
+
+    /// Azure OpenAI API version (e.g. "2024-10-21")
+    #[validate(length(min = 1, max = 50))]
+    pub api_version: Option<String>,
+
+    /// Cloud region (Bedrock "us-east-1", Vertex AI "us-central1")
+    #[validate(length(min = 1, max = 30))]
+    pub region: Option<String>,
+
+    /// Google Cloud project of Vertex AI (defaults to the service account's project)
+    #[validate(length(min = 1, max = 100))]
+    pub project_id: Option<String>,
+
+    /// Time allowed to connect to the provider, in seconds (default 10)
+    #[validate(range(min = 1, max = 120))]
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Time allowed for a whole request, in seconds (default 180)
+    #[validate(range(min = 5, max = 900))]
+    pub timeout_secs: Option<u64>,
+
+    /// Temperature of generations that don't give one (0.0-2.0)
+    #[validate(range(min = 0.0, max = 2.0))]
+    pub temperature: Option<f32>,
+
+    /// Most tokens a reply may use (4096 where the API needs a limit, otherwise the provider's own)
+    #[validate(range(min = 256, max = 32768))]
+    pub max_tokens: Option<u32>,
+
+    /// Retry counts and backoff (defaults if unset)
+    #[validate(nested)]
+    pub retry: Option<RetryPolicy>,
+
+    /// Proxy for this provider, overriding the global one
+    #[validate(nested)]
+    pub proxy: Option<ProxyConfig>,
+
+    /// Connect directly, ignoring the global proxy (e.g. for a local Ollama server)
+    #[serde(default)]
+    pub no_proxy: bool,
+
+    /// Switch to the provider's next saved key when one is unauthorized (401) or rate limited (429)
+    #[serde(default)]
+    pub rotate_keys: bool,
+}
+
+/// Contents of ai_settings.json
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredSettings {
+    #[serde(default)]
+    providers: HashMap<String, ProviderConfig>,
+    /// Proxy used by every provider without its own
+    #[serde(default)]
+    proxy: Option<ProxyConfig>,
+    /// System prompt instructions for generations without their own
+    #[serde(default)]
+    system_prompt: Option<CustomSystemPrompt>,
+}
+
+/// ai_settings.json as written by this version, or the earlier plain provider map
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SettingsFile {
+    Current(StoredSettings),
+    Legacy(HashMap<String, ProviderConfig>),
+}
+
+/// AI provider settings, persisted in the app data directory
+pub struct AiSettings {
+    storage_path: PathBuf,
+    settings: Mutex<StoredSettings>,
+}
+
+impl AiSettings {
+    /// Load saved provider settings
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let storage_path = app_data_dir.join("ai_settings.json");
+
+        let settings = if storage_path.exists() {
+            let data = fs::read_to_string(&storage_path)
+                .map_err(|e| format!("Failed to read AI settings: {}", e))?;
+            match serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse AI settings: {}", e))?
+            {
+                SettingsFile::Current(settings) => settings,
+                SettingsFile::Legacy(providers) => StoredSettings { providers, proxy: None, system_prompt: None },
+            }
+        } else {
+            StoredSettings::default()
+        };
+
+        Ok(Self {
+            storage_path,
+            settings: Mutex::new(settings),
+        })
+    }
+
+    fn save(&self, settings: &StoredSettings) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(settings)
+            .map_err(|e| format!("Failed to serialize AI settings: {}", e))?;
+        fs::write(&self.storage_path, data)
+            .map_err(|e| format!("Failed to write AI settings: {}", e))
+    }
+
+    /// Settings of a provider (defaults if never configured)
+    pub fn provider(&self, provider: &AIProvider) -> ProviderConfig {
+        self.settings
+            .lock()
+            .unwrap()
+            .providers
+            .get(provider.as_str())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replace the settings of a provider
+    pub fn set_provider(&self, provider: &AIProvider, config: ProviderConfig) -> Result<(), String> {
+        config.validate()
+            .map_err(|e| format!("Invalid provider settings: {}", e))?;
+
+        let mut settings = self.settings.lock().unwrap();
+        settings.providers.insert(provider.as_str().to_string(), config);
+        self.save(&settings)
+    }
+
+    /// Proxy used by providers without their own
+    pub fn proxy(&self) -> Option<ProxyConfig> {
+        self.settings.lock().unwrap().proxy.clone()
+    }
+
+    /// Set or clear the global proxy
+    pub fn set_proxy(&self, proxy: Option<ProxyConfig>) -> Result<(), String> {
+        if let Some(proxy) = &proxy {
+            proxy.validate()
+                .map_err(|e| format!("Invalid proxy settings: {}", e))?;
+        }
+
+        let mut settings = self.settings.lock().unwrap();
+        settings.proxy = proxy;
+        self.save(&settings)
+    }
+
+    /// Custom system prompt used by generations without their own
+    pub fn system_prompt(&self) -> Option<CustomSystemPrompt> {
+        self.settings.lock().unwrap().system_prompt.clone()
+    }
+
+    /// Set or clear the custom system prompt (blank text clears it)
+    pub fn set_system_prompt(&self, system_prompt: Option<CustomSystemPrompt>) -> Result<(), String> {
+        let system_prompt = system_prompt.filter(|custom| !custom.text.trim().is_empty());
+        if let Some(custom) = &system_prompt {
+            custom.validate()
+                .map_err(|e| format!("Invalid system prompt: {}", e))?;
+        }
+
+        let mut settings = self.settings.lock().unwrap();
+        settings.system_prompt = system_prompt;
+        self.save(&settings)
+    }
+
+    /// Select the model of a provider (`None` returns to the client's default)
+    pub fn set_model(&self, provider: &AIProvider, model: Option<String>) -> Result<(), String> {
+        let config = ProviderConfig {
+            model: model.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()),
+            ..self.provider(provider)
+        };
+        self.set_provider(provider, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_provider_settings_persist() {
+        let temp_dir = env::temp_dir().join("piano-app-test-ai-settings");
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let settings = AiSettings::new(temp_dir.clone()).unwrap();
+        let config = ProviderConfig {
+            base_url: Some("http://localhost:11434".to_string()),
+            model: Some("llama3.1".to_string()),
+            ..ProviderConfig::default()
+        };
+        settings.set_provider(&AIProvider::Ollama, config).unwrap();
+        assert!(settings
+            .set_provider(&AIProvider::Ollama, ProviderConfig { base_url: Some("not a url".to_string()), ..ProviderConfig::default() })
+            .is_err());
+
+        let reloaded = AiSettings::new(temp_dir.clone()).unwrap();
+        assert_eq!(reloaded.provider(&AIProvider::Ollama).model.as_deref(), Some("llama3.1"));
+        assert!(reloaded.provider(&AIProvider::OpenAI).base_url.is_none());
+
+        // Selecting a model keeps the other settings
+        reloaded.set_model(&AIProvider::Ollama, Some("qwen2.5".to_string())).unwrap();
+        let config = reloaded.provider(&AIProvider::Ollama);
+        assert_eq!(config.model.as_deref(), Some("qwen2.5"));
+        assert_eq!(config.base_url.as_deref(), Some("http://localhost:11434"));
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_generation_defaults_persist() {
+        let temp_dir = env::temp_dir().join("piano-app-test-ai-defaults");
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let settings = AiSettings::new(temp_dir.clone()).unwrap();
+        let config = ProviderConfig { temperature: Some(0.4), max_tokens: Some(8000), ..ProviderConfig::default() };
+        settings.set_provider(&AIProvider::Anthropic, config).unwrap();
+        assert!(settings
+            .set_provider(&AIProvider::Anthropic, ProviderConfig { temperature: Some(3.0), ..ProviderConfig::default() })
+            .is_err());
+        assert!(settings
+            .set_provider(&AIProvider::Anthropic, ProviderConfig { max_tokens: Some(10), ..ProviderConfig::default() })
+            .is_err());
+
+        let reloaded = AiSettings::new(temp_dir.clone()).unwrap().provider(&AIProvider::Anthropic);
+        assert_eq!(reloaded.temperature, Some(0.4));
+        assert_eq!(reloaded.max_tokens, Some(8000));
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_proxy_settings_and_legacy_file() {
+        let temp_dir = env::temp_dir().join("piano-app-test-ai-proxy");
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        // Files written before the global proxy existed are a plain provider map
+        fs::write(temp_dir.join("ai_settings.json"), r#"{"ollama": {"model": "llama3.1"}}"#).unwrap();
+        let settings = AiSettings::new(temp_dir.clone()).unwrap();
+        assert_eq!(settings.provider(&AIProvider::Ollama).model.as_deref(), Some("llama3.1"));
+
+        let proxy = ProxyConfig {
+            url: "socks5://127.0.0.1:1080".to_string(),
+            username: Some("me".to_string()),
+            password: Some(Secret::new("secret".to_string())),
+        };
+        settings.set_proxy(Some(proxy)).unwrap();
+        assert!(settings
+            .set_proxy(Some(ProxyConfig { url: "ftp://proxy:21".to_string(), username: None, password: None }))
+            .is_err());
+
+        // The password is never written to the settings file
+        let data = fs::read_to_string(temp_dir.join("ai_settings.json")).unwrap();
+        assert!(!data.contains("secret"));
+
+        let reloaded = AiSettings::new(temp_dir.clone()).unwrap();
+        assert_eq!(reloaded.proxy().unwrap().username.as_deref(), Some("me"));
+        assert_eq!(reloaded.provider(&AIProvider::Ollama).model.as_deref(), Some("llama3.1"));
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
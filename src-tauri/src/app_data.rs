@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where earlier versions kept app data, relative to the working directory
+pub const LEGACY_DIR: &str = ".piano-app-data";
+
+/// Copy a file or directory tree
+fn copy_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Move everything in the legacy app data directory into `target`
+///
+/// Entries `target` already has are left where they are, so newer data is
+/// never overwritten. Entries are renamed, or copied and removed when the
+/// directories are on different drives. The legacy directory is removed
+/// once empty. Returns the names of the entries moved.
+pub fn migrate(legacy: &Path, target: &Path) -> Result<Vec<PathBuf>, String> {
+    if !legacy.is_dir() || legacy == target {
+        return Ok(Vec::new());
+    }
+    fs::create_dir_all(target).map_err(|e| format!("Failed to create app data directory: {}", e))?;
+
+    let entries = fs::read_dir(legacy).map_err(|e| format!("Failed to read {}: {}", legacy.display(), e))?;
+    let mut moved = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", legacy.display(), e))?;
+        let from = entry.path();
+        let to = target.join(entry.file_name());
+        if to.exists() {
+            continue;
+        }
+
+        if fs::rename(&from, &to).is_err() {
+            copy_recursive(&from, &to).map_err(|e| format!("Failed to move {}: {}", from.display(), e))?;
+            let removed = if from.is_dir() { fs::remove_dir_all(&from) } else { fs::remove_file(&from) };
+            removed.map_err(|e| format!("Failed to remove {}: {}", from.display(), e))?;
+        }
+        moved.push(PathBuf::from(entry.file_name()));
+    }
+
+    // Only succeeds when everything was moved
+    fs::remove_dir(legacy).ok();
+    moved.sort();
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_migrate_legacy_app_data() {
+        let temp_dir = env::temp_dir().join("piano-app-test-migrate");
+        fs::remove_dir_all(&temp_dir).ok();
+        let legacy = temp_dir.join(LEGACY_DIR);
+        let target = temp_dir.join("app-data");
+        fs::create_dir_all(legacy.join("ai_cache")).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(legacy.join(".key"), [7u8; 32]).unwrap();
+        fs::write(legacy.join("api_keys.json"), "{}").unwrap();
+        fs::write(legacy.join("ai_cache").join("entry.json"), "{}").unwrap();
+        fs::write(legacy.join("ai_settings.json"), "old").unwrap();
+        fs::write(target.join("ai_settings.json"), "new").unwrap();
+
+        let moved = migrate(&legacy, &target).unwrap();
+        assert_eq!(moved, vec![PathBuf::from(".key"), PathBuf::from("ai_cache"), PathBuf::from("api_keys.json")]);
+        assert_eq!(fs::read(target.join(".key")).unwrap(), vec![7u8; 32]);
+        assert!(target.join("ai_cache").join("entry.json").exists());
+
+        // Newer data in the target is kept, and the legacy directory stays for what wasn't moved
+        assert_eq!(fs::read_to_string(target.join("ai_settings.json")).unwrap(), "new");
+        assert!(legacy.join("ai_settings.json").exists());
+        assert!(migrate(&legacy, &target).unwrap().is_empty());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
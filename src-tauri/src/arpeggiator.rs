@@ -0,0 +1,279 @@
+use crate::sample_player::SamplePlayer;
+use crate::transport::Transport;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use validator::Validate;
+
+/// Interval at which the arpeggiator thread checks for the next step
+const TICK_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Velocity used for notes latched from a chord selection
+const DEFAULT_CHORD_VELOCITY: u8 = 100;
+
+/// Order in which held notes are played
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArpPattern {
+    Up,
+    Down,
+    Random,
+}
+
+/// Arpeggiator settings
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Validate)]
+pub struct ArpSettings {
+    pub pattern: ArpPattern,
+
+    /// Step rate as a grid division (4 = quarter notes, 8 = eighths, 16 = sixteenths)
+    #[validate(range(min = 1, max = 32))]
+    pub rate: u32,
+
+    /// Number of octaves the pattern spans
+    #[validate(range(min = 1, max = 4))]
+    pub octaves: u8,
+
+    /// Note length as a fraction of the step (1.0 = legato)
+    #[validate(range(min = 0.05, max = 1.0))]
+    pub gate: f32,
+}
+
+impl ArpSettings {
+    /// Step length in beats
+    fn step(&self) -> f64 {
+        4.0 / self.rate as f64
+    }
+}
+
+/// Build the ascending note sequence for the held notes across the octave range
+///
+/// Returns (pitch, velocity) pairs; pitches beyond MIDI 127 are dropped.
+pub fn build_sequence(held: &[(u8, u8)], octaves: u8) -> Vec<(u8, u8)> {
+    let mut base: Vec<(u8, u8)> = held.to_vec();
+    base.sort_by_key(|&(pitch, _)| pitch);
+    base.dedup_by_key(|&mut (pitch, _)| pitch);
+
+    let mut sequence = Vec::new();
+    for octave in 0..octaves {
+        for &(pitch, velocity) in &base {
+            let shifted = pitch as u16 + octave as u16 * 12;
+            if shifted <= 127 {
+                sequence.push((shifted as u8, velocity));
+            }
+        }
+    }
+    sequence
+}
+
+struct ArpState {
+    settings: ArpSettings,
+    held: Vec<(u8, u8)>,  // Keys held on MIDI input (pitch, velocity)
+    chord: Vec<(u8, u8)>, // Notes latched from a chord selection
+    step_index: usize,
+}
+
+impl ArpState {
+    /// Pick the note for the next step, advancing the pattern
+    fn next_note(&mut self) -> Option<(u8, u8)> {
+        // Live keys take precedence over a latched chord
+        let source = if self.held.is_empty() { &self.chord } else { &self.held };
+        let sequence = build_sequence(source, self.settings.octaves);
+        if sequence.is_empty() {
+            self.step_index = 0;
+            return None;
+        }
+
+        let index = match self.settings.pattern {
+            ArpPattern::Up => self.step_index % sequence.len(),
+            ArpPattern::Down => sequence.len() - 1 - (self.step_index % sequence.len()),
+            ArpPattern::Random => rand::thread_rng().gen_range(0..sequence.len()),
+        };
+        self.step_index += 1;
+        Some(sequence[index])
+    }
+}
+
+/// Tempo-synced arpeggiator rendering through the sample player
+pub struct Arpeggiator {
+    state: Arc<Mutex<ArpState>>,
+    sample_player: Arc<SamplePlayer>,
+    transport: Arc<Transport>,
+    worker: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>>, // (keep-running flag, thread)
+}
+
+impl Arpeggiator {
+    pub fn new(sample_player: Arc<SamplePlayer>, transport: Arc<Transport>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ArpState {
+                settings: ArpSettings {
+                    pattern: ArpPattern::Up,
+                    rate: 8,
+                    octaves: 1,
+                    gate: 0.8,
+                },
+                held: Vec::new(),
+                chord: Vec::new(),
+                step_index: 0,
+            })),
+            sample_player,
+            transport,
+            worker: Mutex::new(None),
+        }
+    }
+
+    /// Check whether the arpeggiator is running
+    pub fn is_enabled(&self) -> bool {
+        self.worker.lock().unwrap().is_some()
+    }
+
+    /// Update settings (takes effect on the next step)
+    pub fn set_settings(&self, settings: ArpSettings) {
+        self.state.lock().unwrap().settings = settings;
+    }
+
+    /// Start the arpeggiator
+    pub fn enable(&self, settings: ArpSettings) -> Result<(), String> {
+        self.set_settings(settings);
+
+        let mut worker = self.worker.lock().unwrap();
+        if worker.is_some() {
+            return Ok(());
+        }
+
+        let keep_running = Arc::new(AtomicBool::new(true));
+        let handle = {
+            let keep_running = keep_running.clone();
+            let state = self.state.clone();
+            let sample_player = self.sample_player.clone();
+            let transport = self.transport.clone();
+            thread::Builder::new()
+                .name("arpeggiator".to_string())
+                .spawn(move || Self::run(state, sample_player, transport, keep_running))
+                .map_err(|e| format!("Failed to start arpeggiator thread: {}", e))?
+        };
+
+        *worker = Some((keep_running, handle));
+        Ok(())
+    }
+
+    /// Stop the arpeggiator and forget held and latched notes
+    pub fn disable(&self) {
+        if let Some((keep_running, handle)) = self.worker.lock().unwrap().take() {
+            keep_running.store(false, Ordering::SeqCst);
+            let _ = handle.join();
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.held.clear();
+        state.chord.clear();
+        state.step_index = 0;
+    }
+
+    /// Latch a chord selection as the arpeggio source (empty clears it)
+    pub fn set_chord(&self, pitches: Vec<u8>) {
+        self.state.lock().unwrap().chord = pitches
+            .into_iter()
+            .filter(|&pitch| pitch <= 127)
+            .map(|pitch| (pitch, DEFAULT_CHORD_VELOCITY))
+            .collect();
+    }
+
+    /// Key pressed on MIDI input
+    pub fn note_on(&self, pitch: u8, velocity: u8) {
+        let mut state = self.state.lock().unwrap();
+        if state.held.is_empty() {
+            // Restart the pattern on a fresh chord
+            state.step_index = 0;
+        }
+        state.held.retain(|&(p, _)| p != pitch);
+        state.held.push((pitch, velocity));
+    }
+
+    /// Key released on MIDI input
+    pub fn note_off(&self, pitch: u8) {
+        self.state.lock().unwrap().held.retain(|&(p, _)| p != pitch);
+    }
+
+    /// Arpeggiator thread: plays one note per step, locked to the transport
+    ///
+    /// While the transport is stopped, steps follow a free-running clock at the
+    /// transport tempo so the arpeggio can be played live.
+    fn run(
+        state: Arc<Mutex<ArpState>>,
+        sample_player: Arc<SamplePlayer>,
+        transport: Arc<Transport>,
+        keep_running: Arc<AtomicBool>,
+    ) {
+        let free_start = Instant::now();
+        let mut last_step: Option<i64> = None;
+
+        while keep_running.load(Ordering::SeqCst) {
+            let beat = if transport.is_running() {
+                transport.position()
+            } else {
+                free_start.elapsed().as_secs_f64() * transport.tempo() / 60.0
+            };
+
+            let (step, note, note_seconds) = {
+                let mut state = state.lock().unwrap();
+                let step_beats = state.settings.step();
+                let step = (beat / step_beats).floor() as i64;
+
+                if last_step == Some(step) {
+                    (step, None, 0.0)
+                } else {
+                    let seconds = step_beats * 60.0 / transport.tempo() * state.settings.gate as f64;
+                    (step, state.next_note(), seconds as f32)
+                }
+            };
+
+            if let Some((pitch, velocity)) = note {
+                if let Err(e) = sample_player.play_note(pitch, note_seconds, velocity) {
+                    eprintln!("⚠ Arpeggiator failed to play note: {}", e);
+                }
+            }
+            last_step = Some(step);
+
+            thread::sleep(TICK_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_sorted_across_octaves() {
+        let held = [(64, 90), (60, 100), (67, 80)];
+        let pitches: Vec<u8> = build_sequence(&held, 2).iter().map(|&(p, _)| p).collect();
+        assert_eq!(pitches, vec![60, 64, 67, 72, 76, 79]);
+    }
+
+    #[test]
+    fn test_sequence_drops_out_of_range() {
+        let pitches: Vec<u8> = build_sequence(&[(120, 100)], 3).iter().map(|&(p, _)| p).collect();
+        assert_eq!(pitches, vec![120]);
+    }
+
+    #[test]
+    fn test_down_pattern_order() {
+        let mut state = ArpState {
+            settings: ArpSettings {
+                pattern: ArpPattern::Down,
+                rate: 8,
+                octaves: 1,
+                gate: 0.5,
+            },
+            held: vec![(60, 100), (64, 100), (67, 100)],
+            chord: Vec::new(),
+            step_index: 0,
+        };
+
+        let played: Vec<u8> = (0..4).filter_map(|_| state.next_note()).map(|(p, _)| p).collect();
+        assert_eq!(played, vec![67, 64, 60, 67]);
+    }
+}
@@ -1,5 +1,9 @@
-use rodio::{OutputStream, OutputStreamHandle, Sink};
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Output sample rate of synthesized notes
+const SAMPLE_RATE: u32 = 44100;
 
 /// Sound generation mode
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -28,6 +32,87 @@ impl Default for Envelope {
     }
 }
 
+impl Envelope {
+    /// Amplitude `t` seconds into a note whose key is released at `duration`
+    fn amplitude(&self, t: f32, duration: f32) -> f32 {
+        if t < self.attack {
+            // Attack phase: ramp from 0 to 1
+            t / self.attack
+        } else if t < self.attack + self.decay {
+            // Decay phase: ramp from 1 to sustain level
+            let decay_t = (t - self.attack) / self.decay;
+            1.0 - (1.0 - self.sustain) * decay_t
+        } else if t < duration {
+            // Sustain phase: hold at sustain level
+            self.sustain
+        } else {
+            // Release phase: ramp from sustain to 0
+            let release_t = (t - duration) / self.release;
+            self.sustain * (1.0 - release_t).max(0.0)
+        }
+    }
+}
+
+/// A note synthesized sample by sample as the output thread pulls it
+///
+/// Nothing is generated up front, so `play_note` returns right away however
+/// long the note is.
+struct NoteSource {
+    frequency: f32,
+    duration: f32,
+    envelope: Envelope,
+    velocity_amplitude: f32,
+    volume: f32,
+    sound_mode: SoundMode,
+    index: usize,
+    total_samples: usize,
+}
+
+impl Iterator for NoteSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.index >= self.total_samples {
+            return None;
+        }
+        let t = self.index as f32 / SAMPLE_RATE as f32;
+        self.index += 1;
+
+        let envelope_amp = self.envelope.amplitude(t, self.duration);
+        Some(match self.sound_mode {
+            SoundMode::Piano => {
+                AudioEngine::generate_piano_sample(t, self.frequency, envelope_amp, self.velocity_amplitude, self.volume)
+            }
+            SoundMode::Synthesizer => {
+                AudioEngine::generate_synth_sample(t, self.frequency, envelope_amp, self.velocity_amplitude, self.volume)
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total_samples - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl Source for NoteSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.total_samples - self.index)
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(self.total_samples as f32 / SAMPLE_RATE as f32))
+    }
+}
+
 /// Audio engine for playing piano notes
 pub struct AudioEngine {
     stream_handle: Arc<OutputStreamHandle>,
@@ -88,11 +173,10 @@ impl AudioEngine {
         sine_wave * envelope_amp * velocity_amplitude * volume
     }
 
-    /// Generate a note with ADSR envelope (supports both piano and synth modes)
+    /// Play a note with ADSR envelope (supports both piano and synth modes)
+    ///
+    /// The note is synthesized while it plays, on the audio output thread.
     pub fn play_note(&self, pitch: u8, duration: f32, velocity: u8) -> Result<(), String> {
-        let frequency = Self::midi_to_frequency(pitch);
-        let sample_rate = 44100;
-
         // Use different envelope for piano vs synth
         let envelope = match self.sound_mode {
             SoundMode::Piano => Envelope {
@@ -106,46 +190,18 @@ impl AudioEngine {
 
         // Calculate total duration including release
         let total_duration = duration + envelope.release;
-        let total_samples = (total_duration * sample_rate as f32) as usize;
-
-        // Velocity to amplitude (0-127 -> 0.0-1.0)
-        let velocity_amplitude = (velocity as f32 / 127.0) * 0.5; // Max 0.5 to prevent clipping
-
-        let volume = self.volume;
-        let sound_mode = self.sound_mode;
-
-        // Generate samples with ADSR envelope
-        let samples: Vec<f32> = (0..total_samples)
-            .map(|i| {
-                let t = i as f32 / sample_rate as f32;
-
-                // Calculate envelope amplitude
-                let envelope_amp = if t < envelope.attack {
-                    // Attack phase: ramp from 0 to 1
-                    t / envelope.attack
-                } else if t < envelope.attack + envelope.decay {
-                    // Decay phase: ramp from 1 to sustain level
-                    let decay_t = (t - envelope.attack) / envelope.decay;
-                    1.0 - (1.0 - envelope.sustain) * decay_t
-                } else if t < duration {
-                    // Sustain phase: hold at sustain level
-                    envelope.sustain
-                } else {
-                    // Release phase: ramp from sustain to 0
-                    let release_t = (t - duration) / envelope.release;
-                    envelope.sustain * (1.0 - release_t).max(0.0)
-                };
-
-                // Generate sample based on sound mode
-                match sound_mode {
-                    SoundMode::Piano => Self::generate_piano_sample(t, frequency, envelope_amp, velocity_amplitude, volume),
-                    SoundMode::Synthesizer => Self::generate_synth_sample(t, frequency, envelope_amp, velocity_amplitude, volume),
-                }
-            })
-            .collect();
-
-        // Create a source from the samples
-        let source = rodio::buffer::SamplesBuffer::new(1, sample_rate, samples);
+
+        let source = NoteSource {
+            frequency: Self::midi_to_frequency(pitch),
+            duration,
+            envelope,
+            // Velocity to amplitude (0-127 -> 0.0-1.0)
+            velocity_amplitude: (velocity as f32 / 127.0) * 0.5, // Max 0.5 to prevent clipping
+            volume: self.volume,
+            sound_mode: self.sound_mode,
+            index: 0,
+            total_samples: (total_duration * SAMPLE_RATE as f32) as usize,
+        };
 
         // Create a new sink and play the note
         let sink = Sink::try_new(&*self.stream_handle)
@@ -181,3 +237,50 @@ impl AudioEngine {
         self.sound_mode
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak level of the `period` samples starting at `start`
+    fn peak(samples: &[f32], start: usize, period: usize) -> f32 {
+        samples[start..start + period].iter().fold(0.0, |max, s| max.max(s.abs()))
+    }
+
+    #[test]
+    fn test_note_source_follows_envelope() {
+        let envelope = Envelope {
+            attack: 0.01,
+            decay: 0.01,
+            sustain: 0.5,
+            release: 0.1,
+        };
+        let source = NoteSource {
+            frequency: 441.0, // One period every 100 samples
+            duration: 0.1,
+            envelope,
+            velocity_amplitude: 1.0,
+            volume: 1.0,
+            sound_mode: SoundMode::Synthesizer,
+            index: 0,
+            total_samples: 8820, // 0.2 seconds: the note plus its release
+        };
+        assert_eq!(source.total_duration(), Some(Duration::from_secs_f32(0.2)));
+
+        let samples: Vec<f32> = source.collect();
+        assert_eq!(samples.len(), 8820);
+
+        // Attack: silent at the start, rising towards full level
+        assert_eq!(samples[0], 0.0);
+        assert!(peak(&samples, 0, 100) < 0.3);
+        assert!(peak(&samples, 400, 100) > 0.95);
+
+        // Sustain: held at the sustain level until the key is released
+        assert!((peak(&samples, 2000, 100) - 0.5).abs() < 0.01);
+        assert!((peak(&samples, 4300, 100) - 0.5).abs() < 0.01);
+
+        // Release: fading out to nothing
+        assert!(peak(&samples, 6600, 100) < 0.3);
+        assert!(peak(&samples, 8720, 100) < 0.02);
+    }
+}
@@ -0,0 +1,250 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SizedSample};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the capture thread checks for the stop request
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A recorded audio clip referenced by a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioClip {
+    /// Path of the WAV file, relative to the project file's directory
+    pub path: String,
+    /// Length in seconds
+    pub duration: f64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub recorded_at: String,
+}
+
+/// A capture in progress: the thread owns the cpal stream (which isn't Send)
+struct ActiveRecording {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<AudioClip, String>>,
+}
+
+/// Microphone recorder writing 16-bit WAV clips
+pub struct AudioRecorder {
+    active: Mutex<Option<ActiveRecording>>,
+}
+
+impl AudioRecorder {
+    pub fn new() -> Self {
+        Self {
+            active: Mutex::new(None),
+        }
+    }
+
+    /// List the names of available audio input devices
+    pub fn list_input_devices() -> Result<Vec<String>, String> {
+        let host = cpal::default_host();
+        let devices = host
+            .input_devices()
+            .map_err(|e| format!("Failed to list audio inputs: {}", e))?;
+
+        Ok(devices.filter_map(|device| device.name().ok()).collect())
+    }
+
+    /// Directory holding a project's audio clips (`<project>_audio` next to the project file)
+    pub fn clips_dir(project_path: &Path) -> Result<PathBuf, String> {
+        let parent = project_path
+            .parent()
+            .ok_or("Project path has no parent directory")?;
+        let stem = project_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or("Invalid project file name")?;
+
+        Ok(parent.join(format!("{}_audio", stem)))
+    }
+
+    /// Start recording from `device_name` (or the default input) into the project's clip folder
+    pub fn start(&self, project_path: &Path, device_name: Option<String>) -> Result<(), String> {
+        let mut active = self.active.lock().unwrap();
+        if active.is_some() {
+            return Err("Audio recording already in progress".to_string());
+        }
+
+        let clips_dir = Self::clips_dir(project_path)?;
+        std::fs::create_dir_all(&clips_dir)
+            .map_err(|e| format!("Failed to create audio clip folder: {}", e))?;
+
+        let file_name = format!("clip-{}.wav", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let clip_path = clips_dir.join(&file_name);
+        let relative_path = format!(
+            "{}/{}",
+            clips_dir.file_name().and_then(|s| s.to_str()).unwrap_or_default(),
+            file_name
+        );
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let handle = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("audio-capture".to_string())
+                .spawn(move || Self::capture(clip_path, relative_path, device_name, stop, ready_tx))
+                .map_err(|e| format!("Failed to start audio capture thread: {}", e))?
+        };
+
+        // Wait until the stream is running so device errors reach the caller
+        match ready_rx.recv() {
+            Ok(Ok(())) => {
+                *active = Some(ActiveRecording { stop, handle });
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                let _ = handle.join();
+                Err(e)
+            }
+            Err(_) => Err("Audio capture thread stopped unexpectedly".to_string()),
+        }
+    }
+
+    /// Stop recording and finalize the WAV file
+    pub fn stop(&self) -> Result<AudioClip, String> {
+        let recording = self
+            .active
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or("No audio recording in progress")?;
+
+        recording.stop.store(true, Ordering::SeqCst);
+        recording
+            .handle
+            .join()
+            .map_err(|_| "Audio capture thread panicked".to_string())?
+    }
+
+    /// Capture thread: runs the input stream and streams samples into the WAV writer
+    ///
+    /// Disk writes happen here rather than in the audio callback, which only
+    /// forwards buffers over a channel.
+    fn capture(
+        clip_path: PathBuf,
+        relative_path: String,
+        device_name: Option<String>,
+        stop: Arc<AtomicBool>,
+        ready: Sender<Result<(), String>>,
+    ) -> Result<AudioClip, String> {
+        let (sample_tx, sample_rx) = mpsc::channel::<Vec<f32>>();
+
+        let setup = || -> Result<(cpal::Stream, hound::WavWriter<std::io::BufWriter<std::fs::File>>, u32, u16), String> {
+            let host = cpal::default_host();
+            let device = match &device_name {
+                Some(name) => host
+                    .input_devices()
+                    .map_err(|e| format!("Failed to list audio inputs: {}", e))?
+                    .find(|d| d.name().map(|n| &n == name).unwrap_or(false))
+                    .ok_or_else(|| format!("Audio input not found: {}", name))?,
+                None => host
+                    .default_input_device()
+                    .ok_or("No audio input device available")?,
+            };
+
+            let supported = device
+                .default_input_config()
+                .map_err(|e| format!("Failed to get audio input config: {}", e))?;
+            let config: cpal::StreamConfig = supported.clone().into();
+
+            let stream = match supported.sample_format() {
+                cpal::SampleFormat::F32 => Self::build_stream::<f32>(&device, &config, sample_tx.clone()),
+                cpal::SampleFormat::I16 => Self::build_stream::<i16>(&device, &config, sample_tx.clone()),
+                cpal::SampleFormat::I32 => Self::build_stream::<i32>(&device, &config, sample_tx.clone()),
+                cpal::SampleFormat::U16 => Self::build_stream::<u16>(&device, &config, sample_tx.clone()),
+                format => Err(format!("Unsupported audio input sample format: {}", format)),
+            }?;
+
+            let spec = hound::WavSpec {
+                channels: config.channels,
+                sample_rate: config.sample_rate.0,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let writer = hound::WavWriter::create(&clip_path, spec)
+                .map_err(|e| format!("Failed to create WAV file: {}", e))?;
+
+            stream
+                .play()
+                .map_err(|e| format!("Failed to start audio input: {}", e))?;
+
+            Ok((stream, writer, config.sample_rate.0, config.channels))
+        };
+
+        let (stream, mut writer, sample_rate, channels) = match setup() {
+            Ok(parts) => {
+                let _ = ready.send(Ok(()));
+                parts
+            }
+            Err(e) => {
+                let _ = ready.send(Err(e.clone()));
+                return Err(e);
+            }
+        };
+        drop(sample_tx);
+
+        let mut sample_count: u64 = 0;
+        let mut write_chunk = |chunk: Vec<f32>| -> Result<(), String> {
+            for sample in chunk {
+                let value = (sample.max(-1.0).min(1.0) * i16::MAX as f32) as i16;
+                writer
+                    .write_sample(value)
+                    .map_err(|e| format!("Failed to write audio: {}", e))?;
+                sample_count += 1;
+            }
+            Ok(())
+        };
+
+        while !stop.load(Ordering::SeqCst) {
+            match sample_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(chunk) => write_chunk(chunk)?,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        // Stop the stream, then flush buffers that arrived in the meantime
+        drop(stream);
+        for chunk in sample_rx.try_iter() {
+            write_chunk(chunk)?;
+        }
+
+        writer
+            .finalize()
+            .map_err(|e| format!("Failed to finalize WAV file: {}", e))?;
+
+        Ok(AudioClip {
+            path: relative_path,
+            duration: sample_count as f64 / channels.max(1) as f64 / sample_rate as f64,
+            sample_rate,
+            channels,
+            recorded_at: chrono::Local::now().to_rfc3339(),
+        })
+    }
+
+    /// Build an input stream converting samples of type `T` to f32 buffers
+    fn build_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, tx: Sender<Vec<f32>>) -> Result<cpal::Stream, String>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        device
+            .build_input_stream(
+                config,
+                move |data: &[T], _: &cpal::InputCallbackInfo| {
+                    let _ = tx.send(data.iter().map(|&s| f32::from_sample(s)).collect());
+                },
+                |e| eprintln!("⚠ Audio input error: {}", e),
+                None,
+            )
+            .map_err(|e| format!("Failed to open audio input: {}", e))
+    }
+}
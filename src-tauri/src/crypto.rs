@@ -0,0 +1,68 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use argon2::Argon2;
+use rand::Rng;
+use zeroize::Zeroizing;
+
+/// Length of an Argon2id salt
+pub const SALT_LEN: usize = 16;
+
+/// Length of an AES-GCM nonce (96 bits, the standard size for GCM)
+pub const NONCE_LEN: usize = 12;
+
+/// Random salt for `derive_key`
+pub fn random_salt() -> [u8; SALT_LEN] {
+    rand::thread_rng().gen()
+}
+
+/// Derive a 256-bit key from a password (Argon2id with default parameters)
+///
+/// Argon2id is slow and memory-hard by design, so guessing a password from
+/// a copied file takes far longer than with a plain hash.
+pub fn derive_key(password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, String> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut *key)
+        .map_err(|e| format!("Failed to derive encryption key: {}", e))?;
+    Ok(key)
+}
+
+/// Encrypt with AES-256-GCM under `key`, returning a fresh random nonce and the ciphertext
+pub fn seal(key: &[u8; 32], plaintext: &[u8]) -> Result<([u8; NONCE_LEN], Vec<u8>), String> {
+    let nonce: [u8; NONCE_LEN] = rand::thread_rng().gen();
+    let ciphertext = Aes256Gcm::new(key.into())
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    Ok((nonce, ciphertext))
+}
+
+/// Decrypt what `seal` produced, verifying its authentication tag
+///
+/// A wrong key and tampered data fail the same way.
+pub fn open(key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+    if nonce.len() != NONCE_LEN {
+        return Err(format!("Invalid nonce length {}", nonce.len()));
+    }
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Decryption failed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let salt = random_salt();
+        let key = derive_key("hunter2", &salt).unwrap();
+        let (nonce, ciphertext) = seal(&key, b"secret").unwrap();
+        assert_eq!(open(&key, &nonce, &ciphertext).unwrap(), b"secret");
+
+        let other = derive_key("wrong", &salt).unwrap();
+        assert!(open(&other, &nonce, &ciphertext).is_err());
+        assert!(open(&key, &nonce[1..], &ciphertext).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+/// Track holding percussion; its notes are drum sounds, not pitches
+pub const DRUM_TRACK_ID: &str = "track_drums";
+
+/// MIDI channel of percussion (channel 10, zero-based)
+pub const DRUM_CHANNEL: u8 = 9;
+
+/// A sound of the General MIDI percussion map
+pub struct DrumSound {
+    /// Note number triggering the sound on channel 10
+    pub pitch: u8,
+    pub name: &'static str,
+    /// Sample file (in `samples/drums`) playing the sound
+    pub sample: &'static str,
+}
+
+const fn sound(pitch: u8, name: &'static str, sample: &'static str) -> DrumSound {
+    DrumSound { pitch, name, sample }
+}
+
+/// The General MIDI sounds drum patterns may use, by note number
+///
+/// Sounds without a sample of their own share the closest one of the basic
+/// set (e.g. every tom plays one of three tom samples).
+pub const GM_DRUM_MAP: &[DrumSound] = &[
+    sound(35, "Acoustic Bass Drum", "kick.wav"),
+    sound(36, "Bass Drum 1", "kick.wav"),
+    sound(37, "Side Stick", "rim.wav"),
+    sound(38, "Acoustic Snare", "snare.wav"),
+    sound(39, "Hand Clap", "clap.wav"),
+    sound(40, "Electric Snare", "snare.wav"),
+    sound(41, "Low Floor Tom", "tom_low.wav"),
+    sound(42, "Closed Hi-Hat", "hihat_closed.wav"),
+    sound(43, "High Floor Tom", "tom_low.wav"),
+    sound(44, "Pedal Hi-Hat", "hihat_closed.wav"),
+    sound(45, "Low Tom", "tom_mid.wav"),
+    sound(46, "Open Hi-Hat", "hihat_open.wav"),
+    sound(47, "Low-Mid Tom", "tom_mid.wav"),
+    sound(48, "Hi-Mid Tom", "tom_high.wav"),
+    sound(49, "Crash Cymbal 1", "crash.wav"),
+    sound(50, "High Tom", "tom_high.wav"),
+    sound(51, "Ride Cymbal 1", "ride.wav"),
+    sound(53, "Ride Bell", "ride.wav"),
+    sound(54, "Tambourine", "tambourine.wav"),
+    sound(56, "Cowbell", "cowbell.wav"),
+    sound(57, "Crash Cymbal 2", "crash.wav"),
+    sound(59, "Ride Cymbal 2", "ride.wav"),
+];
+
+/// The drum sound a note number triggers, if it's part of the map
+pub fn drum_sound(pitch: u8) -> Option<&'static DrumSound> {
+    GM_DRUM_MAP.iter().find(|sound| sound.pitch == pitch)
+}
@@ -0,0 +1,269 @@
+use crate::render::RenderedAudio;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::num::{NonZeroU32, NonZeroU8};
+use std::path::Path;
+
+/// Frames handed to the Vorbis encoder per block
+const VORBIS_BLOCK_FRAMES: usize = 4096;
+
+/// Defaults for lossy formats when no option is given
+pub const DEFAULT_MP3_BITRATE: u32 = 192;
+pub const DEFAULT_OGG_QUALITY: f32 = 0.6;
+
+/// Audio file format of a render
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Wav,
+    Mp3,
+    Ogg,
+    Flac,
+}
+
+impl AudioFormat {
+    /// Parse a format name or file extension ("wav", "mp3", "ogg", "flac")
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "wav" => Some(AudioFormat::Wav),
+            "mp3" => Some(AudioFormat::Mp3),
+            "ogg" => Some(AudioFormat::Ogg),
+            "flac" => Some(AudioFormat::Flac),
+            _ => None,
+        }
+    }
+
+    /// File extension for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Flac => "flac",
+        }
+    }
+}
+
+/// Encoder settings for one output file
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeSettings {
+    pub format: AudioFormat,
+    pub bit_depth: u16, // WAV and FLAC (16 or 24)
+    pub bitrate: u32,   // MP3, in kbps
+    pub quality: f32,   // OGG Vorbis VBR quality (0.0-1.0)
+    pub dither: bool,   // TPDF dither on integer conversion
+}
+
+/// Convert a float sample to the nearest signed integer of `bit_depth` bits
+///
+/// Rounding (not truncation toward zero) keeps dithered signals free of a DC
+/// offset and of a dead zone around silence.
+fn to_int(sample: f32, bit_depth: u16) -> i32 {
+    let scale = ((1i32 << (bit_depth - 1)) - 1) as f32;
+    (sample.max(-1.0).min(1.0) * scale).round() as i32
+}
+
+/// Convert samples to integers, optionally with TPDF dither (±1 LSB triangular noise)
+///
+/// Dither turns the truncation distortion of quiet passages and fades into a
+/// constant low noise floor.
+fn to_int_samples(samples: &[f32], bit_depth: u16, dither: bool) -> Vec<i32> {
+    if !dither {
+        return samples.iter().map(|&s| to_int(s, bit_depth)).collect();
+    }
+
+    let mut rng = rand::thread_rng();
+    let lsb = 1.0 / ((1i32 << (bit_depth - 1)) - 1) as f32;
+    samples
+        .iter()
+        .map(|&s| {
+            let noise = rng.gen::<f32>() - rng.gen::<f32>();
+            to_int(s + noise * lsb, bit_depth)
+        })
+        .collect()
+}
+
+/// Closest LAME constant bitrate at or below `kbps`
+fn mp3_bitrate(kbps: u32) -> mp3lame_encoder::Bitrate {
+    use mp3lame_encoder::Bitrate;
+
+    match kbps {
+        0..=111 => Bitrate::Kbps96,
+        112..=127 => Bitrate::Kbps112,
+        128..=159 => Bitrate::Kbps128,
+        160..=191 => Bitrate::Kbps160,
+        192..=223 => Bitrate::Kbps192,
+        224..=255 => Bitrate::Kbps224,
+        256..=319 => Bitrate::Kbps256,
+        _ => Bitrate::Kbps320,
+    }
+}
+
+/// Encode rendered audio to `path` in the requested format
+pub fn encode(audio: &RenderedAudio, path: &Path, settings: &EncodeSettings) -> Result<(), String> {
+    match settings.format {
+        AudioFormat::Wav => write_wav(audio, path, settings.bit_depth, settings.dither),
+        AudioFormat::Mp3 => write_mp3(audio, path, settings.bitrate, settings.dither),
+        AudioFormat::Ogg => write_ogg(audio, path, settings.quality),
+        AudioFormat::Flac => write_flac(audio, path, settings.bit_depth, settings.dither),
+    }
+}
+
+/// Write a 16- or 24-bit PCM WAV file
+fn write_wav(audio: &RenderedAudio, path: &Path, bit_depth: u16, dither: bool) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels: audio.channels,
+        sample_rate: audio.sample_rate,
+        bits_per_sample: bit_depth,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut writer = hound::WavWriter::create(path, spec)
+        .map_err(|e| format!("Failed to create WAV file: {}", e))?;
+
+    for sample in to_int_samples(&audio.samples, bit_depth, dither) {
+        writer
+            .write_sample(sample)
+            .map_err(|e| format!("Failed to write audio: {}", e))?;
+    }
+
+    writer
+        .finalize()
+        .map_err(|e| format!("Failed to finalize WAV file: {}", e))
+}
+
+/// Write a constant-bitrate MP3 file (LAME)
+fn write_mp3(audio: &RenderedAudio, path: &Path, bitrate: u32, dither: bool) -> Result<(), String> {
+    use mp3lame_encoder::{Builder, FlushNoGap, InterleavedPcm, MonoPcm, Quality};
+
+    let mut builder = Builder::new().ok_or("Failed to initialize MP3 encoder")?;
+    builder
+        .set_num_channels(audio.channels as u8)
+        .map_err(|e| format!("Unsupported MP3 channel count: {}", e))?;
+    builder
+        .set_sample_rate(audio.sample_rate)
+        .map_err(|e| format!("Unsupported MP3 sample rate: {}", e))?;
+    builder
+        .set_brate(mp3_bitrate(bitrate))
+        .map_err(|e| format!("Unsupported MP3 bitrate: {}", e))?;
+    builder
+        .set_quality(Quality::Best)
+        .map_err(|e| format!("Failed to set MP3 quality: {}", e))?;
+    let mut encoder = builder
+        .build()
+        .map_err(|e| format!("Failed to initialize MP3 encoder: {}", e))?;
+
+    let pcm: Vec<i16> = to_int_samples(&audio.samples, 16, dither)
+        .into_iter()
+        .map(|s| s as i16)
+        .collect();
+    let mut mp3 = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(pcm.len()));
+
+    let encoded = if audio.channels == 1 {
+        encoder.encode_to_vec(MonoPcm(&pcm), &mut mp3)
+    } else {
+        encoder.encode_to_vec(InterleavedPcm(&pcm), &mut mp3)
+    };
+    encoded.map_err(|e| format!("Failed to encode MP3: {}", e))?;
+    encoder
+        .flush_to_vec::<FlushNoGap>(&mut mp3)
+        .map_err(|e| format!("Failed to encode MP3: {}", e))?;
+
+    std::fs::write(path, mp3).map_err(|e| format!("Failed to write MP3 file: {}", e))
+}
+
+/// Write an Ogg Vorbis file with quality-based VBR
+fn write_ogg(audio: &RenderedAudio, path: &Path, quality: f32) -> Result<(), String> {
+    use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder};
+
+    let file = File::create(path).map_err(|e| format!("Failed to create OGG file: {}", e))?;
+    let sample_rate = NonZeroU32::new(audio.sample_rate).ok_or("Invalid sample rate")?;
+    let channels = NonZeroU8::new(audio.channels as u8).ok_or("Invalid channel count")?;
+
+    let mut encoder = VorbisEncoderBuilder::new(sample_rate, channels, BufWriter::new(file))
+        .map_err(|e| format!("Failed to initialize OGG encoder: {}", e))?
+        .bitrate_management_strategy(VorbisBitrateManagementStrategy::QualityVbr {
+            target_quality: quality,
+        })
+        .build()
+        .map_err(|e| format!("Failed to initialize OGG encoder: {}", e))?;
+
+    // Vorbis takes planar blocks
+    let channel_count = audio.channels as usize;
+    for block in audio.samples.chunks(VORBIS_BLOCK_FRAMES * channel_count) {
+        let planar: Vec<Vec<f32>> = (0..channel_count)
+            .map(|channel| block.iter().skip(channel).step_by(channel_count).copied().collect())
+            .collect();
+
+        encoder
+            .encode_audio_block(&planar)
+            .map_err(|e| format!("Failed to encode OGG: {}", e))?;
+    }
+
+    encoder
+        .finish()
+        .map_err(|e| format!("Failed to finalize OGG file: {}", e))?;
+    Ok(())
+}
+
+/// Write a lossless FLAC file
+fn write_flac(audio: &RenderedAudio, path: &Path, bit_depth: u16, dither: bool) -> Result<(), String> {
+    use flacenc::component::BitRepr;
+    use flacenc::error::Verify;
+
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| format!("Invalid FLAC encoder config: {}", e))?;
+
+    let pcm = to_int_samples(&audio.samples, bit_depth, dither);
+    let source = flacenc::source::MemSource::from_samples(
+        &pcm,
+        audio.channels as usize,
+        bit_depth as usize,
+        audio.sample_rate as usize,
+    );
+
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| format!("Failed to encode FLAC: {}", e))?;
+
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| format!("Failed to encode FLAC: {}", e))?;
+
+    std::fs::write(path, sink.as_slice()).map_err(|e| format!("Failed to write FLAC file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_int_scales_and_clips() {
+        assert_eq!(to_int(1.0, 16), 32767);
+        assert_eq!(to_int(-2.0, 16), -32767);
+        assert_eq!(to_int(0.5, 24), 4194304);
+        assert_eq!(to_int(-0.6 / 32767.0, 16), -1);
+    }
+
+    #[test]
+    fn test_dither_stays_within_one_lsb() {
+        let samples = vec![0.25; 1000];
+        let plain = to_int_samples(&samples, 16, false);
+        let dithered = to_int_samples(&samples, 16, true);
+
+        assert!(plain.iter().all(|&s| s == to_int(0.25, 16)));
+        assert!(dithered.iter().all(|&s| (s - plain[0]).abs() <= 1));
+    }
+
+    #[test]
+    fn test_mp3_bitrate_rounds_down() {
+        assert!(matches!(mp3_bitrate(192), mp3lame_encoder::Bitrate::Kbps192));
+        assert!(matches!(mp3_bitrate(200), mp3lame_encoder::Bitrate::Kbps192));
+        assert!(matches!(mp3_bitrate(64), mp3lame_encoder::Bitrate::Kbps96));
+        assert!(matches!(mp3_bitrate(320), mp3lame_encoder::Bitrate::Kbps320));
+    }
+}
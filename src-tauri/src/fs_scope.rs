@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Extensions project files may be saved with, also picked up when scanning a folder for projects
+pub const PROJECT_EXTENSIONS: [&str; 2] = ["json", "msgpack"];
+
+/// Extension of API key backups
+pub const KEY_BACKUP_EXTENSIONS: [&str; 1] = ["pianokeys"];
+
+/// Extension of portable project bundles
+pub const BUNDLE_EXTENSIONS: [&str; 1] = ["pianoproj"];
+
+/// Extensions of rendered audio files
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["wav", "mp3", "ogg", "flac"];
+
+/// Extensions of standard MIDI files
+pub const MIDI_EXTENSIONS: [&str; 2] = ["mid", "midi"];
+
+/// Directories the user approved for project files, persisted in the app data directory
+///
+/// Paths coming from the webview are only trusted when they resolve (after
+/// following symlinks and `..`) inside one of these directories. Directories
+/// are added through a native folder picker, never directly by the frontend.
+pub struct FsScope {
+    storage_path: PathBuf,
+    directories: Mutex<Vec<PathBuf>>,
+}
+
+impl FsScope {
+    /// Create the scope, loading previously approved directories
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let storage_path = app_data_dir.join("approved_directories.json");
+
+        let directories = if storage_path.exists() {
+            let data = fs::read_to_string(&storage_path)
+                .map_err(|e| format!("Failed to read approved directories: {}", e))?;
+            serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse approved directories: {}", e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            storage_path,
+            directories: Mutex::new(directories),
+        })
+    }
+
+    fn save(&self, directories: &[PathBuf]) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(directories)
+            .map_err(|e| format!("Failed to serialize approved directories: {}", e))?;
+        fs::write(&self.storage_path, data)
+            .map_err(|e| format!("Failed to write approved directories: {}", e))
+    }
+
+    /// Approve a directory (and everything below it); returns its canonical path
+    pub fn approve(&self, directory: &Path) -> Result<PathBuf, String> {
+        let directory = directory
+            .canonicalize()
+            .map_err(|e| format!("Cannot approve {}: {}", directory.display(), e))?;
+        if !directory.is_dir() {
+            return Err(format!("Not a folder: {}", directory.display()));
+        }
+
+        let mut directories = self.directories.lock().unwrap();
+        if !directories.contains(&directory) {
+            directories.push(directory.clone());
+            self.save(&directories)?;
+        }
+        Ok(directory)
+    }
+
+    /// Withdraw approval of a directory
+    ///
+    /// Approvals are stored canonical, so the path is resolved the same way;
+    /// a folder that no longer exists can only be revoked by its stored path.
+    pub fn revoke(&self, directory: &Path) -> Result<(), String> {
+        let directory = directory.canonicalize().unwrap_or_else(|_| directory.to_path_buf());
+        let mut directories = self.directories.lock().unwrap();
+        directories.retain(|d| *d != directory);
+        self.save(&directories)
+    }
+
+    /// Approved directories
+    pub fn directories(&self) -> Vec<PathBuf> {
+        self.directories.lock().unwrap().clone()
+    }
+
+    fn contains(&self, canonical: &Path) -> bool {
+        self.directories
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|directory| canonical.starts_with(directory))
+    }
+
+    /// Check that a path has one of `extensions`
+    fn check_extension(path: &Path, extensions: &[&str]) -> Result<(), String> {
+        let extension_allowed = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)));
+        if !extension_allowed {
+            return Err(format!(
+                "Access denied: {} must have one of the extensions {}",
+                path.display(),
+                extensions.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that an existing file with one of `extensions` may be read
+    ///
+    /// The extension is checked on the file a symlink points to as well, so a
+    /// `song.json` link can't expose some other file.
+    pub fn check_read(&self, path: &Path, extensions: &[&str]) -> Result<PathBuf, String> {
+        Self::check_extension(path, extensions)?;
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        Self::check_extension(&canonical, extensions)?;
+
+        if !self.contains(&canonical) {
+            return Err(format!("Access denied: {} is outside the approved folders", path.display()));
+        }
+        Ok(canonical)
+    }
+
+    /// Check that a file may be written: inside an approved directory and with an allowed extension
+    ///
+    /// An existing file is written where it resolves to, so that target needs
+    /// an allowed extension too.
+    pub fn check_write(&self, path: &Path, extensions: &[&str]) -> Result<PathBuf, String> {
+        Self::check_extension(path, extensions)?;
+        let canonical = self.check_location(path)?;
+        if path.exists() {
+            Self::check_extension(&canonical, extensions)?;
+        }
+        Ok(canonical)
+    }
+
+    /// Check that a folder files are read from or written to (created if
+    /// missing) lies in an approved directory
+    pub fn check_dir(&self, path: &Path) -> Result<PathBuf, String> {
+        if path.exists() && !path.is_dir() {
+            return Err(format!("Not a folder: {}", path.display()));
+        }
+        self.check_location(path)
+    }
+
+    /// Resolve a path that may not exist yet and check it lies in an approved directory
+    fn check_location(&self, path: &Path) -> Result<PathBuf, String> {
+        // An existing path is resolved itself so a symlink can't point outside the scope
+        let canonical = if path.exists() {
+            path.canonicalize()
+                .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?
+        } else {
+            let name = path.file_name().ok_or_else(|| format!("Invalid file path: {}", path.display()))?;
+            let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            parent
+                .canonicalize()
+                .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?
+                .join(name)
+        };
+
+        if !self.contains(&canonical) {
+            return Err(format!("Access denied: {} is outside the approved folders", path.display()));
+        }
+        Ok(canonical)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_scope_checks() {
+        let temp_dir = env::temp_dir().join("piano-app-test-scope");
+        let projects = temp_dir.join("projects");
+        let other = temp_dir.join("other");
+        fs::remove_dir_all(&temp_dir).ok();
+        fs::create_dir_all(&projects).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        fs::write(other.join("secret.json"), "{}").unwrap();
+
+        let scope = FsScope::new(temp_dir.clone()).unwrap();
+        scope.revoke(&projects.canonicalize().unwrap()).unwrap();
+        assert!(scope.check_write(&projects.join("song.json"), &PROJECT_EXTENSIONS).is_err());
+
+        scope.approve(&projects).unwrap();
+        assert!(scope.check_write(&projects.join("song.json"), &PROJECT_EXTENSIONS).is_ok());
+        assert!(scope.check_write(&projects.join("song.exe"), &PROJECT_EXTENSIONS).is_err());
+        assert!(scope.check_write(&other.join("song.json"), &PROJECT_EXTENSIONS).is_err());
+        assert!(scope.check_read(&projects.join("..").join("other").join("secret.json"), &PROJECT_EXTENSIONS).is_err());
+
+        // Reads need an allowed extension too, and folders must be inside the scope
+        fs::write(projects.join("notes.txt"), "").unwrap();
+        assert!(scope.check_read(&projects.join("notes.txt"), &PROJECT_EXTENSIONS).is_err());
+        assert!(scope.check_dir(&projects.join("exports")).is_ok());
+        assert!(scope.check_dir(&other).is_err());
+        assert!(scope.check_dir(&projects.join("..").join("other").join("exports")).is_err());
+        assert!(scope.check_dir(&projects.join("notes.txt")).is_err());
+
+        // A link with a project extension can't stand in for another file
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(projects.join("notes.txt"), projects.join("link.json")).unwrap();
+            assert!(scope.check_read(&projects.join("link.json"), &PROJECT_EXTENSIONS).is_err());
+            assert!(scope.check_write(&projects.join("link.json"), &PROJECT_EXTENSIONS).is_err());
+
+            fs::write(projects.join("real.json"), "{}").unwrap();
+            std::os::unix::fs::symlink(projects.join("real.json"), projects.join("alias.json")).unwrap();
+            assert_eq!(
+                scope.check_read(&projects.join("alias.json"), &PROJECT_EXTENSIONS).unwrap(),
+                projects.canonicalize().unwrap().join("real.json")
+            );
+        }
+
+        // Approvals persist
+        let reloaded = FsScope::new(temp_dir.clone()).unwrap();
+        assert_eq!(reloaded.directories(), vec![projects.canonicalize().unwrap()]);
+
+        // Revoking resolves the path like approving does
+        reloaded.revoke(&other.join("..").join("projects")).unwrap();
+        assert!(reloaded.directories().is_empty());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
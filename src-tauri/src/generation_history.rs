@@ -0,0 +1,266 @@
+use crate::ai_models::{AIProvider, MelodyRequest, MelodyResponse, Note};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Generations kept in the app-level log; the oldest are dropped beyond this
+pub const MAX_LOG_ENTRIES: usize = 500;
+
+/// An AI generation kept in the project so earlier takes can be compared and restored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationRecord {
+    pub id: String,
+    pub prompt: String,
+    pub measures: u32,
+    pub response: MelodyResponse,
+}
+
+impl GenerationRecord {
+    pub fn new(prompt: String, measures: u32, response: MelodyResponse) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            prompt,
+            measures,
+            response,
+        }
+    }
+}
+
+/// Summary of a generation for history lists (without the notes)
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationSummary {
+    pub id: String,
+    pub prompt: String,
+    pub provider: AIProvider,
+    pub model_name: String,
+    pub timestamp: String,
+    pub measures: u32,
+    pub note_count: usize,
+    /// Whether the user kept it (app-level log only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<GenerationOutcome>,
+}
+
+impl From<&GenerationRecord> for GenerationSummary {
+    fn from(record: &GenerationRecord) -> Self {
+        Self {
+            id: record.id.clone(),
+            prompt: record.prompt.clone(),
+            provider: record.response.metadata.provider.clone(),
+            model_name: record.response.metadata.model_name.clone(),
+            timestamp: record.response.metadata.timestamp.clone(),
+            measures: record.measures,
+            note_count: record.response.notes.len(),
+            outcome: None,
+        }
+    }
+}
+
+/// What the user did with a generation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GenerationOutcome {
+    #[default]
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+/// A generation in the app-level log, with the full request it was made from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedGeneration {
+    #[serde(flatten)]
+    pub record: GenerationRecord,
+    pub request: MelodyRequest,
+    #[serde(default)]
+    pub outcome: GenerationOutcome,
+}
+
+/// Every generation made in the app, whether or not a project was saved,
+/// persisted in the app data directory
+pub struct GenerationLog {
+    storage_path: PathBuf,
+    entries: Mutex<Vec<LoggedGeneration>>,
+}
+
+impl GenerationLog {
+    /// Load the saved log
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let storage_path = app_data_dir.join("generation_log.json");
+
+        let entries = if storage_path.exists() {
+            let data = fs::read_to_string(&storage_path)
+                .map_err(|e| format!("Failed to read generation log: {}", e))?;
+            serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse generation log: {}", e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            storage_path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    fn save(&self, entries: &[LoggedGeneration]) -> Result<(), String> {
+        let data = serde_json::to_string(entries)
+            .map_err(|e| format!("Failed to serialize generation log: {}", e))?;
+        fs::write(&self.storage_path, data)
+            .map_err(|e| format!("Failed to write generation log: {}", e))
+    }
+
+    /// Log a generation; returns its id
+    pub fn add(&self, request: &MelodyRequest, response: &MelodyResponse) -> Result<String, String> {
+        let record = GenerationRecord::new(request.prompt.clone(), request.measures, response.clone());
+        let id = record.id.clone();
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push(LoggedGeneration { record, request: request.clone(), outcome: GenerationOutcome::Pending });
+        let excess = entries.len().saturating_sub(MAX_LOG_ENTRIES);
+        entries.drain(..excess);
+        self.save(&entries)?;
+        Ok(id)
+    }
+
+    /// Summaries of the logged generations, oldest first
+    pub fn list(&self) -> Vec<GenerationSummary> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| GenerationSummary { outcome: Some(entry.outcome), ..GenerationSummary::from(&entry.record) })
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<LoggedGeneration> {
+        self.entries.lock().unwrap().iter().find(|entry| entry.record.id == id).cloned()
+    }
+
+    /// Mark a generation accepted or rejected
+    pub fn set_outcome(&self, id: &str, outcome: GenerationOutcome) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .iter_mut()
+            .find(|entry| entry.record.id == id)
+            .ok_or_else(|| format!("Generation not found: {}", id))?;
+        entry.outcome = outcome;
+        self.save(&entries)
+    }
+
+    /// Empty the log
+    pub fn clear(&self) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.save(&entries)
+    }
+}
+
+/// Add records from `existing` that are missing in `history` (matched by id)
+///
+/// Generations are appended to the file on disk as they happen, so a save
+/// from a frontend that hasn't reloaded the history must not drop them.
+pub fn merge_missing(history: &mut Vec<GenerationRecord>, existing: Vec<GenerationRecord>) {
+    for record in existing {
+        if !history.iter().any(|r| r.id == record.id) {
+            history.push(record);
+        }
+    }
+    history.sort_by(|a, b| a.response.metadata.timestamp.cmp(&b.response.metadata.timestamp));
+}
+
+/// Copy the notes of a past generation for re-insertion
+///
+/// Notes get fresh ids, are moved to `track_id` (if given) and shifted by `offset` beats.
+pub fn reinsert_notes(record: &GenerationRecord, track_id: Option<&str>, offset: f64) -> Vec<Note> {
+    record
+        .response
+        .notes
+        .iter()
+        .map(|note| Note {
+            id: uuid::Uuid::new_v4().to_string(),
+            start_time: note.start_time + offset,
+            track_id: track_id.unwrap_or(note.track_id.as_str()).to_string(),
+            ..note.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_models::GenerationMetadata;
+    use std::env;
+
+    fn record(id: &str, timestamp: &str) -> GenerationRecord {
+        GenerationRecord {
+            id: id.to_string(),
+            prompt: "jazzy".to_string(),
+            measures: 4,
+            response: MelodyResponse {
+                notes: vec![Note {
+                    id: "n1".to_string(),
+                    pitch: 60,
+                    start_time: 1.0,
+                    duration: 1.0,
+                    velocity: 100,
+                    track_id: "track_ai".to_string(),
+                }],
+                chords: Vec::new(),
+                dynamics: Vec::new(),
+                pedal: Vec::new(),
+                metadata: GenerationMetadata {
+                    provider: AIProvider::OpenAI,
+                    timestamp: timestamp.to_string(),
+                    model_name: "gpt-4".to_string(),
+                    temperature: 1.0,
+                    scale: None,
+                    seed: None,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_merge_missing_keeps_both_sides() {
+        let mut history = vec![record("b", "2024-01-02")];
+        merge_missing(&mut history, vec![record("a", "2024-01-01"), record("b", "2024-01-02")]);
+
+        let ids: Vec<&str> = history.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_reinsert_notes_gets_new_ids() {
+        let notes = reinsert_notes(&record("a", "2024-01-01"), Some("track_2"), 4.0);
+
+        assert_eq!(notes.len(), 1);
+        assert_ne!(notes[0].id, "n1");
+        assert_eq!(notes[0].track_id, "track_2");
+        assert_eq!(notes[0].start_time, 5.0);
+    }
+
+    #[test]
+    fn test_generation_log_persists_outcomes() {
+        let temp_dir = env::temp_dir().join("piano-app-test-generation-log");
+        fs::create_dir_all(&temp_dir).unwrap();
+        fs::remove_file(temp_dir.join("generation_log.json")).ok();
+
+        let log = GenerationLog::new(temp_dir.clone()).unwrap();
+        let request = MelodyRequest { prompt: "jazzy".to_string(), ..MelodyRequest::default() };
+        let id = log.add(&request, &record("a", "2024-01-01").response).unwrap();
+        log.set_outcome(&id, GenerationOutcome::Accepted).unwrap();
+        assert!(log.set_outcome("missing", GenerationOutcome::Rejected).is_err());
+
+        let reloaded = GenerationLog::new(temp_dir.clone()).unwrap();
+        let summaries = reloaded.list();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].prompt, "jazzy");
+        assert_eq!(summaries[0].outcome, Some(GenerationOutcome::Accepted));
+        assert_eq!(reloaded.get(&id).unwrap().request.measures, 4);
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
@@ -0,0 +1,105 @@
+use crate::api_key_storage::{GoogleCredentials, Secret, ServiceAccountKey};
+use anyhow::{Context, Result};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Tokens are refreshed this long before they expire
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// Access token minted for a service account, reused until shortly before it expires
+struct CachedToken {
+    client_email: String,
+    access_token: Secret,
+    expires_at: i64,
+}
+
+static TOKEN_CACHE: Mutex<Option<CachedToken>> = Mutex::new(None);
+
+#[derive(Debug, Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Secret,
+    expires_in: i64,
+}
+
+/// Bearer token for Google Cloud APIs
+///
+/// OAuth access tokens are used as-is; service accounts are exchanged for an
+/// access token with a signed JWT (RFC 7523), cached for the token lifetime.
+pub async fn access_token(client: &Client, credentials: &GoogleCredentials) -> Result<Secret> {
+    match credentials {
+        GoogleCredentials::AccessToken(token) => Ok(token.clone()),
+        GoogleCredentials::ServiceAccount(key) => service_account_token(client, key).await,
+    }
+}
+
+async fn service_account_token(client: &Client, key: &ServiceAccountKey) -> Result<Secret> {
+    let now = chrono::Utc::now().timestamp();
+
+    if let Some(cached) = TOKEN_CACHE.lock().unwrap().as_ref() {
+        if cached.client_email == key.client_email && cached.expires_at - EXPIRY_MARGIN_SECS > now {
+            return Ok(cached.access_token.clone());
+        }
+    }
+
+    let token_uri = key.token_uri.as_deref().unwrap_or(DEFAULT_TOKEN_URI);
+    let assertion = Secret::new(signed_assertion(key, token_uri, now)?);
+
+    let response = client
+        .post(token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", assertion.as_str()),
+        ])
+        .send()
+        .await
+        .context("Failed to reach Google OAuth")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(anyhow::anyhow!("Google OAuth error ({}): {}", status, error_text));
+    }
+
+    let token: TokenResponse = response
+        .json()
+        .await
+        .context("Failed to parse Google OAuth response")?;
+
+    *TOKEN_CACHE.lock().unwrap() = Some(CachedToken {
+        client_email: key.client_email.clone(),
+        access_token: token.access_token.clone(),
+        expires_at: now + token.expires_in,
+    });
+
+    Ok(token.access_token)
+}
+
+/// JWT asserting the service account's identity, signed with its private key
+fn signed_assertion(key: &ServiceAccountKey, token_uri: &str, now: i64) -> Result<String> {
+    let claims = Claims {
+        iss: &key.client_email,
+        scope: CLOUD_PLATFORM_SCOPE,
+        aud: token_uri,
+        iat: now,
+        exp: now + 3600,
+    };
+
+    let encoding_key = EncodingKey::from_rsa_pem(key.private_key.as_bytes())
+        .context("Service account private key is not a valid RSA key")?;
+    jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &encoding_key)
+        .context("Failed to sign service account assertion")
+}
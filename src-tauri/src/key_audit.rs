@@ -0,0 +1,265 @@
+use crate::ai_models::AIProvider;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Entries a query returns when the caller doesn't give a limit
+pub const DEFAULT_QUERY_LIMIT: usize = 200;
+
+/// An HTTP request to a provider, as the client saw it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApiCall {
+    /// URL without the query string
+    pub endpoint: String,
+    pub status: u16,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+}
+
+tokio::task_local! {
+    /// Requests made inside `collect_calls`
+    static CALLS: RefCell<Vec<ApiCall>>;
+}
+
+/// Note a provider's response; does nothing outside `collect_calls`
+pub fn record_response(response: &reqwest::Response) {
+    let mut url = response.url().clone();
+    url.set_query(None);
+    let call = ApiCall { endpoint: url.to_string(), status: response.status().as_u16(), ..ApiCall::default() };
+    let _ = CALLS.try_with(|calls| calls.borrow_mut().push(call));
+}
+
+/// Add the token counts a provider reported to the last response noted
+pub fn record_usage(input_tokens: Option<u64>, output_tokens: Option<u64>) {
+    let _ = CALLS.try_with(|calls| {
+        if let Some(call) = calls.borrow_mut().last_mut() {
+            call.input_tokens = input_tokens;
+            call.output_tokens = output_tokens;
+        }
+    });
+}
+
+/// Run `future`, collecting the provider requests it makes
+pub async fn collect_calls<F: Future>(future: F) -> (F::Output, Vec<ApiCall>) {
+    CALLS
+        .scope(RefCell::new(Vec::new()), async move {
+            let output = future.await;
+            (output, CALLS.with(|calls| calls.take()))
+        })
+        .await
+}
+
+/// A request made with a provider key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// RFC 3339 (UTC)
+    pub timestamp: String,
+    pub provider: AIProvider,
+    /// Saved key used (see `list_ai_api_keys`); None for keys from environment variables
+    pub key_id: Option<String>,
+    pub key_label: Option<String>,
+    /// What the request was for: "generate", "analyze", "list_models", ...
+    pub operation: String,
+    /// None when the provider couldn't be reached
+    pub endpoint: Option<String>,
+    pub status: Option<u16>,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Which entries `KeyAudit::query` returns
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AuditQuery {
+    pub provider: Option<AIProvider>,
+    pub key_id: Option<String>,
+    /// RFC 3339 bounds, inclusive
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Most entries returned, newest first (default 200)
+    pub limit: Option<usize>,
+}
+
+/// Entries matching a query, with totals over all of them (not just those returned)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditReport {
+    pub entries: Vec<AuditEntry>,
+    pub requests: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("Invalid time {}: {}", time, e))
+}
+
+/// Append-only log of the requests made with each provider key, one JSON
+/// entry per line in the app data directory
+///
+/// Entries are never rewritten, so the log can account for a shared key's use.
+pub struct KeyAudit {
+    storage_path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl KeyAudit {
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        fs::create_dir_all(&app_data_dir).map_err(|e| format!("Failed to create app data directory: {}", e))?;
+        Ok(Self { storage_path: app_data_dir.join("api_key_audit.jsonl"), lock: Mutex::new(()) })
+    }
+
+    /// Log the requests of one provider call
+    ///
+    /// A call that failed before reaching the provider is logged without an
+    /// endpoint; a call's error goes with its last request.
+    pub fn record(
+        &self,
+        provider: &AIProvider,
+        key: Option<(String, Option<String>)>,
+        operation: &str,
+        calls: Vec<ApiCall>,
+        error: Option<String>,
+    ) -> Result<(), String> {
+        let timestamp = Utc::now().to_rfc3339();
+        let (key_id, key_label) = key.unzip();
+        let entry = |call: Option<ApiCall>, error: Option<String>| AuditEntry {
+            timestamp: timestamp.clone(),
+            provider: provider.clone(),
+            key_id: key_id.clone(),
+            key_label: key_label.clone().flatten(),
+            operation: operation.to_string(),
+            endpoint: call.as_ref().map(|call| call.endpoint.clone()),
+            status: call.as_ref().map(|call| call.status),
+            input_tokens: call.as_ref().and_then(|call| call.input_tokens),
+            output_tokens: call.as_ref().and_then(|call| call.output_tokens),
+            error,
+        };
+
+        let entries: Vec<AuditEntry> = if calls.is_empty() {
+            vec![entry(None, error)]
+        } else {
+            let last = calls.len() - 1;
+            calls
+                .into_iter()
+                .enumerate()
+                .map(|(i, call)| entry(Some(call), if i == last { error.clone() } else { None }))
+                .collect()
+        };
+
+        let mut lines = String::new();
+        for entry in &entries {
+            let line = serde_json::to_string(entry).map_err(|e| format!("Failed to serialize audit entry: {}", e))?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+
+        let _guard = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.storage_path)
+            .map_err(|e| format!("Failed to open key audit log: {}", e))?;
+        file.write_all(lines.as_bytes()).map_err(|e| format!("Failed to write key audit log: {}", e))
+    }
+
+    /// Entries matching `query`, newest first
+    ///
+    /// The log is read line by line, keeping only the newest `limit` matches.
+    /// Lines that can't be parsed (e.g. cut off by a crash, or being written
+    /// right now) are skipped.
+    pub fn query(&self, query: &AuditQuery) -> Result<AuditReport, String> {
+        let since = query.since.as_deref().map(parse_time).transpose()?;
+        let until = query.until.as_deref().map(parse_time).transpose()?;
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+
+        let mut report = AuditReport { entries: Vec::new(), requests: 0, input_tokens: 0, output_tokens: 0 };
+        let file = match File::open(&self.storage_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(report),
+            Err(e) => return Err(format!("Failed to read key audit log: {}", e)),
+        };
+
+        let mut newest = VecDeque::with_capacity(limit.min(DEFAULT_QUERY_LIMIT));
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Failed to read key audit log: {}", e))?;
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else { continue };
+            if query.provider.as_ref().is_some_and(|provider| *provider != entry.provider)
+                || query.key_id.as_ref().is_some_and(|key_id| entry.key_id.as_ref() != Some(key_id))
+            {
+                continue;
+            }
+            let Ok(time) = parse_time(&entry.timestamp) else { continue };
+            if since.is_some_and(|since| time < since) || until.is_some_and(|until| time > until) {
+                continue;
+            }
+
+            report.requests += 1;
+            report.input_tokens += entry.input_tokens.unwrap_or(0);
+            report.output_tokens += entry.output_tokens.unwrap_or(0);
+            if limit > 0 {
+                if newest.len() == limit {
+                    newest.pop_front();
+                }
+                newest.push_back(entry);
+            }
+        }
+
+        report.entries = newest.into_iter().rev().collect();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_audit_log_records_and_queries() {
+        let temp_dir = env::temp_dir().join("piano-app-test-key-audit");
+        fs::remove_dir_all(&temp_dir).ok();
+        let audit = KeyAudit::new(temp_dir.clone()).unwrap();
+
+        let call = |status, tokens: Option<(u64, u64)>| ApiCall {
+            endpoint: "https://api.openai.com/v1/chat/completions".to_string(),
+            status,
+            input_tokens: tokens.map(|(input, _)| input),
+            output_tokens: tokens.map(|(_, output)| output),
+        };
+        let team = Some(("openai:team".to_string(), Some("team".to_string())));
+        audit.record(&AIProvider::OpenAI, team.clone(), "generate", vec![call(200, Some((120, 300)))], None).unwrap();
+        audit
+            .record(&AIProvider::OpenAI, team, "generate", vec![call(500, None), call(200, Some((100, 250)))], None)
+            .unwrap();
+        audit.record(&AIProvider::Gemini, None, "analyze", Vec::new(), Some("offline".to_string())).unwrap();
+
+        let all = audit.query(&AuditQuery::default()).unwrap();
+        assert_eq!(all.requests, 4);
+        assert_eq!(all.entries[0].error.as_deref(), Some("offline"));
+        assert_eq!(all.entries[0].endpoint, None);
+
+        let team_use = audit.query(&AuditQuery { key_id: Some("openai:team".to_string()), ..AuditQuery::default() }).unwrap();
+        assert_eq!((team_use.requests, team_use.input_tokens, team_use.output_tokens), (3, 220, 550));
+        assert_eq!(team_use.entries[0].key_label.as_deref(), Some("team"));
+
+        let limited = audit.query(&AuditQuery { limit: Some(1), ..AuditQuery::default() }).unwrap();
+        assert_eq!((limited.entries.len(), limited.requests), (1, 4));
+        assert_eq!(limited.entries[0].provider, AIProvider::Gemini);
+        let later = AuditQuery { since: Some("2999-01-01T00:00:00Z".to_string()), ..AuditQuery::default() };
+        assert_eq!(audit.query(&later).unwrap().requests, 0);
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
@@ -0,0 +1,171 @@
+use crate::midi::MidiEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use validator::Validate;
+
+/// Computer keyboard layout used to map keys to pitches
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    Qwerty,
+    Azerty,
+    Bepo,
+}
+
+/// QWERTY: bottom row C3-B3, home/number rows C4-G5 (mirrors `keyboardLayouts.ts`)
+const QWERTY_KEYS: &[(&str, u8)] = &[
+    ("z", 48), ("s", 49), ("x", 50), ("d", 51), ("c", 52), ("v", 53), ("g", 54), ("b", 55),
+    ("h", 56), ("n", 57), ("j", 58), ("m", 59),
+    ("q", 60), ("2", 61), ("w", 62), ("3", 63), ("e", 64), ("r", 65), ("5", 66), ("t", 67),
+    ("6", 68), ("y", 69), ("7", 70), ("u", 71), ("i", 72),
+    ("9", 73), ("o", 74), ("0", 75), ("p", 76), ("[", 77), ("=", 78), ("]", 79),
+];
+
+/// AZERTY: same physical positions as QWERTY
+const AZERTY_KEYS: &[(&str, u8)] = &[
+    ("w", 48), ("s", 49), ("x", 50), ("d", 51), ("c", 52), ("v", 53), ("g", 54), ("b", 55),
+    ("h", 56), ("n", 57), ("j", 58), (",", 59),
+    ("a", 60), ("é", 61), ("z", 62), ("\"", 63), ("e", 64), ("r", 65), ("(", 66), ("t", 67),
+    ("-", 68), ("y", 69), ("è", 70), ("u", 71), ("i", 72),
+    ("ç", 73), ("o", 74), ("à", 75), ("p", 76), ("^", 77), ("=", 78), ("$", 79),
+];
+
+/// BÉPO: same physical positions as QWERTY, extended to A5
+const BEPO_KEYS: &[(&str, u8)] = &[
+    ("ê", 48), ("\"", 49), ("à", 50), ("1", 51), ("y", 52), ("x", 53), ("2", 54), (".", 55),
+    ("3", 56), ("k", 57), ("4", 58), ("'", 59),
+    ("a", 60), ("b", 61), ("u", 62), ("é", 63), ("i", 64), ("e", 65), ("p", 66), (",", 67),
+    ("o", 68), ("c", 69), ("è", 70), ("t", 71), ("s", 72),
+    ("^", 73), ("r", 74), ("v", 75), ("n", 76), ("m", 77), ("d", 78), ("ç", 79), ("l", 80),
+    ("q", 81),
+];
+
+impl KeyboardLayout {
+    fn keys(&self) -> &'static [(&'static str, u8)] {
+        match self {
+            KeyboardLayout::Qwerty => QWERTY_KEYS,
+            KeyboardLayout::Azerty => AZERTY_KEYS,
+            KeyboardLayout::Bepo => BEPO_KEYS,
+        }
+    }
+
+    /// Base pitch (before octave shift) for a key value, case-insensitive
+    pub fn pitch_for(&self, key: &str) -> Option<u8> {
+        let key = key.to_lowercase();
+        self.keys().iter().find(|(k, _)| *k == key).map(|&(_, pitch)| pitch)
+    }
+}
+
+/// Computer keyboard note settings
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Validate)]
+pub struct KeyboardSettings {
+    pub layout: KeyboardLayout,
+
+    /// Octave transposition applied to the layout
+    #[validate(range(min = -3, max = 3))]
+    pub octave_shift: i8,
+
+    /// Velocity of keyboard notes (1-127)
+    #[validate(range(min = 1, max = 127))]
+    pub velocity: u8,
+}
+
+impl Default for KeyboardSettings {
+    fn default() -> Self {
+        Self {
+            layout: KeyboardLayout::Qwerty,
+            octave_shift: 0,
+            velocity: 100,
+        }
+    }
+}
+
+/// Turns computer keyboard events into note events in the backend
+///
+/// Tracks which pitch each held key started, so changing octave while a key
+/// is down still releases the right note, and auto-repeat is ignored.
+pub struct KeyboardEngine {
+    settings: Mutex<KeyboardSettings>,
+    held: Mutex<HashMap<String, u8>>, // key -> sounding pitch
+}
+
+impl KeyboardEngine {
+    pub fn new() -> Self {
+        Self {
+            settings: Mutex::new(KeyboardSettings::default()),
+            held: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn settings(&self) -> KeyboardSettings {
+        *self.settings.lock().unwrap()
+    }
+
+    pub fn set_settings(&self, settings: KeyboardSettings) {
+        *self.settings.lock().unwrap() = settings;
+    }
+
+    /// Translate a key press/release into a note event (None if unmapped or auto-repeat)
+    pub fn key_event(&self, key: &str, down: bool) -> Option<MidiEvent> {
+        let key = key.to_lowercase();
+        let mut held = self.held.lock().unwrap();
+
+        if !down {
+            return held
+                .remove(&key)
+                .map(|pitch| MidiEvent::NoteOff { channel: 0, pitch });
+        }
+
+        if held.contains_key(&key) {
+            return None;
+        }
+
+        let settings = self.settings();
+        let base = settings.layout.pitch_for(&key)? as i16;
+        let pitch = base + settings.octave_shift as i16 * 12;
+        if !(0..=127).contains(&pitch) {
+            return None;
+        }
+
+        held.insert(key, pitch as u8);
+        Some(MidiEvent::NoteOn {
+            channel: 0,
+            pitch: pitch as u8,
+            velocity: settings.velocity,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_lookup() {
+        assert_eq!(KeyboardLayout::Qwerty.pitch_for("Q"), Some(60));
+        assert_eq!(KeyboardLayout::Azerty.pitch_for("a"), Some(60));
+        assert_eq!(KeyboardLayout::Bepo.pitch_for("q"), Some(81));
+        assert_eq!(KeyboardLayout::Qwerty.pitch_for("F1"), None);
+    }
+
+    #[test]
+    fn test_octave_shift_and_release() {
+        let engine = KeyboardEngine::new();
+        engine.set_settings(KeyboardSettings {
+            octave_shift: 1,
+            ..KeyboardSettings::default()
+        });
+
+        assert_eq!(
+            engine.key_event("q", true),
+            Some(MidiEvent::NoteOn { channel: 0, pitch: 72, velocity: 100 })
+        );
+        // Auto-repeat is ignored
+        assert_eq!(engine.key_event("q", true), None);
+
+        // Releasing after an octave change still stops the sounding pitch
+        engine.set_settings(KeyboardSettings::default());
+        assert_eq!(engine.key_event("q", false), Some(MidiEvent::NoteOff { channel: 0, pitch: 72 }));
+    }
+}
@@ -1,17 +1,103 @@
 mod sample_player;
 mod ai_models;
+mod ai_cache;
 mod ai_client;
+mod ai_conversations;
 mod ai_prompts;
+mod ai_rate_limit;
+mod ai_retry;
+mod ai_schema;
+mod ai_scoring;
+mod ai_settings;
 mod api_key_storage;
+mod app_data;
+mod aws_sigv4;
+mod google_auth;
+mod key_audit;
+mod arpeggiator;
+mod audio_input;
+mod crypto;
+mod drum_kit;
+mod encoders;
+mod fs_scope;
+mod generation_history;
+mod keyboard_input;
+mod local_model;
+mod lyrics;
+mod midi;
+mod midi_clipboard;
+mod midi_file;
+mod midi_learn;
+mod midi_router;
+mod mpe;
+mod music_theory;
+mod offline_generator;
+mod project_backups;
+mod project_crypto;
+mod project_file;
+mod project_library;
+mod project_metadata;
+mod project_repair;
+mod project_search;
+mod project_watcher;
+mod prompt_history;
+mod prompt_templates;
+mod quantize;
+mod recorder;
+mod render;
+mod step_recorder;
+mod tap_tempo;
+mod tempo_estimate;
+mod transport;
+mod voice_leading;
+mod voicing;
 
 use sample_player::SamplePlayer;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use tauri::State;
-use ai_models::{AIProvider, MelodyRequest, MelodyResponse, Scale as AIScale};
-use ai_client::create_client;
-use api_key_storage::ApiKeyManager;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio_util::sync::CancellationToken;
+use ai_models::{AIProvider, ChordSymbol, CustomSystemPrompt, EndingFix, GenerationMode, GenerationOptions, GenerationPhase, GenerationProgressEvent, MelodyCritique, MelodyRequest, MelodyResponse, Note as AINote, Scale as AIScale, SongSection, StyleTransferResponse};
+use ai_cache::ResponseCache;
+use ai_scoring::MelodyScore;
+use lyrics::NoteLyric;
+use ai_client::{create_client, AIClient, HttpClients, GENERATION_CANCELLED, MAX_TITLE_CHARS, PROVIDER_UNREACHABLE};
+use ai_conversations::Conversations;
+use ai_rate_limit::{GenerationQueuedEvent, Permit, RateLimited, RateLimiter, MAX_RETRY_AFTER};
+use ai_settings::{AiSettings, ProviderConfig, ProxyConfig};
+use api_key_storage::{ApiKeyManager, AwsCredentials, KeySource, KeyVerification, ProviderStatus, Secret, StoredKey};
+use arpeggiator::{ArpSettings, Arpeggiator};
+use audio_input::{AudioClip, AudioRecorder};
+use encoders::AudioFormat;
+use fs_scope::FsScope;
+use key_audit::{AuditQuery, AuditReport, KeyAudit};
+use generation_history::{GenerationLog, GenerationOutcome, GenerationRecord, GenerationSummary};
 use validator::Validate;
+use voice_leading::VoiceWarning;
+use voicing::VoicingOptions;
+use keyboard_input::{KeyboardEngine, KeyboardSettings};
+use midi::{MidiEvent, MidiManager};
+use midi_learn::{LearnTarget, MidiLearn, MidiMapping};
+use midi_router::MidiRouter;
+use mpe::{MpeProcessor, MpeSettings};
+use music_theory::{ChordSuggestion, HarmonicAnalysis, KeyCandidate, ScaleInfo};
+use project_backups::{BackupInfo, BackupPolicy};
+use project_file::ProjectFormat;
+use project_library::{LibraryEntry, LibraryQuery, ProjectLibrary};
+use project_metadata::ProjectMetadata;
+use project_repair::Diagnostic;
+use project_search::SearchResult;
+use project_watcher::ProjectWatcher;
+use prompt_history::PromptHistory;
+use prompt_templates::{PromptTemplate, PromptTemplates};
+use quantize::{GrooveTemplate, QuantizeSettings};
+use recorder::{RecordedTake, Recorder};
+use render::{RenderOptions, RenderProgress};
+use step_recorder::{StepCursor, StepRecorder};
+use tap_tempo::TapTempo;
+use tempo_estimate::TempoEstimate;
+use transport::{Marker, Transport};
 
 // Wrapper for OutputStream to make it Send + Sync
 // SAFETY: OutputStream is thread-safe but doesn't implement Send/Sync on macOS due to CoreAudio FFI
@@ -26,132 +112,3264 @@ struct AppState {
     sample_player: Arc<SamplePlayer>,
     _stream: Arc<StreamWrapper>,
     api_key_manager: Arc<Mutex<ApiKeyManager>>,
+    key_audit: KeyAudit,
+    http_clients: HttpClients,
+    midi: Arc<MidiManager>,
+    transport: Arc<Transport>,
+    recorder: Arc<Recorder>,
+    step_recorder: Arc<StepRecorder>,
+    arpeggiator: Arc<Arpeggiator>,
+    mpe: Arc<MpeProcessor>,
+    midi_learn: Arc<MidiLearn>,
+    keyboard: KeyboardEngine,
+    tap_tempo: TapTempo,
+    audio_recorder: AudioRecorder,
+    render_jobs: Mutex<HashMap<String, CancellationToken>>, // Running renders and exports by job id
+    library: ProjectLibrary,
+    fs_scope: FsScope,
+    project_watcher: ProjectWatcher,
+    ai_settings: AiSettings,
+    generations: Mutex<HashMap<String, CancellationToken>>, // In-flight AI generations by request id
+    conversations: Conversations,
+    generation_log: GenerationLog,
+    rate_limiter: RateLimiter,
+    response_cache: ResponseCache,
+    prompt_templates: PromptTemplates,
+    prompt_history: PromptHistory,
 }
 
-#[derive(Serialize, Deserialize)]
+impl AppState {
+    /// Build a router sending note/control events to every live-input subsystem
+    fn midi_router(&self, app: AppHandle) -> MidiRouter {
+        MidiRouter {
+            sample_player: self.sample_player.clone(),
+            transport: self.transport.clone(),
+            recorder: self.recorder.clone(),
+            step_recorder: self.step_recorder.clone(),
+            arpeggiator: self.arpeggiator.clone(),
+            mpe: self.mpe.clone(),
+            midi_learn: self.midi_learn.clone(),
+            app,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Note {
     id: String,
     pitch: u8,
     start_time: f32,
     duration: f32,
     velocity: u8,
+    #[serde(default)]
+    track_id: String,
+}
+
+/// Track mixer settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Track {
+    id: String,
+    name: String,
+    #[serde(default = "default_track_volume")]
+    volume: f32, // 0.0-1.0
+    #[serde(default)]
+    pan: f32, // -1.0 (left) to 1.0 (right)
+    #[serde(default)]
+    muted: bool,
+    #[serde(default)]
+    solo: bool,
+}
+
+fn default_track_volume() -> f32 {
+    1.0
+}
+
+/// Sustain pedal (CC64) state change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PedalEvent {
+    time: f32, // In beats
+    down: bool,
+}
+
+/// A custom sample played for a range of keys instead of the built-in piano
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SampleMapping {
+    /// Path of the audio file, relative to the project file's directory
+    path: String,
+    /// Key the sample was recorded at
+    root_pitch: u8,
+    /// Lowest and highest keys using the sample
+    low_pitch: u8,
+    high_pitch: u8,
+}
+
+#[derive(Default, Serialize, Deserialize, Validate)]
+struct ProjectData {
+    notes: Vec<Note>,
+    tempo: u16,
+    #[serde(default)]
+    time_signature: ai_models::TimeSignature,
+    name: String,
+    #[serde(default)]
+    created_at: String,
+    #[serde(default)]
+    pedal_events: Vec<PedalEvent>,
+    #[serde(default)]
+    audio_clips: Vec<AudioClip>,
+    #[serde(default)]
+    sample_mappings: Vec<SampleMapping>,
+    #[serde(default)]
+    tracks: Vec<Track>,
+    #[serde(default)]
+    #[validate(nested)]
+    metadata: ProjectMetadata,
+    #[serde(default)]
+    #[validate(nested)]
+    markers: Vec<Marker>,
+    #[serde(default)]
+    generations: Vec<GenerationRecord>,
+    #[serde(default)]
+    lyrics: Vec<NoteLyric>,
+}
+
+/// Play a single note
+#[tauri::command]
+fn play_note(pitch: u8, duration: f32, velocity: u8, state: State<AppState>) -> Result<(), String> {
+    // Use Arc to allow concurrent playback - no mutex needed for read-only operations
+    // SamplePlayer is read-only during playback, Arc allows concurrent access
+    state.sample_player.play_note(pitch, duration, velocity)
+}
+
+/// Play a drum hit: `pitch` is a General MIDI drum note (used for the drum track)
+#[tauri::command]
+fn play_drum(pitch: u8, velocity: u8, state: State<AppState>) -> Result<(), String> {
+    state.sample_player.play_drum(pitch, velocity)
+}
+
+/// Set the sustain pedal state (used by playback to honor the pedal lane)
+#[tauri::command]
+fn set_sustain_pedal(down: bool, state: State<AppState>) {
+    state.sample_player.set_sustain(down);
+}
+
+/// Set the master volume (0.0-1.0)
+#[tauri::command]
+fn set_volume(volume: f32, state: State<AppState>) {
+    state.sample_player.set_volume(volume);
+}
+
+/// MIDI panic: silence all internal voices and send note-off/all-sound-off to every MIDI output
+#[tauri::command]
+fn all_notes_off(state: State<AppState>) -> Result<(), String> {
+    // Silence internal voices first so local sound stops even if a MIDI port fails
+    state.arpeggiator.disable();
+    state.sample_player.stop_all_voices();
+    state.midi.send_panic()
+}
+
+/// Save project to a file
+///
+/// Metadata is validated and `modified_at` stamped; `created_at` is set on the
+/// first save. AI generations already recorded in the file are kept. The file
+/// is replaced atomically; the previous version is kept as `<path>.bak` and as
+/// a timestamped copy in `backups/`, pruned by the backup policy.
+/// `format` defaults to the format of the existing file, or JSON for new files.
+///
+/// With a `password` the file is encrypted (Argon2 + AES-256-GCM) and left out
+/// of the project library so its name, tags and prompts aren't stored in plain text.
+/// `current_password` only unlocks the existing file to carry its history over,
+/// so encryption can be removed or the password changed.
+/// An existing file that can't be read (an encrypted one without its password,
+/// or a damaged one) is never overwritten; the save fails instead.
+#[tauri::command]
+fn save_project(
+    mut project: ProjectData,
+    path: String,
+    format: Option<ProjectFormat>,
+    password: Option<String>,
+    current_password: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let now = chrono::Local::now().to_rfc3339();
+    if project.created_at.is_empty() {
+        project.created_at = now.clone();
+    }
+    project.metadata.normalize();
+    project.metadata.modified_at = Some(now);
+
+    project.validate()
+        .map_err(|e| format!("Invalid project: {}", e))?;
+    // Write where the check resolved to, so a symlink swapped in afterwards can't redirect the save
+    let path = path_string(state.fs_scope.check_write(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+
+    let password = password.as_deref();
+    let previous = std::fs::read(&path).ok();
+    let mut format = format;
+    if let Some(data) = previous.as_deref() {
+        // Overwriting a file that can't be read would drop its history (and its encryption)
+        let (existing, existing_format) = decode_project_data(data, current_password.as_deref()).map_err(|e| {
+            if e == project_crypto::PASSWORD_REQUIRED {
+                e
+            } else {
+                format!("Can't read the existing file, so it wasn't overwritten: {}", e)
+            }
+        })?;
+        generation_history::merge_missing(&mut project.generations, existing.generations);
+        format = format.or(Some(existing_format));
+    }
+
+    // Don't leave readable copies behind when a plain project gets encrypted
+    let newly_encrypted = password.is_some()
+        && previous.as_deref().is_some_and(|data| !project_crypto::is_encrypted(data));
+    if !newly_encrypted {
+        keep_backup(std::path::Path::new(&path), &state);
+    }
+
+    let data = encode_project_file(&project, format.unwrap_or_default(), password)?;
+    state.project_watcher.expect_write(std::path::Path::new(&path), &data);
+    project_file::write_atomic(std::path::Path::new(&path), &data)?;
+
+    if password.is_some() {
+        if newly_encrypted {
+            let _ = std::fs::remove_file(project_file::backup_path(std::path::Path::new(&path)));
+            // The encrypted file is already written; leftover backups don't undo the save
+            if let Err(e) = project_backups::purge_plaintext_backups(std::path::Path::new(&path)) {
+                eprintln!("⚠ {}", e);
+            }
+        }
+        return state.library.remove(&path);
+    }
+
+    // The file is saved; a stale library entry is only cosmetic
+    if let Err(e) = state.library.index(&path, &project) {
+        eprintln!("⚠ {}", e);
+    }
+    Ok(())
+}
+
+/// Library setting holding the backup policy (JSON)
+const BACKUP_POLICY_SETTING: &str = "backup_policy";
+
+/// Current backup policy, or the default if none was set
+fn backup_policy(state: &AppState) -> BackupPolicy {
+    state
+        .library
+        .setting(BACKUP_POLICY_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Copy the current version of a project into its `backups/` folder and prune old ones
+///
+/// Backup failures are logged rather than blocking the save.
+fn keep_backup(path: &std::path::Path, state: &AppState) {
+    let result = project_backups::create_backup(path)
+        .and_then(|_| project_backups::prune_backups(path, &backup_policy(state)));
+    if let Err(e) = result {
+        eprintln!("⚠ Backup failed: {}", e);
+    }
+}
+
+/// Get the rotating backup policy
+#[tauri::command]
+fn get_backup_policy(state: State<AppState>) -> BackupPolicy {
+    backup_policy(&state)
+}
+
+/// Set how many backups are kept per project
+#[tauri::command]
+fn set_backup_policy(policy: BackupPolicy, state: State<AppState>) -> Result<(), String> {
+    policy.validate()
+        .map_err(|e| format!("Invalid backup policy: {}", e))?;
+
+    let json = serde_json::to_string(&policy)
+        .map_err(|e| format!("Failed to serialize backup policy: {}", e))?;
+    state.library.set_setting(BACKUP_POLICY_SETTING, &json)
+}
+
+/// List the backups of a project, newest first
+#[tauri::command]
+fn list_backups(path: String, state: State<AppState>) -> Result<Vec<BackupInfo>, String> {
+    let path = state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?;
+    project_backups::list_backups(&path)
+}
+
+/// Replace a project with one of its backups (the current version is backed up first)
+#[tauri::command]
+fn restore_backup(path: String, id: String, state: State<AppState>) -> Result<(), String> {
+    let path = &state.fs_scope.check_write(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?;
+    let data = project_backups::read_backup(path, &id)?;
+    state.project_watcher.expect_write(path, &data);
+    project_backups::restore_backup(path, &data)?;
+    project_backups::prune_backups(path, &backup_policy(&state))?;
+    Ok(())
+}
+
+/// Serialize a project and optionally encrypt it
+fn encode_project_file(project: &ProjectData, format: ProjectFormat, password: Option<&str>) -> Result<Vec<u8>, String> {
+    let data = project_file::encode_project(project, format)?;
+    match password {
+        Some(password) => project_crypto::encrypt(&data, password),
+        None => Ok(data),
+    }
+}
+
+/// Serialize a project, optionally encrypt it, and write it atomically
+///
+/// The watcher is told about the write first, so it isn't reported as an external change.
+fn write_project(
+    path: &str,
+    project: &ProjectData,
+    format: ProjectFormat,
+    password: Option<&str>,
+    watcher: &ProjectWatcher,
+) -> Result<(), String> {
+    let data = encode_project_file(project, format, password)?;
+    watcher.expect_write(std::path::Path::new(path), &data);
+    project_file::write_atomic(std::path::Path::new(path), &data)
+}
+
+/// A path returned by the `fs_scope` checks, as the string the project helpers take
+fn path_string(path: std::path::PathBuf) -> Result<String, String> {
+    path.into_os_string()
+        .into_string()
+        .map_err(|path| format!("Invalid file path: {}", path.to_string_lossy()))
+}
+
+/// Change a saved project in place, keeping its format
+///
+/// Encrypted projects are decrypted with `password` and saved encrypted again;
+/// plain projects stay plain even if a password is given.
+fn update_project(
+    path: &str,
+    password: Option<&str>,
+    state: &AppState,
+    edit: impl FnOnce(&mut ProjectData) -> Result<(), String>,
+) -> Result<(), String> {
+    let data = std::fs::read(path)
+        .map_err(|e| project_file::describe_io_error("read", std::path::Path::new(path), &e))?;
+    let password = project_crypto::password_for_edit(&data, password);
+    let (mut project, format) = decode_project_data(&data, password)?;
+    edit(&mut project)?;
+    write_project(path, &project, format, password, &state.project_watcher)
+}
+
+/// Read and parse a project file (JSON or MessagePack)
+fn read_project(path: &str) -> Result<ProjectData, String> {
+    read_project_with_format(path).map(|(project, _)| project)
+}
+
+/// Read and parse a project file, also returning the format it was stored in
+///
+/// Encrypted projects fail with `PASSWORD_REQUIRED`; use `read_project_file` with a password.
+fn read_project_with_format(path: &str) -> Result<(ProjectData, ProjectFormat), String> {
+    read_project_file(path, None)
+}
+
+/// Read and parse a project file, decrypting it with `password` if it is encrypted
+fn read_project_file(path: &str, password: Option<&str>) -> Result<(ProjectData, ProjectFormat), String> {
+    let data = std::fs::read(path)
+        .map_err(|e| project_file::describe_io_error("read", std::path::Path::new(path), &e))?;
+
+    decode_project_data(&data, password)
+}
+
+/// Parse project file contents, decrypting them first if needed
+fn decode_project_data(data: &[u8], password: Option<&str>) -> Result<(ProjectData, ProjectFormat), String> {
+    if !project_crypto::is_encrypted(data) {
+        return project_file::decode_project(data);
+    }
+
+    let password = password.ok_or(project_crypto::PASSWORD_REQUIRED)?;
+    project_file::decode_project(&project_crypto::decrypt(data, password)?)
+}
+
+/// Ask the user to pick a folder that project files may be saved to and loaded from
+///
+/// The folder comes from a native dialog, so the webview can't widen the scope
+/// on its own. Returns `None` if the dialog was cancelled.
+#[tauri::command]
+async fn approve_directory(app: AppHandle, state: State<'_, AppState>) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    // Wait for the dialog without holding a runtime worker thread while it's open
+    let (picked, folder) = tokio::sync::oneshot::channel();
+    app.dialog().file().pick_folder(move |folder| {
+        let _ = picked.send(folder);
+    });
+    let Some(folder) = folder.await.map_err(|_| "Folder dialog closed unexpectedly".to_string())? else {
+        return Ok(None);
+    };
+    let folder = folder
+        .into_path()
+        .map_err(|e| format!("Invalid folder: {}", e))?;
+
+    let approved = state.fs_scope.approve(&folder)?;
+    Ok(Some(approved.to_string_lossy().to_string()))
+}
+
+/// List the folders approved for project files
+#[tauri::command]
+fn list_approved_directories(state: State<AppState>) -> Vec<String> {
+    state
+        .fs_scope
+        .directories()
+        .iter()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Withdraw approval of a folder
+#[tauri::command]
+fn revoke_directory(path: String, state: State<AppState>) -> Result<(), String> {
+    state.fs_scope.revoke(std::path::Path::new(&path))
+}
+
+/// A loaded project with the problems found in its notes
+///
+/// The project fields are flattened, so this reads like a plain project plus
+/// `diagnostics` and `repaired`.
+#[derive(Serialize)]
+struct LoadedProject {
+    #[serde(flatten)]
+    project: ProjectData,
+    diagnostics: Vec<Diagnostic>,
+    repaired: bool, // Whether the notes were fixed (the file itself is unchanged)
+}
+
+/// Load project from a JSON or MessagePack file (detected from its contents)
+///
+/// Notes are checked for invalid pitches, times, tracks and duplicates. With
+/// `repair` (the default) they are fixed in the returned project; otherwise the
+/// project is returned as stored. Either way the problems are listed in
+/// `diagnostics`. Encrypted projects need their `password`; without one the
+/// error is `"Project is password-protected"`. The file is then watched for
+/// external changes (see `watch_project`).
+#[tauri::command]
+fn load_project(
+    path: String,
+    repair: Option<bool>,
+    password: Option<String>,
+    app: AppHandle,
+    state: State<AppState>,
+) -> Result<LoadedProject, String> {
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    let (mut project, _) = read_project_file(&path, password.as_deref())?;
+    if let Err(e) = watch_project_file(&path, app, &state) {
+        eprintln!("⚠ {}", e);
+    }
+    let repair = repair.unwrap_or(true);
+    let diagnostics = project_repair::check(&mut project, repair);
+
+    if !diagnostics.is_empty() {
+        eprintln!("⚠ {} problem(s) found in {}", diagnostics.len(), path);
+    }
+    if password.is_none() {
+        if let Err(e) = state.library.index(&path, &project) {
+            eprintln!("⚠ {}", e);
+        }
+    }
+
+    Ok(LoadedProject {
+        project,
+        repaired: repair && !diagnostics.is_empty(),
+        diagnostics,
+    })
+}
+
+/// Watch a project file and emit `project-changed` when it is modified or deleted by something else
+fn watch_project_file(path: &str, app: AppHandle, state: &AppState) -> Result<(), String> {
+    state.project_watcher.watch(std::path::Path::new(path), move |change| {
+        let _ = app.emit("project-changed", change);
+    })
+}
+
+/// Watch the open project for external changes (cloud sync, another instance)
+///
+/// Replaces the watch started by `load_project`, e.g. after "Save As".
+/// Saves made by the app itself are not reported.
+#[tauri::command]
+fn watch_project(path: String, app: AppHandle, state: State<AppState>) -> Result<(), String> {
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    watch_project_file(&path, app, &state)
+}
+
+/// Stop watching the open project (e.g. when it is closed)
+#[tauri::command]
+fn unwatch_project(state: State<AppState>) {
+    state.project_watcher.unwatch();
+}
+
+/// List projects in the library for the project browser
+#[tauri::command]
+fn query_library(query: LibraryQuery, state: State<AppState>) -> Result<Vec<LibraryEntry>, String> {
+    state.library.query(&query)
+}
+
+/// Index all projects under `folder` and forget deleted ones; returns the number indexed
+#[tauri::command]
+fn rescan_library(folder: String, state: State<AppState>) -> Result<usize, String> {
+    let folder = state.fs_scope.check_dir(std::path::Path::new(&folder))?;
+    state.library.rescan(&folder)
+}
+
+/// Library setting holding the folder `search_projects` scans
+const PROJECTS_FOLDER_SETTING: &str = "projects_folder";
+
+/// Set the folder scanned by `search_projects`
+#[tauri::command]
+fn set_projects_folder(path: String, state: State<AppState>) -> Result<(), String> {
+    if !std::path::Path::new(&path).is_dir() {
+        return Err(format!("Not a folder: {}", path));
+    }
+    let folder = state.fs_scope.check_dir(std::path::Path::new(&path))?;
+    state.library.set_setting(PROJECTS_FOLDER_SETTING, &folder.to_string_lossy())
+}
+
+/// Get the configured projects folder, if any
+#[tauri::command]
+fn get_projects_folder(state: State<AppState>) -> Result<Option<String>, String> {
+    state.library.setting(PROJECTS_FOLDER_SETTING)
+}
+
+/// Search projects in the projects folder by name, metadata and AI prompt history
+///
+/// Files changed since they were last indexed are re-indexed first, so new
+/// and edited projects are found; results are ranked best first.
+#[tauri::command]
+async fn search_projects(query: String, app: AppHandle) -> Result<Vec<SearchResult>, String> {
+    tauri::async_runtime::spawn_blocking(move || -> Result<Vec<SearchResult>, String> {
+        let state = app.state::<AppState>();
+        let folder = state
+            .library
+            .setting(PROJECTS_FOLDER_SETTING)?
+            .ok_or("No projects folder configured")?;
+        // The folder may have been revoked since it was set
+        let folder = state.fs_scope.check_dir(std::path::Path::new(&folder))?;
+
+        state.library.refresh(&folder)?;
+        let entries: Vec<LibraryEntry> = state
+            .library
+            .query(&LibraryQuery::default())?
+            .into_iter()
+            .filter(|entry| std::path::Path::new(&entry.path).starts_with(&folder))
+            .collect();
+
+        Ok(project_search::search(&entries, &query))
+    })
+    .await
+    .map_err(|e| format!("Search failed: {}", e))?
+}
+
+/// Remove a project from the library without deleting its file
+#[tauri::command]
+fn remove_from_library(path: String, state: State<AppState>) -> Result<(), String> {
+    state.library.remove(&path)
+}
+
+/// Package a saved project, its recorded audio clips and its custom samples into a portable `.pianoproj` bundle
+#[tauri::command]
+fn export_project_bundle(path: String, bundle_path: String, state: State<AppState>) -> Result<(), String> {
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    let project = read_project(&path)?;
+    let project_dir = std::path::Path::new(&path)
+        .parent()
+        .ok_or("Project path has no parent directory")?;
+
+    // Bundles always carry JSON for portability
+    let json = project_file::encode_project(&project, ProjectFormat::Json)?;
+    let assets = project_file::project_assets(&project);
+
+    let bundle_path = std::path::Path::new(&bundle_path).with_extension(project_file::BUNDLE_EXTENSION);
+    let bundle_path = state.fs_scope.check_write(&bundle_path, &fs_scope::BUNDLE_EXTENSIONS)?;
+    project_file::write_bundle(&json, project_dir, &assets, &bundle_path)
+}
+
+/// Extract a `.pianoproj` bundle into `target_dir`, returning the path of the project file to load
+#[tauri::command]
+fn import_project_bundle(bundle_path: String, target_dir: String, state: State<AppState>) -> Result<String, String> {
+    let bundle = state.fs_scope.check_read(std::path::Path::new(&bundle_path), &fs_scope::BUNDLE_EXTENSIONS)?;
+    let target_dir = state.fs_scope.check_dir(std::path::Path::new(&target_dir))?;
+    let name = bundle
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or("Invalid bundle file name")?;
+
+    let project_path = project_file::extract_bundle(&bundle, &target_dir, name)?;
+    Ok(project_path.to_string_lossy().to_string())
+}
+
+/// Render a project file offline to WAV, MP3, OGG or FLAC using the playback sample engine
+///
+/// Runs faster than real time on a background thread, emitting `render-progress`
+/// events; returns the rendered length in seconds. Abort with `cancel_render(job_id)`.
+#[tauri::command]
+async fn render_project(
+    job_id: String,
+    path: String,
+    output_path: String,
+    options: RenderOptions,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<f64, String> {
+    options.validate()
+        .map_err(|e| format!("Invalid render options: {}", e))?;
+
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    let output_path = state.fs_scope.check_write(std::path::Path::new(&output_path), &fs_scope::AUDIO_EXTENSIONS)?;
+    let project = read_project(&path)?;
+    let sample_player = state.sample_player.clone();
+    let cancel = CancellationToken::new();
+    let _job = register_render_job(&state, &job_id, &cancel)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut progress = RenderProgress::new(&cancel, |event| {
+            let _ = app.emit("render-progress", event);
+        });
+
+        let audio = render::render_project(&sample_player, &project, &options, &mut progress)?;
+        encoders::encode(&audio, &output_path, &options.encode_settings())?;
+
+        progress.finish();
+        Ok(audio.duration())
+    })
+    .await
+    .map_err(|e| format!("Render task failed: {}", e))?
+}
+
+/// Status of one file in a batch export (`batch-export-status` event payload)
+#[derive(Debug, Clone, Serialize)]
+struct BatchExportStatus {
+    path: String,
+    index: usize,
+    total: usize,
+    status: String, // "rendering", "done", "failed" or "cancelled"
+    output: Option<String>,
+    error: Option<String>,
+}
+
+/// First free `<stem>.<extension>` in `dir`, numbering it `<stem> (2).<extension>`, ... if taken
+///
+/// Batch exports never replace existing files, including ones written
+/// earlier in the same batch from a project with the same name.
+fn unique_output_path(dir: &std::path::Path, stem: &str, extension: &str) -> std::path::PathBuf {
+    let mut output = dir.join(format!("{}.{}", stem, extension));
+    let mut number = 2;
+    while output.exists() {
+        output = dir.join(format!("{} ({}).{}", stem, number, extension));
+        number += 1;
+    }
+    output
+}
+
+/// Export one project file for `batch_export`, returning the written file
+fn export_project_file(
+    sample_player: &SamplePlayer,
+    path: &str,
+    out_dir: &std::path::Path,
+    format: Option<AudioFormat>, // None exports MIDI
+    options: &RenderOptions,
+    cancel: &CancellationToken,
+) -> Result<std::path::PathBuf, String> {
+    let project = read_project(path)?;
+    let stem = std::path::Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("project");
+
+    match format {
+        None => {
+            let output = unique_output_path(out_dir, stem, "mid");
+            midi_file::write_project(&project, &output)?;
+            Ok(output)
+        }
+        Some(format) => {
+            let options = RenderOptions { format, ..options.clone() };
+            let output = unique_output_path(out_dir, stem, format.extension());
+
+            let mut progress = RenderProgress::new(cancel, |_| {});
+            let audio = render::render_project(sample_player, &project, &options, &mut progress)?;
+            encoders::encode(&audio, &output, &options.encode_settings())?;
+            Ok(output)
+        }
+    }
+}
+
+/// Export several project files to audio ("wav", "mp3", "ogg", "flac") or "midi" in `out_dir`
+///
+/// Files are processed one after another; a failing file doesn't stop the
+/// batch. Outputs are named after the projects, numbered ("song (2).wav")
+/// rather than replacing a file already in `out_dir`. Emits a
+/// `batch-export-status` event as each file starts and ends, and returns the
+/// final status of every file. `cancel_render(job_id)` stops the batch.
+#[tauri::command]
+async fn batch_export(
+    job_id: String,
+    paths: Vec<String>,
+    format: String,
+    out_dir: String,
+    options: Option<RenderOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<BatchExportStatus>, String> {
+    let audio_format = match format.to_lowercase().as_str() {
+        "midi" | "mid" => None,
+        other => Some(
+            AudioFormat::from_extension(other)
+                .ok_or_else(|| format!("Unsupported export format: {}", format))?,
+        ),
+    };
+
+    let options = options.unwrap_or_default();
+    options.validate()
+        .map_err(|e| format!("Invalid render options: {}", e))?;
+
+    // Every file is checked up front so nothing is exported from a batch that reaches outside the scope
+    let resolved = paths
+        .iter()
+        .map(|path| path_string(state.fs_scope.check_read(std::path::Path::new(path), &fs_scope::PROJECT_EXTENSIONS)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let out_dir = state.fs_scope.check_dir(std::path::Path::new(&out_dir))?;
+    std::fs::create_dir_all(&out_dir)
+        .map_err(|e| format!("Failed to create export folder: {}", e))?;
+
+    let sample_player = state.sample_player.clone();
+    let cancel = CancellationToken::new();
+    let _job = register_render_job(&state, &job_id, &cancel)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let total = paths.len();
+        let mut results = Vec::with_capacity(total);
+
+        for (index, (path, resolved)) in paths.into_iter().zip(resolved).enumerate() {
+            let mut status = BatchExportStatus {
+                path,
+                index,
+                total,
+                status: "rendering".to_string(),
+                output: None,
+                error: None,
+            };
+
+            if cancel.is_cancelled() {
+                status.status = "cancelled".to_string();
+                let _ = app.emit("batch-export-status", status.clone());
+                results.push(status);
+                continue;
+            }
+
+            let _ = app.emit("batch-export-status", status.clone());
+
+            match export_project_file(&sample_player, &resolved, &out_dir, audio_format, &options, &cancel) {
+                Ok(output) => {
+                    status.status = "done".to_string();
+                    status.output = Some(output.to_string_lossy().to_string());
+                }
+                Err(e) if e == render::RENDER_CANCELLED => {
+                    status.status = "cancelled".to_string();
+                }
+                Err(e) => {
+                    status.status = "failed".to_string();
+                    status.error = Some(e);
+                }
+            }
+
+            let _ = app.emit("batch-export-status", status.clone());
+            results.push(status);
+        }
+
+        Ok(results)
+    })
+    .await
+    .map_err(|e| format!("Export task failed: {}", e))?
+}
+
+/// Abort a running render, stem render or batch export (a render fails with "Render cancelled")
+///
+/// Returns false if no job with this id is running.
+#[tauri::command]
+fn cancel_render(job_id: String, state: State<AppState>) -> bool {
+    match state.render_jobs.lock().unwrap().get(&job_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// A render job's entry in `AppState::render_jobs`, removed when dropped
+struct RegisteredRenderJob<'a> {
+    state: &'a AppState,
+    job_id: String,
+}
+
+impl Drop for RegisteredRenderJob<'_> {
+    fn drop(&mut self) {
+        self.state.render_jobs.lock().unwrap().remove(&self.job_id);
+    }
+}
+
+/// Make a render job cancellable with `cancel_render` under `job_id`
+///
+/// Fails if a job with the same id is still running.
+fn register_render_job<'a>(
+    state: &'a AppState,
+    job_id: &str,
+    cancel: &CancellationToken,
+) -> Result<RegisteredRenderJob<'a>, String> {
+    let mut jobs = state.render_jobs.lock().unwrap();
+    if jobs.contains_key(job_id) {
+        return Err(format!("A render with id {} is already running", job_id));
+    }
+    jobs.insert(job_id.to_string(), cancel.clone());
+    Ok(RegisteredRenderJob { state, job_id: job_id.to_string() })
+}
+
+/// Render one audio file per audible track (honoring mute/solo, gain and pan) into `output_dir`
+///
+/// All stems have the same length so they line up when imported into a DAW.
+/// Emits `render-progress` events and returns the paths of the written files.
+/// Abort with `cancel_render(job_id)`.
+#[tauri::command]
+async fn render_stems(
+    job_id: String,
+    path: String,
+    output_dir: String,
+    options: RenderOptions,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    options.validate()
+        .map_err(|e| format!("Invalid render options: {}", e))?;
+
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    let output_dir = state.fs_scope.check_dir(std::path::Path::new(&output_dir))?;
+    let project = read_project(&path)?;
+    let sample_player = state.sample_player.clone();
+    let cancel = CancellationToken::new();
+    let _job = register_render_job(&state, &job_id, &cancel)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut progress = RenderProgress::new(&cancel, |event| {
+            let _ = app.emit("render-progress", event);
+        });
+
+        // Stems are all mixed before anything is written, so a cancelled render leaves no files
+        let stems = render::render_stems(&sample_player, &project, &options, &mut progress)?;
+
+        std::fs::create_dir_all(&output_dir)
+            .map_err(|e| format!("Failed to create stem folder: {}", e))?;
+
+        let settings = options.encode_settings();
+        let mut written = Vec::new();
+
+        for (index, (name, audio)) in stems.iter().enumerate() {
+            let file_name = format!(
+                "{:02}-{}.{}",
+                index + 1,
+                render::sanitize_file_name(name),
+                settings.format.extension()
+            );
+            let stem_path = output_dir.join(file_name);
+            encoders::encode(audio, &stem_path, &settings)?;
+            written.push(stem_path.to_string_lossy().to_string());
+        }
+
+        progress.finish();
+        Ok(written)
+    })
+    .await
+    .map_err(|e| format!("Render task failed: {}", e))?
+}
+
+// ============================================================================
+// Transport & MIDI Commands
+// ============================================================================
+
+/// List available MIDI input ports
+#[tauri::command]
+fn list_midi_inputs() -> Result<Vec<String>, String> {
+    MidiManager::list_input_ports()
+}
+
+/// Connect a MIDI input port (replaces the current input)
+#[tauri::command]
+fn connect_midi_input(port: String, app: AppHandle, state: State<AppState>) -> Result<(), String> {
+    let router = state.midi_router(app);
+    state.midi.connect_input(&port, move |event| router.handle(event))
+}
+
+/// Disconnect the current MIDI input port
+#[tauri::command]
+fn disconnect_midi_input(state: State<AppState>) {
+    state.midi.disconnect_input();
+}
+
+/// Bind the next incoming MIDI CC to an app parameter
+///
+/// Emits `midi-learn-complete` with the new mapping once a controller is moved.
+#[tauri::command]
+fn start_midi_learn(target: LearnTarget, state: State<AppState>) {
+    state.midi_learn.start_learn(target);
+}
+
+/// Abort a pending MIDI learn
+#[tauri::command]
+fn cancel_midi_learn(state: State<AppState>) {
+    state.midi_learn.cancel_learn();
+}
+
+/// List learned MIDI controller mappings
+#[tauri::command]
+fn list_midi_mappings(state: State<AppState>) -> Vec<MidiMapping> {
+    state.midi_learn.mappings()
+}
+
+/// Remove the mapping of a MIDI controller
+#[tauri::command]
+fn delete_midi_mapping(channel: u8, controller: u8, state: State<AppState>) -> Result<(), String> {
+    state.midi_learn.remove_mapping(channel, controller)
+}
+
+/// Handle a computer keyboard key press/release (`key` is the `KeyboardEvent.key` value)
+///
+/// Notes are triggered in the backend and routed like MIDI input (recording,
+/// step record, arpeggiator). Returns the pitch started or stopped, if mapped.
+#[tauri::command]
+fn key_event(key: String, down: bool, app: AppHandle, state: State<AppState>) -> Option<u8> {
+    let event = state.keyboard.key_event(&key, down)?;
+    let pitch = match event {
+        MidiEvent::NoteOn { pitch, .. } | MidiEvent::NoteOff { pitch, .. } => Some(pitch),
+        _ => None,
+    };
+
+    state.midi_router(app).handle(event);
+    pitch
+}
+
+/// Configure computer keyboard layout, octave shift and velocity
+#[tauri::command]
+fn set_keyboard_settings(settings: KeyboardSettings, state: State<AppState>) -> Result<(), String> {
+    settings.validate()
+        .map_err(|e| format!("Invalid keyboard settings: {}", e))?;
+    state.keyboard.set_settings(settings);
+    Ok(())
+}
+
+/// Get the current computer keyboard settings
+#[tauri::command]
+fn get_keyboard_settings(state: State<AppState>) -> KeyboardSettings {
+    state.keyboard.settings()
+}
+
+/// Configure MPE handling of MIDI input
+#[tauri::command]
+fn set_mpe_settings(settings: MpeSettings, state: State<AppState>) -> Result<(), String> {
+    settings.validate()
+        .map_err(|e| format!("Invalid MPE settings: {}", e))?;
+    state.mpe.set_settings(settings);
+    Ok(())
+}
+
+/// Get the current MPE settings
+#[tauri::command]
+fn get_mpe_settings(state: State<AppState>) -> MpeSettings {
+    state.mpe.settings()
+}
+
+/// Start recording MIDI input into a track
+///
+/// Starts the transport at `tempo` (or the current tempo) if it isn't running;
+/// note times are recorded in beats from the transport start. A transport
+/// started here is stopped again by `stop_recording`.
+#[tauri::command]
+fn start_recording(track_id: String, tempo: Option<f64>, state: State<AppState>) -> Result<(), String> {
+    if track_id.trim().is_empty() {
+        return Err("A track must be selected for recording".to_string());
+    }
+
+    let start_transport = !state.transport.is_running();
+    if start_transport {
+        state.transport.start(tempo.unwrap_or_else(|| state.transport.tempo()))?;
+    }
+
+    state.recorder.start(track_id, start_transport);
+    Ok(())
+}
+
+/// Set input quantization applied to notes as they're recorded (None disables it)
+#[tauri::command]
+fn set_record_quantize(settings: Option<QuantizeSettings>, state: State<AppState>) -> Result<(), String> {
+    if let Some(ref settings) = settings {
+        settings.validate()
+            .map_err(|e| format!("Invalid quantize settings: {}", e))?;
+    }
+
+    state.recorder.set_quantize(settings);
+    Ok(())
+}
+
+/// Grid resolution grooves are measured on unless the caller asks for another (sixteenths)
+const DEFAULT_GROOVE_DIVISION: u32 = 16;
+
+/// Measure the timing and velocity feel of a MIDI performance as a groove template
+///
+/// Every track of the file counts. The template covers one measure of
+/// `time_signature` (4/4 if not given) at `grid_division` steps per whole
+/// note (16 if not given), and can be applied to other notes with `apply_groove`.
+#[tauri::command]
+fn extract_groove(
+    path: String,
+    grid_division: Option<u32>,
+    time_signature: Option<ai_models::TimeSignature>,
+    state: State<AppState>,
+) -> Result<GrooveTemplate, String> {
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::MIDI_EXTENSIONS)?)?;
+    let grid_division = grid_division.unwrap_or(DEFAULT_GROOVE_DIVISION);
+    if !(1..=64).contains(&grid_division) {
+        return Err(format!("Invalid grid division: {}", grid_division));
+    }
+    let time_signature = time_signature.unwrap_or_default();
+    ai_models::validate_time_signature(&time_signature)
+        .map_err(|_| format!("Invalid time signature {}", time_signature))?;
+
+    let bytes = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let played: Vec<(f64, u8)> = midi_file::parse_notes(&bytes, "")?
+        .iter()
+        .map(|note| (note.start_time as f64, note.velocity))
+        .collect();
+    GrooveTemplate::extract(&played, grid_division, time_signature.beats_per_measure())
+        .ok_or_else(|| format!("No notes in {}", path))
+}
+
+/// Give notes the feel of a groove template: its timing and accents, blended in by `strength` (default 1.0)
+#[tauri::command]
+fn apply_groove(notes: Vec<Note>, groove: GrooveTemplate, strength: Option<f64>) -> Result<Vec<Note>, String> {
+    groove.validate().map_err(|e| format!("Invalid groove template: {}", e))?;
+    let strength = strength.unwrap_or(1.0);
+    if !(0.0..=1.0).contains(&strength) {
+        return Err(format!("Invalid groove strength: {}", strength));
+    }
+
+    Ok(notes
+        .into_iter()
+        .map(|note| {
+            let (start, velocity) = groove.apply(note.start_time as f64, note.velocity, strength);
+            Note { start_time: start as f32, velocity, ..note }
+        })
+        .collect())
+}
+
+/// Stop recording, returning the recorded notes and pedal events
+///
+/// The transport is stopped too if `start_recording` started it; playback
+/// that was already running keeps going.
+#[tauri::command]
+fn stop_recording(state: State<AppState>) -> Result<RecordedTake, String> {
+    let (take, started_transport) = state.recorder.stop(state.transport.position());
+    if started_transport {
+        state.transport.stop()?;
+    }
+    Ok(take)
+}
+
+/// Start step-record entry into a track at `start_beat`
+///
+/// `step_length` is how far the cursor advances per entry; `duration` (defaults
+/// to the step length) is the length of each placed note, both in beats.
+#[tauri::command]
+fn start_step_record(
+    track_id: String,
+    start_beat: f64,
+    step_length: f64,
+    duration: Option<f64>,
+    state: State<AppState>,
+) -> Result<StepCursor, String> {
+    if track_id.trim().is_empty() {
+        return Err("A track must be selected for step recording".to_string());
+    }
+    state.step_recorder.start(track_id, start_beat, step_length, duration)
+}
+
+/// Change step length and note duration for the next entries
+#[tauri::command]
+fn set_step_length(step_length: f64, duration: Option<f64>, state: State<AppState>) -> Result<StepCursor, String> {
+    state.step_recorder.set_lengths(step_length, duration)
+}
+
+/// Enter a note at the step cursor and advance
+#[tauri::command]
+fn step_input_note(pitch: u8, velocity: u8, state: State<AppState>) -> Result<AINote, String> {
+    state.step_recorder.input_note(pitch, velocity)
+}
+
+/// Advance the step cursor without entering a note
+#[tauri::command]
+fn step_rest(state: State<AppState>) -> Result<StepCursor, String> {
+    state.step_recorder.rest()
+}
+
+/// Stop step recording and return every note entered in the session
+#[tauri::command]
+fn stop_step_record(state: State<AppState>) -> Vec<AINote> {
+    state.step_recorder.stop()
+}
+
+/// Enable the arpeggiator (or update its settings while running)
+#[tauri::command]
+fn enable_arpeggiator(settings: ArpSettings, state: State<AppState>) -> Result<(), String> {
+    settings.validate()
+        .map_err(|e| format!("Invalid arpeggiator settings: {}", e))?;
+    state.arpeggiator.enable(settings)
+}
+
+/// Disable the arpeggiator
+#[tauri::command]
+fn disable_arpeggiator(state: State<AppState>) {
+    state.arpeggiator.disable();
+}
+
+/// Arpeggiate a chord selection (an empty list clears it)
+#[tauri::command]
+fn set_arpeggiator_chord(pitches: Vec<u8>, state: State<AppState>) {
+    state.arpeggiator.set_chord(pitches);
+}
+
+/// List available MIDI output ports
+#[tauri::command]
+fn list_midi_outputs() -> Result<Vec<String>, String> {
+    MidiManager::list_output_ports()
+}
+
+/// Select the MIDI output that receives clock/start/stop (None disables clock output)
+#[tauri::command]
+fn set_midi_clock_output(port: Option<String>, state: State<AppState>) -> Result<(), String> {
+    state.midi.set_clock_output(port)
+}
+
+/// Start the backend transport from the beginning at the given tempo
+#[tauri::command]
+fn start_transport(tempo: f64, state: State<AppState>) -> Result<(), String> {
+    state.transport.start(tempo)
+}
+
+/// Stop the backend transport
+#[tauri::command]
+fn stop_transport(state: State<AppState>) -> Result<(), String> {
+    state.transport.stop()
+}
+
+/// Change the transport tempo while running
+#[tauri::command]
+fn set_transport_tempo(tempo: f64, state: State<AppState>) -> Result<(), String> {
+    state.transport.set_tempo(tempo)
+}
+
+/// Move the transport playhead to a beat
+#[tauri::command]
+fn seek_transport(beat: f64, state: State<AppState>) -> Result<(), String> {
+    state.transport.seek(beat)
+}
+
+/// Load the project's markers into the transport (replaces the previous ones)
+#[tauri::command]
+fn set_markers(markers: Vec<Marker>, state: State<AppState>) -> Result<(), String> {
+    for marker in &markers {
+        marker.validate()
+            .map_err(|e| format!("Invalid marker {}: {}", marker.name, e))?;
+    }
+    state.transport.set_markers(markers);
+    Ok(())
+}
+
+/// Jump the transport to a marker, returning its position in beats
+#[tauri::command]
+fn jump_to_marker(id: String, state: State<AppState>) -> Result<f64, String> {
+    state.transport.jump_to_marker(&id)
+}
+
+/// Register a tempo tap; returns the averaged BPM once at least two taps were made
+#[tauri::command]
+fn tap_tempo(state: State<AppState>) -> Option<f64> {
+    state.tap_tempo.tap()
+}
+
+/// Get the current transport position in beats
+#[tauri::command]
+fn get_transport_position(state: State<AppState>) -> f64 {
+    state.transport.position()
+}
+
+// ============================================================================
+// Audio Input Commands
+// ============================================================================
+
+/// List available audio input devices (microphones)
+#[tauri::command]
+fn list_audio_inputs() -> Result<Vec<String>, String> {
+    AudioRecorder::list_input_devices()
+}
+
+/// Start recording from a microphone into a WAV clip next to the project file
+#[tauri::command]
+fn start_audio_recording(project_path: String, device: Option<String>, state: State<AppState>) -> Result<(), String> {
+    // Clips are written next to the project
+    let project_path = state.fs_scope.check_write(std::path::Path::new(&project_path), &fs_scope::PROJECT_EXTENSIONS)?;
+    state.audio_recorder.start(&project_path, device)
+}
+
+/// Stop the microphone recording, returning the clip to add to the project
+#[tauri::command]
+fn stop_audio_recording(state: State<AppState>) -> Result<AudioClip, String> {
+    state.audio_recorder.stop()
+}
+
+// ============================================================================
+// AI Melody Generation Commands
+// ============================================================================
+
+/// Start of the error message of a generation with a provider that has no API key
+const NO_API_KEY: &str = "No API key configured";
+
+/// A failed generation, telling apart the failures the offline generator can stand in for
+#[derive(Debug)]
+enum GenerationError {
+    /// The provider needs an API key and has none
+    NoApiKey(String),
+    /// The provider couldn't be reached
+    Unreachable(String),
+    Other(String),
+}
+
+impl GenerationError {
+    /// Whether the offline generator may answer instead
+    fn allows_offline_fallback(&self) -> bool {
+        matches!(self, GenerationError::NoApiKey(_) | GenerationError::Unreachable(_))
+    }
+}
+
+impl std::fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenerationError::NoApiKey(message) | GenerationError::Unreachable(message) | GenerationError::Other(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl From<String> for GenerationError {
+    fn from(message: String) -> Self {
+        GenerationError::Other(message)
+    }
+}
+
+impl From<GenerationError> for String {
+    fn from(error: GenerationError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Get the API key of a provider
+///
+/// Providers that don't need one (Ollama, custom endpoints) get the saved key
+/// if there is one, otherwise an empty key. The key is copied out so the lock
+/// isn't held across awaits, and wiped when the caller is done with it.
+fn provider_api_key(state: &AppState, provider: &AIProvider) -> Result<Secret, GenerationError> {
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    let api_key = api_key_manager
+        .get_api_key(provider)
+        .map_err(|e| format!("Failed to get API key: {}", e))?;
+
+    match api_key {
+        Some(api_key) => Ok(api_key),
+        None if !provider.requires_api_key() => Ok(Secret::default()),
+        None => Err(GenerationError::NoApiKey(format!("{} for {}", NO_API_KEY, provider.as_str()))),
+    }
+}
+
+/// Switch a provider to its next saved key, returning that key
+fn rotate_api_key(state: &AppState, provider: &AIProvider) -> Option<Secret> {
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    match api_key_manager.rotate_key(provider) {
+        Ok(next) => next,
+        Err(e) => {
+            eprintln!("⚠ Failed to rotate {} API key: {}", provider.as_str(), e);
+            None
+        }
+    }
+}
+
+/// Record a request made with a provider's key, for `get_provider_status`
+fn record_key_use<T>(state: &AppState, provider: &AIProvider, result: &Result<T, String>) {
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    if let Err(e) = api_key_manager.record_use(provider, result.as_ref().err().map(String::as_str)) {
+        eprintln!("⚠ Failed to record API key use: {}", e);
+    }
+}
+
+/// Run a call that makes requests with a provider's key, logging each request in the key audit log
+///
+/// Providers used without any key aren't logged.
+async fn audited<T, E: std::fmt::Display>(
+    state: &AppState,
+    provider: &AIProvider,
+    operation: &str,
+    call: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let (source, key) = {
+        let api_key_manager = state.api_key_manager.lock().unwrap();
+        (api_key_manager.api_key_source(provider), api_key_manager.key_in_use(provider))
+    };
+    // Keyless providers (local models, Ollama) have no key use to account for
+    if source.is_none() {
+        return call.await;
+    }
+
+    let (result, calls) = key_audit::collect_calls(call).await;
+    let error = result.as_ref().err().map(ToString::to_string);
+    if let Err(e) = state.key_audit.record(provider, key, operation, calls, error) {
+        eprintln!("⚠ {}", e);
+    }
+    result
+}
+
+/// Get the settings (base URL, model, default temperature and token limit, timeouts) of an AI provider
+#[tauri::command]
+fn get_ai_provider_config(provider: String, state: State<'_, AppState>) -> Result<ProviderConfig, String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    Ok(state.ai_settings.provider(&ai_provider))
+}
+
+/// Set the settings (base URL, model, default temperature and token limit, timeouts) of an AI provider
+///
+/// A proxy password is stored encrypted by the API key manager, not with the settings.
+#[tauri::command]
+fn set_ai_provider_config(
+    provider: String,
+    mut config: ProviderConfig,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+
+    let password = config.proxy.as_mut().map(|proxy| proxy.password.take());
+    state.ai_settings.set_provider(&ai_provider, config)?;
+    store_proxy_password(&state, &proxy_secret_name(Some(&ai_provider)), password)
+}
+
+/// Get the global proxy for AI requests
+#[tauri::command]
+fn get_ai_proxy(state: State<'_, AppState>) -> Option<ProxyConfig> {
+    state.ai_settings.proxy()
+}
+
+/// Set (or clear, with `None`) the proxy used by AI providers without their own
+#[tauri::command]
+fn set_ai_proxy(mut proxy: Option<ProxyConfig>, state: State<'_, AppState>) -> Result<(), String> {
+    let password = proxy.as_mut().map(|proxy| proxy.password.take());
+    state.ai_settings.set_proxy(proxy)?;
+    store_proxy_password(&state, &proxy_secret_name(None), password)
+}
+
+/// Get the saved custom system prompt used by generations without their own
+#[tauri::command]
+fn get_custom_system_prompt(state: State<'_, AppState>) -> Option<CustomSystemPrompt> {
+    state.ai_settings.system_prompt()
+}
+
+/// Save (or clear, with `None`) the custom system prompt used by every generation
+///
+/// A request's `options.system_prompt` takes its place for that request.
+#[tauri::command]
+fn set_custom_system_prompt(system_prompt: Option<CustomSystemPrompt>, state: State<'_, AppState>) -> Result<(), String> {
+    state.ai_settings.set_system_prompt(system_prompt)
+}
+
+/// Key manager entry of a proxy password: per provider, or the global proxy's
+fn proxy_secret_name(provider: Option<&AIProvider>) -> String {
+    match provider {
+        Some(provider) => format!("proxy:{}", provider.as_str()),
+        None => "proxy".to_string(),
+    }
+}
+
+/// Update a stored proxy password
+///
+/// `None` means the proxy was removed; `Some(None)` keeps the saved password
+/// (the UI never receives it back); an empty password clears it.
+fn store_proxy_password(state: &AppState, name: &str, password: Option<Option<Secret>>) -> Result<(), String> {
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    let result = match password {
+        Some(None) => Ok(()),
+        Some(Some(password)) if !password.is_empty() => api_key_manager.save_secret(name, &password),
+        _ => api_key_manager.delete_secret(name),
+    };
+    result.map_err(|e| format!("Failed to save proxy password: {}", e))
+}
+
+/// Settings a provider's client is created with: the global proxy applies
+/// unless the provider has its own (or opts out), with its password filled in
+fn provider_config(state: &AppState, provider: &AIProvider) -> Result<ProviderConfig, String> {
+    let mut config = state.ai_settings.provider(provider);
+    if config.no_proxy {
+        return Ok(config);
+    }
+
+    let secret_name = if config.proxy.is_some() {
+        proxy_secret_name(Some(provider))
+    } else {
+        config.proxy = state.ai_settings.proxy();
+        proxy_secret_name(None)
+    };
+
+    if let Some(proxy) = config.proxy.as_mut() {
+        let api_key_manager = state.api_key_manager.lock().unwrap();
+        proxy.password = api_key_manager
+            .get_secret(&secret_name)
+            .map_err(|e| format!("Failed to get proxy password: {}", e))?;
+    }
+    Ok(config)
+}
+
+/// Times a generation is resent after a 429 response
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// Generate a melody using AI
+///
+/// Identical requests are answered from the response cache unless `bypass_cache` is set.
+/// When `project_path` is given, the generation is recorded in that project's history.
+/// Emits `generation-progress` events tagged with `request_id` (generated if not given).
+///
+/// Without an API key for the provider, or when the provider can't be reached,
+/// the melody comes from the offline generator instead (model name
+/// "offline-markov"), unless `no_offline_fallback` is set.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_melody(
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    let mut options = options.unwrap_or_default();
+    let request = generation_request(GenerationMode::Melody, prompt, scale, measures, &provider, temperature, &options)?;
+    let request_id = options.request_id.get_or_insert_with(|| uuid::Uuid::new_v4().to_string()).clone();
+    let project_path = options.project_path.clone();
+    let project_password = options.project_password.clone();
+    let fallback = !options.no_offline_fallback;
+
+    match generate_with(request.clone(), options, &app, &state, None).await {
+        Err(e) if fallback && e.allows_offline_fallback() => {
+            eprintln!("⚠ {}; generating offline", e);
+            let mut response = offline_generator::generate(&request);
+            response.shift(request.start_beat);
+            record_generation(&state, project_path.as_deref(), project_password.as_deref(), &request, &response);
+            let event = GenerationProgressEvent { request_id, phase: GenerationPhase::Offline, attempt: 0 };
+            let _ = app.emit("generation-progress", event);
+            Ok(response)
+        }
+        result => result.map_err(String::from),
+    }
+}
+
+/// Generate a chord progression: chord symbols plus their voicings on the chord track
+///
+/// Takes the same options, and emits the same events, as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_chords(
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    let options = options.unwrap_or_default();
+    let request = generation_request(GenerationMode::Chords, prompt, scale, measures, &provider, temperature, &options)?;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Generate a full arrangement: melody, harmony and bass on their own tracks in one request
+///
+/// Notes land on the right-hand, harmony and bass tracks by the role the
+/// model gives them. Takes the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_arrangement(
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    let options = options.unwrap_or_default();
+    let request = generation_request(GenerationMode::Arrange, prompt, scale, measures, &provider, temperature, &options)?;
+    run_generation(request, options, &app, &state).await
+}
+
+/// A vocal melody and the syllable each of its notes sings
+#[derive(Debug, Clone, Serialize)]
+struct LyricsResponse {
+    #[serde(flatten)]
+    response: MelodyResponse,
+    lyrics: Vec<NoteLyric>,
+}
+
+/// Set a lyric to a vocal melody, one note per syllable
+///
+/// Syllables and their stress are worked out from the text (English rules of
+/// thumb): stressed syllables land on beats, and lines end on longer notes or
+/// rests. `measures` defaults to about a syllable per beat. With
+/// `options.project_path`, each note's syllable is stored in the project.
+/// Takes the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_from_lyrics(
+    lyric: String,
+    prompt: Option<String>,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<LyricsResponse, String> {
+    let options = options.unwrap_or_default();
+    let syllables = lyrics::syllables(&lyric);
+    if syllables.is_empty() {
+        return Err("The lyric has no words to sing".to_string());
+    }
+    if syllables.len() > lyrics::MAX_SYLLABLES {
+        return Err(format!(
+            "The lyric is too long ({} syllables, max {})",
+            syllables.len(),
+            lyrics::MAX_SYLLABLES
+        ));
+    }
+
+    let measures = measures.unwrap_or_else(|| (syllables.len() as f64 / options.beats_per_measure()).ceil().clamp(1.0, 16.0) as u32);
+    let prompt = prompt.filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "A singable melody".to_string());
+    let mode = GenerationMode::Lyrics { syllables: syllables.clone() };
+    let request = generation_request(mode, prompt, scale, Some(measures), &provider, temperature, &options)?;
+    let project_path = options.project_path.clone();
+    let project_password = options.project_password.clone();
+    let response = run_generation(request, options, &app, &state).await?;
+
+    // Validation guarantees a note per syllable; they are sung in time order
+    let mut sung: Vec<&AINote> = response.notes.iter().collect();
+    sung.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+    let lyrics: Vec<NoteLyric> = sung
+        .iter()
+        .zip(&syllables)
+        .map(|(note, syllable)| NoteLyric { note_id: note.id.clone(), syllable: syllable.text.clone() })
+        .collect();
+
+    if let Some(path) = project_path.as_deref() {
+        let appended = state
+            .fs_scope
+            .check_write(std::path::Path::new(path), &fs_scope::PROJECT_EXTENSIONS)
+            .and_then(path_string)
+            .and_then(|path| append_lyrics(&path, project_password.as_deref(), &lyrics, &state));
+        if let Err(e) = appended {
+            eprintln!("⚠ Failed to store lyrics in {}: {}", path, e);
+        }
+    }
+
+    Ok(LyricsResponse { response, lyrics })
+}
+
+/// Add note lyrics to a saved project
+fn append_lyrics(path: &str, password: Option<&str>, lyrics: &[NoteLyric], state: &AppState) -> Result<(), String> {
+    update_project(path, password, state, |project| {
+        project.lyrics.extend_from_slice(lyrics);
+        Ok(())
+    })
+}
+
+/// Fewest and most notes of a motif
+const MOTIF_NOTES: std::ops::RangeInclusive<usize> = 2..=12;
+
+/// Generate a melody developing the marked motif (sequence, inversion, augmentation, ...)
+///
+/// The melody must contain the motif's interval pattern, as is or
+/// transformed, at least twice. Takes the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_from_motif(
+    motif: Vec<AINote>,
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    if !MOTIF_NOTES.contains(&motif.len()) {
+        return Err(format!(
+            "A motif needs {} to {} notes",
+            MOTIF_NOTES.start(),
+            MOTIF_NOTES.end()
+        ));
+    }
+    check_context_notes(&motif)?;
+    let options = options.unwrap_or_default();
+
+    // Motif times are sent relative to its first note
+    let start = motif.iter().map(|note| note.start_time).fold(f64::INFINITY, f64::min);
+    let motif = motif
+        .into_iter()
+        .map(|note| AINote { start_time: note.start_time - start, ..note })
+        .collect();
+
+    let mode = GenerationMode::Motif { motif };
+    let request = generation_request(mode, prompt, scale, measures, &provider, temperature, &options)?;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Most notes sent to the AI as context
+const MAX_CONTEXT_NOTES: usize = 500;
+
+/// Check notes sent to the AI as context (to harmonize, continue, vary, ...)
+fn check_context_notes(notes: &[AINote]) -> Result<(), String> {
+    if notes.is_empty() {
+        return Err("No notes selected".to_string());
+    }
+    if notes.len() > MAX_CONTEXT_NOTES {
+        return Err(format!("Too many notes (max {})", MAX_CONTEXT_NOTES));
+    }
+    for note in notes {
+        note.validate().map_err(|e| format!("Invalid note: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Move notes so the bar of the earliest one starts at beat 0
+///
+/// Passages are generated from beat 0; the returned offset (whole bars) goes
+/// into `start_beat` to move the result back into place. `earliest` can pull
+/// the first bar further back, e.g. to a chord starting before the notes.
+/// Also returns the measures the shifted notes span.
+fn from_first_bar(notes: Vec<AINote>, beats: f64, earliest: Option<f64>) -> (Vec<AINote>, f64, u32) {
+    let start = notes
+        .iter()
+        .map(|note| note.start_time)
+        .fold(earliest.unwrap_or(f64::INFINITY), f64::min);
+    let offset = (start / beats).floor() * beats;
+    let notes: Vec<AINote> = notes
+        .into_iter()
+        .map(|note| AINote { start_time: note.start_time - offset, ..note })
+        .collect();
+    let measures = ai_models::measures_spanned(&notes, beats);
+    (notes, offset, measures)
+}
+
+/// Harmonize a melody: supporting chords or counterlines on the harmony track
+///
+/// `style` describes the accompaniment ("jazzy block chords", "a gentle
+/// counter-melody"). The melody itself isn't returned, and new notes never
+/// clash with it. Takes the same options as `generate_melody`.
+#[tauri::command]
+async fn harmonize_melody(
+    notes: Vec<AINote>,
+    scale: Option<AIScale>,
+    style: Option<String>,
+    provider: String,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    check_context_notes(&notes)?;
+    let options = options.unwrap_or_default();
+
+    // The melody is harmonized from beat 0; the harmony is moved back to where the melody is
+    let (melody, offset, measures) = from_first_bar(notes, options.beats_per_measure(), None);
+    let style = style.filter(|s| !s.trim().is_empty()).unwrap_or_else(|| "Supporting chords".to_string());
+
+    let mode = GenerationMode::Harmonize { melody };
+    let mut request = generation_request(mode, style, scale, Some(measures), &provider, None, &options)?;
+    request.start_beat = offset;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Write a left-hand part for a right-hand melody, on the left-hand track
+///
+/// `style` picks the pattern: "alberti"/"classical" for Alberti bass,
+/// "stride"/"ragtime"/"jazz" for stride, "arpeggio", "waltz", and block
+/// chords otherwise. The melody itself isn't returned. Takes the same options
+/// as `generate_melody`.
+#[tauri::command]
+async fn generate_left_hand(
+    notes: Vec<AINote>,
+    scale: Option<AIScale>,
+    style: Option<String>,
+    provider: String,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    check_context_notes(&notes)?;
+    let options = options.unwrap_or_default();
+
+    // The left hand is written from beat 0 and moved back under the melody
+    let (melody, offset, measures) = from_first_bar(notes, options.beats_per_measure(), None);
+    let style = style.filter(|s| !s.trim().is_empty()).unwrap_or_else(|| "Block chords".to_string());
+
+    let mode = GenerationMode::LeftHand { melody };
+    let mut request = generation_request(mode, style, scale, Some(measures), &provider, None, &options)?;
+    request.start_beat = offset;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Write a bass line on the bass track, following `chords` or the harmony of `notes`
+///
+/// Pass the chord track's symbols as `chords` when there is one; `notes` are
+/// the other parts, sent as context (either may be empty, not both). The line
+/// spans the measures of both. Takes the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_bass(
+    notes: Vec<AINote>,
+    chords: Option<Vec<ChordSymbol>>,
+    scale: Option<AIScale>,
+    style: Option<String>,
+    provider: String,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    let chords = chords.unwrap_or_default();
+    if notes.is_empty() && chords.is_empty() {
+        return Err("Select notes or chords to build the bass line on".to_string());
+    }
+    if !notes.is_empty() {
+        check_context_notes(&notes)?;
+    }
+    if chords.len() > MAX_CONTEXT_NOTES {
+        return Err(format!("Too many chords (max {})", MAX_CONTEXT_NOTES));
+    }
+    let options = options.unwrap_or_default();
+
+    // The line is written from the bar of the earliest note or chord, then moved back into place
+    let beats = options.beats_per_measure();
+    let first_chord = chords.iter().map(|chord| chord.start_time).reduce(f64::min);
+    let (context, offset, measures) = from_first_bar(notes, beats, first_chord);
+    let chords: Vec<ChordSymbol> = chords
+        .into_iter()
+        .map(|chord| ChordSymbol { start_time: chord.start_time - offset, ..chord })
+        .collect();
+
+    let chords_end = chords
+        .iter()
+        .map(|chord| chord.start_time + chord.duration)
+        .fold(0.0, f64::max);
+    let measures = measures.max((chords_end / beats).ceil() as u32);
+    let style = style.filter(|s| !s.trim().is_empty()).unwrap_or_else(|| "Roots and approach notes".to_string());
+
+    let mode = GenerationMode::Bass { chords, context };
+    let mut request = generation_request(mode, style, scale, Some(measures), &provider, None, &options)?;
+    request.start_beat = offset;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Generate a drum pattern of General MIDI percussion notes on the drum track
+///
+/// `notes` are the other parts, sent as context for the groove to lock in
+/// with (optional). No scale applies to drums. Takes the same options as
+/// `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_drums(
+    prompt: String,
+    notes: Option<Vec<AINote>>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    let context = notes.unwrap_or_default();
+    if !context.is_empty() {
+        check_context_notes(&context)?;
+    }
+    let options = options.unwrap_or_default();
+    let measures = measures.or_else(|| (!context.is_empty()).then(|| ai_models::measures_spanned(&context, options.beats_per_measure())));
+
+    let mode = GenerationMode::Drums { context };
+    let request = generation_request(mode, prompt, None, measures, &provider, temperature, &options)?;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Measures of preceding music sent as context when continuing a passage
+const DEFAULT_CONTEXT_MEASURES: u32 = 4;
+
+/// Continue a passage: generate the `measures` after the last note of `notes`
+///
+/// The last `context_measures` (default 4) are sent as context, and the new
+/// notes start at the next measure boundary. Without a `scale`, the key
+/// detected from `notes` is used when it is clear (see `detect_key`). Takes
+/// the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn continue_passage(
+    notes: Vec<AINote>,
+    prompt: Option<String>,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    context_measures: Option<u32>,
+    provider: String,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    check_context_notes(&notes)?;
+    let options = options.unwrap_or_default();
+    let scale = scale.or_else(|| {
+        music_theory::detect_key(&notes)
+            .first()
+            .filter(|key| key.score >= music_theory::KEY_CONFIDENCE)
+            .map(KeyCandidate::scale)
+    });
+
+    // Continue at the measure after the last note ends
+    let beats = options.beats_per_measure();
+    let start_beat = ai_models::measures_spanned(&notes, beats) as f64 * beats;
+    let context_start = start_beat - context_measures.unwrap_or(DEFAULT_CONTEXT_MEASURES).max(1) as f64 * beats;
+    let context = notes
+        .into_iter()
+        .filter(|note| note.start_time + note.duration > context_start)
+        .map(|note| AINote { start_time: note.start_time - start_beat, ..note })
+        .collect();
+
+    let prompt = prompt.filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "Continue naturally".to_string());
+    let mode = GenerationMode::Continue { context };
+    let mut request = generation_request(mode, prompt, scale, measures, &provider, None, &options)?;
+    request.start_beat = start_beat;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Key candidates returned by `detect_key` unless the caller asks for another number
+const DEFAULT_KEY_CANDIDATES: usize = 5;
+
+/// Guess the key of `notes`: the likeliest major and minor keys, best first
+///
+/// Scores are correlations with Krumhansl's key profiles (-1 to 1); above
+/// 0.6 the best key is a safe default for the scale selector. Returns at
+/// most `limit` keys (default 5), none without notes.
+#[tauri::command]
+fn detect_key(notes: Vec<AINote>, limit: Option<usize>) -> Vec<KeyCandidate> {
+    let mut keys = music_theory::detect_key(&notes);
+    keys.truncate(limit.unwrap_or(DEFAULT_KEY_CANDIDATES));
+    keys
+}
+
+/// Suggest chords to follow `previous` in `key`, from harmony rules (no AI call)
+///
+/// Diatonic moves come first, then secondary dominants; each comes with its
+/// Roman numeral, function and the reason it works. Without `previous`,
+/// suggests chords that establish the key. `sevenths` suggests seventh chords.
+#[tauri::command]
+fn suggest_chords(key: AIScale, previous: Option<String>, sevenths: Option<bool>) -> Result<Vec<ChordSuggestion>, String> {
+    music_theory::check_root(&key.root)?;
+    let previous = previous.filter(|p| !p.trim().is_empty());
+    Ok(music_theory::suggest_chords(&key, previous.as_deref(), sevenths.unwrap_or(false)))
+}
+
+/// Notes voicing each chord symbol at its beat, for stamping accompaniment without AI
+///
+/// `options` pick close, open or drop-2 voicings, the inversion, the lowest
+/// pitch the voicing starts from (C3 by default), velocity and track.
+#[tauri::command]
+fn voice_chords(chords: Vec<ChordSymbol>, options: Option<VoicingOptions>) -> Result<Vec<AINote>, String> {
+    let options = options.unwrap_or_default();
+    options.validate().map_err(|e| format!("Invalid voicing options: {}", e))?;
+    if chords.len() > MAX_CONTEXT_NOTES {
+        return Err(format!("Too many chords (max {})", MAX_CONTEXT_NOTES));
+    }
+    voicing::voice_chords(&chords, &options)
+}
+
+/// Notes, note names and chords of a scale, for highlighting in-scale keys
+///
+/// `mode` is any scale mode ("dorian", "blues", ...). MIDI notes cover the
+/// whole 0-127 range.
+#[tauri::command]
+fn get_scale_info(root: String, mode: ai_models::ScaleMode) -> Result<ScaleInfo, String> {
+    music_theory::check_root(&root)?;
+    Ok(music_theory::scale_info(&AIScale { root, mode, octave: None }))
+}
+
+/// Label each beat's harmony with a chord and Roman numeral, and find modulations
+///
+/// Numerals are relative to `key`, or to the key detected from `notes` when
+/// none is given, and follow the music into new keys it modulates to. Feeds
+/// the analysis lane; no AI call.
+#[tauri::command]
+fn analyze_harmony(
+    notes: Vec<AINote>,
+    key: Option<AIScale>,
+    time_signature: Option<ai_models::TimeSignature>,
+) -> Result<HarmonicAnalysis, String> {
+    check_context_notes(&notes)?;
+    let key = match key {
+        Some(key) => key,
+        None => match music_theory::detect_key(&notes).first() {
+            Some(candidate) => candidate.scale(),
+            None => return Ok(HarmonicAnalysis { chords: Vec::new(), modulations: Vec::new() }),
+        },
+    };
+    music_theory::check_root(&key.root)?;
+    let time_signature = time_signature.unwrap_or_default();
+    ai_models::validate_time_signature(&time_signature)
+        .map_err(|_| format!("Invalid time signature {}", time_signature))?;
+    let beats = time_signature.beats_per_measure();
+    Ok(music_theory::analyze_harmony(&notes, &key, beats))
+}
+
+/// Fill the gap from `gap_start` to `gap_end` (in beats) between existing notes
+///
+/// Notes up to `context_measures` (default 4) before and after the gap are
+/// sent as context; notes starting inside the gap are left out, as the infill
+/// replaces them. Takes the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn infill_region(
+    notes: Vec<AINote>,
+    gap_start: f64,
+    gap_end: f64,
+    prompt: Option<String>,
+    scale: Option<AIScale>,
+    context_measures: Option<u32>,
+    provider: String,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    check_context_notes(&notes)?;
+    let options = options.unwrap_or_default();
+    if !(gap_start >= 0.0 && gap_end > gap_start) {
+        return Err("The gap must end after it starts".to_string());
+    }
+
+    let length = gap_end - gap_start;
+    let beats = options.beats_per_measure();
+    let context_beats = context_measures.unwrap_or(DEFAULT_CONTEXT_MEASURES).max(1) as f64 * beats;
+    let relative = |note: AINote| AINote { start_time: note.start_time - gap_start, ..note };
+    let (before, after): (Vec<AINote>, Vec<AINote>) = notes
+        .into_iter()
+        .filter(|note| note.start_time < gap_start || note.start_time >= gap_end)
+        .partition(|note| note.start_time < gap_start);
+    let before = before
+        .into_iter()
+        .filter(|note| note.start_time + note.duration > gap_start - context_beats)
+        .map(relative)
+        .collect();
+    let after = after
+        .into_iter()
+        .filter(|note| note.start_time < gap_end + context_beats)
+        .map(relative)
+        .collect();
+
+    let prompt = prompt.filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "Connect the two passages".to_string());
+    let measures = (length / beats).ceil() as u32;
+    let mode = GenerationMode::Infill { before, after, length };
+    let mut request = generation_request(mode, prompt, scale, Some(measures), &provider, None, &options)?;
+    request.start_beat = gap_start;
+    run_generation(request, options, &app, &state).await
+}
+
+/// Candidates generated when none are asked for
+const DEFAULT_CANDIDATES: u32 = 3;
+
+/// Most candidates per batch
+const MAX_CANDIDATES: u32 = 8;
+
+/// One of several generations for the same request, to audition and pick from
+#[derive(Debug, Clone, Serialize)]
+struct Candidate {
+    /// Request id of the candidate (`<request_id>-<n>`), as in its progress events
+    id: String,
+    #[serde(flatten)]
+    response: MelodyResponse,
+}
+
+/// Generate `count` melodies (default 3) for one prompt at once, to audition and pick one
+///
+/// Takes the same arguments and options as `generate_melody`; see
+/// `run_candidates` for ids, progress events and cancelling.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_melody_candidates(
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    count: Option<u32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<Candidate>, String> {
+    let options = options.unwrap_or_default();
+    let request = generation_request(GenerationMode::Melody, prompt, scale, measures, &provider, temperature, &options)?;
+    run_candidates(request, options, count.unwrap_or(DEFAULT_CANDIDATES), &app, &state).await
+}
+
+/// Generate variations of a phrase that keep its contour, as candidates to compare
+///
+/// `amount` (0.0-1.0, default 0.5) sets how far the variations may stray;
+/// `count` (default 3) how many are generated. Takes the same options as
+/// `generate_melody`; see `run_candidates` for progress ids and cancelling.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_variation(
+    notes: Vec<AINote>,
+    amount: Option<f32>,
+    count: Option<u32>,
+    scale: Option<AIScale>,
+    provider: String,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<Candidate>, String> {
+    check_context_notes(&notes)?;
+    let options = options.unwrap_or_default();
+    let amount = amount.unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&amount) {
+        return Err("Variation amount must be between 0 and 1".to_string());
+    }
+
+    // The variation starts at beat 0; it's moved back to where the phrase was
+    let (theme, offset, measures) = from_first_bar(notes, options.beats_per_measure(), None);
+
+    let mode = GenerationMode::Variation { theme, amount };
+    let mut request = generation_request(mode, "Vary this phrase".to_string(), scale, Some(measures), &provider, None, &options)?;
+    request.start_beat = offset;
+    run_candidates(request, options, count.unwrap_or(DEFAULT_CANDIDATES), &app, &state).await
+}
+
+/// Start a conversation refining a generation with follow-up instructions
+///
+/// `prompt` is what `notes` were generated from. Returns the conversation id
+/// for `refine_generation`.
+#[tauri::command]
+fn start_refinement(
+    prompt: String,
+    notes: Vec<AINote>,
+    options: Option<GenerationOptions>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    check_context_notes(&notes)?;
+    let options = options.unwrap_or_default();
+    Ok(state.conversations.start(prompt, notes, options.beats_per_measure()))
+}
+
+/// Edit the latest melody of a refinement conversation ("make bar 3 busier", "less jumpy")
+///
+/// The earlier instructions and melodies are sent as conversation turns, and
+/// the edited melody takes the place of the passage refined. Takes the same
+/// options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn refine_generation(
+    conversation_id: String,
+    instruction: String,
+    scale: Option<AIScale>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    let conversation = state
+        .conversations
+        .get(&conversation_id)
+        .ok_or_else(|| format!("No refinement conversation with id {}", conversation_id))?;
+    let options = options.unwrap_or_default();
+
+    let mode = GenerationMode::Refine { history: conversation.turns };
+    let mut request = generation_request(mode, instruction, scale, Some(conversation.measures), &provider, temperature, &options)?;
+    request.start_beat = conversation.start_beat;
+    let instruction = request.prompt.clone();
+
+    let response = run_generation(request, options, &app, &state).await?;
+    state.conversations.record(&conversation_id, instruction, &response.notes)?;
+    Ok(response)
+}
+
+/// Close a refinement conversation
+#[tauri::command]
+fn end_refinement(conversation_id: String, state: State<'_, AppState>) {
+    state.conversations.end(&conversation_id);
+}
+
+/// Re-render `notes` in a target style ("make this baroque", "make it lo-fi jazz")
+///
+/// Contour and measure structure are kept. Result notes matching an original
+/// note (same pitch and start) take its id, and `diff` lists which notes were
+/// kept, changed, removed and added. Takes the same options as `generate_melody`.
+#[tauri::command]
+async fn restyle_notes(
+    notes: Vec<AINote>,
+    style: String,
+    scale: Option<AIScale>,
+    provider: String,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<StyleTransferResponse, String> {
+    check_context_notes(&notes)?;
+    let options = options.unwrap_or_default();
+
+    // The passage is restyled from beat 0 and moved back to where it was
+    let (source, offset, measures) = from_first_bar(notes.clone(), options.beats_per_measure(), None);
+
+    let mode = GenerationMode::Restyle { source };
+    let mut request = generation_request(mode, style, scale, Some(measures), &provider, None, &options)?;
+    request.start_beat = offset;
+    let mut response = run_generation(request, options, &app, &state).await?;
+
+    let diff = ai_models::diff_notes(&notes, &mut response.notes);
+    Ok(StyleTransferResponse { response, diff })
+}
+
+/// A candidate with its musical score
+#[derive(Debug, Clone, Serialize)]
+struct ScoredCandidate {
+    #[serde(flatten)]
+    candidate: Candidate,
+    score: MelodyScore,
+}
+
+/// Generate `count` melodies (default 3) and return them ranked by musical score, best first
+///
+/// For a "surprise me" pick: the first result is the strongest. Candidates
+/// are scored on contour, rhythmic variety, consonance and range (see
+/// `ai_scoring`). Takes the same arguments and options as `generate_melody_candidates`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_best_of(
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: String,
+    temperature: Option<f32>,
+    count: Option<u32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<ScoredCandidate>, String> {
+    let options = options.unwrap_or_default();
+    let request = generation_request(GenerationMode::Melody, prompt, scale, measures, &provider, temperature, &options)?;
+    let candidates = run_candidates(request, options, count.unwrap_or(DEFAULT_CANDIDATES), &app, &state).await?;
+    Ok(rank_candidates(candidates))
+}
+
+/// Score candidates and sort them best first
+fn rank_candidates(candidates: Vec<Candidate>) -> Vec<ScoredCandidate> {
+    let mut ranked: Vec<ScoredCandidate> = candidates
+        .into_iter()
+        .map(|candidate| ScoredCandidate { score: ai_scoring::score(&candidate.response.notes), candidate })
+        .collect();
+    ranked.sort_by(|a, b| b.score.overall.total_cmp(&a.score.overall));
+    ranked
+}
+
+/// Rate notes on the scoring criteria used to rank candidates
+#[tauri::command]
+fn score_melody(notes: Vec<AINote>) -> MelodyScore {
+    ai_scoring::score(&notes)
+}
+
+/// Flag parallel fifths and octaves, awkward leaps and voice crossings in `notes`
+///
+/// Each track is a voice, or several when it plays chords. Warnings carry the
+/// ids of the notes to highlight, in time order.
+#[tauri::command]
+fn check_voice_leading(notes: Vec<AINote>) -> Vec<VoiceWarning> {
+    voice_leading::check(&notes)
+}
+
+/// Ask a provider for feedback on the user's notes: phrasing, harmony issues and suggested edits
+///
+/// Unlike generation this changes nothing; each suggested edit names the ids
+/// of the notes it applies to. `focus` narrows the feedback ("the ending").
+/// Queued with the provider's generations.
+#[tauri::command]
+async fn analyze_melody(
+    notes: Vec<AINote>,
+    scale: Option<AIScale>,
+    focus: Option<String>,
+    provider: String,
+    state: State<'_, AppState>,
+) -> Result<MelodyCritique, String> {
+    check_context_notes(&notes)?;
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key = provider_api_key(&state, &ai_provider)?;
+    let client = create_client(&ai_provider, &provider_config(&state, &ai_provider)?, &state.http_clients);
+
+    // Number the notes in playing order
+    let mut notes = notes;
+    notes.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(a.pitch.cmp(&b.pitch)));
+
+    let _permit = state.rate_limiter.acquire(ai_provider.as_str(), |_| {}).await;
+    let analysis = client.analyze_melody(&notes, scale.as_ref(), focus.as_deref(), &api_key);
+    let result = audited(&state, &ai_provider, "analyze", analysis)
+        .await
+        .map_err(|e| format!("Failed to analyze melody: {}", e));
+    record_key_use(&state, &ai_provider, &result);
+    result
+}
+
+/// Title suggestions returned by `suggest_title` unless the caller asks for another number
+const DEFAULT_TITLE_COUNT: u32 = 5;
+
+/// Most title suggestions one `suggest_title` call returns
+const MAX_TITLE_COUNT: u32 = 10;
+
+/// Ask a provider for name ideas for a project, from its notes and/or the prompt it was generated from
+///
+/// Returns up to `count` (default 5) distinct titles; `accept_title` stores
+/// the one the user picks. Queued with the provider's generations.
+#[tauri::command]
+async fn suggest_title(
+    notes: Vec<AINote>,
+    prompt: Option<String>,
+    scale: Option<AIScale>,
+    count: Option<u32>,
+    provider: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let count = count.unwrap_or(DEFAULT_TITLE_COUNT);
+    if count == 0 || count > MAX_TITLE_COUNT {
+        return Err(format!("Title count must be between 1 and {}", MAX_TITLE_COUNT));
+    }
+    let prompt = prompt.filter(|p| !p.trim().is_empty());
+    if notes.is_empty() && prompt.is_none() {
+        return Err("Nothing to name: give notes or a prompt".to_string());
+    }
+    if !notes.is_empty() {
+        check_context_notes(&notes)?;
+    }
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key = provider_api_key(&state, &ai_provider)?;
+    let client = create_client(&ai_provider, &provider_config(&state, &ai_provider)?, &state.http_clients);
+
+    let _permit = state.rate_limiter.acquire(ai_provider.as_str(), |_| {}).await;
+    let titles = client.suggest_titles(&notes, scale.as_ref(), prompt.as_deref(), count, &api_key);
+    let result = audited(&state, &ai_provider, "suggest_title", titles)
+        .await
+        .map_err(|e| format!("Failed to suggest titles: {}", e));
+    record_key_use(&state, &ai_provider, &result);
+    result
+}
+
+/// Store a title the user picked as the project's name, in the file at `path`
+///
+/// Encrypted projects need their `password`; it never encrypts a plain one.
+/// The project library is updated for plain projects.
+#[tauri::command]
+fn accept_title(path: String, title: String, password: Option<String>, state: State<AppState>) -> Result<(), String> {
+    let title = title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err(format!("Title must be 1 to {} characters", MAX_TITLE_CHARS));
+    }
+    let path = path_string(state.fs_scope.check_write(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+
+    let data = std::fs::read(&path)
+        .map_err(|e| project_file::describe_io_error("read", std::path::Path::new(&path), &e))?;
+    // The file keeps its encryption state: a password only unlocks an encrypted one
+    let password = project_crypto::password_for_edit(&data, password.as_deref());
+    let (mut project, format) = decode_project_data(&data, password)?;
+    project.name = title.to_string();
+    project.metadata.modified_at = Some(chrono::Local::now().to_rfc3339());
+
+    let data = encode_project_file(&project, format, password)?;
+    state.project_watcher.expect_write(std::path::Path::new(&path), &data);
+    project_file::write_atomic(std::path::Path::new(&path), &data)?;
+
+    // Encrypted projects stay out of the library
+    if password.is_none() {
+        if let Err(e) = state.library.index(&path, &project) {
+            eprintln!("⚠ {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Aborts spawned tasks when dropped, so they don't outlive the command that started them
+struct AbortOnDrop(Vec<tokio::task::AbortHandle>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// Generate up to `count` candidates for one request, to audition and pick from
+///
+/// Candidates are requested in parallel, as many at once as the provider's
+/// rate limiter admits; the rest queue like any other generations. If the
+/// command is dropped, candidates still running are aborted. Each is tagged
+/// `<request_id>-<n>` in progress events and in the result; cancelling
+/// `request_id` cancels the whole batch. With a seed, candidate n uses
+/// seed + n - 1. Failed candidates are left out; the batch fails only if all
+/// of them do. Results keep the candidates' order.
+async fn run_candidates(
+    request: MelodyRequest,
+    options: GenerationOptions,
+    count: u32,
+    app: &AppHandle,
+    state: &AppState,
+) -> Result<Vec<Candidate>, String> {
+    if count == 0 || count > MAX_CANDIDATES {
+        return Err(format!("Candidate count must be between 1 and {}", MAX_CANDIDATES));
+    }
+
+    let batch_id = options.request_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let batch = CancellationToken::new();
+    let registered = register_generation(state, &batch_id, &batch)?;
+
+    let mut tasks = Vec::new();
+    for n in 1..=count {
+        let id = format!("{}-{}", batch_id, n);
+        let seed = request.seed.map(|seed| seed.wrapping_add(n as u64 - 1));
+        let candidate_options = GenerationOptions {
+            request_id: Some(id.clone()),
+            seed,
+            // Identical unseeded candidates would all be answered by the same cache entry
+            bypass_cache: options.bypass_cache || seed.is_none(),
+            ..options.clone()
+        };
+        let candidate = MelodyRequest { seed, ..request.clone() };
+
+        let app = app.clone();
+        let batch = batch.clone();
+        let task = tauri::async_runtime::spawn(async move {
+            let state = app.state::<AppState>();
+            generate_with(candidate, candidate_options, &app, &state, Some(&batch)).await.map_err(String::from)
+        });
+        tasks.push((id, task));
+    }
+    let _abort = AbortOnDrop(tasks.iter().map(|(_, task)| task.inner().abort_handle()).collect());
+
+    let mut candidates = Vec::new();
+    let mut last_error = None;
+    for (id, task) in tasks {
+        match task.await {
+            Ok(Ok(response)) => candidates.push(Candidate { id, response }),
+            Ok(Err(_)) if batch.is_cancelled() => {}
+            Ok(Err(e)) => {
+                eprintln!("⚠ Candidate {} failed: {}", id, e);
+                last_error = Some(e);
+            }
+            Err(e) => last_error = Some(format!("Candidate {} failed: {}", id, e)),
+        }
+    }
+    drop(registered);
+
+    if batch.is_cancelled() {
+        return Err(GENERATION_CANCELLED.to_string());
+    }
+    if candidates.is_empty() {
+        return Err(last_error.unwrap_or_else(|| "No candidates were generated".to_string()));
+    }
+    Ok(candidates)
+}
+
+/// Total measures of a song generated section by section
+const SONG_MEASURES: std::ops::RangeInclusive<u32> = 16..=32;
+
+/// Sections of a song when none are given: Intro, A, B, A'
+fn default_song_sections() -> Vec<SongSection> {
+    [("Intro", 4), ("A", 8), ("B", 8), ("A'", 8)]
+        .into_iter()
+        .map(|(label, measures)| SongSection { label: label.to_string(), measures })
+        .collect()
+}
+
+/// A song stitched together from its sections, with a marker at the start of each
+#[derive(Debug, Clone, Serialize)]
+struct SongResponse {
+    #[serde(flatten)]
+    response: MelodyResponse,
+    markers: Vec<Marker>,
+}
+
+/// Generate a song as labeled sections (default Intro/A/B/A'), 16-32 measures in all
+///
+/// Every section is its own request, continuing from the end of the one
+/// before; a primed label ("A'") restates the section it names. Sections are
+/// tagged `<request_id>-<n>` in progress events, and cancelling `request_id`
+/// cancels the rest of the song. With `options.project_path`, a marker per
+/// section is added to the project. Takes the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_song(
+    prompt: String,
+    sections: Option<Vec<SongSection>>,
+    scale: Option<AIScale>,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<SongResponse, String> {
+    let options = options.unwrap_or_default();
+    let sections = sections.unwrap_or_else(default_song_sections);
+    for section in &sections {
+        section.validate()
+            .map_err(|e| format!("Invalid section {}: {}", section.label, e))?;
+    }
+    let total: u32 = sections.iter().map(|section| section.measures).sum();
+    if !SONG_MEASURES.contains(&total) {
+        return Err(format!(
+            "A song needs {} to {} measures (the sections add up to {})",
+            SONG_MEASURES.start(),
+            SONG_MEASURES.end(),
+            total
+        ));
+    }
+
+    let structure = sections
+        .iter()
+        .map(|section| format!("{} ({} measures)", section.label, section.measures))
+        .collect::<Vec<_>>()
+        .join(" → ");
+
+    let batch_id = options.request_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let batch = CancellationToken::new();
+    let registered = register_generation(&state, &batch_id, &batch)?;
+
+    let mut song: Option<MelodyResponse> = None;
+    let mut markers = Vec::new();
+    let mut written: Vec<(String, f64, Vec<AINote>)> = Vec::new(); // (label, start beat, notes)
+    let beats = options.beats_per_measure();
+    let mut start_beat = 0.0;
+    let mut result = Ok(());
+
+    for (n, section) in sections.iter().enumerate() {
+        let context = song.as_ref().map_or_else(Vec::new, |song| {
+            let context_start = start_beat - DEFAULT_CONTEXT_MEASURES as f64 * beats;
+            song.notes
+                .iter()
+                .filter(|note| note.start_time + note.duration > context_start)
+                .map(|note| AINote { start_time: note.start_time - start_beat, ..note.clone() })
+                .collect()
+        });
+        let reprise = section
+            .reprise_of()
+            .and_then(|base| written.iter().find(|(label, _, _)| label == base))
+            .map_or_else(Vec::new, |(_, base_start, notes)| {
+                notes
+                    .iter()
+                    .map(|note| AINote { start_time: note.start_time - base_start, ..note.clone() })
+                    .collect()
+            });
+
+        let mode = GenerationMode::Section {
+            label: section.label.clone(),
+            structure: structure.clone(),
+            context,
+            reprise,
+        };
+        let section_options = GenerationOptions {
+            request_id: Some(format!("{}-{}", batch_id, n + 1)),
+            ending: options.ending.filter(|_| n + 1 == sections.len()),
+            ..options.clone()
+        };
+        let request = match generation_request(mode, prompt.clone(), scale.clone(), Some(section.measures), &provider, temperature, &section_options) {
+            Ok(request) => MelodyRequest { start_beat, ..request },
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+
+        match generate_with(request, section_options, &app, &state, Some(&batch)).await.map_err(String::from) {
+            Ok(response) => {
+                markers.push(Marker {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    name: section.label.clone(),
+                    beat: start_beat,
+                });
+                written.push((section.label.clone(), start_beat, response.notes.clone()));
+                match &mut song {
+                    Some(song) => {
+                        song.notes.extend(response.notes);
+                        song.chords.extend(response.chords);
+                        song.dynamics.extend(response.dynamics);
+                        song.pedal.extend(response.pedal);
+                    }
+                    None => song = Some(response),
+                }
+            }
+            Err(e) => {
+                result = Err(format!("Section {} failed: {}", section.label, e));
+                break;
+            }
+        }
+        start_beat += section.measures as f64 * beats;
+    }
+    drop(registered);
+
+    if batch.is_cancelled() {
+        return Err(GENERATION_CANCELLED.to_string());
+    }
+    result?;
+    let response = song.ok_or("No sections were generated")?;
+
+    if let Some(path) = options.project_path.as_deref() {
+        let appended = state
+            .fs_scope
+            .check_write(std::path::Path::new(path), &fs_scope::PROJECT_EXTENSIONS)
+            .and_then(path_string)
+            .and_then(|path| append_markers(&path, options.project_password.as_deref(), &markers, &state));
+        if let Err(e) = appended {
+            eprintln!("⚠ Failed to add section markers to {}: {}", path, e);
+        }
+    }
+
+    Ok(SongResponse { response, markers })
+}
+
+/// Measures a long piece may have; longer than one request (16 measures) allows
+const LONG_MEASURES: std::ops::RangeInclusive<u32> = 32..=64;
+
+/// Measures generated per request of a long piece
+const CHUNK_MEASURES: u32 = 8;
+
+/// Measures of the piece so far each chunk receives as context
+const CHUNK_OVERLAP_MEASURES: u32 = 2;
+
+/// Generate a long piece (32-64 measures) in chunks of 8 measures
+///
+/// Each chunk continues the piece, seeing its last 2 measures as context,
+/// and must connect to them (see `GenerationMode::Continue`); a chord
+/// progression carries on across chunks. Chunks are tagged
+/// `<request_id>-<n>` in progress events, and cancelling `request_id`
+/// cancels the rest of the piece. Takes the same options as `generate_melody`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_long_piece(
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: u32,
+    provider: String,
+    temperature: Option<f32>,
+    options: Option<GenerationOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<MelodyResponse, String> {
+    let options = options.unwrap_or_default();
+    if !LONG_MEASURES.contains(&measures) {
+        return Err(format!(
+            "A long piece needs {} to {} measures (use generate_melody for shorter ones)",
+            LONG_MEASURES.start(),
+            LONG_MEASURES.end()
+        ));
+    }
+
+    let batch_id = options.request_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let batch = CancellationToken::new();
+    let registered = register_generation(&state, &batch_id, &batch)?;
+
+    let beats = options.beats_per_measure();
+    let mut piece: Option<MelodyResponse> = None;
+    let mut written = 0;
+    let mut result = Ok(());
+
+    while written < measures {
+        let chunk = CHUNK_MEASURES.min(measures - written);
+        let start_beat = written as f64 * beats;
+        let mode = match &piece {
+            None => GenerationMode::Melody,
+            Some(piece) => {
+                let context_start = start_beat - CHUNK_OVERLAP_MEASURES as f64 * beats;
+                let context = piece
+                    .notes
+                    .iter()
+                    .filter(|note| note.start_time + note.duration > context_start)
+                    .map(|note| AINote { start_time: note.start_time - start_beat, ..note.clone() })
+                    .collect();
+                GenerationMode::Continue { context }
+            }
+        };
+        let ending = if written + chunk == measures { "; end the piece with a cadence" } else { "" };
+        let chunk_prompt = format!(
+            "{} (measures {}-{} of a {}-measure piece{})",
+            prompt,
+            written + 1,
+            written + chunk,
+            measures,
+            ending
+        );
+        let chunk_options = GenerationOptions {
+            request_id: Some(format!("{}-{}", batch_id, written / CHUNK_MEASURES + 1)),
+            chord_progression: options
+                .chord_progression
+                .as_deref()
+                .map(|progression| ai_models::progression_from_bar(progression, written)),
+            ending: options.ending.filter(|_| written + chunk == measures),
+            ..options.clone()
+        };
+        let request = match generation_request(mode, chunk_prompt, scale.clone(), Some(chunk), &provider, temperature, &chunk_options) {
+            Ok(request) => MelodyRequest { start_beat, ..request },
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+
+        match generate_with(request, chunk_options, &app, &state, Some(&batch)).await.map_err(String::from) {
+            Ok(response) => match &mut piece {
+                Some(piece) => {
+                    piece.notes.extend(response.notes);
+                    piece.chords.extend(response.chords);
+                    piece.dynamics.extend(response.dynamics);
+                    piece.pedal.extend(response.pedal);
+                }
+                None => piece = Some(response),
+            },
+            Err(e) => {
+                result = Err(format!("Measures {}-{} failed: {}", written + 1, written + chunk, e));
+                break;
+            }
+        }
+        written += chunk;
+    }
+    drop(registered);
+
+    if batch.is_cancelled() {
+        return Err(GENERATION_CANCELLED.to_string());
+    }
+    result?;
+    piece.ok_or_else(|| "No measures were generated".to_string())
+}
+
+/// Add markers to a saved project
+fn append_markers(path: &str, password: Option<&str>, markers: &[Marker], state: &AppState) -> Result<(), String> {
+    update_project(path, password, state, |project| {
+        project.markers.extend_from_slice(markers);
+        Ok(())
+    })
+}
+
+/// Build, sanitize and validate a generation request
+fn generation_request(
+    mode: GenerationMode,
+    prompt: String,
+    scale: Option<AIScale>,
+    measures: Option<u32>,
+    provider: &str,
+    temperature: Option<f32>,
+    options: &GenerationOptions,
+) -> Result<MelodyRequest, String> {
+    // Parse provider
+    let ai_provider = AIProvider::from_str(provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+
+    // Build request
+    let mut request = MelodyRequest {
+        prompt,
+        scale,
+        measures: measures.unwrap_or(4),
+        model_provider: ai_provider,
+        temperature,
+        seed: options.seed,
+        mode,
+        start_beat: 0.0,
+        chord_progression: options.chord_progression.clone().filter(|p| !p.trim().is_empty()),
+        time_signature: options.time_signature.unwrap_or_default(),
+        system_prompt: options.system_prompt.clone().filter(|custom| !custom.text.trim().is_empty()),
+        max_tokens: options.max_tokens,
+    };
+
+    // Sanitize inputs before validation
+    request.sanitize_prompt();
+
+    // Validate request
+    request.validate()
+        .map_err(|e| format!("Invalid request: {}", e))?;
+
+    Ok(request)
+}
+
+/// Run a generation request: from the cache, or through the provider's queue with retries
+///
+/// Progress, queueing, cancellation, caching and project history work the
+/// same for every generation mode.
+async fn run_generation(
+    request: MelodyRequest,
+    options: GenerationOptions,
+    app: &AppHandle,
+    state: &AppState,
+) -> Result<MelodyResponse, String> {
+    generate_with(request, options, app, state, None).await.map_err(String::from)
+}
+
+/// `run_generation`, also cancelled with `parent` (the batch it belongs to)
+async fn generate_with(
+    mut request: MelodyRequest,
+    options: GenerationOptions,
+    app: &AppHandle,
+    state: &AppState,
+    parent: Option<&CancellationToken>,
+) -> Result<MelodyResponse, GenerationError> {
+    if request.system_prompt.is_none() {
+        request.system_prompt = state.ai_settings.system_prompt();
+    }
+    // Registered first, so a cancel sent right after the request is never missed
+    let request_id = options.request_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let cancel = parent.map_or_else(CancellationToken::new, CancellationToken::child_token);
+    let registered = register_generation(state, &request_id, &cancel)?;
+
+    let ai_provider = request.model_provider.clone();
+    let mut api_key = provider_api_key(state, &ai_provider)?;
+
+    // Create client and generate with retry mechanism
+    let config = provider_config(state, &ai_provider)?;
+    request.temperature = request.temperature.or(config.temperature);
+    request.max_tokens = request.max_tokens.or(config.max_tokens);
+    let model = config.model.as_deref().or(config.deployment.as_deref()).or(config.model_path.as_deref());
+    let client = create_client(&ai_provider, &config, &state.http_clients);
+    let retry_policy = config.retry.clone().unwrap_or_default();
+    let report = |phase: GenerationPhase, attempt: u32| {
+        let event = GenerationProgressEvent { request_id: request_id.clone(), phase, attempt };
+        let _ = app.emit("generation-progress", event);
+    };
+
+    let cached = if options.bypass_cache { None } else { state.response_cache.get(&request, model) };
+    if let Some(mut response) = cached {
+        if let Some(ending) = options.ending {
+            response = fix_ending(response, ending, &request, None)
+                .await
+                .map_err(|e| GenerationError::Other(e.to_string()))?;
+        }
+        response.shift(request.start_beat);
+        report(GenerationPhase::Done, 0);
+        record_generation(state, options.project_path.as_deref(), options.project_password.as_deref(), &request, &response);
+        return Ok(response);
+    }
+
+    let result = async {
+        // Wait for our turn with this provider, emitting `generation-queued` if others are ahead
+        let mut permit = tokio::select! {
+            _ = cancel.cancelled() => return Err(anyhow::anyhow!(GENERATION_CANCELLED)),
+            permit = state.rate_limiter.acquire(ai_provider.as_str(), |position| {
+                let event = GenerationQueuedEvent { request_id: request_id.clone(), position };
+                let _ = app.emit("generation-queued", event);
+            }) => permit,
+        };
+
+        let mut rate_limited = 0;
+        let mut rotations = 0;
+        loop {
+            let generation = client.generate_melody_with_retry(&request, &api_key, &retry_policy, &report, &cancel);
+            let result = audited(state, &ai_provider, "generate", generation).await;
+
+            // Try the provider's other keys before giving up on or waiting out a rejected one
+            let rejected = config.rotate_keys && result.as_ref().is_err_and(ai_retry::is_key_rejected);
+            let key_count = if rejected { state.api_key_manager.lock().unwrap().key_count(&ai_provider) } else { 0 };
+            if rotations + 1 < key_count {
+                if let Some(next) = rotate_api_key(state, &ai_provider) {
+                    rotations += 1;
+                    api_key = next;
+                    continue;
+                }
+            }
+            let retry_after = match &result {
+                Err(e) => e.downcast_ref::<RateLimited>().map(|limited| limited.retry_after),
+                Ok(_) => None,
+            };
+
+            // Honor Retry-After a few times, holding back the whole provider queue meanwhile
+            match retry_after {
+                Some(delay) if rate_limited < MAX_RATE_LIMIT_RETRIES && delay <= MAX_RETRY_AFTER => {
+                    rate_limited += 1;
+                    report(GenerationPhase::RateLimited, 0);
+                    permit.back_off(delay);
+                    tokio::select! {
+                        _ = cancel.cancelled() => return Err(anyhow::anyhow!(GENERATION_CANCELLED)),
+                        _ = permit.ready() => {}
+                    }
+                }
+                _ => return result.map(|response| (response, permit)),
+            }
+        }
+    }
+    .await;
+
+    // The reply is cached with dynamics applied but before any ending fix (cache hits
+    // skip dynamics and get the fix); a cadence retry still holds the queue permit
+    let result = match result {
+        Ok((mut response, permit)) => {
+            response.apply_dynamics();
+            if let Err(e) = state.response_cache.put(&request, model, &response) {
+                eprintln!("⚠ Failed to cache AI response: {}", e);
+            }
+            match options.ending {
+                Some(ending) => {
+                    let retry = CadenceRetry { client: client.as_ref(), api_key: &api_key, state, cancel: &cancel, permit };
+                    fix_ending(response, ending, &request, Some(retry)).await
+                }
+                None => Ok(response),
+            }
+        }
+        Err(e) => Err(e),
+    };
+    drop(registered);
+
+    let phase = match &result {
+        Ok(_) => GenerationPhase::Done,
+        Err(_) if cancel.is_cancelled() => GenerationPhase::Cancelled,
+        Err(_) => GenerationPhase::Failed,
+    };
+    report(phase, 0);
+    let unreachable = result.as_ref().is_err_and(ai_retry::is_unreachable);
+    let result = result.map_err(|e| {
+        let unreachable = if ai_retry::is_unreachable(&e) { format!("{}: ", PROVIDER_UNREACHABLE) } else { String::new() };
+        format!("Failed to generate {}: {}{}", request.mode.description(), unreachable, e)
+    });
+    if phase != GenerationPhase::Cancelled {
+        record_key_use(state, &ai_provider, &result);
+    }
+    let mut response = result.map_err(|message| {
+        if unreachable { GenerationError::Unreachable(message) } else { GenerationError::Other(message) }
+    })?;
+    response.shift(request.start_beat);
+    record_generation(state, options.project_path.as_deref(), options.project_password.as_deref(), &request, &response);
+
+    Ok(response)
 }
 
-#[derive(Serialize, Deserialize)]
-struct ProjectData {
-    notes: Vec<Note>,
-    tempo: u16,
+/// Feedback for `EndingFix::Retry`, sent to the provider like a validation error
+const CADENCE_RETRY: &str = "The music doesn't end on a cadence. Keep it as it is, but make the final measure \
+    close on the tonic chord approached from the dominant (V-I), with the melody ending on the tonic";
+
+/// What `fix_ending` needs to ask the provider again, within the generation it belongs to
+struct CadenceRetry<'a> {
+    client: &'a dyn AIClient,
+    api_key: &'a str,
+    state: &'a AppState,
+    cancel: &'a CancellationToken, // The generation's token, so a cancel aborts the retry
+    permit: Permit,                 // Still held, so the retry waits its turn in the provider queue
+}
+
+/// Make sure a generated piece ends on an authentic or plagal cadence, as `ending` asks
+///
+/// Works in the request's scale, or the key detected from the notes when it
+/// is clear; without either (and for drums) the notes are left alone. The
+/// cache keeps the provider's reply as it was, so the fix applies to cached
+/// replies too. Cached replies (`retry` is None) never spend a request: the
+/// cadence is appended. Fails only if the generation is cancelled during a retry.
+async fn fix_ending(
+    mut response: MelodyResponse,
+    ending: EndingFix,
+    request: &MelodyRequest,
+    retry: Option<CadenceRetry<'_>>,
+) -> anyhow::Result<MelodyResponse> {
+    if matches!(request.mode, GenerationMode::Drums { .. }) {
+        return Ok(response);
+    }
+    let beats = request.time_signature.beats_per_measure();
+    let key = request.scale.clone().or_else(|| {
+        music_theory::detect_key(&response.notes)
+            .first()
+            .filter(|key| key.score >= music_theory::KEY_CONFIDENCE)
+            .map(KeyCandidate::scale)
+    });
+    let Some(key) = key.filter(|key| music_theory::check_root(&key.root).is_ok()) else {
+        return Ok(response);
+    };
+    let conclusive = |notes: &[AINote]| {
+        music_theory::detect_cadence(notes, &key, beats).is_some_and(|cadence| cadence.is_conclusive())
+    };
+    if conclusive(&response.notes) {
+        return Ok(response);
+    }
+
+    if let (EndingFix::Retry, Some(retry)) = (ending, retry) {
+        let provider = &request.model_provider;
+        let result = tokio::select! {
+            _ = retry.cancel.cancelled() => return Err(anyhow::anyhow!(GENERATION_CANCELLED)),
+            result = async {
+                retry.permit.ready().await;
+                let call = retry.client.generate_melody_retry(request, retry.api_key, CADENCE_RETRY);
+                audited(retry.state, provider, "generate", call).await.map_err(|e| e.to_string())
+            } => result,
+        };
+        record_key_use(retry.state, provider, &result);
+
+        match result {
+            Ok(mut retried) if retried.validate_request(request).is_ok() && conclusive(&retried.notes) => {
+                retried.apply_dynamics();
+                return Ok(retried);
+            }
+            Ok(_) => eprintln!("⚠ Retried ending still lacks a cadence; appending one"),
+            Err(e) => eprintln!("⚠ Failed to retry the ending ({}); appending a cadence", e),
+        }
+    }
+
+    let start = ai_models::measures_spanned(&response.notes, beats) as f64 * beats;
+    let velocity = response
+        .notes
+        .iter()
+        .max_by(|a, b| a.start_time.total_cmp(&b.start_time))
+        .map_or(80, |note| note.velocity);
+    let cadence = music_theory::authentic_cadence(&key, start, beats, velocity, request.mode.default_track());
+    response.notes.extend(cadence);
+    Ok(response)
+}
+
+/// Abort an in-flight AI generation (it fails with "Generation cancelled")
+///
+/// Returns false if no generation with this id is running.
+#[tauri::command]
+fn cancel_generation(request_id: String, state: State<AppState>) -> bool {
+    match state.generations.lock().unwrap().get(&request_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// A generation's entry in `AppState::generations`, removed when dropped
+struct RegisteredGeneration<'a> {
+    state: &'a AppState,
+    request_id: String,
+}
+
+impl Drop for RegisteredGeneration<'_> {
+    fn drop(&mut self) {
+        self.state.generations.lock().unwrap().remove(&self.request_id);
+    }
+}
+
+/// Make a generation cancellable with `cancel_generation` under `request_id`
+///
+/// Fails if a generation with the same id is still running.
+fn register_generation<'a>(
+    state: &'a AppState,
+    request_id: &str,
+    cancel: &CancellationToken,
+) -> Result<RegisteredGeneration<'a>, String> {
+    let mut generations = state.generations.lock().unwrap();
+    if generations.contains_key(request_id) {
+        return Err(format!("A generation with id {} is already running", request_id));
+    }
+    generations.insert(request_id.to_string(), cancel.clone());
+    Ok(RegisteredGeneration { state, request_id: request_id.to_string() })
+}
+
+/// Record a generation in the app-level log and the prompt history, and in a project's history if a project is given
+///
+/// The melody is still returned if the log or project can't be updated.
+fn record_generation(
+    state: &AppState,
+    project_path: Option<&str>,
+    project_password: Option<&str>,
+    request: &MelodyRequest,
+    response: &MelodyResponse,
+) {
+    if let Err(e) = state.generation_log.add(request, response) {
+        eprintln!("⚠ Failed to log generation: {}", e);
+    }
+    if let Err(e) = state.prompt_history.record(&request.prompt) {
+        eprintln!("⚠ {}", e);
+    }
+    let Some(path) = project_path else { return };
+
+    let record = GenerationRecord::new(request.prompt.clone(), request.measures, response.clone());
+    let appended = state
+        .fs_scope
+        .check_write(std::path::Path::new(path), &fs_scope::PROJECT_EXTENSIONS)
+        .and_then(path_string)
+        .and_then(|path| append_generation(&path, project_password, record, state));
+    if let Err(e) = appended {
+        eprintln!("⚠ Failed to record generation in {}: {}", path, e);
+    }
+}
+
+/// Delete every cached AI response
+#[tauri::command]
+fn clear_ai_cache(state: State<AppState>) -> Result<(), String> {
+    state.response_cache.clear()
+}
+
+/// Past prompts starting with `prefix`, for autocomplete: most used first
+///
+/// Returns at most `limit` prompts (default 8). Prompts are remembered as
+/// they are generated with.
+#[tauri::command]
+fn get_prompt_suggestions(prefix: String, limit: Option<u32>, state: State<'_, AppState>) -> Vec<String> {
+    let limit = limit.map_or(prompt_history::DEFAULT_SUGGESTIONS, |limit| limit as usize);
+    state.prompt_history.suggestions(&prefix, limit)
+}
+
+/// Forget every past prompt
+#[tauri::command]
+fn clear_prompt_history(state: State<'_, AppState>) -> Result<(), String> {
+    state.prompt_history.clear()
+}
+
+/// List the prompt templates: the shipped presets, then the user's own
+#[tauri::command]
+fn list_prompt_templates(state: State<'_, AppState>) -> Vec<PromptTemplate> {
+    state.prompt_templates.list()
+}
+
+/// Save a new prompt template with `{genre}`, `{mood}` and `{tempo}` placeholders
+#[tauri::command]
+fn create_prompt_template(
     name: String,
-    created_at: String,
+    template: String,
+    defaults: Option<HashMap<String, String>>,
+    state: State<'_, AppState>,
+) -> Result<PromptTemplate, String> {
+    state.prompt_templates.create(name, template, defaults.unwrap_or_default())
 }
 
-/// Play a single note
+/// Replace one of the user's prompt templates
 #[tauri::command]
-fn play_note(pitch: u8, duration: f32, velocity: u8, state: State<AppState>) -> Result<(), String> {
-    // Use Arc to allow concurrent playback - no mutex needed for read-only operations
-    // SamplePlayer is read-only during playback, Arc allows concurrent access
-    state.sample_player.play_note(pitch, duration, velocity)
+fn update_prompt_template(template: PromptTemplate, state: State<'_, AppState>) -> Result<(), String> {
+    state.prompt_templates.update(template)
+}
+
+/// Delete one of the user's prompt templates
+#[tauri::command]
+fn delete_prompt_template(id: String, state: State<'_, AppState>) -> Result<(), String> {
+    state.prompt_templates.delete(&id)
 }
 
-/// Save project to a JSON file
+/// Fill in a prompt template's placeholders, giving the prompt to generate with
+///
+/// Placeholders missing from `values` take the template's defaults.
 #[tauri::command]
-fn save_project(notes: Vec<Note>, tempo: u16, name: String, path: String) -> Result<(), String> {
-    use std::fs;
+fn render_prompt_template(
+    id: String,
+    values: Option<HashMap<String, String>>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let template = state
+        .prompt_templates
+        .get(&id)
+        .ok_or_else(|| format!("No prompt template with id {}", id))?;
+    template.render(&values.unwrap_or_default())
+}
+
+/// Append a generation to a saved project's history
+fn append_generation(path: &str, password: Option<&str>, record: GenerationRecord, state: &AppState) -> Result<(), String> {
+    update_project(path, password, state, |project| {
+        project.generations.push(record);
+        Ok(())
+    })
+}
 
-    let project_data = ProjectData {
-        notes,
-        tempo,
-        name,
-        created_at: chrono::Local::now().to_rfc3339(),
+/// List AI generations, oldest first: those recorded in a project, or with no
+/// `path` every generation in the app-level log (`password` for encrypted projects)
+#[tauri::command]
+fn list_generations(
+    path: Option<String>,
+    password: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<GenerationSummary>, String> {
+    let Some(path) = path else {
+        return Ok(state.generation_log.list());
     };
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    let (project, _) = read_project_file(&path, password.as_deref())?;
+    Ok(project.generations.iter().map(GenerationSummary::from).collect())
+}
 
-    let json = serde_json::to_string_pretty(&project_data)
-        .map_err(|e| format!("Failed to serialize project: {}", e))?;
+/// Get the notes of a generation from the app-level log to insert again
+///
+/// Notes stay where they were generated unless shifted by `offset` beats,
+/// and on their own track unless `track_id` is given. The generation is
+/// marked accepted.
+#[tauri::command]
+fn reapply_generation(
+    id: String,
+    track_id: Option<String>,
+    offset: Option<f64>,
+    state: State<AppState>,
+) -> Result<Vec<AINote>, String> {
+    let entry = state
+        .generation_log
+        .get(&id)
+        .ok_or_else(|| format!("Generation not found: {}", id))?;
+    state.generation_log.set_outcome(&id, GenerationOutcome::Accepted)?;
 
-    fs::write(&path, json)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+    Ok(generation_history::reinsert_notes(&entry.record, track_id.as_deref(), offset.unwrap_or(0.0)))
+}
 
-    Ok(())
+/// Mark a generation in the app-level log as accepted or rejected
+#[tauri::command]
+fn set_generation_outcome(id: String, outcome: GenerationOutcome, state: State<AppState>) -> Result<(), String> {
+    state.generation_log.set_outcome(&id, outcome)
 }
 
-/// Load project from a JSON file
+/// Empty the app-level generation log (project histories are kept)
 #[tauri::command]
-fn load_project(path: String) -> Result<ProjectData, String> {
-    use std::fs;
+fn clear_generation_log(state: State<AppState>) -> Result<(), String> {
+    state.generation_log.clear()
+}
 
-    let json = fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read file: {}", e))?;
+/// Get the notes of a past generation to insert again, on `track_id` shifted by `offset` beats
+#[tauri::command]
+fn reinsert_generation(
+    path: String,
+    id: String,
+    track_id: String,
+    offset: Option<f64>,
+    password: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<AINote>, String> {
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    let (project, _) = read_project_file(&path, password.as_deref())?;
+    let record = project
+        .generations
+        .iter()
+        .find(|record| record.id == id)
+        .ok_or_else(|| format!("Generation not found: {}", id))?;
 
-    let project_data: ProjectData = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to parse project file: {}", e))?;
+    Ok(generation_history::reinsert_notes(record, Some(&track_id), offset.unwrap_or(0.0)))
+}
 
-    Ok(project_data)
+/// Remove a generation from a project's history (`password` for encrypted projects)
+#[tauri::command]
+fn delete_generation(path: String, id: String, password: Option<String>, state: State<AppState>) -> Result<(), String> {
+    let path = path_string(state.fs_scope.check_write(std::path::Path::new(&path), &fs_scope::PROJECT_EXTENSIONS)?)?;
+    update_project(&path, password.as_deref(), &state, |project| {
+        let count = project.generations.len();
+        project.generations.retain(|record| record.id != id);
+        if project.generations.len() == count {
+            return Err(format!("Generation not found: {}", id));
+        }
+        Ok(())
+    })
 }
 
-// ============================================================================
-// AI Melody Generation Commands
-// ============================================================================
+/// Copy notes to the system clipboard as a MIDI file, for pasting into a DAW
+#[tauri::command]
+fn copy_notes_as_midi(notes: Vec<Note>, tempo: u16) -> Result<(), String> {
+    if notes.is_empty() {
+        return Err("No notes selected".to_string());
+    }
+    midi_clipboard::copy(midi_file::notes_to_bytes(&notes, tempo))
+}
 
-/// Generate a melody using AI
+/// Paste MIDI from the system clipboard as new notes on `track_id`, shifted by `offset` beats
 #[tauri::command]
-async fn generate_melody(
-    prompt: String,
-    scale: Option<AIScale>,
-    measures: Option<u32>,
-    provider: String,
-    temperature: Option<f32>,
-    state: State<'_, AppState>,
-) -> Result<MelodyResponse, String> {
-    // Parse provider
-    let ai_provider = AIProvider::from_str(&provider)
-        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+fn paste_notes_from_midi(track_id: String, offset: Option<f32>) -> Result<Vec<Note>, String> {
+    let bytes = midi_clipboard::paste()?;
+    let mut notes = midi_file::parse_notes(&bytes, &track_id)?;
 
-    // Get API key (clone to avoid holding lock across await)
-    let api_key = {
-        let api_key_manager = state.api_key_manager.lock().unwrap();
-        api_key_manager
-            .get_api_key(&ai_provider)
-            .map_err(|e| format!("Failed to get API key: {}", e))?
-            .ok_or_else(|| format!("No API key configured for {}", provider))?
-    };
+    let offset = offset.unwrap_or(0.0);
+    for note in &mut notes {
+        note.start_time += offset;
+    }
+    Ok(notes)
+}
 
-    // Build request
-    let mut request = MelodyRequest {
-        prompt,
-        scale,
-        measures: measures.unwrap_or(4),
-        model_provider: ai_provider.clone(),
-        temperature,
-    };
+/// Tempo MIDI files without a tempo event play at
+const MIDI_DEFAULT_TEMPO: f64 = 120.0;
 
-    // Sanitize inputs before validation
-    request.sanitize_prompt();
+/// Confidence an estimated tempo needs to be used for an import
+const MIN_TEMPO_CONFIDENCE: f64 = 0.5;
 
-    // Validate request
-    request.validate()
-        .map_err(|e| format!("Invalid request: {}", e))?;
+/// Notes of an imported MIDI file and the tempo their beats are counted at
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportedMidi {
+    notes: Vec<Note>,
+    /// Tempo to offer as the project tempo (BPM)
+    tempo: f64,
+    /// Whether `tempo` was estimated from the notes' timing rather than set in the file
+    tempo_estimated: bool,
+}
 
-    // Create client and generate melody with retry mechanism
-    let client = create_client(&ai_provider);
-    let response = client
-        .generate_melody_with_retry(&request, &api_key)
-        .await
-        .map_err(|e| format!("Failed to generate melody: {}", e))?;
+/// Import the notes of a MIDI file onto `track_id`
+///
+/// Files without a tempo event play at 120 BPM, which rarely matches a free
+/// performance; their tempo is estimated from the notes' timing instead and,
+/// when the estimate is clear, the notes are placed on that tempo's beats.
+#[tauri::command]
+fn import_midi_file(path: String, track_id: String, state: State<AppState>) -> Result<ImportedMidi, String> {
+    let path = path_string(state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::MIDI_EXTENSIONS)?)?;
+    let bytes = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let (mut notes, tempo) = midi_file::parse_file(&bytes, &track_id)?;
+    if let Some(tempo) = tempo {
+        return Ok(ImportedMidi { notes, tempo, tempo_estimated: false });
+    }
 
-    Ok(response)
+    let onsets: Vec<f64> = notes.iter().map(|note| note.start_time as f64 * 60.0 / MIDI_DEFAULT_TEMPO).collect();
+    match tempo_estimate::estimate(&onsets).filter(|estimate| estimate.confidence >= MIN_TEMPO_CONFIDENCE) {
+        Some(estimate) => {
+            let scale = (estimate.bpm / MIDI_DEFAULT_TEMPO) as f32;
+            for note in &mut notes {
+                note.start_time *= scale;
+                note.duration *= scale;
+            }
+            Ok(ImportedMidi { notes, tempo: estimate.bpm, tempo_estimated: true })
+        }
+        None => Ok(ImportedMidi { notes, tempo: MIDI_DEFAULT_TEMPO, tempo_estimated: false }),
+    }
+}
+
+/// Estimate the tempo of notes placed at `tempo` BPM (such as transcribed audio)
+///
+/// None with fewer than four distinct note starts.
+#[tauri::command]
+fn estimate_tempo(notes: Vec<Note>, tempo: f64) -> Result<Option<TempoEstimate>, String> {
+    if !tempo.is_finite() || tempo <= 0.0 {
+        return Err(format!("Invalid tempo: {}", tempo));
+    }
+    let onsets: Vec<f64> = notes.iter().map(|note| note.start_time as f64 * 60.0 / tempo).collect();
+    Ok(tempo_estimate::estimate(&onsets))
 }
 
 /// Save an API key for an AI provider
+///
+/// With `verify`, the key is then checked by listing the provider's models
+/// (for providers where that needs the key) and the outcome recorded. A
+/// rejected key stays saved, but the error says so right away.
 #[tauri::command]
-fn save_ai_api_key(
+async fn save_ai_api_key(
     provider: String,
     api_key: String,
+    verify: Option<bool>,
     state: State<'_, AppState>,
-) -> Result<(), String> {
+) -> Result<Option<KeyVerification>, String> {
     let ai_provider = AIProvider::from_str(&provider)
         .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
 
     // Validate and sanitize API key
+    let api_key = Secret::new(api_key);
     let sanitized_key = api_key.trim();
     if sanitized_key.is_empty() {
         return Err("API key cannot be empty".to_string());
@@ -173,7 +3391,73 @@ fn save_ai_api_key(
         // Guard is dropped here when scope ends
     }
 
-    Ok(())
+    if !verify.unwrap_or(false) || !ai_provider.can_verify_api_key() {
+        return Ok(None);
+    }
+
+    let client = create_client(&ai_provider, &provider_config(&state, &ai_provider)?, &state.http_clients);
+    let result = audited(&state, &ai_provider, "verify_key", client.list_models(sanitized_key)).await;
+    let verification = state
+        .api_key_manager
+        .lock()
+        .unwrap()
+        .set_verification(&ai_provider, result.is_ok())
+        .map_err(|e| format!("Failed to record API key verification: {}", e))?;
+
+    match result {
+        Ok(_) => Ok(Some(verification)),
+        Err(e) => Err(format!("API key saved, but {} rejected it: {}", ai_provider.as_str(), e)),
+    }
+}
+
+/// Whether a provider accepted its saved API key when last checked (None if never checked)
+#[tauri::command]
+fn get_api_key_verification(provider: String, state: State<'_, AppState>) -> Result<Option<KeyVerification>, String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .get_verification(&ai_provider)
+        .map_err(|e| format!("Failed to get API key verification: {}", e))
+}
+
+/// Save the AWS access key pair used for Bedrock
+#[tauri::command]
+fn save_aws_credentials(
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let secret_access_key = Secret::new(secret_access_key);
+    let session_token = session_token.map(Secret::new);
+    let credentials = AwsCredentials {
+        access_key_id: access_key_id.trim().to_string(),
+        secret_access_key: Secret::new(secret_access_key.trim().to_string()),
+        session_token: session_token
+            .as_ref()
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(|t| Secret::new(t.to_string())),
+    };
+    if credentials.access_key_id.is_empty() || credentials.secret_access_key.is_empty() {
+        return Err("Access key ID and secret access key are required".to_string());
+    }
+
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .save_aws_credentials(&credentials)
+        .map_err(|e| format!("Failed to save AWS credentials: {}", e))
+}
+
+/// Save the Google credentials used for Vertex AI (service account key JSON or OAuth access token)
+#[tauri::command]
+fn save_google_credentials(credentials: String, state: State<'_, AppState>) -> Result<(), String> {
+    let credentials = Secret::new(credentials);
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .save_google_credentials(&credentials)
+        .map_err(|e| format!("Failed to save Google credentials: {}", e))
 }
 
 /// Delete an API key for an AI provider
@@ -213,23 +3497,205 @@ fn get_configured_ai_providers(state: State<'_, AppState>) -> Result<Vec<String>
     Ok(provider_names)
 }
 
-/// Test if an AI provider connection works
+/// Save another API key for a provider, returning its id
+///
+/// Providers can hold several keys (e.g. personal and team); the first one
+/// saved is used until another is chosen with `set_active_api_key`.
 #[tauri::command]
-async fn test_ai_connection(
+fn add_ai_api_key(
     provider: String,
+    api_key: String,
+    label: Option<String>,
     state: State<'_, AppState>,
-) -> Result<bool, String> {
+) -> Result<String, String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key = Secret::new(api_key);
+    let sanitized_key = api_key.trim();
+    if sanitized_key.is_empty() || sanitized_key.len() > 500 || sanitized_key.chars().any(|c| c.is_control()) {
+        return Err("API key must be 1-500 characters without control characters".to_string());
+    }
+
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .add_api_key(&ai_provider, label.as_deref(), sanitized_key)
+        .map_err(|e| format!("Failed to save API key: {}", e))
+}
+
+/// List the API keys saved for a provider, with the one in use marked
+#[tauri::command]
+fn list_ai_api_keys(provider: String, state: State<'_, AppState>) -> Result<Vec<StoredKey>, String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .list_api_keys(&ai_provider)
+        .map_err(|e| format!("Failed to list API keys: {}", e))
+}
+
+/// Use another of a provider's saved API keys
+#[tauri::command]
+fn set_active_api_key(provider: String, id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .set_active_key(&ai_provider, &id)
+        .map_err(|e| format!("Failed to select API key: {}", e))
+}
+
+/// Delete one of a provider's saved API keys by id
+#[tauri::command]
+fn remove_ai_api_key(provider: String, id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .delete_key(&ai_provider, &id)
+        .map_err(|e| format!("Failed to delete API key: {}", e))
+}
+
+/// Source, label, last use and last error of every provider's key, for the settings
+#[tauri::command]
+fn get_provider_status(state: State<'_, AppState>) -> Result<Vec<ProviderStatus>, String> {
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .provider_status()
+        .map_err(|e| format!("Failed to get provider status: {}", e))
+}
+
+/// Requests made with provider keys (which key, when, endpoint, status, tokens), newest first
+///
+/// Filters by provider, key id and time range; the totals cover every
+/// matching request even when `limit` cuts the list short.
+#[tauri::command]
+async fn query_api_key_audit(query: Option<AuditQuery>, app: AppHandle) -> Result<AuditReport, String> {
+    tauri::async_runtime::spawn_blocking(move || app.state::<AppState>().key_audit.query(&query.unwrap_or_default()))
+        .await
+        .map_err(|e| format!("Key audit query failed: {}", e))?
+}
+
+/// Name the saved API key of a provider (None or empty to remove the label)
+#[tauri::command]
+fn set_api_key_label(provider: String, label: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .set_label(&ai_provider, label.as_deref())
+        .map_err(|e| format!("Failed to set API key label: {}", e))
+}
+
+/// Where a provider's API key comes from: saved in the app or an environment variable (None if it has none)
+#[tauri::command]
+fn get_api_key_source(provider: String, state: State<'_, AppState>) -> Result<Option<KeySource>, String> {
     let ai_provider = AIProvider::from_str(&provider)
         .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    Ok(api_key_manager.api_key_source(&ai_provider))
+}
 
-    // Get API key (clone to avoid holding lock across await)
-    let api_key = {
+/// Export every saved API key and secret to `path`, encrypted with a backup password
+///
+/// The encrypted key file only opens on this machine; a backup moves keys to another one.
+#[tauri::command]
+fn export_api_keys(path: String, password: String, state: State<'_, AppState>) -> Result<(), String> {
+    let password = Secret::new(password);
+    let path = state.fs_scope.check_write(std::path::Path::new(&path), &fs_scope::KEY_BACKUP_EXTENSIONS)?;
+    let backup = {
         let api_key_manager = state.api_key_manager.lock().unwrap();
         api_key_manager
-            .get_api_key(&ai_provider)
-            .map_err(|e| format!("Failed to get API key: {}", e))?
-            .ok_or_else(|| "No API key configured".to_string())?
+            .export_backup(&password)
+            .map_err(|e| format!("Failed to export API keys: {}", e))?
     };
+    std::fs::write(&path, backup).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Import the API keys and secrets of a backup made by `export_api_keys`, returning their names
+#[tauri::command]
+fn import_api_keys(path: String, password: String, state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let password = Secret::new(password);
+    let path = state.fs_scope.check_read(std::path::Path::new(&path), &fs_scope::KEY_BACKUP_EXTENSIONS)?;
+    let backup = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .import_backup(&backup, &password)
+        .map_err(|e| format!("Failed to import API keys: {}", e))
+}
+
+/// Whether a master password protects the API keys, and whether it still has to be entered
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiKeyLockStatus {
+    master_password: bool,
+    locked: bool,
+    /// Saved keys couldn't be decrypted at startup (e.g. app data from another machine) and must be entered again
+    needs_reentry: bool,
+}
+
+#[tauri::command]
+fn get_api_key_lock_status(state: State<'_, AppState>) -> ApiKeyLockStatus {
+    let api_key_manager = state.api_key_manager.lock().unwrap();
+    ApiKeyLockStatus {
+        master_password: api_key_manager.has_master_password(),
+        locked: api_key_manager.is_locked(),
+        needs_reentry: api_key_manager.needs_reentry(),
+    }
+}
+
+/// Unlock the API keys with the master password (needed once per app start)
+#[tauri::command]
+fn unlock_api_keys(password: String, state: State<'_, AppState>) -> Result<(), String> {
+    let password = Secret::new(password);
+    let mut api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager.unlock(&password).map_err(|e| e.to_string())
+}
+
+/// Set, change or (with None) remove the master password protecting the API keys
+#[tauri::command]
+fn set_master_password(password: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+    let password = password.map(Secret::new);
+    let mut api_key_manager = state.api_key_manager.lock().unwrap();
+    api_key_manager
+        .set_master_password(password.as_deref().map(String::as_str))
+        .map_err(|e| format!("Failed to set master password: {}", e))
+}
+
+/// List the models an AI provider offers (empty if it can't list them)
+#[tauri::command]
+async fn list_models(provider: String, state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    let api_key = provider_api_key(&state, &ai_provider)?;
+
+    let client = create_client(&ai_provider, &provider_config(&state, &ai_provider)?, &state.http_clients);
+    let mut models = audited(&state, &ai_provider, "list_models", client.list_models(&api_key))
+        .await
+        .map_err(|e| format!("Failed to list models: {}", e))?;
+
+    models.sort();
+    models.dedup();
+    Ok(models)
+}
+
+/// Select the model used for an AI provider (`None` for the default model)
+#[tauri::command]
+fn set_ai_model(provider: String, model: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+    state.ai_settings.set_model(&ai_provider, model)
+}
+
+/// Test if an AI provider connection works
+#[tauri::command]
+async fn test_ai_connection(
+    provider: String,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    let ai_provider = AIProvider::from_str(&provider)
+        .ok_or_else(|| format!("Invalid AI provider: {}", provider))?;
+
+    let api_key = provider_api_key(&state, &ai_provider)?;
 
     // Try a simple test request
     let test_request = MelodyRequest {
@@ -238,13 +3704,22 @@ async fn test_ai_connection(
         measures: 1,
         model_provider: ai_provider.clone(),
         temperature: Some(1.0),
+        seed: None,
+        mode: GenerationMode::Melody,
+        start_beat: 0.0,
+        chord_progression: None,
+        time_signature: ai_models::TimeSignature::default(),
+        system_prompt: None,
+        max_tokens: None,
     };
 
-    let client = create_client(&ai_provider);
-    match client.generate_melody(&test_request, &api_key).await {
+    let client = create_client(&ai_provider, &provider_config(&state, &ai_provider)?, &state.http_clients);
+    let result = match audited(&state, &ai_provider, "test_connection", client.generate_melody(&test_request, &api_key)).await {
         Ok(_) => Ok(true),
         Err(e) => Err(format!("Connection test failed: {}", e)),
-    }
+    };
+    record_key_use(&state, &ai_provider, &result);
+    result
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -253,30 +3728,229 @@ pub fn run() {
     let (sample_player, stream) = SamplePlayer::new()
         .expect("Failed to load piano samples. Please ensure sample files are in the samples directory.");
 
-    // Initialize API key manager with default app data path
-    let app_data_dir = std::env::current_dir()
-        .expect("Failed to get current directory")
-        .join(".piano-app-data");
-    let api_key_manager = ApiKeyManager::new(app_data_dir)
-        .expect("Failed to initialize API key manager");
-
     println!("✓ Using piano samples ({} loaded)", sample_player.sample_count());
+    if sample_player.drum_count() > 0 {
+        println!("✓ Using drum samples ({} drum notes)", sample_player.drum_count());
+    }
+
+    let sample_player = Arc::new(sample_player);
+    let midi = Arc::new(MidiManager::new());
+    let transport = Arc::new(Transport::new(midi.clone()));
+    let arpeggiator = Arc::new(Arpeggiator::new(sample_player.clone(), transport.clone()));
+
+    let stream = Arc::new(StreamWrapper(stream));
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .manage(AppState {
-            sample_player: Arc::new(sample_player),
-            _stream: Arc::new(StreamWrapper(stream)),
-            api_key_manager: Arc::new(Mutex::new(api_key_manager)),
+        .plugin(tauri_plugin_dialog::init())
+        .setup(move |app| {
+            // Stores live in the per-user app data directory; earlier versions kept
+            // them next to the working directory
+            let app_data_dir = app.path().app_data_dir()?;
+            if let Ok(current_dir) = std::env::current_dir() {
+                match app_data::migrate(&current_dir.join(app_data::LEGACY_DIR), &app_data_dir) {
+                    Ok(moved) if !moved.is_empty() => {
+                        println!("✓ Moved {} app data entries to {}", moved.len(), app_data_dir.display())
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("⚠ Failed to migrate app data: {}", e),
+                }
+            }
+            let api_key_manager = ApiKeyManager::new(app_data_dir.clone())
+                .expect("Failed to initialize API key manager");
+            let key_audit = KeyAudit::new(app_data_dir.clone())
+                .expect("Failed to open API key audit log");
+            let midi_learn = MidiLearn::new(app_data_dir.clone())
+                .expect("Failed to load MIDI mappings");
+            let library = ProjectLibrary::new(app_data_dir.clone())
+                .expect("Failed to open project library");
+            let fs_scope = FsScope::new(app_data_dir.clone())
+                .expect("Failed to load approved directories");
+            let ai_settings = AiSettings::new(app_data_dir.clone())
+                .expect("Failed to load AI settings");
+            let generation_log = GenerationLog::new(app_data_dir.clone())
+                .expect("Failed to load generation log");
+            let prompt_templates = PromptTemplates::new(app_data_dir.clone())
+                .expect("Failed to load prompt templates");
+            let prompt_history = PromptHistory::new(app_data_dir.clone())
+                .expect("Failed to load prompt history");
+            let response_cache = ResponseCache::new(app_data_dir)
+                .expect("Failed to initialize AI response cache");
+
+            app.manage(AppState {
+                sample_player,
+                _stream: stream,
+                api_key_manager: Arc::new(Mutex::new(api_key_manager)),
+                key_audit,
+                http_clients: HttpClients::new(),
+                midi,
+                transport,
+                recorder: Arc::new(Recorder::new()),
+                step_recorder: Arc::new(StepRecorder::new()),
+                arpeggiator,
+                mpe: Arc::new(MpeProcessor::new()),
+                midi_learn: Arc::new(midi_learn),
+                keyboard: KeyboardEngine::new(),
+                tap_tempo: TapTempo::new(),
+                audio_recorder: AudioRecorder::new(),
+                render_jobs: Mutex::new(HashMap::new()),
+                library,
+                fs_scope,
+                project_watcher: ProjectWatcher::new(),
+                ai_settings,
+                generations: Mutex::new(HashMap::new()),
+                conversations: Conversations::new(),
+                generation_log,
+                rate_limiter: RateLimiter::new(),
+                response_cache,
+                prompt_templates,
+                prompt_history,
+            });
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             play_note,
+            play_drum,
+            set_sustain_pedal,
+            set_volume,
+            all_notes_off,
             save_project,
             load_project,
+            watch_project,
+            unwatch_project,
+            approve_directory,
+            list_approved_directories,
+            revoke_directory,
+            get_backup_policy,
+            set_backup_policy,
+            list_backups,
+            restore_backup,
+            query_library,
+            rescan_library,
+            remove_from_library,
+            set_projects_folder,
+            get_projects_folder,
+            search_projects,
+            export_project_bundle,
+            import_project_bundle,
+            render_project,
+            render_stems,
+            cancel_render,
+            batch_export,
+            list_midi_inputs,
+            connect_midi_input,
+            disconnect_midi_input,
+            start_midi_learn,
+            cancel_midi_learn,
+            list_midi_mappings,
+            delete_midi_mapping,
+            key_event,
+            set_keyboard_settings,
+            get_keyboard_settings,
+            set_mpe_settings,
+            get_mpe_settings,
+            start_recording,
+            stop_recording,
+            set_record_quantize,
+            extract_groove,
+            apply_groove,
+            start_step_record,
+            set_step_length,
+            step_input_note,
+            step_rest,
+            stop_step_record,
+            enable_arpeggiator,
+            disable_arpeggiator,
+            set_arpeggiator_chord,
+            list_midi_outputs,
+            set_midi_clock_output,
+            start_transport,
+            stop_transport,
+            set_transport_tempo,
+            seek_transport,
+            set_markers,
+            jump_to_marker,
+            get_transport_position,
+            tap_tempo,
+            list_audio_inputs,
+            start_audio_recording,
+            stop_audio_recording,
+            copy_notes_as_midi,
+            paste_notes_from_midi,
+            import_midi_file,
+            estimate_tempo,
             generate_melody,
+            generate_melody_candidates,
+            generate_best_of,
+            score_melody,
+            check_voice_leading,
+            analyze_melody,
+            suggest_title,
+            accept_title,
+            generate_chords,
+            generate_arrangement,
+            generate_from_motif,
+            generate_from_lyrics,
+            harmonize_melody,
+            generate_left_hand,
+            generate_bass,
+            generate_drums,
+            continue_passage,
+            detect_key,
+            suggest_chords,
+            get_scale_info,
+            voice_chords,
+            analyze_harmony,
+            infill_region,
+            generate_variation,
+            restyle_notes,
+            start_refinement,
+            refine_generation,
+            end_refinement,
+            generate_song,
+            generate_long_piece,
+            get_ai_provider_config,
+            set_ai_provider_config,
+            list_generations,
+            reinsert_generation,
+            reapply_generation,
+            set_generation_outcome,
+            clear_generation_log,
+            delete_generation,
             save_ai_api_key,
+            save_aws_credentials,
+            save_google_credentials,
+            list_models,
+            cancel_generation,
+            clear_ai_cache,
+            get_prompt_suggestions,
+            clear_prompt_history,
+            list_prompt_templates,
+            create_prompt_template,
+            update_prompt_template,
+            delete_prompt_template,
+            render_prompt_template,
+            set_ai_model,
+            get_ai_proxy,
+            set_ai_proxy,
+            get_custom_system_prompt,
+            set_custom_system_prompt,
             delete_ai_api_key,
             get_configured_ai_providers,
+            get_api_key_verification,
+            get_api_key_source,
+            get_provider_status,
+            query_api_key_audit,
+            add_ai_api_key,
+            list_ai_api_keys,
+            set_active_api_key,
+            remove_ai_api_key,
+            set_api_key_label,
+            get_api_key_lock_status,
+            export_api_keys,
+            import_api_keys,
+            unlock_api_keys,
+            set_master_password,
             test_ai_connection
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,226 @@
+//! Melody generation with a small neural model run locally from an ONNX file
+//!
+//! Models follow the event encoding of Magenta's melody RNN, one event per
+//! sixteenth note:
+//! - `0`: no event (the current note keeps sounding, or silence continues)
+//! - `1`: note off
+//! - `2..`: note on, for pitches from `LOWEST_PITCH` up
+//!
+//! The model takes the events so far as an `int64` tensor of shape `[1, steps]`
+//! and returns logits of shape `[1, steps, classes]`; the last step's logits
+//! give the next event. Inference needs the `local-model` feature (tract).
+
+use crate::ai_models::{GenerationMetadata, MelodyRequest, MelodyResponse, Note, Scale};
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::path::Path;
+
+/// Lowest pitch the model plays (C3)
+pub const LOWEST_PITCH: u8 = 48;
+
+/// Pitches the model plays, from `LOWEST_PITCH` (C3-B5)
+pub const PITCH_COUNT: usize = 36;
+
+const NO_EVENT: usize = 0;
+const NOTE_OFF: usize = 1;
+const FIRST_NOTE_ON: usize = 2;
+
+/// Events per beat (sixteenth notes)
+const STEPS_PER_BEAT: usize = 4;
+
+/// Velocity of the generated notes (the model has no dynamics)
+const VELOCITY: u8 = 80;
+
+/// Generate a melody with the model in `path`
+///
+/// The melody starts on the tonic; pitches outside the scale (when one is
+/// given) are never sampled. Runs the model once per sixteenth note, so call
+/// it off the async runtime.
+pub fn generate(path: &Path, model_name: &str, request: &MelodyRequest) -> Result<MelodyResponse> {
+    let model = inference::load(path)?;
+    let mut rng = match request.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let temperature = request.temperature.unwrap_or(1.0);
+    let allowed = allowed_events(request.scale.as_ref());
+
+    let beats = request.measures as f64 * request.time_signature.beats_per_measure();
+    let steps = (beats * STEPS_PER_BEAT as f64).round() as usize;
+    let tonic = request.scale.as_ref().map_or(0, |scale| Scale::note_to_offset(&scale.root).rem_euclid(12));
+    let mut events = vec![note_on(LOWEST_PITCH + 12 + tonic as u8) as i64];
+
+    while events.len() < steps {
+        let logits = inference::next_logits(&model, &events)?;
+        events.push(sample(&mut rng, &logits, &allowed, temperature) as i64);
+    }
+
+    let track_id = request.mode.default_track();
+    let notes = events_to_notes(&events)
+        .into_iter()
+        .map(|(pitch, start, length)| Note {
+            id: uuid::Uuid::new_v4().to_string(),
+            pitch,
+            start_time: start as f64 / STEPS_PER_BEAT as f64,
+            duration: length as f64 / STEPS_PER_BEAT as f64,
+            velocity: VELOCITY,
+            track_id: track_id.to_string(),
+        })
+        .collect();
+
+    Ok(MelodyResponse {
+        notes,
+        chords: Vec::new(),
+        dynamics: Vec::new(),
+        pedal: Vec::new(),
+        metadata: GenerationMetadata {
+            provider: request.model_provider.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            model_name: model_name.to_string(),
+            temperature,
+            scale: request.scale.clone(),
+            seed: request.seed,
+        },
+    })
+}
+
+fn note_on(pitch: u8) -> usize {
+    FIRST_NOTE_ON + (pitch - LOWEST_PITCH) as usize
+}
+
+/// Which events may be sampled: no-event, note off, and notes in the scale
+fn allowed_events(scale: Option<&Scale>) -> Vec<bool> {
+    let classes: Option<Vec<i32>> = scale.map(|scale| {
+        let notes = Scale { octave: None, ..scale.clone() }.get_midi_notes();
+        notes.iter().map(|&pitch| pitch as i32 % 12).collect()
+    });
+
+    let mut allowed = vec![true; FIRST_NOTE_ON];
+    allowed.extend((0..PITCH_COUNT).map(|i| {
+        let class = (LOWEST_PITCH as i32 + i as i32) % 12;
+        classes.as_ref().map_or(true, |classes| classes.contains(&class))
+    }));
+    allowed
+}
+
+/// Sample the next event from the model's logits, at `temperature`
+fn sample(rng: &mut StdRng, logits: &[f32], allowed: &[bool], temperature: f32) -> usize {
+    let temperature = temperature.max(0.05);
+    let candidates: Vec<(usize, f32)> = logits
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| allowed.get(i).copied().unwrap_or(false))
+        .map(|(i, &logit)| (i, logit / temperature))
+        .collect();
+    let Some(max) = candidates.iter().map(|&(_, logit)| logit).reduce(f32::max) else {
+        return NO_EVENT;
+    };
+
+    let weights: Vec<f32> = candidates.iter().map(|&(_, logit)| (logit - max).exp()).collect();
+    let mut target = rng.gen_range(0.0..weights.iter().sum::<f32>());
+    for (&(i, _), weight) in candidates.iter().zip(&weights) {
+        if target < *weight {
+            return i;
+        }
+        target -= weight;
+    }
+    candidates.last().map_or(NO_EVENT, |&(i, _)| i)
+}
+
+/// Notes (pitch, start step, length in steps) played by a sequence of events
+///
+/// A note lasts until the next note on or note off, or the end of the sequence.
+fn events_to_notes(events: &[i64]) -> Vec<(u8, usize, usize)> {
+    let mut notes = Vec::new();
+    let mut sounding: Option<(u8, usize)> = None;
+
+    for (step, &event) in events.iter().enumerate() {
+        let event = event as usize;
+        if event == NO_EVENT || event >= FIRST_NOTE_ON + PITCH_COUNT {
+            continue;
+        }
+        if let Some((pitch, start)) = sounding.take() {
+            notes.push((pitch, start, step - start));
+        }
+        if event >= FIRST_NOTE_ON {
+            sounding = Some((LOWEST_PITCH + (event - FIRST_NOTE_ON) as u8, step));
+        }
+    }
+    if let Some((pitch, start)) = sounding {
+        notes.push((pitch, start, events.len() - start));
+    }
+    notes
+}
+
+#[cfg(feature = "local-model")]
+mod inference {
+    use anyhow::{Context, Result};
+    use std::path::Path;
+    use tract_onnx::prelude::*;
+
+    pub type Model = TypedRunnableModel<TypedModel>;
+
+    pub fn load(path: &Path) -> Result<Model> {
+        tract_onnx::onnx()
+            .model_for_path(path)
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .with_context(|| format!("Failed to load local model {}", path.display()))
+    }
+
+    /// Logits of the event following `events`
+    pub fn next_logits(model: &Model, events: &[i64]) -> Result<Vec<f32>> {
+        let input = tract_ndarray::Array2::from_shape_vec((1, events.len()), events.to_vec())?;
+        let outputs = model
+            .run(tvec!(Tensor::from(input).into()))
+            .context("Local model inference failed")?;
+        let logits = outputs[0].to_array_view::<f32>()?;
+        let last = logits.shape().get(1).copied().unwrap_or(0);
+        anyhow::ensure!(logits.ndim() == 3 && last > 0, "Unexpected local model output shape {:?}", logits.shape());
+        Ok(logits.slice(tract_ndarray::s![0, last - 1, ..]).to_vec())
+    }
+}
+
+#[cfg(not(feature = "local-model"))]
+mod inference {
+    use anyhow::{bail, Result};
+    use std::path::Path;
+
+    pub struct Model;
+
+    pub fn load(_path: &Path) -> Result<Model> {
+        bail!("Local models aren't supported by this build (enable the local-model feature)")
+    }
+
+    pub fn next_logits(_model: &Model, _events: &[i64]) -> Result<Vec<f32>> {
+        bail!("Local models aren't supported by this build (enable the local-model feature)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_models::ScaleMode;
+
+    #[test]
+    fn test_events_to_notes_and_scale_mask() {
+        // C4 held for 4 steps, rest, then E4 to the end
+        let events = [note_on(60), NO_EVENT, NO_EVENT, NO_EVENT, NOTE_OFF, note_on(64), NO_EVENT];
+        let events: Vec<i64> = events.iter().map(|&e| e as i64).collect();
+        assert_eq!(events_to_notes(&events), vec![(60, 0, 4), (64, 5, 2)]);
+
+        let c_major = Scale { root: "C".to_string(), mode: ScaleMode::Major, octave: Some(4) };
+        let allowed = allowed_events(Some(&c_major));
+        assert!(allowed[NO_EVENT] && allowed[NOTE_OFF]);
+        assert!(allowed[note_on(60)] && !allowed[note_on(61)]);
+
+        // Disallowed events are never sampled, however likely
+        let mut logits = vec![0.0; FIRST_NOTE_ON + PITCH_COUNT];
+        logits[note_on(61)] = 100.0;
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..50 {
+            assert_ne!(sample(&mut rng, &logits, &allowed, 1.0), note_on(61));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+/// Most syllables a lyric may have (about 16 measures of a busy vocal line)
+pub const MAX_SYLLABLES: usize = 128;
+
+/// One-syllable words sung unstressed: articles, pronouns, prepositions, auxiliaries
+const UNSTRESSED_WORDS: &[&str] = &[
+    "a", "an", "the", "and", "or", "but", "nor", "so", "as", "if", "of", "to", "in", "on", "at", "by", "for",
+    "from", "with", "up", "i", "me", "my", "you", "your", "we", "us", "our", "he", "him", "his", "she", "her",
+    "it", "its", "they", "them", "their", "is", "am", "are", "was", "were", "be", "been", "do", "does", "did",
+    "has", "had", "have", "can", "will", "would", "could", "should", "i'm", "it's", "that", "this",
+];
+
+/// First syllables that usually leave the stress to the next one ("a-GAIN", "be-FORE", "re-TURN")
+const UNSTRESSED_PREFIXES: &[&str] = &["a", "be", "de", "re", "un", "con", "com", "ex", "for", "to", "en", "e"];
+
+/// A sung syllable of a lyric, with what the melody needs to know about it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Syllable {
+    pub text: String,
+    /// Carries the stress of its word (one-syllable words unless they are function words)
+    pub stressed: bool,
+    /// Last syllable of a line of the lyric, where the phrase can breathe
+    pub line_end: bool,
+}
+
+/// The syllable a note of a lyrics melody sings, stored in the project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteLyric {
+    pub note_id: String,
+    pub syllable: String,
+}
+
+/// Split a lyric into syllables with their stress, line by line
+///
+/// English rules of thumb: a syllable per vowel group, a final silent "e" or
+/// "-ed" folded into the syllable before, consonants between vowels going
+/// with the next syllable when single and split when doubled.
+pub fn syllables(lyric: &str) -> Vec<Syllable> {
+    let mut result = Vec::new();
+    for line in lyric.lines() {
+        let start = result.len();
+        for word in line.split_whitespace() {
+            let word: String = word
+                .chars()
+                .filter(|c| c.is_alphabetic() || *c == '\'')
+                .collect::<String>()
+                .trim_matches('\'')
+                .to_string();
+            if word.is_empty() {
+                continue;
+            }
+
+            let parts = split_word(&word);
+            let stress = stressed_syllable(&word, &parts);
+            result.extend(parts.into_iter().enumerate().map(|(i, text)| Syllable {
+                text,
+                stressed: Some(i) == stress,
+                line_end: false,
+            }));
+        }
+        if let Some(last) = result[start..].last_mut() {
+            last.line_end = true;
+        }
+    }
+    result
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c.to_ascii_lowercase(), 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+/// Split a word at its syllable boundaries ("water" → "wa", "ter")
+fn split_word(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let lower = word.to_lowercase();
+
+    // Vowel groups: (start, end) char ranges; a leading "y" is a consonant ("yes")
+    let mut nuclei: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if is_vowel(chars[i]) && !(i == 0 && chars[i].eq_ignore_ascii_case(&'y')) {
+            let start = i;
+            while i < chars.len() && is_vowel(chars[i]) {
+                i += 1;
+            }
+            nuclei.push((start, i));
+        } else {
+            i += 1;
+        }
+    }
+
+    // Silent endings: "love", "loved" (but "little", "wanted" keep theirs)
+    if nuclei.len() > 1 {
+        let (start, end) = nuclei[nuclei.len() - 1];
+        let before = start.checked_sub(1).map(|i| chars[i].to_ascii_lowercase());
+        let silent_e = end == chars.len() && end - start == 1 && lower.ends_with('e') && !lower.ends_with("le");
+        let silent_ed = lower.ends_with("ed") && end == chars.len() - 1 && !matches!(before, Some('t' | 'd'));
+        let silent_es = lower.ends_with("es")
+            && end == chars.len() - 1
+            && !matches!(before, Some('s' | 'x' | 'z' | 'h' | 'c' | 'g'));
+        if silent_e || silent_ed || silent_es {
+            nuclei.pop();
+        }
+    }
+    if nuclei.len() < 2 {
+        return vec![word.to_string()];
+    }
+
+    // Cut the consonants between two vowel groups
+    let mut cuts = Vec::new();
+    for pair in nuclei.windows(2) {
+        let (gap_start, gap_end) = (pair[0].1, pair[1].0);
+        let consonants = gap_end - gap_start;
+        cuts.push(if consonants <= 1 { gap_start } else { gap_start + 1 });
+    }
+
+    let mut parts = Vec::new();
+    let mut from = 0;
+    for cut in cuts {
+        parts.push(chars[from..cut].iter().collect());
+        from = cut;
+    }
+    parts.push(chars[from..].iter().collect());
+    parts
+}
+
+/// Index of the stressed syllable of a word, if any
+fn stressed_syllable(word: &str, parts: &[String]) -> Option<usize> {
+    let lower = word.to_lowercase();
+    if parts.len() == 1 {
+        return (!UNSTRESSED_WORDS.contains(&lower.as_str())).then_some(0);
+    }
+
+    let first = parts[0].to_lowercase();
+    if UNSTRESSED_PREFIXES.contains(&first.as_str()) {
+        Some(1)
+    } else {
+        Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(lyric: &str) -> Vec<String> {
+        syllables(lyric).into_iter().map(|syllable| syllable.text).collect()
+    }
+
+    #[test]
+    fn test_syllable_counts() {
+        assert_eq!(texts("Water"), vec!["Wa", "ter"]);
+        assert_eq!(texts("love loved wanted"), vec!["love", "loved", "wan", "ted"]);
+        assert_eq!(texts("Little yellow"), vec!["Lit", "tle", "yel", "low"]);
+        assert_eq!(texts("  ...  "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_stress_and_line_ends() {
+        let sung = syllables("The sun is rising\nagain");
+        let stressed: Vec<&str> = sung.iter().filter(|s| s.stressed).map(|s| s.text.as_str()).collect();
+        assert_eq!(stressed, vec!["sun", "ri", "gain"]);
+
+        let line_ends: Vec<&str> = sung.iter().filter(|s| s.line_end).map(|s| s.text.as_str()).collect();
+        assert_eq!(line_ends, vec!["sing", "gain"]);
+    }
+}
@@ -0,0 +1,252 @@
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Client name reported to the OS MIDI subsystem
+const MIDI_CLIENT_NAME: &str = "Piano Player";
+
+/// Controller number of the sustain (damper) pedal
+pub const CC_SUSTAIN: u8 = 64;
+
+/// Channel mode messages used for MIDI panic
+const CC_ALL_SOUND_OFF: u8 = 120;
+const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// A parsed channel voice message from a MIDI input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiEvent {
+    NoteOn { channel: u8, pitch: u8, velocity: u8 },
+    NoteOff { channel: u8, pitch: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    /// Pitch bend, centered at 0 (-8192 to 8191)
+    PitchBend { channel: u8, value: i16 },
+    /// Channel pressure (aftertouch); per-note pressure under MPE
+    ChannelPressure { channel: u8, pressure: u8 },
+}
+
+impl MidiEvent {
+    /// Parse a raw MIDI message (unsupported messages return None)
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let status = *message.first()?;
+        let channel = status & 0x0F;
+
+        match (status & 0xF0, message.get(1), message.get(2)) {
+            // Note-on with velocity 0 is a note-off by convention (running status devices)
+            (0x90, Some(&pitch), Some(&0)) | (0x80, Some(&pitch), Some(_)) => {
+                Some(MidiEvent::NoteOff { channel, pitch })
+            }
+            (0x90, Some(&pitch), Some(&velocity)) => Some(MidiEvent::NoteOn { channel, pitch, velocity }),
+            (0xB0, Some(&controller), Some(&value)) => {
+                Some(MidiEvent::ControlChange { channel, controller, value })
+            }
+            (0xE0, Some(&lsb), Some(&msb)) => {
+                let value = (((msb as i16) & 0x7F) << 7 | ((lsb as i16) & 0x7F)) - 8192;
+                Some(MidiEvent::PitchBend { channel, value })
+            }
+            (0xD0, Some(&pressure), _) => Some(MidiEvent::ChannelPressure { channel, pressure }),
+            _ => None,
+        }
+    }
+}
+
+/// MIDI device manager holding open input and output connections
+pub struct MidiManager {
+    outputs: Mutex<HashMap<String, MidiOutputConnection>>, // port name -> open connection
+    clock_output: Mutex<Option<String>>,                   // port receiving clock/start/stop
+    input: Mutex<Option<MidiInputConnection<()>>>,
+}
+
+impl MidiManager {
+    pub fn new() -> Self {
+        Self {
+            outputs: Mutex::new(HashMap::new()),
+            clock_output: Mutex::new(None),
+            input: Mutex::new(None),
+        }
+    }
+
+    /// List the names of all available MIDI input ports
+    pub fn list_input_ports() -> Result<Vec<String>, String> {
+        let midi_in = MidiInput::new(MIDI_CLIENT_NAME)
+            .map_err(|e| format!("Failed to initialize MIDI input: {}", e))?;
+
+        Ok(midi_in
+            .ports()
+            .iter()
+            .filter_map(|port| midi_in.port_name(port).ok())
+            .collect())
+    }
+
+    /// Connect to the named input port, replacing any previous input connection
+    ///
+    /// `handler` is called on the MIDI driver thread for every supported message.
+    pub fn connect_input<F>(&self, port_name: &str, mut handler: F) -> Result<(), String>
+    where
+        F: FnMut(MidiEvent) + Send + 'static,
+    {
+        self.disconnect_input();
+
+        let midi_in = MidiInput::new(MIDI_CLIENT_NAME)
+            .map_err(|e| format!("Failed to initialize MIDI input: {}", e))?;
+
+        let port = midi_in
+            .ports()
+            .into_iter()
+            .find(|port| midi_in.port_name(port).map(|name| name == port_name).unwrap_or(false))
+            .ok_or_else(|| format!("MIDI input port not found: {}", port_name))?;
+
+        let connection = midi_in
+            .connect(
+                &port,
+                "piano-player-in",
+                move |_timestamp, message, _| {
+                    if let Some(event) = MidiEvent::parse(message) {
+                        handler(event);
+                    }
+                },
+                (),
+            )
+            .map_err(|e| format!("Failed to connect to MIDI input {}: {}", port_name, e))?;
+
+        *self.input.lock().unwrap() = Some(connection);
+        Ok(())
+    }
+
+    /// Close the current input connection, if any
+    pub fn disconnect_input(&self) {
+        if let Some(connection) = self.input.lock().unwrap().take() {
+            connection.close();
+        }
+    }
+
+    /// List the names of all available MIDI output ports
+    pub fn list_output_ports() -> Result<Vec<String>, String> {
+        let midi_out = MidiOutput::new(MIDI_CLIENT_NAME)
+            .map_err(|e| format!("Failed to initialize MIDI output: {}", e))?;
+
+        Ok(midi_out
+            .ports()
+            .iter()
+            .filter_map(|port| midi_out.port_name(port).ok())
+            .collect())
+    }
+
+    /// Open a connection to the named output port (no-op if already open)
+    pub fn connect_output(&self, port_name: &str) -> Result<(), String> {
+        let mut outputs = self.outputs.lock().unwrap();
+        if outputs.contains_key(port_name) {
+            return Ok(());
+        }
+
+        let midi_out = MidiOutput::new(MIDI_CLIENT_NAME)
+            .map_err(|e| format!("Failed to initialize MIDI output: {}", e))?;
+
+        let port = midi_out
+            .ports()
+            .into_iter()
+            .find(|port| midi_out.port_name(port).map(|name| name == port_name).unwrap_or(false))
+            .ok_or_else(|| format!("MIDI output port not found: {}", port_name))?;
+
+        let connection = midi_out
+            .connect(&port, "piano-player-out")
+            .map_err(|e| format!("Failed to connect to MIDI output {}: {}", port_name, e))?;
+
+        outputs.insert(port_name.to_string(), connection);
+        Ok(())
+    }
+
+    /// Select the output port that receives MIDI clock (None disables clock output)
+    pub fn set_clock_output(&self, port_name: Option<String>) -> Result<(), String> {
+        if let Some(ref name) = port_name {
+            self.connect_output(name)?;
+        }
+
+        *self.clock_output.lock().unwrap() = port_name;
+        Ok(())
+    }
+
+    /// Get the currently selected clock output port
+    pub fn clock_output(&self) -> Option<String> {
+        self.clock_output.lock().unwrap().clone()
+    }
+
+    /// Send a raw message to the clock output port, if one is selected
+    pub fn send_clock_message(&self, message: &[u8]) {
+        let port_name = match self.clock_output() {
+            Some(name) => name,
+            None => return,
+        };
+
+        let mut outputs = self.outputs.lock().unwrap();
+        if let Some(connection) = outputs.get_mut(&port_name) {
+            // Clock runs on a timing thread, so report failures instead of propagating them
+            if let Err(e) = connection.send(message) {
+                eprintln!("⚠ Failed to send MIDI clock to {}: {}", port_name, e);
+            }
+        }
+    }
+
+    /// MIDI panic: release the sustain pedal and send all-sound-off and
+    /// all-notes-off on every channel of every open output
+    pub fn send_panic(&self) -> Result<(), String> {
+        let mut outputs = self.outputs.lock().unwrap();
+        let mut failed_ports = Vec::new();
+
+        for (port_name, connection) in outputs.iter_mut() {
+            for channel in 0..16u8 {
+                let status = 0xB0 | channel;
+                let messages = [
+                    [status, CC_SUSTAIN, 0],
+                    [status, CC_ALL_SOUND_OFF, 0],
+                    [status, CC_ALL_NOTES_OFF, 0],
+                ];
+
+                // Keep going on failure so one broken port doesn't leave others hanging
+                if messages.iter().any(|message| connection.send(message).is_err()) {
+                    failed_ports.push(port_name.clone());
+                    break;
+                }
+            }
+        }
+
+        if !failed_ports.is_empty() {
+            return Err(format!("Failed to send panic to MIDI outputs: {}", failed_ports.join(", ")));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pitch_bend() {
+        assert_eq!(MidiEvent::parse(&[0xE2, 0x00, 0x40]), Some(MidiEvent::PitchBend { channel: 2, value: 0 }));
+        assert_eq!(MidiEvent::parse(&[0xE0, 0x00, 0x00]), Some(MidiEvent::PitchBend { channel: 0, value: -8192 }));
+        assert_eq!(MidiEvent::parse(&[0xE0, 0x7F, 0x7F]), Some(MidiEvent::PitchBend { channel: 0, value: 8191 }));
+    }
+
+    #[test]
+    fn test_parse_note_messages() {
+        assert_eq!(
+            MidiEvent::parse(&[0x91, 60, 100]),
+            Some(MidiEvent::NoteOn { channel: 1, pitch: 60, velocity: 100 })
+        );
+        assert_eq!(MidiEvent::parse(&[0x80, 60, 64]), Some(MidiEvent::NoteOff { channel: 0, pitch: 60 }));
+        // Note-on with zero velocity is a note-off
+        assert_eq!(MidiEvent::parse(&[0x90, 60, 0]), Some(MidiEvent::NoteOff { channel: 0, pitch: 60 }));
+    }
+
+    #[test]
+    fn test_parse_control_change() {
+        assert_eq!(
+            MidiEvent::parse(&[0xB0, CC_SUSTAIN, 127]),
+            Some(MidiEvent::ControlChange { channel: 0, controller: CC_SUSTAIN, value: 127 })
+        );
+        assert_eq!(MidiEvent::parse(&[0xF8]), None);
+        assert_eq!(MidiEvent::parse(&[0xD3, 90]), Some(MidiEvent::ChannelPressure { channel: 3, pressure: 90 }));
+        assert_eq!(MidiEvent::parse(&[0xB0, 64]), None);
+    }
+}
@@ -0,0 +1,27 @@
+use clipboard_rs::{Clipboard, ClipboardContent, ClipboardContext};
+
+/// Clipboard type under which Standard MIDI File data is exchanged with other apps
+#[cfg(target_os = "macos")]
+const MIDI_FORMAT: &str = "public.midi-audio";
+#[cfg(not(target_os = "macos"))]
+const MIDI_FORMAT: &str = "audio/midi";
+
+fn context() -> Result<ClipboardContext, String> {
+    ClipboardContext::new().map_err(|e| format!("Failed to open clipboard: {}", e))
+}
+
+/// Put Standard MIDI File bytes on the system clipboard
+pub fn copy(bytes: Vec<u8>) -> Result<(), String> {
+    context()?
+        .set(vec![ClipboardContent::Other(MIDI_FORMAT.to_string(), bytes)])
+        .map_err(|e| format!("Failed to copy to clipboard: {}", e))
+}
+
+/// Read Standard MIDI File bytes from the system clipboard
+pub fn paste() -> Result<Vec<u8>, String> {
+    context()?
+        .get_buffer(MIDI_FORMAT)
+        .ok()
+        .filter(|bytes| !bytes.is_empty())
+        .ok_or_else(|| "The clipboard doesn't contain MIDI data".to_string())
+}
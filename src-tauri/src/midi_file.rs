@@ -0,0 +1,399 @@
+use crate::drum_kit::{DRUM_CHANNEL, DRUM_TRACK_ID};
+use crate::midi::CC_SUSTAIN;
+use crate::{Note, ProjectData};
+use std::path::Path;
+
+/// Resolution of exported files (ticks per quarter note)
+const TICKS_PER_BEAT: u16 = 480;
+
+/// Meta event types
+const META_TRACK_NAME: u8 = 0x03;
+const META_TEMPO: u8 = 0x51;
+const META_END_OF_TRACK: u8 = 0x2F;
+
+/// Append a variable-length quantity (7 bits per byte, high bit set on all but the last)
+fn write_vlq(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Convert beats to ticks
+fn beats_to_ticks(beats: f32) -> u32 {
+    (beats.max(0.0) as f64 * TICKS_PER_BEAT as f64).round() as u32
+}
+
+/// Build an `MTrk` chunk from (tick, event bytes) pairs
+///
+/// Events are sorted by tick; at equal ticks the given order is kept, so
+/// callers list note-offs before note-ons to avoid cutting retriggered notes.
+fn track_chunk(name: &str, mut events: Vec<(u32, Vec<u8>)>) -> Vec<u8> {
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut data = Vec::new();
+    write_vlq(&mut data, 0);
+    data.extend([0xFF, META_TRACK_NAME]);
+    write_vlq(&mut data, name.len() as u32);
+    data.extend(name.as_bytes());
+
+    let mut last_tick = 0;
+    for (tick, bytes) in events {
+        write_vlq(&mut data, tick - last_tick);
+        data.extend(bytes);
+        last_tick = tick;
+    }
+
+    write_vlq(&mut data, 0);
+    data.extend([0xFF, META_END_OF_TRACK, 0x00]);
+
+    let mut chunk = b"MTrk".to_vec();
+    chunk.extend((data.len() as u32).to_be_bytes());
+    chunk.extend(data);
+    chunk
+}
+
+/// Note-on/off events of notes, offs first so a note ending where another starts isn't cut
+///
+/// Notes of the drum track go to the General MIDI percussion channel (10).
+fn note_events<'a>(notes: impl Iterator<Item = &'a Note>) -> Vec<(u32, Vec<u8>)> {
+    let mut offs = Vec::new();
+    let mut ons = Vec::new();
+
+    for note in notes {
+        let start = beats_to_ticks(note.start_time);
+        let end = beats_to_ticks(note.start_time + note.duration).max(start + 1);
+        let pitch = note.pitch.min(127);
+        let channel = if note.track_id == DRUM_TRACK_ID { DRUM_CHANNEL } else { 0 };
+
+        ons.push((start, vec![0x90 | channel, pitch, note.velocity.clamp(1, 127)]));
+        offs.push((end, vec![0x80 | channel, pitch, 0]));
+    }
+
+    offs.extend(ons);
+    offs
+}
+
+/// Encode a project as a type 1 Standard MIDI File
+///
+/// The first track carries the tempo; each project track becomes a MIDI track
+/// (notes of unknown tracks go into an extra one). Sustain pedal events are
+/// written to the first note track.
+pub fn to_bytes(project: &ProjectData) -> Vec<u8> {
+    let mut tracks = Vec::new();
+
+    // Conductor track
+    let tempo = project.tempo.max(1) as u32;
+    let micros_per_beat = 60_000_000 / tempo;
+    let mut tempo_event = vec![0xFF, META_TEMPO, 0x03];
+    tempo_event.extend(&micros_per_beat.to_be_bytes()[1..]);
+    tracks.push(track_chunk(&project.name, vec![(0, tempo_event)]));
+
+    let mut groups: Vec<(&str, Vec<&Note>)> = project
+        .tracks
+        .iter()
+        .map(|track| {
+            let notes = project.notes.iter().filter(|n| n.track_id == track.id).collect();
+            (track.name.as_str(), notes)
+        })
+        .collect();
+
+    let orphans: Vec<&Note> = project
+        .notes
+        .iter()
+        .filter(|n| !project.tracks.iter().any(|t| t.id == n.track_id))
+        .collect();
+    if !orphans.is_empty() {
+        groups.push(("Untitled", orphans));
+    }
+
+    for (index, (name, notes)) in groups.into_iter().enumerate() {
+        let mut events = note_events(notes.into_iter());
+
+        if index == 0 {
+            events.extend(project.pedal_events.iter().map(|pedal| {
+                let value = if pedal.down { 127 } else { 0 };
+                (beats_to_ticks(pedal.time), vec![0xB0, CC_SUSTAIN, value])
+            }));
+        }
+
+        tracks.push(track_chunk(name, events));
+    }
+
+    let mut bytes = b"MThd".to_vec();
+    bytes.extend(6u32.to_be_bytes());
+    bytes.extend(1u16.to_be_bytes()); // Format 1: simultaneous tracks
+    bytes.extend((tracks.len() as u16).to_be_bytes());
+    bytes.extend(TICKS_PER_BEAT.to_be_bytes());
+    for track in tracks {
+        bytes.extend(track);
+    }
+    bytes
+}
+
+/// Encode a note selection as a type 0 Standard MIDI File (e.g. for the clipboard)
+///
+/// Notes are shifted so the earliest one starts at tick 0.
+pub fn notes_to_bytes(notes: &[Note], tempo: u16) -> Vec<u8> {
+    let micros_per_beat = 60_000_000 / tempo.max(1) as u32;
+    let mut tempo_event = vec![0xFF, META_TEMPO, 0x03];
+    tempo_event.extend(&micros_per_beat.to_be_bytes()[1..]);
+
+    let first = notes.iter().map(|n| n.start_time).fold(f32::INFINITY, f32::min);
+    let base = if first.is_finite() { beats_to_ticks(first) } else { 0 };
+
+    let mut events = vec![(0, tempo_event)];
+    events.extend(note_events(notes.iter()).into_iter().map(|(tick, bytes)| (tick - base, bytes)));
+
+    let mut bytes = b"MThd".to_vec();
+    bytes.extend(6u32.to_be_bytes());
+    bytes.extend(0u16.to_be_bytes()); // Format 0: single track
+    bytes.extend(1u16.to_be_bytes());
+    bytes.extend(TICKS_PER_BEAT.to_be_bytes());
+    bytes.extend(track_chunk("Clipboard", events));
+    bytes
+}
+
+/// Cursor over MIDI file bytes
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(count).filter(|&end| end <= self.data.len());
+        let end = end.ok_or("Truncated MIDI data")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Invalid variable-length quantity in MIDI data".to_string())
+    }
+}
+
+/// Read the notes of one `MTrk` chunk as (start tick, end tick, pitch, velocity)
+///
+/// The first tempo event found (microseconds per beat) is stored in `tempo`
+/// if it is still unset.
+fn parse_track(data: &[u8], tempo: &mut Option<u32>) -> Result<Vec<(u32, u32, u8, u8)>, String> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut notes = Vec::new();
+    let mut held: Vec<(u8, u8, u32, u8)> = Vec::new(); // (channel, pitch, start, velocity)
+    let mut tick = 0u32;
+    let mut running_status = None;
+
+    while reader.pos < data.len() {
+        tick = tick.saturating_add(reader.vlq()?);
+
+        let mut status = reader.u8()?;
+        match status {
+            0xFF => {
+                let kind = reader.u8()?;
+                let length = reader.vlq()? as usize;
+                let body = reader.take(length)?;
+                if kind == META_TEMPO && body.len() == 3 && tempo.is_none() {
+                    *tempo = Some(u32::from_be_bytes([0, body[0], body[1], body[2]]));
+                }
+                if kind == META_END_OF_TRACK {
+                    break;
+                }
+                continue;
+            }
+            0xF0 | 0xF7 => {
+                let length = reader.vlq()? as usize;
+                reader.take(length)?;
+                continue;
+            }
+            0x80..=0xEF => running_status = Some(status),
+            _ => {
+                // Data byte: reuse the previous status
+                status = running_status.ok_or("MIDI data byte without status")?;
+                reader.pos -= 1;
+            }
+        }
+
+        let channel = status & 0x0F;
+        let first = reader.u8()?;
+        let second = if matches!(status & 0xF0, 0xC0 | 0xD0) { 0 } else { reader.u8()? };
+
+        match status & 0xF0 {
+            0x90 if second > 0 => held.push((channel, first, tick, second)),
+            0x80 | 0x90 => {
+                if let Some(index) = held.iter().position(|&(c, p, _, _)| c == channel && p == first) {
+                    let (_, pitch, start, velocity) = held.remove(index);
+                    notes.push((start, tick, pitch, velocity));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Notes never released end with the track
+    notes.extend(held.into_iter().map(|(_, pitch, start, velocity)| (start, tick, pitch, velocity)));
+    Ok(notes)
+}
+
+/// Parse the notes of a Standard MIDI File (any format, all tracks merged)
+///
+/// Notes get fresh ids and are placed on `track_id`; times are in beats.
+pub fn parse_notes(bytes: &[u8], track_id: &str) -> Result<Vec<Note>, String> {
+    parse_file(bytes, track_id).map(|(notes, _)| notes)
+}
+
+/// Parse the notes of a Standard MIDI File, and its tempo in BPM if it sets one
+pub fn parse_file(bytes: &[u8], track_id: &str) -> Result<(Vec<Note>, Option<f64>), String> {
+    let mut reader = Reader { data: bytes, pos: 0 };
+    if reader.take(4)? != b"MThd" {
+        return Err("Not a MIDI file".to_string());
+    }
+    let header_length = reader.u32()? as usize;
+    let header = reader.take(header_length)?;
+    if header.len() < 6 {
+        return Err("Invalid MIDI header".to_string());
+    }
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if division & 0x8000 != 0 || division == 0 {
+        return Err("SMPTE-timed MIDI files are not supported".to_string());
+    }
+
+    let mut notes = Vec::new();
+    let mut tempo = None;
+    while reader.pos < bytes.len() {
+        let kind = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let data = reader.take(length)?;
+        if kind == b"MTrk" {
+            notes.extend(parse_track(data, &mut tempo)?);
+        }
+    }
+
+    notes.sort_by_key(|&(start, _, pitch, _)| (start, pitch));
+    let ticks_per_beat = division as f32;
+    let notes = notes
+        .into_iter()
+        .map(|(start, end, pitch, velocity)| Note {
+            id: uuid::Uuid::new_v4().to_string(),
+            pitch,
+            start_time: start as f32 / ticks_per_beat,
+            duration: (end.max(start + 1) - start) as f32 / ticks_per_beat,
+            velocity,
+            track_id: track_id.to_string(),
+        })
+        .collect();
+    let bpm = tempo.filter(|&micros| micros > 0).map(|micros| 60_000_000.0 / micros as f64);
+    Ok((notes, bpm))
+}
+
+/// Write a project to a `.mid` file
+pub fn write_project(project: &ProjectData, path: &Path) -> Result<(), String> {
+    std::fs::write(path, to_bytes(project)).map_err(|e| format!("Failed to write MIDI file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_vlq() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            write_vlq(&mut out, value);
+            out
+        };
+
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(0x7F), vec![0x7F]);
+        assert_eq!(encode(0x80), vec![0x81, 0x00]);
+        assert_eq!(encode(0x3FFF), vec![0xFF, 0x7F]);
+        assert_eq!(encode(0x0FFF_FFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn test_clipboard_roundtrip() {
+        let note = |pitch, start_time, duration| Note {
+            id: String::new(),
+            pitch,
+            start_time,
+            duration,
+            velocity: 90,
+            track_id: "t1".to_string(),
+        };
+        let notes = vec![note(60, 4.0, 1.0), note(64, 4.5, 0.5), note(60, 5.0, 1.0)];
+
+        let parsed = parse_notes(&notes_to_bytes(&notes, 100), "t2").unwrap();
+        let summary: Vec<(u8, f32, f32)> = parsed.iter().map(|n| (n.pitch, n.start_time, n.duration)).collect();
+        assert_eq!(summary, vec![(60, 0.0, 1.0), (64, 0.5, 0.5), (60, 1.0, 1.0)]);
+        assert!(parsed.iter().all(|n| n.track_id == "t2" && n.velocity == 90));
+        assert_eq!(parse_file(&notes_to_bytes(&notes, 100), "t2").unwrap().1, Some(100.0));
+    }
+
+    #[test]
+    fn test_parse_running_status() {
+        // Note on C4, then (running status) note on with velocity 0 one beat later
+        let mut track = vec![0x00, 0x90, 60, 100, 0x60, 60, 0x00];
+        track.extend([0x00, 0xFF, META_END_OF_TRACK, 0x00]);
+
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend([0, 0, 0, 1, 0, 96]);
+        bytes.extend(b"MTrk");
+        bytes.extend((track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+
+        let notes = parse_notes(&bytes, "").unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!((notes[0].pitch, notes[0].duration), (60, 1.0));
+        assert!(parse_notes(b"RIFF", "").is_err());
+    }
+
+    #[test]
+    fn test_header_and_track_count() {
+        let project = ProjectData {
+            notes: vec![Note {
+                id: "n1".to_string(),
+                pitch: 60,
+                start_time: 0.0,
+                duration: 1.0,
+                velocity: 100,
+                track_id: String::new(),
+            }],
+            tempo: 120,
+            name: "Test".to_string(),
+            ..ProjectData::default()
+        };
+
+        let bytes = to_bytes(&project);
+        assert_eq!(&bytes[0..4], b"MThd");
+        assert_eq!(&bytes[10..12], &[0, 2]); // Conductor + one note track
+        assert_eq!(&bytes[12..14], &TICKS_PER_BEAT.to_be_bytes());
+        assert_eq!(&bytes[14..18], b"MTrk");
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// App parameter that a MIDI controller can be mapped to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LearnTarget {
+    MasterVolume,
+    TrackGain { track_id: String },
+    TransportToggle,
+    TransportStart,
+    TransportStop,
+}
+
+/// A learned controller -> parameter binding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub channel: u8,
+    pub controller: u8,
+    pub target: LearnTarget,
+}
+
+/// Result of offering a control change to MIDI learn
+#[derive(Debug, PartialEq)]
+pub enum CcAction {
+    /// The controller was bound to the target being learned
+    Learned(MidiMapping),
+    /// The controller is mapped; apply `value` to the target
+    Mapped(LearnTarget, u8),
+    /// Not mapped; handle the control change normally
+    Unmapped,
+}
+
+/// MIDI learn: binds incoming CCs to app parameters, persisted in the app data directory
+pub struct MidiLearn {
+    storage_path: PathBuf,
+    mappings: Mutex<Vec<MidiMapping>>,
+    learning: Mutex<Option<LearnTarget>>,
+}
+
+impl MidiLearn {
+    /// Create the MIDI learn store, loading saved mappings
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let storage_path = app_data_dir.join("midi_mappings.json");
+
+        let mappings = if storage_path.exists() {
+            let data = fs::read_to_string(&storage_path)
+                .map_err(|e| format!("Failed to read MIDI mappings: {}", e))?;
+            serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse MIDI mappings: {}", e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            storage_path,
+            mappings: Mutex::new(mappings),
+            learning: Mutex::new(None),
+        })
+    }
+
+    fn save(&self, mappings: &[MidiMapping]) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(mappings)
+            .map_err(|e| format!("Failed to serialize MIDI mappings: {}", e))?;
+        fs::write(&self.storage_path, data)
+            .map_err(|e| format!("Failed to write MIDI mappings: {}", e))
+    }
+
+    /// Wait for the next incoming CC and bind it to `target`
+    pub fn start_learn(&self, target: LearnTarget) {
+        *self.learning.lock().unwrap() = Some(target);
+    }
+
+    /// Abort a pending learn
+    pub fn cancel_learn(&self) {
+        *self.learning.lock().unwrap() = None;
+    }
+
+    /// List all mappings
+    pub fn mappings(&self) -> Vec<MidiMapping> {
+        self.mappings.lock().unwrap().clone()
+    }
+
+    /// Remove the mapping for a controller
+    pub fn remove_mapping(&self, channel: u8, controller: u8) -> Result<(), String> {
+        let mut mappings = self.mappings.lock().unwrap();
+        mappings.retain(|m| !(m.channel == channel && m.controller == controller));
+        self.save(&mappings)
+    }
+
+    /// Offer an incoming control change to MIDI learn
+    ///
+    /// While learning, the CC is bound to the pending target (replacing any
+    /// earlier binding of that controller or target) and consumed.
+    pub fn handle_cc(&self, channel: u8, controller: u8, value: u8) -> CcAction {
+        if let Some(target) = self.learning.lock().unwrap().take() {
+            let mapping = MidiMapping {
+                channel,
+                controller,
+                target,
+            };
+
+            let mut mappings = self.mappings.lock().unwrap();
+            mappings.retain(|m| !(m.channel == channel && m.controller == controller) && m.target != mapping.target);
+            mappings.push(mapping.clone());
+
+            // The binding is live even if persisting fails; it just won't survive a restart
+            if let Err(e) = self.save(&mappings) {
+                eprintln!("⚠ {}", e);
+            }
+
+            return CcAction::Learned(mapping);
+        }
+
+        self.mappings
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|m| m.channel == channel && m.controller == controller)
+            .map(|m| CcAction::Mapped(m.target.clone(), value))
+            .unwrap_or(CcAction::Unmapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_learn_then_map() {
+        let temp_dir = env::temp_dir().join("piano-app-test-midi-learn");
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let learn = MidiLearn::new(temp_dir.clone()).unwrap();
+        assert_eq!(learn.handle_cc(0, 7, 100), CcAction::Unmapped);
+
+        learn.start_learn(LearnTarget::MasterVolume);
+        assert!(matches!(learn.handle_cc(0, 7, 100), CcAction::Learned(_)));
+        assert_eq!(learn.handle_cc(0, 7, 42), CcAction::Mapped(LearnTarget::MasterVolume, 42));
+
+        // Mappings survive a reload
+        let reloaded = MidiLearn::new(temp_dir.clone()).unwrap();
+        assert_eq!(reloaded.mappings().len(), 1);
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_relearning_target_replaces_old_controller() {
+        let temp_dir = env::temp_dir().join("piano-app-test-midi-relearn");
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let learn = MidiLearn::new(temp_dir.clone()).unwrap();
+        learn.start_learn(LearnTarget::TransportToggle);
+        learn.handle_cc(0, 20, 127);
+        learn.start_learn(LearnTarget::TransportToggle);
+        learn.handle_cc(0, 21, 127);
+
+        assert_eq!(learn.mappings().len(), 1);
+        assert_eq!(learn.handle_cc(0, 20, 127), CcAction::Unmapped);
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
@@ -0,0 +1,118 @@
+use crate::arpeggiator::Arpeggiator;
+use crate::midi::{MidiEvent, CC_SUSTAIN};
+use crate::midi_learn::{CcAction, LearnTarget, MidiLearn};
+use crate::mpe::MpeProcessor;
+use crate::recorder::Recorder;
+use crate::sample_player::SamplePlayer;
+use crate::step_recorder::StepRecorder;
+use crate::transport::Transport;
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+
+/// Payload of the `midi-control` event, for targets owned by the frontend (e.g. track gains)
+#[derive(Debug, Clone, Serialize)]
+struct MidiControlEvent {
+    target: LearnTarget,
+    value: f32, // Normalized 0.0-1.0
+}
+
+/// Dispatches incoming MIDI events to the audio engine, recorders and controls
+pub struct MidiRouter {
+    pub sample_player: Arc<SamplePlayer>,
+    pub transport: Arc<Transport>,
+    pub recorder: Arc<Recorder>,
+    pub step_recorder: Arc<StepRecorder>,
+    pub arpeggiator: Arc<Arpeggiator>,
+    pub mpe: Arc<MpeProcessor>,
+    pub midi_learn: Arc<MidiLearn>,
+    pub app: AppHandle,
+}
+
+impl MidiRouter {
+    /// Handle a single event (called on the MIDI driver thread)
+    pub fn handle(&self, event: MidiEvent) {
+        let beat = self.transport.position();
+
+        // Learned controllers take precedence over their default meaning
+        if let MidiEvent::ControlChange { channel, controller, value } = event {
+            match self.midi_learn.handle_cc(channel, controller, value) {
+                CcAction::Learned(mapping) => {
+                    let _ = self.app.emit("midi-learn-complete", mapping);
+                    return;
+                }
+                CcAction::Mapped(target, value) => {
+                    self.apply_control(target, value);
+                    return;
+                }
+                CcAction::Unmapped => {}
+            }
+        }
+
+        match event {
+            MidiEvent::NoteOn { channel, pitch, velocity } => {
+                // With the arpeggiator on, held keys feed the arpeggio instead of sounding directly
+                if self.arpeggiator.is_enabled() {
+                    self.arpeggiator.note_on(pitch, velocity);
+                } else if let Err(e) = self.sample_player.note_on(pitch, velocity, channel) {
+                    // Monitor through the sample player; playback errors must not stop recording
+                    eprintln!("⚠ Failed to play MIDI input note: {}", e);
+                }
+                self.recorder.note_on(beat, pitch, velocity);
+                self.step_recorder.note_on(pitch, velocity);
+            }
+            MidiEvent::NoteOff { channel, pitch } => {
+                self.arpeggiator.note_off(pitch);
+                self.sample_player.note_off(pitch, channel);
+                self.recorder.note_off(beat, pitch);
+                self.step_recorder.note_off(pitch);
+            }
+            MidiEvent::ControlChange { controller: CC_SUSTAIN, value, .. } => {
+                // Values 64-127 mean pedal down
+                let down = value >= 64;
+                self.sample_player.set_sustain(down);
+                self.recorder.record_pedal(beat, down);
+            }
+            MidiEvent::ControlChange { .. } => {}
+            MidiEvent::PitchBend { channel, value } => {
+                self.mpe.pitch_bend(&self.sample_player, channel, value);
+            }
+            MidiEvent::ChannelPressure { channel, pressure } => {
+                self.mpe.pressure(&self.sample_player, channel, pressure);
+            }
+        }
+    }
+
+    /// Apply a mapped controller value to its target
+    fn apply_control(&self, target: LearnTarget, value: u8) {
+        let normalized = value as f32 / 127.0;
+        // Buttons send 127 on press and 0 on release; only act on the press
+        let pressed = value >= 64;
+
+        let result = match target {
+            LearnTarget::MasterVolume => {
+                self.sample_player.set_volume(normalized);
+                Ok(())
+            }
+            LearnTarget::TrackGain { .. } => {
+                // Track mixing lives in the frontend
+                let _ = self.app.emit("midi-control", MidiControlEvent { target, value: normalized });
+                Ok(())
+            }
+            LearnTarget::TransportToggle if pressed => {
+                if self.transport.is_running() {
+                    self.transport.stop()
+                } else {
+                    self.transport.start(self.transport.tempo())
+                }
+            }
+            LearnTarget::TransportStart if pressed => self.transport.start(self.transport.tempo()),
+            LearnTarget::TransportStop if pressed => self.transport.stop(),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            eprintln!("⚠ Failed to apply MIDI control: {}", e);
+        }
+    }
+}
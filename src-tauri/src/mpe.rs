@@ -0,0 +1,112 @@
+use crate::sample_player::SamplePlayer;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use validator::Validate;
+
+/// MIDI Polyphonic Expression settings
+///
+/// With MPE enabled each note arrives on its own member channel, so channel
+/// pitch bend and pressure become per-note expression. The master channel
+/// bends every note at once.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Validate)]
+pub struct MpeSettings {
+    pub enabled: bool,
+
+    /// Master channel (0 = lower zone, channel 1; 15 = upper zone, channel 16)
+    #[validate(range(min = 0, max = 15))]
+    pub master_channel: u8,
+
+    /// Pitch bend range of member channels in semitones (MPE default: 48)
+    #[validate(range(min = 0.0, max = 96.0))]
+    pub member_bend_range: f32,
+
+    /// Pitch bend range of the master channel, or of all channels without MPE (default: 2)
+    #[validate(range(min = 0.0, max = 96.0))]
+    pub master_bend_range: f32,
+}
+
+impl Default for MpeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            master_channel: 0,
+            member_bend_range: 48.0,
+            master_bend_range: 2.0,
+        }
+    }
+}
+
+impl MpeSettings {
+    /// Resolve a pitch bend message to (target channel, semitones)
+    ///
+    /// A `None` channel means the bend applies to every voice (MPE master channel).
+    pub fn resolve_bend(&self, channel: u8, value: i16) -> (Option<u8>, f32) {
+        let normalized = value as f32 / 8192.0;
+
+        if !self.enabled {
+            (Some(channel), normalized * self.master_bend_range)
+        } else if channel == self.master_channel {
+            (None, normalized * self.master_bend_range)
+        } else {
+            (Some(channel), normalized * self.member_bend_range)
+        }
+    }
+}
+
+/// Routes pitch bend and pressure from MIDI input to per-note expression
+pub struct MpeProcessor {
+    settings: Mutex<MpeSettings>,
+}
+
+impl MpeProcessor {
+    pub fn new() -> Self {
+        Self {
+            settings: Mutex::new(MpeSettings::default()),
+        }
+    }
+
+    pub fn settings(&self) -> MpeSettings {
+        *self.settings.lock().unwrap()
+    }
+
+    pub fn set_settings(&self, settings: MpeSettings) {
+        *self.settings.lock().unwrap() = settings;
+    }
+
+    /// Apply a pitch bend message to the sample player
+    pub fn pitch_bend(&self, sample_player: &SamplePlayer, channel: u8, value: i16) {
+        match self.settings().resolve_bend(channel, value) {
+            (Some(channel), semitones) => sample_player.set_channel_bend(channel, semitones),
+            (None, semitones) => sample_player.set_global_bend(semitones),
+        }
+    }
+
+    /// Apply a channel pressure message to the sample player
+    pub fn pressure(&self, sample_player: &SamplePlayer, channel: u8, pressure: u8) {
+        sample_player.set_channel_pressure(channel, pressure as f32 / 127.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bend_without_mpe_uses_standard_range() {
+        let settings = MpeSettings::default();
+        assert_eq!(settings.resolve_bend(3, 8192 / 2), (Some(3), 1.0));
+    }
+
+    #[test]
+    fn test_member_and_master_bend_with_mpe() {
+        let settings = MpeSettings {
+            enabled: true,
+            ..MpeSettings::default()
+        };
+
+        // Member channel: per-note bend with the wide range
+        assert_eq!(settings.resolve_bend(2, -8192), (Some(2), -48.0));
+        // Master channel: bends everything with the narrow range
+        assert_eq!(settings.resolve_bend(0, -8192), (None, -2.0));
+    }
+}
@@ -0,0 +1,844 @@
+use crate::ai_models::{self, ChordSymbol, Note, Scale, ScaleMode};
+use serde::Serialize;
+
+/// Pitch class names, sharps for black keys (as in the scale selector)
+pub const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Krumhansl-Kessler probe-tone ratings of each pitch class in a major key, from the tonic
+const MAJOR_PROFILE: [f64; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+
+/// Krumhansl-Kessler probe-tone ratings of each pitch class in a minor key, from the tonic
+const MINOR_PROFILE: [f64; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+/// Correlation above which a detected key is trusted without asking the user
+pub const KEY_CONFIDENCE: f64 = 0.6;
+
+/// A key the notes may be in, with how well they fit it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyCandidate {
+    pub root: String,
+    pub mode: ScaleMode,
+    /// Correlation of the notes' pitch-class weights with the key's profile (-1 to 1)
+    pub score: f64,
+}
+
+impl KeyCandidate {
+    /// The key as a scale in every octave
+    pub fn scale(&self) -> Scale {
+        Scale { root: self.root.clone(), mode: self.mode, octave: None }
+    }
+}
+
+fn correlation(a: &[f64; 12], b: &[f64; 12]) -> f64 {
+    let mean_a = a.iter().sum::<f64>() / 12.0;
+    let mean_b = b.iter().sum::<f64>() / 12.0;
+    let covariance: f64 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+    let spread_a: f64 = a.iter().map(|x| (x - mean_a).powi(2)).sum();
+    let spread_b: f64 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+    if spread_a == 0.0 || spread_b == 0.0 {
+        return 0.0;
+    }
+    covariance / (spread_a * spread_b).sqrt()
+}
+
+/// Rank the 24 major and minor keys by how well `notes` fit them (Krumhansl-Schmuckler)
+///
+/// Each pitch class is weighted by how long it sounds, so passing tones
+/// count for less than held notes. Best first; empty without notes.
+pub fn detect_key(notes: &[Note]) -> Vec<KeyCandidate> {
+    let mut weights = [0.0; 12];
+    for note in notes {
+        weights[(note.pitch % 12) as usize] += note.duration.max(0.0);
+    }
+    if weights.iter().all(|&w| w == 0.0) {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<KeyCandidate> = (0..12)
+        .flat_map(|tonic| [(tonic, ScaleMode::Major, &MAJOR_PROFILE), (tonic, ScaleMode::Minor, &MINOR_PROFILE)])
+        .map(|(tonic, mode, profile)| {
+            let rotated: [f64; 12] = std::array::from_fn(|pc| profile[(pc + 12 - tonic) % 12]);
+            KeyCandidate {
+                root: NOTE_NAMES[tonic].to_string(),
+                mode,
+                score: correlation(&weights, &rotated),
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates
+}
+
+/// Pitch class names with flats for black keys
+const FLAT_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"];
+
+/// Roman numerals of the seven scale degrees
+const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
+
+/// Semitones above the tonic of each degree of the major scale, for accidentals in numerals
+const MAJOR_DEGREES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// Usual next chords from each scale degree (0 = I), likeliest first
+///
+/// Common-practice root motion: tonic to anything, predominants to
+/// dominants, dominants home (or deceptively to vi).
+const PROGRESSIONS: [&[usize]; 7] = [
+    &[3, 4, 5, 1, 2],
+    &[4, 6, 3],
+    &[5, 3, 1],
+    &[4, 0, 1, 6],
+    &[0, 5, 3],
+    &[1, 3, 4],
+    &[0, 2],
+];
+
+/// Role of a chord in its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HarmonicFunction {
+    Tonic,
+    Predominant,
+    Dominant,
+    /// Dominant of a chord other than the tonic (V7/x)
+    SecondaryDominant,
+}
+
+impl HarmonicFunction {
+    fn of_degree(degree: usize) -> Self {
+        match degree {
+            1 | 3 => HarmonicFunction::Predominant,
+            4 | 6 => HarmonicFunction::Dominant,
+            _ => HarmonicFunction::Tonic,
+        }
+    }
+}
+
+/// A chord built by stacking thirds on a scale degree
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiatonicChord {
+    /// Scale degree, 0 for the tonic
+    pub degree: usize,
+    /// Roman numeral ("ii7", "bVII", "vii°")
+    pub numeral: String,
+    /// Chord symbol ("Dm7")
+    pub symbol: String,
+    pub function: HarmonicFunction,
+    /// Pitch classes (0-11), root first
+    pub pitch_classes: Vec<i32>,
+}
+
+/// A chord that could come next, and why
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChordSuggestion {
+    pub symbol: String,
+    pub numeral: String,
+    pub function: HarmonicFunction,
+    pub reason: String,
+}
+
+/// Check that a key's root is a note name ("C", "F#", "Bb")
+pub fn check_root(root: &str) -> Result<(), String> {
+    if NOTE_NAMES.iter().chain(&FLAT_NAMES).any(|name| name.eq_ignore_ascii_case(root.trim())) {
+        Ok(())
+    } else {
+        Err(format!("Invalid root note: {}", root))
+    }
+}
+
+/// Pitch class (0-11) of a scale's tonic
+pub fn tonic(scale: &Scale) -> i32 {
+    Scale::note_to_offset(&scale.root).rem_euclid(12)
+}
+
+/// Whether a key is written with flats (F major, D minor, ...) rather than sharps
+fn uses_flats(scale: &Scale) -> bool {
+    if scale.root.contains('b') {
+        return true;
+    }
+    if scale.root.contains('#') {
+        return false;
+    }
+    // Tonic of the relative major decides
+    let relative_major = if scale.mode.is_minor() { tonic(scale) + 3 } else { tonic(scale) };
+    matches!(relative_major.rem_euclid(12), 1 | 3 | 5 | 8 | 10)
+}
+
+/// Name of a pitch class, spelled for the key
+pub fn spell(pitch_class: i32, flats: bool) -> &'static str {
+    let names = if flats { &FLAT_NAMES } else { &NOTE_NAMES };
+    names[pitch_class.rem_euclid(12) as usize]
+}
+
+/// Seven-note scale the chords of a mode are built from
+///
+/// Pentatonic and blues scales borrow the chords of their parent major or minor key.
+fn heptatonic(mode: ScaleMode) -> &'static [i32] {
+    match mode {
+        ScaleMode::MajorPentatonic => ScaleMode::Major.intervals(),
+        ScaleMode::MinorPentatonic | ScaleMode::Blues => ScaleMode::Minor.intervals(),
+        _ => mode.intervals(),
+    }
+}
+
+/// Symbol suffix and numeral suffix of a chord, from its intervals above the root
+fn quality(intervals: &[i32]) -> (&'static str, &'static str) {
+    match intervals {
+        [4, 7] => ("", ""),
+        [3, 7] => ("m", ""),
+        [3, 6] => ("dim", "°"),
+        [4, 8] => ("+", "+"),
+        [4, 7, 11] => ("maj7", "maj7"),
+        [4, 7, 10] => ("7", "7"),
+        [3, 7, 10] => ("m7", "7"),
+        [3, 6, 10] => ("m7b5", "ø7"),
+        [3, 6, 9] => ("dim7", "°7"),
+        [3, 7, 11] => ("m(maj7)", "maj7"),
+        [4, 8, 11] => ("+maj7", "+maj7"),
+        _ => ("", ""),
+    }
+}
+
+/// The chords of a key: a triad (or seventh chord) on each scale degree
+pub fn diatonic_chords(scale: &Scale, sevenths: bool) -> Vec<DiatonicChord> {
+    chords_of(heptatonic(scale.mode), scale, sevenths)
+}
+
+fn chords_of(intervals: &[i32], scale: &Scale, sevenths: bool) -> Vec<DiatonicChord> {
+    let tonic = tonic(scale);
+    let flats = uses_flats(scale);
+    let size = if sevenths { 4 } else { 3 };
+
+    (0..7)
+        .map(|degree| {
+            let root = intervals[degree];
+            let stack: Vec<i32> = (0..size).map(|i| intervals[(degree + 2 * i) % 7]).collect();
+            let above: Vec<i32> = stack[1..].iter().map(|&pc| (pc - root).rem_euclid(12)).collect();
+            let (symbol_suffix, numeral_suffix) = quality(&above);
+
+            let accidental = match root - MAJOR_DEGREES[degree] {
+                -1 => "b",
+                1 => "#",
+                _ => "",
+            };
+            let numeral = if above[0] == 3 { NUMERALS[degree].to_lowercase() } else { NUMERALS[degree].to_string() };
+
+            DiatonicChord {
+                degree,
+                numeral: format!("{}{}{}", accidental, numeral, numeral_suffix),
+                symbol: format!("{}{}", spell(tonic + root, flats), symbol_suffix),
+                function: HarmonicFunction::of_degree(degree),
+                pitch_classes: stack.iter().map(|&pc| (tonic + pc).rem_euclid(12)).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Chords suggestions draw from: the key's own, with the raised-leading-tone
+/// dominant (V, vii°) in natural minor
+fn suggestion_chords(scale: &Scale, sevenths: bool) -> Vec<DiatonicChord> {
+    let mut chords = diatonic_chords(scale, sevenths);
+    if scale.mode == ScaleMode::Minor {
+        let harmonic = chords_of(ScaleMode::HarmonicMinor.intervals(), scale, sevenths);
+        chords[4] = harmonic[4].clone();
+        chords[6] = harmonic[6].clone();
+    }
+    chords
+}
+
+/// Why `to` makes sense after `from`
+fn transition_reason(from: &DiatonicChord, to: &DiatonicChord) -> String {
+    use HarmonicFunction::*;
+    match (from.function, to.function, from.degree, to.degree) {
+        (Dominant, Tonic, _, 0) => "Resolves the dominant home (authentic cadence)".to_string(),
+        (Dominant, Tonic, 4, 5) => "Deceptive cadence: avoids the expected tonic".to_string(),
+        (Predominant, Tonic, 3, 0) => "Plagal motion back to the tonic".to_string(),
+        (Predominant, Dominant, ..) => "Predominant leads to the dominant".to_string(),
+        (Tonic, Predominant, ..) => "Moves away from the tonic toward a cadence".to_string(),
+        (Tonic, Dominant, ..) => "Builds tension toward the dominant".to_string(),
+        _ if (to.pitch_classes[0] - from.pitch_classes[0]).rem_euclid(12) == 5 => {
+            "Root falls a fifth, the strongest progression".to_string()
+        }
+        _ => format!("Common move from {} to {}", from.numeral, to.numeral),
+    }
+}
+
+/// Pitch classes of a chord symbol
+fn chord_classes(symbol: &str) -> Vec<i32> {
+    ChordSymbol { symbol: symbol.trim().to_string(), start_time: 0.0, duration: 0.0 }.pitch_classes()
+}
+
+/// Dominant seventh resolving to `target`, as a suggestion
+fn secondary_dominant(target: &DiatonicChord, flats: bool) -> ChordSuggestion {
+    let root = target.pitch_classes[0] + 7;
+    let target_numeral = target.numeral.trim_end_matches(['7', '°', 'ø', '+']).trim_end_matches("maj");
+    ChordSuggestion {
+        symbol: format!("{}7", spell(root, flats)),
+        numeral: format!("V7/{}", target_numeral),
+        function: HarmonicFunction::SecondaryDominant,
+        reason: format!("Tonicizes {} ({}) with its own dominant", target.symbol, target_numeral),
+    }
+}
+
+fn suggest(chord: &DiatonicChord, reason: String) -> ChordSuggestion {
+    ChordSuggestion {
+        symbol: chord.symbol.clone(),
+        numeral: chord.numeral.clone(),
+        function: chord.function,
+        reason,
+    }
+}
+
+/// Chords that could follow `previous` in a key, likeliest first
+///
+/// Diatonic moves come first, then secondary dominants of the suggested
+/// chords. A secondary dominant is followed by the chord it resolves to.
+/// Without a previous chord (or one the key doesn't explain), suggests
+/// chords that establish the key.
+pub fn suggest_chords(scale: &Scale, previous: Option<&str>, sevenths: bool) -> Vec<ChordSuggestion> {
+    let chords = suggestion_chords(scale, sevenths);
+    let flats = uses_flats(scale);
+
+    let previous_root = previous.and_then(|symbol| ai_models::pitch_class(symbol.trim()));
+    let previous_classes = previous.map(chord_classes);
+    // The key's chord with the same root and third (natural minor's own v and VII count too)
+    let natural = diatonic_chords(scale, sevenths);
+    let from = previous_root.zip(previous_classes.as_ref()).and_then(|(root, classes)| {
+        chords
+            .iter()
+            .chain(&natural)
+            .find(|chord| chord.pitch_classes[0] == root && classes.contains(&chord.pitch_classes[1]))
+            .map(|chord| &chords[chord.degree])
+    });
+
+    let mut suggestions = Vec::new();
+    match from {
+        Some(from) => {
+            for &degree in PROGRESSIONS[from.degree] {
+                suggestions.push(suggest(&chords[degree], transition_reason(from, &chords[degree])));
+            }
+        }
+        None => {
+            // A dominant-quality chord a fifth above a scale degree resolves there
+            let resolves_to = previous_root.zip(previous_classes.as_ref()).and_then(|(root, classes)| {
+                let dominant = classes.contains(&((root + 4) % 12)) && !classes.contains(&((root + 3) % 12));
+                chords.iter().find(|chord| dominant && chord.pitch_classes[0] == (root + 5) % 12)
+            });
+            if let Some(target) = resolves_to {
+                suggestions.push(suggest(target, format!("Resolves the secondary dominant to {}", target.numeral)));
+            }
+            for (degree, reason) in [
+                (0, "Establishes the key"),
+                (3, "Opens out to the subdominant"),
+                (4, "Sets up a return home"),
+                (5, "Relative minor/major color"),
+            ] {
+                suggestions.push(suggest(&chords[degree], reason.to_string()));
+            }
+        }
+    }
+
+    let targets: Vec<&DiatonicChord> = suggestions
+        .iter()
+        .filter_map(|suggestion| chords.iter().find(|chord| chord.symbol == suggestion.symbol))
+        .filter(|chord| chord.degree != 0 && !chord.numeral.contains(['°', 'ø']))
+        .take(2)
+        .collect();
+    suggestions.extend(targets.into_iter().map(|target| secondary_dominant(target, flats)));
+
+    let mut seen = std::collections::HashSet::new();
+    suggestions.retain(|suggestion| seen.insert(suggestion.symbol.clone()));
+    suggestions
+}
+
+/// What the keyboard and piano roll need to highlight a scale
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScaleInfo {
+    pub root: String,
+    pub mode: ScaleMode,
+    /// Pitch classes (0-11) of the scale, from the tonic up
+    pub pitch_classes: Vec<i32>,
+    /// Names of the scale's notes, in the same order, spelled for the key
+    pub note_names: Vec<String>,
+    /// Every MIDI note (0-127) in the scale
+    pub midi_notes: Vec<u8>,
+    /// Triad on each degree
+    pub chords: Vec<DiatonicChord>,
+    /// Seventh chord on each degree
+    pub seventh_chords: Vec<DiatonicChord>,
+}
+
+/// Notes, names and chords of a scale
+///
+/// Pentatonic and blues scales list the chords of their parent major or minor key.
+pub fn scale_info(scale: &Scale) -> ScaleInfo {
+    let tonic = tonic(scale);
+    let flats = uses_flats(scale);
+    let pitch_classes: Vec<i32> = scale.mode.intervals().iter().map(|i| (tonic + i) % 12).collect();
+
+    ScaleInfo {
+        root: scale.root.clone(),
+        mode: scale.mode,
+        note_names: pitch_classes.iter().map(|&pc| spell(pc, flats).to_string()).collect(),
+        midi_notes: (0..=127u8).filter(|&pitch| pitch_classes.contains(&(pitch as i32 % 12))).collect(),
+        pitch_classes,
+        chords: diatonic_chords(scale, false),
+        seventh_chords: diatonic_chords(scale, true),
+    }
+}
+
+/// Chord qualities harmonic analysis recognizes: symbol suffix and intervals above the root
+const CHORD_TEMPLATES: [(&str, &[i32]); 8] = [
+    ("", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("+", &[0, 4, 8]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("m7b5", &[0, 3, 6, 10]),
+];
+
+/// Numerals of chromatic chords by semitones above the tonic
+const CHROMATIC_NUMERALS: [&str; 12] = ["I", "bII", "II", "bIII", "III", "IV", "#IV", "V", "bVI", "VI", "bVII", "VII"];
+
+/// Lead a new key needs over the current one, in profile correlation, to count as a modulation
+const MODULATION_MARGIN: f64 = 0.1;
+
+/// A stretch of music over one chord
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarmonySpan {
+    pub start_time: f64,
+    pub duration: f64,
+    /// Chord symbol ("Dm7")
+    pub symbol: String,
+    /// Roman numeral in the key at that point ("ii7", "V7/V", "bVI")
+    pub numeral: String,
+    /// Key the numeral refers to ("G major")
+    pub key: String,
+}
+
+/// A change of key
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Modulation {
+    /// Start of the measure the new key takes over
+    pub start_time: f64,
+    pub from: String,
+    pub to: String,
+}
+
+/// Chords and keys of a passage
+#[derive(Debug, Clone, Serialize)]
+pub struct HarmonicAnalysis {
+    pub chords: Vec<HarmonySpan>,
+    pub modulations: Vec<Modulation>,
+}
+
+fn key_name(scale: &Scale) -> String {
+    format!("{} {}", scale.root, scale.mode)
+}
+
+/// Time each pitch class sounds within `start..end`, and the lowest pitch sounding
+fn window_weights(notes: &[Note], start: f64, end: f64) -> ([f64; 12], Option<u8>) {
+    let mut weights = [0.0; 12];
+    let mut bass: Option<u8> = None;
+    for note in notes {
+        let overlap = (note.start_time + note.duration).min(end) - note.start_time.max(start);
+        if overlap > 1e-9 {
+            weights[(note.pitch % 12) as usize] += overlap;
+            bass = Some(bass.map_or(note.pitch, |b| b.min(note.pitch)));
+        }
+    }
+    (weights, bass)
+}
+
+/// Chord (root, template index) that best explains the pitch-class weights
+///
+/// Sevenths are only chosen when all four tones sound; a triad needs its
+/// root and one other tone. The bass note favors chords built on it.
+fn best_chord(weights: &[f64; 12], bass: Option<u8>) -> Option<(i32, usize)> {
+    let total: f64 = weights.iter().sum();
+    let mut best: Option<(f64, i32, usize)> = None;
+
+    for root in 0..12 {
+        for (index, (_, intervals)) in CHORD_TEMPLATES.iter().enumerate() {
+            let tones: Vec<usize> = intervals.iter().map(|i| ((root + i) % 12) as usize).collect();
+            let sounding = tones.iter().filter(|&&pc| weights[pc] > 0.0).count();
+            if weights[tones[0]] == 0.0 || sounding < 2 || (tones.len() == 4 && sounding < 4) {
+                continue;
+            }
+            let inside: f64 = tones.iter().map(|&pc| weights[pc]).sum();
+            let mut score = 2.0 * inside - total;
+            if bass.is_some_and(|bass| (bass % 12) as i32 == root) {
+                score += 0.5 * total;
+            }
+            if !best.is_some_and(|(best_score, ..)| score <= best_score + 1e-9) {
+                best = Some((score, root, index));
+            }
+        }
+    }
+    best.map(|(_, root, index)| (root, index))
+}
+
+/// Roman numeral of a chord in `key`
+///
+/// Chords of the key (including the harmonic-minor V and vii°) get their
+/// degree; dominants of other degrees are labeled V/x; anything else gets a
+/// chromatic numeral ("bVI").
+fn roman_numeral(root: i32, template: usize, key: &Scale) -> String {
+    let (suffix, intervals) = CHORD_TEMPLATES[template];
+    let mut classes: Vec<i32> = intervals.iter().map(|i| (root + i) % 12).collect();
+    classes.sort_unstable();
+    let seventh = intervals.len() == 4;
+
+    let chords = suggestion_chords(key, seventh);
+    let same = |chord: &&DiatonicChord| {
+        let mut tones = chord.pitch_classes.clone();
+        tones.sort_unstable();
+        tones == classes
+    };
+    if let Some(chord) = chords.iter().find(same) {
+        return chord.numeral.clone();
+    }
+    if let Some(chord) = diatonic_chords(key, seventh).iter().find(same) {
+        return chord.numeral.clone();
+    }
+
+    let dominant = suffix.is_empty() || suffix == "7";
+    if dominant {
+        let target = chords
+            .iter()
+            .find(|chord| chord.degree != 0 && chord.pitch_classes[0] == (root + 5) % 12 && !chord.numeral.contains(['°', 'ø']));
+        if let Some(target) = target {
+            return format!("V{}/{}", suffix, numeral_degree(&target.numeral));
+        }
+    }
+
+    let numeral = CHROMATIC_NUMERALS[(root - tonic(key)).rem_euclid(12) as usize];
+    let (numeral, quality) = match suffix {
+        "m" => (numeral.to_lowercase(), ""),
+        "dim" => (numeral.to_lowercase(), "°"),
+        "m7" => (numeral.to_lowercase(), "7"),
+        "m7b5" => (numeral.to_lowercase(), "ø7"),
+        other => (numeral.to_string(), other),
+    };
+    format!("{}{}", numeral, quality)
+}
+
+/// A detected major or minor key, spelled as it would be written
+fn detected_key(candidate: &KeyCandidate) -> Scale {
+    let pitch_class = Scale::note_to_offset(&candidate.root).rem_euclid(12);
+    let relative_major = if candidate.mode.is_minor() { pitch_class + 3 } else { pitch_class };
+    let flats = matches!(relative_major.rem_euclid(12), 1 | 3 | 5 | 8 | 10);
+    Scale { root: spell(pitch_class, flats).to_string(), mode: candidate.mode, octave: None }
+}
+
+/// Key of each measure, from the notes around it
+///
+/// A measure moves to a new key when the notes of it and its neighbors fit
+/// that key clearly better than the current one, and the next measure agrees.
+fn measure_keys(notes: &[Note], key: &Scale, measures: usize, beats_per_measure: f64) -> Vec<Scale> {
+    let rankings: Vec<Vec<KeyCandidate>> = (0..measures)
+        .map(|measure| {
+            let start = (measure as f64 - 1.0) * beats_per_measure;
+            let end = (measure as f64 + 2.0) * beats_per_measure;
+            let window: Vec<Note> = notes
+                .iter()
+                .filter(|n| n.start_time < end && n.start_time + n.duration > start)
+                .cloned()
+                .collect();
+            detect_key(&window)
+        })
+        .collect();
+    let confident = |measure: usize| rankings.get(measure)?.first().filter(|best| best.score >= KEY_CONFIDENCE);
+    // Church modes and the like are compared as the major or minor key on their tonic
+    let fit = |measure: usize, scale: &Scale| {
+        rankings[measure]
+            .iter()
+            .find(|c| c.mode.is_minor() == scale.mode.is_minor() && Scale::note_to_offset(&c.root).rem_euclid(12) == tonic(scale))
+            .map_or(-1.0, |c| c.score)
+    };
+
+    let mut current = key.clone();
+    let mut keys = Vec::with_capacity(measures);
+    for measure in 0..measures {
+        if let Some(best) = confident(measure) {
+            let moved = fit(measure, &current) < best.score - MODULATION_MARGIN;
+            let confirmed = measure + 1 == measures
+                || confident(measure + 1).is_some_and(|next| next.root == best.root && next.mode == best.mode);
+            if moved && confirmed {
+                current = detected_key(best);
+            }
+        }
+        keys.push(current.clone());
+    }
+    keys
+}
+
+/// Label the harmony of each beat with a chord and its Roman numeral, and find modulations
+///
+/// Each beat's chord is the one whose tones best cover what sounds during
+/// it (favoring the bass note); repeated chords merge into one span. Beats
+/// without notes have no chord. Numerals follow the key in force, which
+/// starts as `key` and changes where the music clearly settles in another.
+pub fn analyze_harmony(notes: &[Note], key: &Scale, beats_per_measure: f64) -> HarmonicAnalysis {
+    let end = notes.iter().map(|n| n.start_time + n.duration).fold(0.0, f64::max);
+    let measures = (end / beats_per_measure).ceil() as usize;
+    let keys = measure_keys(notes, key, measures, beats_per_measure);
+
+    let mut chords: Vec<HarmonySpan> = Vec::new();
+    let beats = end.ceil() as usize;
+    for beat in 0..beats {
+        let start = beat as f64;
+        let (weights, bass) = window_weights(notes, start, start + 1.0);
+        let Some((root, template)) = best_chord(&weights, bass) else {
+            continue;
+        };
+        let key = &keys[((start / beats_per_measure) as usize).min(measures.saturating_sub(1))];
+        let symbol = format!("{}{}", spell(root, uses_flats(key)), CHORD_TEMPLATES[template].0);
+
+        match chords.last_mut() {
+            Some(last) if last.symbol == symbol && (last.start_time + last.duration - start).abs() < 1e-9 && last.key == key_name(key) => {
+                last.duration += 1.0;
+            }
+            _ => chords.push(HarmonySpan {
+                start_time: start,
+                duration: 1.0,
+                symbol,
+                numeral: roman_numeral(root, template, key),
+                key: key_name(key),
+            }),
+        }
+    }
+
+    let mut modulations = Vec::new();
+    let mut previous = key_name(key);
+    for (measure, scale) in keys.iter().enumerate() {
+        let name = key_name(scale);
+        if name != previous {
+            modulations.push(Modulation {
+                start_time: measure as f64 * beats_per_measure,
+                from: previous,
+                to: name.clone(),
+            });
+            previous = name;
+        }
+    }
+
+    HarmonicAnalysis { chords, modulations }
+}
+
+/// One-line summary of an analysis for prompts: numerals per span, with key changes
+///
+/// "I | vi | ii7 | V7 | (to D major) I | IV"
+pub fn harmony_summary(analysis: &HarmonicAnalysis) -> String {
+    let mut parts = Vec::new();
+    let mut key: Option<&str> = None;
+    for span in &analysis.chords {
+        if key.is_some_and(|key| key != span.key) {
+            parts.push(format!("(to {}) {}", span.key, span.numeral));
+        } else {
+            parts.push(span.numeral.clone());
+        }
+        key = Some(&span.key);
+    }
+    parts.join(" | ")
+}
+
+/// How a passage ends harmonically
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cadence {
+    /// V (or vii°) to I: the strongest ending
+    Authentic,
+    /// IV to I
+    Plagal,
+    /// Ending on V, leaving the music open
+    Half,
+    /// V to vi, avoiding the expected tonic
+    Deceptive,
+}
+
+impl Cadence {
+    /// Whether the cadence closes the piece (authentic or plagal)
+    pub fn is_conclusive(&self) -> bool {
+        matches!(self, Cadence::Authentic | Cadence::Plagal)
+    }
+}
+
+/// Scale degree of a numeral, without quality marks ("V7" -> "V", "vii°" -> "vii")
+fn numeral_degree(numeral: &str) -> &str {
+    numeral.trim_end_matches(['7', '°', 'ø', '+']).trim_end_matches("maj")
+}
+
+/// The cadence formed by the last two chords of `notes`, if any
+///
+/// Chords are found as in `analyze_harmony`, in the key the passage ends in.
+pub fn detect_cadence(notes: &[Note], key: &Scale, beats_per_measure: f64) -> Option<Cadence> {
+    let analysis = analyze_harmony(notes, key, beats_per_measure);
+    let last = analysis.chords.last()?;
+    let previous = analysis.chords.iter().rev().nth(1).filter(|chord| chord.key == last.key);
+    let from = previous.map(|chord| numeral_degree(&chord.numeral));
+
+    match (from, numeral_degree(&last.numeral)) {
+        (Some("V" | "vii"), "I" | "i") => Some(Cadence::Authentic),
+        (Some("IV" | "iv"), "I" | "i") => Some(Cadence::Plagal),
+        (Some("V"), "vi" | "VI" | "bVI") => Some(Cadence::Deceptive),
+        (_, "V") => Some(Cadence::Half),
+        _ => None,
+    }
+}
+
+/// Lowest pitch of `pitch_class` at or above `floor`
+fn pitch_from(pitch_class: i32, floor: i32) -> u8 {
+    (floor + (pitch_class - floor).rem_euclid(12)) as u8
+}
+
+/// A measure closing on an authentic cadence in `key`, starting at `start`
+///
+/// The first half of the measure is a dominant seventh, the second the tonic
+/// triad, with the leading tone resolving to the tonic on top and the bass
+/// moving from the fifth degree to the root.
+pub fn authentic_cadence(key: &Scale, start: f64, beats_per_measure: f64, velocity: u8, track_id: &str) -> Vec<Note> {
+    let tonic = tonic(key);
+    let third = if key.mode.is_minor() { 3 } else { 4 };
+    let half = beats_per_measure / 2.0;
+
+    let melody_tonic = pitch_from(tonic, 66) as i32;
+    let bass_tonic = pitch_from(tonic, 48) as i32;
+    let dominant = [bass_tonic - 5, pitch_from(tonic + 2, 52) as i32, pitch_from(tonic + 5, 52) as i32, melody_tonic - 1];
+    let resolution = [bass_tonic, pitch_from(tonic + third, 52) as i32, pitch_from(tonic + 7, 52) as i32, melody_tonic];
+
+    [(start, dominant), (start + half, resolution)]
+        .into_iter()
+        .flat_map(|(time, chord)| {
+            chord.into_iter().map(move |pitch| Note {
+                id: uuid::Uuid::new_v4().to_string(),
+                pitch: pitch as u8,
+                start_time: time,
+                duration: half,
+                velocity,
+                track_id: track_id.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(pitch: u8, start_time: f64, duration: f64) -> Note {
+        Note {
+            id: format!("{}-{}", pitch, start_time),
+            pitch,
+            start_time,
+            duration,
+            velocity: 80,
+            track_id: "track_right_hand".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_detect_key() {
+        // A minor melody leaning on A, C and E, with a G# leading tone
+        let pitches = [69, 71, 72, 74, 76, 72, 69, 68, 69, 64, 69];
+        let notes: Vec<Note> = pitches.iter().enumerate().map(|(i, &p)| note(p, i as f64, 1.0)).collect();
+
+        let keys = detect_key(&notes);
+        assert_eq!(keys.len(), 24);
+        assert_eq!((keys[0].root.as_str(), keys[0].mode), ("A", ScaleMode::Minor));
+        assert!(keys[0].score > KEY_CONFIDENCE);
+
+        // An arpeggiated D major triad
+        let notes: Vec<Note> = [62, 66, 69, 74, 69, 66, 62].iter().map(|&p| note(p, 0.0, 2.0)).collect();
+        assert_eq!((detect_key(&notes)[0].root.as_str(), detect_key(&notes)[0].mode), ("D", ScaleMode::Major));
+        assert!(detect_key(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_chord_suggestions() {
+        let c_major = Scale { root: "C".to_string(), mode: ScaleMode::Major, octave: None };
+        let symbols: Vec<String> = diatonic_chords(&c_major, true).into_iter().map(|chord| chord.symbol).collect();
+        assert_eq!(symbols, vec!["Cmaj7", "Dm7", "Em7", "Fmaj7", "G7", "Am7", "Bm7b5"]);
+
+        // After the dominant: home, deceptive, then the secondary dominant of vi
+        let after_v = suggest_chords(&c_major, Some("G7"), false);
+        assert_eq!(after_v[0].symbol, "C");
+        assert_eq!(after_v[0].function, HarmonicFunction::Tonic);
+        assert_eq!(after_v[1].numeral, "vi");
+        assert!(after_v.iter().any(|s| s.symbol == "E7" && s.numeral == "V7/vi"));
+
+        // A secondary dominant resolves to its target first
+        assert_eq!(suggest_chords(&c_major, Some("A7"), false)[0].symbol, "Dm");
+
+        // Minor keys use the raised leading tone, and flat spellings where the key has flats
+        let d_minor = Scale { root: "D".to_string(), mode: ScaleMode::Minor, octave: None };
+        let chords = suggestion_chords(&d_minor, false);
+        assert_eq!(chords[4].symbol, "A");
+        assert_eq!((chords[5].symbol.as_str(), chords[5].numeral.as_str()), ("Bb", "bVI"));
+    }
+
+    #[test]
+    fn test_analyze_harmony() {
+        let block = |pitches: &[u8], start: f64, duration: f64| -> Vec<Note> {
+            pitches.iter().map(|&p| note(p, start, duration)).collect()
+        };
+        // I | V7/ii | ii | V7 | I in C major
+        let notes: Vec<Note> = [
+            block(&[48, 64, 67], 0.0, 2.0),
+            block(&[57, 61, 64, 67], 2.0, 1.0),
+            block(&[50, 65, 69], 3.0, 1.0),
+            block(&[55, 59, 62, 65], 4.0, 2.0),
+            block(&[48, 64, 67], 6.0, 2.0),
+        ]
+        .concat();
+        let c_major = Scale { root: "C".to_string(), mode: ScaleMode::Major, octave: None };
+
+        let analysis = analyze_harmony(&notes, &c_major, 4.0);
+        let labels: Vec<(&str, &str, f64)> =
+            analysis.chords.iter().map(|c| (c.symbol.as_str(), c.numeral.as_str(), c.duration)).collect();
+        assert_eq!(
+            labels,
+            vec![("C", "I", 2.0), ("A7", "V7/ii", 1.0), ("Dm", "ii", 1.0), ("G7", "V7", 2.0), ("C", "I", 2.0)]
+        );
+        assert!(analysis.modulations.is_empty());
+        assert_eq!(harmony_summary(&analysis), "I | V7/ii | ii | V7 | I");
+    }
+
+    #[test]
+    fn test_cadences() {
+        let c_minor = Scale { root: "C".to_string(), mode: ScaleMode::Minor, octave: None };
+        let cadence = authentic_cadence(&c_minor, 4.0, 4.0, 90, "track_right_hand");
+        let pitches: Vec<u8> = cadence.iter().map(|n| n.pitch).collect();
+        assert_eq!(pitches, vec![43, 62, 53, 71, 48, 63, 55, 72]);
+        assert_eq!(detect_cadence(&cadence, &c_minor, 4.0), Some(Cadence::Authentic));
+
+        // The same chords the other way round end on the dominant: only a half cadence
+        let open: Vec<Note> = cadence
+            .iter()
+            .map(|n| Note { start_time: if n.start_time < 6.0 { 6.0 } else { 4.0 }, ..n.clone() })
+            .collect();
+        assert_eq!(detect_cadence(&open, &c_minor, 4.0), Some(Cadence::Half));
+        assert!(!Cadence::Half.is_conclusive());
+    }
+
+    #[test]
+    fn test_scale_info() {
+        let f_major = scale_info(&Scale { root: "F".to_string(), mode: ScaleMode::Major, octave: None });
+        assert_eq!(f_major.note_names, vec!["F", "G", "A", "Bb", "C", "D", "E"]);
+        assert_eq!(f_major.midi_notes.len(), 75);
+        assert!(f_major.midi_notes.contains(&70) && !f_major.midi_notes.contains(&71));
+        assert_eq!(f_major.chords[3].symbol, "Bb");
+        assert_eq!(f_major.seventh_chords[4].symbol, "C7");
+
+        let a_blues = scale_info(&Scale { root: "A".to_string(), mode: ScaleMode::Blues, octave: None });
+        assert_eq!(a_blues.note_names, vec!["A", "C", "D", "D#", "E", "G"]);
+        assert_eq!(a_blues.chords[0].symbol, "Am");
+    }
+}
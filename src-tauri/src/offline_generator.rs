@@ -0,0 +1,268 @@
+use crate::ai_models::{GenerationMetadata, MelodyRequest, MelodyResponse, Note, Scale, ScaleMode};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Model name in the metadata of offline generations
+pub const OFFLINE_MODEL_NAME: &str = "offline-markov";
+
+/// Register of offline melodies (C4-C6)
+const LOWEST: u8 = 60;
+const HIGHEST: u8 = 84;
+
+/// Melodic moves in scale steps, with the weights of the Markov chain
+const MOVES: [i32; 9] = [-4, -3, -2, -1, 0, 1, 2, 3, 4];
+
+/// Note lengths in beats the rhythm chain picks from
+const DURATIONS: [f64; 4] = [0.25, 0.5, 1.0, 2.0];
+
+/// Tendencies taken from the prompt's style keywords
+struct Style {
+    /// Weight of leaps (3+ steps) against steps
+    leaps: f64,
+    /// Preferred direction of motion (-1, 0 or 1)
+    direction: i32,
+    /// Weights of `DURATIONS`
+    rhythm: [f64; 4],
+    velocity: u8,
+}
+
+fn style(prompt: &str) -> Style {
+    let prompt = prompt.to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|word| prompt.contains(word));
+
+    let leaps = if has(&["leaping", "angular", "wide intervals", "jumpy"]) {
+        3.0
+    } else if has(&["stepwise", "scalar", "smooth", "calm", "lullaby"]) {
+        0.3
+    } else {
+        1.0
+    };
+    let direction = if has(&["ascending", "rising", "upward"]) {
+        1
+    } else if has(&["descending", "falling", "downward"]) {
+        -1
+    } else {
+        0
+    };
+    let rhythm = if has(&["fast", "quick", "rapid", "energetic", "busy"]) {
+        [3.0, 4.0, 1.5, 0.3]
+    } else if has(&["slow", "leisurely", "calm", "ballad", "lullaby"]) {
+        [0.1, 1.0, 3.0, 2.0]
+    } else {
+        [0.5, 3.0, 3.0, 1.0]
+    };
+    let velocity = if has(&["soft", "quiet", "gentle"]) {
+        55
+    } else if has(&["loud", "powerful", "forte"]) {
+        105
+    } else {
+        80
+    };
+
+    Style { leaps, direction, rhythm, velocity }
+}
+
+/// Index of `weights` picked at random in proportion to the weights
+///
+/// `temperature` flattens (above 1) or sharpens (below 1) the distribution.
+fn pick(rng: &mut StdRng, weights: &[f64], temperature: f32) -> usize {
+    let exponent = 1.0 / (temperature as f64).max(0.1);
+    let weights: Vec<f64> = weights.iter().map(|w| w.max(0.0).powf(exponent)).collect();
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return 0;
+    }
+
+    let mut target = rng.gen_range(0.0..total);
+    for (i, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return i;
+        }
+        target -= weight;
+    }
+    weights.len() - 1
+}
+
+/// Weights of `MOVES` after the move `previous`
+///
+/// Steps are likelier than leaps, and a leap is followed by a step back
+/// (gap fill), which keeps the line singable.
+fn move_weights(previous: i32, style: &Style) -> Vec<f64> {
+    MOVES
+        .iter()
+        .map(|&step| {
+            let mut weight = match step.abs() {
+                0 => 1.0,
+                1 => 4.0,
+                2 => 2.0,
+                3 => style.leaps,
+                _ => 0.5 * style.leaps,
+            };
+            if previous.abs() >= 3 && step.abs() <= 2 && step.signum() == -previous.signum() {
+                weight *= 3.0;
+            }
+            if style.direction != 0 && step.signum() == style.direction {
+                weight *= 1.5;
+            }
+            weight
+        })
+        .collect()
+}
+
+/// Index in `pitches` of the pitch nearest to `pitch` with a pitch class in `classes`
+fn nearest_in(pitches: &[u8], pitch: u8, classes: &[i32]) -> Option<usize> {
+    pitches
+        .iter()
+        .enumerate()
+        .filter(|&(_, &p)| classes.contains(&(p as i32 % 12)))
+        .min_by_key(|&(_, &p)| (p as i32 - pitch as i32).abs())
+        .map(|(i, _)| i)
+}
+
+/// Generate a melody without AI: a Markov chain over scale steps and note
+/// lengths, steered by the prompt's style keywords
+///
+/// Phrases last two measures and end on a long note, the last one on the
+/// tonic; with a chord progression, notes on strong beats are chord tones.
+/// The same request and seed give the same melody.
+pub fn generate(request: &MelodyRequest) -> MelodyResponse {
+    let mut rng = match request.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let style = style(&request.prompt);
+    let temperature = request.temperature.unwrap_or(1.0);
+
+    let scale = request.scale.clone().unwrap_or(Scale {
+        root: "C".to_string(),
+        mode: ScaleMode::Major,
+        octave: None,
+    });
+    let mut pitches: Vec<u8> = scale.get_midi_notes().into_iter().filter(|p| (LOWEST..=HIGHEST).contains(p)).collect();
+    if pitches.is_empty() {
+        pitches = scale.get_midi_notes();
+    }
+    pitches.sort_unstable();
+    pitches.dedup();
+    let tonic = Scale::note_to_offset(&scale.root).rem_euclid(12);
+
+    let time_signature = request.time_signature;
+    let measure = time_signature.beats_per_measure();
+    let strong_beats = time_signature.strong_beats();
+    let chords = request.progression();
+
+    let mut index = nearest_in(&pitches, 67, &[tonic]).unwrap_or(pitches.len() / 2);
+    let mut previous_move = 0;
+    let mut previous_duration = 2;
+    let mut notes = Vec::new();
+
+    for bar in 0..request.measures {
+        let bar_start = bar as f64 * measure;
+        let bar_end = bar_start + measure;
+        let last_bar = bar + 1 == request.measures;
+        let phrase_end = last_bar || bar % 2 == 1;
+        let mut time = bar_start;
+
+        while time < bar_end - 1e-9 {
+            let remaining = bar_end - time;
+
+            // A phrase closes with one long note filling the end of its measure
+            let cadence = phrase_end && remaining <= measure / 2.0 + 1e-9;
+            let duration = if cadence {
+                remaining
+            } else {
+                let mut weights: Vec<f64> = DURATIONS
+                    .iter()
+                    .map(|&d| if d <= remaining + 1e-9 { 1.0 } else { 0.0 })
+                    .zip(style.rhythm)
+                    .map(|(fits, weight)| fits * weight)
+                    .collect();
+                // Repeating the last length keeps the rhythm coherent
+                if weights[previous_duration] > 0.0 {
+                    weights[previous_duration] *= 2.0;
+                }
+                previous_duration = pick(&mut rng, &weights, temperature);
+                DURATIONS[previous_duration]
+            };
+
+            if !notes.is_empty() {
+                let step = MOVES[pick(&mut rng, &move_weights(previous_move, &style), temperature)];
+                let target = (index as i32 + step).clamp(0, pitches.len() as i32 - 1);
+                previous_move = target - index as i32;
+                index = target as usize;
+            }
+
+            // Chord tones where the harmony is heard: strong beats and phrase ends
+            let beat_in_bar = time - bar_start;
+            let strong = strong_beats.iter().any(|beat| (beat - beat_in_bar).abs() < 1e-9);
+            let chord = chords.iter().find(|c| time >= c.start_time - 1e-9 && time < c.start_time + c.duration - 1e-9);
+            let classes = match (cadence && last_bar, chord) {
+                (true, _) => vec![tonic],
+                (false, Some(chord)) if strong || cadence => chord.pitch_classes(),
+                (false, None) if cadence => vec![tonic, (tonic + 4) % 12, (tonic + 3) % 12, (tonic + 7) % 12],
+                _ => Vec::new(),
+            };
+            if !classes.is_empty() {
+                if let Some(snapped) = nearest_in(&pitches, pitches[index], &classes) {
+                    index = snapped;
+                }
+            }
+
+            let accent = if strong { 8 } else { 0 };
+            let velocity = (style.velocity as i32 + accent + rng.gen_range(-6..=6)).clamp(1, 127) as u8;
+            notes.push(Note {
+                id: uuid::Uuid::new_v4().to_string(),
+                pitch: pitches[index],
+                start_time: time,
+                duration,
+                velocity,
+                track_id: request.mode.default_track().to_string(),
+            });
+            time += duration;
+        }
+    }
+
+    MelodyResponse {
+        notes,
+        chords: Vec::new(),
+        dynamics: Vec::new(),
+        pedal: Vec::new(),
+        metadata: GenerationMetadata {
+            provider: request.model_provider.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            model_name: OFFLINE_MODEL_NAME.to_string(),
+            temperature,
+            scale: request.scale.clone(),
+            seed: request.seed,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_melody_passes_validation() {
+        let request = MelodyRequest {
+            prompt: "A calm, stepwise tune".to_string(),
+            scale: Some(Scale { root: "D".to_string(), mode: ScaleMode::Minor, octave: Some(4) }),
+            measures: 4,
+            seed: Some(7),
+            chord_progression: Some("Dm | Gm | A | Dm".to_string()),
+            ..MelodyRequest::default()
+        };
+
+        for seed in 0..20 {
+            let response = generate(&MelodyRequest { seed: Some(seed), ..request.clone() });
+            assert!(response.validate_request(&request).is_ok(), "seed {}", seed);
+            let last = response.notes.last().unwrap();
+            assert_eq!(last.pitch % 12, 2);
+            assert_eq!(last.start_time + last.duration, 16.0);
+        }
+
+        let pitches = |response: MelodyResponse| response.notes.iter().map(|n| n.pitch).collect::<Vec<_>>();
+        assert_eq!(pitches(generate(&request)), pitches(generate(&request)));
+        assert_eq!(generate(&request).metadata.model_name, OFFLINE_MODEL_NAME);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::project_crypto;
+use crate::project_file::{self, describe_io_error};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use validator::Validate;
+
+/// Folder next to a project holding its rotating backups
+pub const BACKUPS_DIR: &str = "backups";
+
+/// How many backups to keep per project
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct BackupPolicy {
+    /// Newest backups kept
+    #[validate(range(min = 1, max = 100))]
+    pub max_backups: usize,
+
+    /// Backups older than this are removed (the newest one is always kept)
+    #[validate(range(min = 1, max = 3650))]
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            max_backups: 10,
+            max_age_days: None,
+        }
+    }
+}
+
+/// A timestamped backup of a project
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub id: String,
+    pub created_at: String,
+    pub size: u64,
+}
+
+/// Folder holding the backups of one project (`backups/<file name>/` next to it)
+pub fn backups_dir(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    path.with_file_name(BACKUPS_DIR).join(name)
+}
+
+/// Copy the current version of `path` into its backups folder
+///
+/// Backups are named after the time they were taken, so names sort
+/// chronologically. Returns `None` when there is no file to back up yet.
+pub fn create_backup(path: &Path) -> Result<Option<PathBuf>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let dir = backups_dir(path);
+    fs::create_dir_all(&dir).map_err(|e| describe_io_error("create", &dir, &e))?;
+
+    let mut name = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f").to_string();
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+
+    let backup = dir.join(name);
+    fs::copy(path, &backup).map_err(|e| describe_io_error("back up", path, &e))?;
+    Ok(Some(backup))
+}
+
+/// Backups of a project, newest first
+pub fn list_backups(path: &Path) -> Result<Vec<BackupInfo>, String> {
+    let dir = backups_dir(path);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(describe_io_error("read", &dir, &e)),
+    };
+
+    let mut backups: Vec<BackupInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            let created_at = metadata
+                .modified()
+                .map(|time| chrono::DateTime::<chrono::Local>::from(time).to_rfc3339())
+                .unwrap_or_default();
+
+            Some(BackupInfo {
+                id: entry.file_name().to_string_lossy().to_string(),
+                created_at,
+                size: metadata.len(),
+            })
+        })
+        .collect();
+
+    backups.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(backups)
+}
+
+/// Delete backups beyond the policy; returns how many were removed
+pub fn prune_backups(path: &Path, policy: &BackupPolicy) -> Result<usize, String> {
+    let backups = list_backups(path)?;
+    let cutoff = policy
+        .max_age_days
+        .map(|days| chrono::Local::now() - chrono::Duration::days(days as i64));
+
+    let mut removed = 0;
+    for (index, backup) in backups.iter().enumerate() {
+        let too_many = index >= policy.max_backups;
+        let too_old = index > 0
+            && cutoff.is_some_and(|cutoff| {
+                chrono::DateTime::parse_from_rfc3339(&backup.created_at).is_ok_and(|created| created < cutoff)
+            });
+
+        if too_many || too_old {
+            let file = backups_dir(path).join(&backup.id);
+            fs::remove_file(&file).map_err(|e| describe_io_error("delete", &file, &e))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Read one of a project's backups
+pub fn read_backup(path: &Path, id: &str) -> Result<Vec<u8>, String> {
+    // Ids are plain file names; anything else could point outside the folder
+    if Path::new(id).file_name().and_then(|name| name.to_str()) != Some(id) {
+        return Err(format!("Invalid backup id: {}", id));
+    }
+
+    let backup = backups_dir(path).join(id);
+    fs::read(&backup).map_err(|e| describe_io_error("read", &backup, &e))
+}
+
+/// Replace a project with the contents of one of its backups
+///
+/// The current version is backed up first, so a restore can itself be undone.
+pub fn restore_backup(path: &Path, data: &[u8]) -> Result<(), String> {
+    create_backup(path)?;
+    project_file::write_atomic(path, data)
+}
+
+/// Delete the backups of a project that aren't encrypted; returns how many were removed
+///
+/// Used when a project is first saved with a password, so no readable copy
+/// of it stays behind.
+pub fn purge_plaintext_backups(path: &Path) -> Result<usize, String> {
+    let dir = backups_dir(path);
+    let mut removed = 0;
+    for backup in list_backups(path)? {
+        let file = dir.join(&backup.id);
+        let data = fs::read(&file).map_err(|e| describe_io_error("read", &file, &e))?;
+        if !project_crypto::is_encrypted(&data) {
+            fs::remove_file(&file).map_err(|e| describe_io_error("delete", &file, &e))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_backup_rotation_and_restore() {
+        let temp_dir = env::temp_dir().join("piano-app-test-backups");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("song.json");
+
+        assert!(create_backup(&path).unwrap().is_none());
+
+        for version in 0..4 {
+            fs::write(&path, format!("v{}", version)).unwrap();
+            create_backup(&path).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(list_backups(&path).unwrap().len(), 4);
+
+        let policy = BackupPolicy { max_backups: 2, max_age_days: None };
+        assert_eq!(prune_backups(&path, &policy).unwrap(), 2);
+        let backups = list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 2);
+
+        // Restore the older remaining backup (v2); the current v3 is backed up
+        restore_backup(&path, &read_backup(&path, &backups[1].id).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v2");
+        assert_eq!(list_backups(&path).unwrap().len(), 3);
+
+        assert!(read_backup(&path, "../song.json").is_err());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_purge_plaintext_backups() {
+        let temp_dir = env::temp_dir().join("piano-app-test-backups-purge");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("song.json");
+
+        fs::write(&path, "plain").unwrap();
+        create_backup(&path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        fs::write(&path, project_crypto::encrypt(b"secret", "hunter2").unwrap()).unwrap();
+        create_backup(&path).unwrap();
+
+        assert_eq!(purge_plaintext_backups(&path).unwrap(), 1);
+        let backups = list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert!(project_crypto::is_encrypted(&read_backup(&path, &backups[0].id).unwrap()));
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
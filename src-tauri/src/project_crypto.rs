@@ -0,0 +1,89 @@
+use crate::crypto::{self, NONCE_LEN, SALT_LEN};
+
+/// Header identifying an encrypted project file
+const MAGIC: &[u8; 8] = b"PIANOENC";
+const VERSION: u8 = 1;
+
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// Error returned when an encrypted project is opened without a password
+pub const PASSWORD_REQUIRED: &str = "Project is password-protected";
+
+/// Whether file contents are an encrypted project
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Password to decode and re-save an edited file with: only kept if the file is encrypted
+///
+/// Edits keep a file's encryption state, so a password passed along for a
+/// plain project never encrypts it (its plain backups and library entry would
+/// be left behind).
+pub fn password_for_edit<'a>(data: &[u8], password: Option<&'a str>) -> Option<&'a str> {
+    password.filter(|_| is_encrypted(data))
+}
+
+/// Encrypt serialized project data with a password
+///
+/// Layout: magic, version, salt, nonce, then the AES-256-GCM ciphertext.
+/// A fresh salt and nonce are used for every save.
+pub fn encrypt(data: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    if password.is_empty() {
+        return Err("Password cannot be empty".to_string());
+    }
+
+    let salt = crypto::random_salt();
+    let key = crypto::derive_key(password, &salt)?;
+    let (nonce, ciphertext) = crypto::seal(&key, data).map_err(|e| format!("Failed to encrypt project: {}", e))?;
+
+    let mut out = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend(ciphertext);
+    Ok(out)
+}
+
+/// Decrypt an encrypted project file
+pub fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    if !is_encrypted(data) || data.len() < HEADER_LEN {
+        return Err("Not an encrypted project file".to_string());
+    }
+    if data[MAGIC.len()] != VERSION {
+        return Err(format!("Unsupported encrypted project version {}", data[MAGIC.len()]));
+    }
+
+    let salt = &data[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+    let nonce = &data[MAGIC.len() + 1 + SALT_LEN..HEADER_LEN];
+
+    let key = crypto::derive_key(password, salt)?;
+    // GCM authentication fails the same way for a wrong password and a tampered file
+    crypto::open(&key, nonce, &data[HEADER_LEN..]).map_err(|_| "Wrong password or damaged project file".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let encrypted = encrypt(b"{\"name\":\"Demo\"}", "hunter2").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(b"{}"));
+
+        assert_eq!(decrypt(&encrypted, "hunter2").unwrap(), b"{\"name\":\"Demo\"}");
+        assert!(decrypt(&encrypted, "wrong").is_err());
+        assert!(encrypt(b"{}", "").is_err());
+    }
+
+    #[test]
+    fn test_edit_keeps_encryption_state() {
+        // A plain project stays plain even when a password is given
+        assert_eq!(password_for_edit(b"{\"name\":\"Demo\"}", Some("hunter2")), None);
+
+        let encrypted = encrypt(b"{\"name\":\"Demo\"}", "hunter2").unwrap();
+        assert_eq!(password_for_edit(&encrypted, Some("hunter2")), Some("hunter2"));
+        assert_eq!(password_for_edit(&encrypted, None), None);
+    }
+}
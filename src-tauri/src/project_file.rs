@@ -0,0 +1,376 @@
+use crate::ProjectData;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Extension of project bundles (zip archives with the project and its assets)
+pub const BUNDLE_EXTENSION: &str = "pianoproj";
+
+/// Name of the project JSON inside a bundle
+const BUNDLE_PROJECT_ENTRY: &str = "project.json";
+
+/// On-disk encoding of a project file
+///
+/// JSON is readable and diffable; MessagePack is much smaller and faster for
+/// projects with tens of thousands of notes.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectFormat {
+    #[default]
+    Json,
+    #[serde(alias = "messagepack")]
+    Msgpack,
+}
+
+impl ProjectFormat {
+    /// Detect the encoding of project file contents
+    ///
+    /// JSON projects are objects, so they start with `{` (after an optional
+    /// BOM and whitespace); anything else is read as MessagePack.
+    pub fn detect(data: &[u8]) -> Self {
+        let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => ProjectFormat::Json,
+            Some(_) => ProjectFormat::Msgpack,
+            None => ProjectFormat::Json,
+        }
+    }
+}
+
+/// Serialize a project in the given format
+pub fn encode_project<T: Serialize>(project: &T, format: ProjectFormat) -> Result<Vec<u8>, String> {
+    match format {
+        ProjectFormat::Json => serde_json::to_vec_pretty(project)
+            .map_err(|e| format!("Failed to serialize project: {}", e)),
+        // Field names are kept so `#[serde(default)]` works for fields added later
+        ProjectFormat::Msgpack => rmp_serde::to_vec_named(project)
+            .map_err(|e| format!("Failed to serialize project: {}", e)),
+    }
+}
+
+/// Parse project file contents, auto-detecting the format
+pub fn decode_project<T: DeserializeOwned>(data: &[u8]) -> Result<(T, ProjectFormat), String> {
+    let format = ProjectFormat::detect(data);
+    let project = match format {
+        ProjectFormat::Json => {
+            let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+            serde_json::from_slice(data).map_err(|e| format!("Failed to parse project file: {}", e))?
+        }
+        ProjectFormat::Msgpack => rmp_serde::from_slice(data)
+            .map_err(|e| format!("Failed to parse project file (MessagePack): {}", e))?,
+    };
+    Ok((project, format))
+}
+
+/// Path of the backup kept of the previous version of a file (`song.json` -> `song.json.bak`)
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Path of the temporary file a save is written to before it replaces `path`
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Turn an I/O error into a message the user can act on
+pub fn describe_io_error(action: &str, path: &Path, error: &io::Error) -> String {
+    // ERROR_HANDLE_DISK_FULL / ERROR_DISK_FULL on Windows, ENOSPC elsewhere
+    let disk_full = if cfg!(windows) {
+        matches!(error.raw_os_error(), Some(39) | Some(112))
+    } else {
+        error.raw_os_error() == Some(28)
+    };
+
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => format!(
+            "Permission denied: cannot {} {}. Check that the folder is writable and the file isn't read-only.",
+            action,
+            path.display()
+        ),
+        io::ErrorKind::NotFound => format!("Cannot {} {}: file or folder not found", action, path.display()),
+        _ if disk_full => format!("Cannot {} {}: the disk is full", action, path.display()),
+        _ => format!("Failed to {} {}: {}", action, path.display(), error),
+    }
+}
+
+/// Write a file atomically
+///
+/// The data goes to a temporary file next to `path`, which is flushed to disk
+/// and then renamed over the target, so a crash or full disk leaves either the
+/// old or the new version, never a truncated file. The previous version is
+/// kept as `<path>.bak`.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let temp = temp_path(path);
+
+    let written = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .map_err(|e| describe_io_error("write", path, &e));
+
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    if path.exists() {
+        // A missing backup shouldn't block saving the new version
+        if let Err(e) = fs::copy(path, backup_path(path)) {
+            eprintln!("⚠ {}", describe_io_error("back up", path, &e));
+        }
+    }
+
+    if let Err(e) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        return Err(describe_io_error("replace", path, &e));
+    }
+
+    // Persist the rename itself (directory entry) on Unix
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that an asset path stays inside the project folder (relative, no `..`)
+fn is_contained(relative: &Path) -> bool {
+    relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Files a project references, relative to its folder: recorded audio clips and custom samples
+pub fn project_assets(project: &ProjectData) -> Vec<String> {
+    let mut assets: Vec<String> = project.audio_clips.iter().map(|clip| clip.path.clone()).collect();
+    for mapping in &project.sample_mappings {
+        if !assets.contains(&mapping.path) {
+            assets.push(mapping.path.clone());
+        }
+    }
+    assets
+}
+
+/// Package a project and its assets into a `.pianoproj` bundle
+///
+/// `assets` are paths relative to `project_dir` (e.g. recorded audio clips);
+/// they keep their relative location inside the bundle so references in the
+/// project JSON still resolve after extraction.
+pub fn write_bundle(project_json: &[u8], project_dir: &Path, assets: &[String], bundle_path: &Path) -> Result<(), String> {
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+    zip.start_file(BUNDLE_PROJECT_ENTRY, options)
+        .map_err(|e| format!("Failed to write bundle: {}", e))?;
+    zip.write_all(project_json)
+        .map_err(|e| format!("Failed to write bundle: {}", e))?;
+
+    for asset in assets {
+        let relative = Path::new(asset);
+        if !is_contained(relative) {
+            return Err(format!("Asset path escapes the project folder: {}", asset));
+        }
+
+        let data = fs::read(project_dir.join(relative))
+            .map_err(|e| describe_io_error("read", &project_dir.join(relative), &e))?;
+
+        // Zip entries always use forward slashes
+        let entry = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(entry, options)
+            .map_err(|e| format!("Failed to add {} to bundle: {}", asset, e))?;
+        zip.write_all(&data)
+            .map_err(|e| format!("Failed to add {} to bundle: {}", asset, e))?;
+    }
+
+    let bytes = zip
+        .finish()
+        .map_err(|e| format!("Failed to write bundle: {}", e))?
+        .into_inner();
+
+    write_atomic(bundle_path, &bytes)
+}
+
+/// Extract a `.pianoproj` bundle into `target_dir`
+///
+/// The project is written as `<project_name>.json` next to its assets.
+/// Nothing is extracted if any of the files already exists, so an import
+/// never overwrites another project or its assets. Returns the path of the
+/// extracted project file.
+pub fn extract_bundle(bundle_path: &Path, target_dir: &Path, project_name: &str) -> Result<PathBuf, String> {
+    let file = File::open(bundle_path).map_err(|e| describe_io_error("open", bundle_path, &e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("Invalid project bundle: {}", e))?;
+    let project_path = target_dir.join(format!("{}.json", project_name));
+
+    // Where each entry goes (None for folders), checked before anything is written
+    let mut outputs = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .map_err(|e| format!("Invalid project bundle: {}", e))?;
+
+        // enclosed_name rejects absolute paths and `..` (zip slip)
+        let relative = match entry.enclosed_name() {
+            Some(relative) => relative,
+            None => return Err(format!("Unsafe path in project bundle: {}", entry.name())),
+        };
+
+        let output = if relative == Path::new(BUNDLE_PROJECT_ENTRY) {
+            project_path.clone()
+        } else {
+            target_dir.join(&relative)
+        };
+        if entry.is_dir() {
+            outputs.push((output, true));
+            continue;
+        }
+        if output.exists() {
+            return Err(format!("{} already exists; import the bundle into another folder", output.display()));
+        }
+        outputs.push((output, false));
+    }
+
+    if !outputs.iter().any(|(output, _)| *output == project_path) {
+        return Err("Invalid project bundle: project.json is missing".to_string());
+    }
+
+    fs::create_dir_all(target_dir).map_err(|e| describe_io_error("create", target_dir, &e))?;
+    for (index, (output, is_dir)) in outputs.into_iter().enumerate() {
+        if is_dir {
+            fs::create_dir_all(&output).map_err(|e| describe_io_error("create", &output, &e))?;
+            continue;
+        }
+
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| format!("Invalid project bundle: {}", e))?;
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent).map_err(|e| describe_io_error("create", parent, &e))?;
+        }
+        let mut out = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&output)
+            .map_err(|e| describe_io_error("write", &output, &e))?;
+        io::copy(&mut entry, &mut out).map_err(|e| describe_io_error("write", &output, &e))?;
+    }
+
+    Ok(project_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_write_atomic_keeps_backup() {
+        let temp_dir = env::temp_dir().join("piano-app-test-atomic");
+        fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("song.json");
+
+        write_atomic(&path, b"first").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read(backup_path(&path)).unwrap(), b"first");
+        assert!(!temp_path(&path).exists());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let temp_dir = env::temp_dir().join("piano-app-test-bundle");
+        let source = temp_dir.join("source");
+        fs::create_dir_all(source.join("song_audio")).unwrap();
+        fs::write(source.join("song_audio").join("clip.wav"), b"RIFF").unwrap();
+
+        let bundle = temp_dir.join("song.pianoproj");
+        write_bundle(b"{}", &source, &["song_audio/clip.wav".to_string()], &bundle).unwrap();
+
+        let target = temp_dir.join("target");
+        let project = extract_bundle(&bundle, &target, "song").unwrap();
+        assert_eq!(fs::read(&project).unwrap(), b"{}");
+        assert_eq!(fs::read(target.join("song_audio").join("clip.wav")).unwrap(), b"RIFF");
+
+        // A second import into the same folder is refused without touching the first
+        fs::write(&project, b"{\"edited\":true}").unwrap();
+        assert!(extract_bundle(&bundle, &target, "song").is_err());
+        assert_eq!(fs::read(&project).unwrap(), b"{\"edited\":true}");
+
+        // Assets outside the project folder are refused
+        assert!(write_bundle(b"{}", &source, &["../secret".to_string()], &bundle).is_err());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_project_assets_include_sample_mappings() {
+        let clip = crate::audio_input::AudioClip {
+            path: "song_audio/clip.wav".to_string(),
+            duration: 1.0,
+            sample_rate: 44100,
+            channels: 1,
+            recorded_at: String::new(),
+        };
+        let mapping = |path: &str, low_pitch, high_pitch| crate::SampleMapping {
+            path: path.to_string(),
+            root_pitch: low_pitch,
+            low_pitch,
+            high_pitch,
+        };
+        let project = ProjectData {
+            audio_clips: vec![clip],
+            sample_mappings: vec![
+                mapping("samples/bell.wav", 60, 71),
+                mapping("samples/bell.wav", 72, 83),
+                mapping("samples/pad.wav", 36, 59),
+            ],
+            ..ProjectData::default()
+        };
+
+        assert_eq!(project_assets(&project), vec!["song_audio/clip.wav", "samples/bell.wav", "samples/pad.wav"]);
+    }
+
+    #[test]
+    fn test_project_format_roundtrip() {
+        let value = serde_json::json!({ "name": "Song", "tempo": 120, "notes": [] });
+
+        for format in [ProjectFormat::Json, ProjectFormat::Msgpack] {
+            let bytes = encode_project(&value, format).unwrap();
+            assert_eq!(ProjectFormat::detect(&bytes), format);
+
+            let (decoded, detected): (serde_json::Value, _) = decode_project(&bytes).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(detected, format);
+        }
+
+        assert_eq!(ProjectFormat::detect(b"\xEF\xBB\xBF  {}"), ProjectFormat::Json);
+    }
+
+    #[test]
+    fn test_write_atomic_reports_missing_folder() {
+        let path = env::temp_dir().join("piano-app-missing-folder").join("song.json");
+
+        let error = write_atomic(&path, b"data").unwrap_err();
+        assert!(error.contains("not found"));
+    }
+}
@@ -0,0 +1,487 @@
+use crate::fs_scope::PROJECT_EXTENSIONS;
+use crate::project_backups::BACKUPS_DIR;
+use crate::project_file;
+use crate::ProjectData;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How deep `rescan` descends into subfolders
+const MAX_SCAN_DEPTH: usize = 4;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS projects (
+    path          TEXT PRIMARY KEY,
+    name          TEXT NOT NULL,
+    author        TEXT,
+    description   TEXT,
+    key_signature TEXT,
+    tempo         INTEGER NOT NULL,
+    note_count    INTEGER NOT NULL,
+    tags          TEXT NOT NULL,
+    prompts       TEXT NOT NULL,
+    created_at    TEXT NOT NULL,
+    modified_at   TEXT
+);
+CREATE INDEX IF NOT EXISTS projects_name ON projects (name);
+CREATE INDEX IF NOT EXISTS projects_modified ON projects (modified_at);
+CREATE TABLE IF NOT EXISTS scanned_files (
+    path  TEXT PRIMARY KEY,
+    mtime INTEGER NOT NULL,
+    size  INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settings (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// A project as listed in the project browser
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LibraryEntry {
+    pub path: String,
+    pub name: String,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub key_signature: Option<String>,
+    pub tempo: u16,
+    pub note_count: usize,
+    pub tags: Vec<String>,
+    pub prompts: Vec<String>,
+    pub created_at: String,
+    pub modified_at: Option<String>,
+}
+
+impl LibraryEntry {
+    fn from_project(path: &str, project: &ProjectData) -> Self {
+        Self {
+            path: path.to_string(),
+            name: project.name.clone(),
+            author: project.metadata.author.clone(),
+            description: project.metadata.description.clone(),
+            key_signature: project.metadata.key_signature.clone(),
+            tempo: project.tempo,
+            note_count: project.notes.len(),
+            tags: project.metadata.tags.clone(),
+            prompts: project.generations.iter().map(|g| g.prompt.clone()).collect(),
+            created_at: project.created_at.clone(),
+            modified_at: project.metadata.modified_at.clone(),
+        }
+    }
+}
+
+/// Sort column of a library query
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibrarySort {
+    Name,
+    #[default]
+    Modified,
+    Created,
+    Tempo,
+}
+
+impl LibrarySort {
+    fn column(&self) -> &'static str {
+        match self {
+            LibrarySort::Name => "name COLLATE NOCASE",
+            LibrarySort::Modified => "modified_at",
+            LibrarySort::Created => "created_at",
+            LibrarySort::Tempo => "tempo",
+        }
+    }
+}
+
+/// Filters and ordering for the project browser; empty fields don't filter
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LibraryQuery {
+    /// Substring of the name, description, tags or AI prompts
+    pub text: Option<String>,
+    /// Exact tag (case-insensitive)
+    pub tag: Option<String>,
+    pub key_signature: Option<String>,
+    #[serde(default)]
+    pub sort: LibrarySort,
+    #[serde(default)]
+    pub descending: bool,
+    pub limit: Option<u32>,
+}
+
+/// Escape LIKE wildcards so user text matches literally
+fn like_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Index of all known projects, stored in an SQLite database in the app data directory
+pub struct ProjectLibrary {
+    conn: Mutex<Connection>,
+}
+
+impl ProjectLibrary {
+    /// Open (or create) the library database
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let conn = Connection::open(app_data_dir.join("library.db"))
+            .map_err(|e| format!("Failed to open project library: {}", e))?;
+        // Libraries from before file sizes were recorded: the scan cache is simply rebuilt
+        if conn.prepare("SELECT size FROM scanned_files LIMIT 0").is_err() {
+            conn.execute_batch("DROP TABLE IF EXISTS scanned_files")
+                .map_err(|e| format!("Failed to initialize project library: {}", e))?;
+        }
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| format!("Failed to initialize project library: {}", e))?;
+
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Add or update the entry of a project file
+    pub fn index(&self, path: &str, project: &ProjectData) -> Result<(), String> {
+        let entry = LibraryEntry::from_project(path, project);
+        let tags = serde_json::to_string(&entry.tags).map_err(|e| e.to_string())?;
+        let prompts = serde_json::to_string(&entry.prompts).map_err(|e| e.to_string())?;
+
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO projects
+                 (path, name, author, description, key_signature, tempo, note_count, tags, prompts, created_at, modified_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    entry.path,
+                    entry.name,
+                    entry.author,
+                    entry.description,
+                    entry.key_signature,
+                    entry.tempo,
+                    entry.note_count as i64,
+                    tags,
+                    prompts,
+                    entry.created_at,
+                    entry.modified_at,
+                ],
+            )
+            .map_err(|e| format!("Failed to index project: {}", e))?;
+        Ok(())
+    }
+
+    /// Remove a project from the library (the file itself is left alone)
+    pub fn remove(&self, path: &str) -> Result<(), String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM projects WHERE path = ?1", params![path])
+            .map_err(|e| format!("Failed to remove project from library: {}", e))?;
+        Ok(())
+    }
+
+    /// Read a library setting
+    pub fn setting(&self, key: &str) -> Result<Option<String>, String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT value FROM settings WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .map_err(|e| format!("Failed to read library setting: {}", e))
+    }
+
+    /// Store a library setting
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<(), String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )
+            .map_err(|e| format!("Failed to write library setting: {}", e))?;
+        Ok(())
+    }
+
+    /// Look up the entry of one project file
+    pub fn get(&self, path: &str) -> Result<Option<LibraryEntry>, String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT * FROM projects WHERE path = ?1", params![path], Self::read_entry)
+            .optional()
+            .map_err(|e| format!("Failed to query project library: {}", e))
+    }
+
+    /// List projects matching `query`
+    pub fn query(&self, query: &LibraryQuery) -> Result<Vec<LibraryEntry>, String> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+
+        if let Some(text) = query.text.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            values.push(like_pattern(text));
+            let n = values.len();
+            conditions.push(format!(
+                "(name LIKE ?{n} ESCAPE '\\' OR description LIKE ?{n} ESCAPE '\\' \
+                 OR tags LIKE ?{n} ESCAPE '\\' OR prompts LIKE ?{n} ESCAPE '\\')"
+            ));
+        }
+        if let Some(tag) = query.tag.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            values.push(tag.to_string());
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM json_each(projects.tags) WHERE lower(value) = lower(?{}))",
+                values.len()
+            ));
+        }
+        if let Some(key) = query.key_signature.as_deref().map(str::trim).filter(|k| !k.is_empty()) {
+            values.push(key.to_string());
+            conditions.push(format!("lower(key_signature) = lower(?{})", values.len()));
+        }
+
+        let mut sql = "SELECT * FROM projects".to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        let direction = if query.descending { "DESC" } else { "ASC" };
+        sql.push_str(&format!(" ORDER BY {} {}, path ASC", query.sort.column(), direction));
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare(&sql)
+            .map_err(|e| format!("Failed to query project library: {}", e))?;
+        let rows = statement
+            .query_map(params_from_iter(values.iter()), Self::read_entry)
+            .map_err(|e| format!("Failed to query project library: {}", e))?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to query project library: {}", e))
+    }
+
+    /// Re-index every project file under `folder` and drop entries whose file is gone
+    ///
+    /// Returns the number of projects indexed. Files that aren't valid
+    /// projects are skipped.
+    pub fn rescan(&self, folder: &Path) -> Result<usize, String> {
+        self.scan(folder, false)
+    }
+
+    /// Like `rescan`, but only reads files whose mtime or size changed since they were last scanned
+    ///
+    /// Returns the number of projects re-indexed.
+    pub fn refresh(&self, folder: &Path) -> Result<usize, String> {
+        self.scan(folder, true)
+    }
+
+    fn scan(&self, folder: &Path, only_changed: bool) -> Result<usize, String> {
+        let mut files = Vec::new();
+        collect_project_files(folder, 0, &mut files);
+
+        let scanned = self.scanned_files()?;
+        let mut indexed = 0;
+        for file in files {
+            let path = file.to_string_lossy().to_string();
+            let stamp = file_stamp(&file);
+            if only_changed && stamp.is_some() && scanned.get(&path) == stamp.as_ref() {
+                continue;
+            }
+
+            // Invalid files are remembered too, so they aren't read again until they change
+            let Ok(data) = fs::read(&file) else { continue };
+            if let Ok((project, _)) = project_file::decode_project::<ProjectData>(&data) {
+                self.index(&path, &project)?;
+                indexed += 1;
+            }
+            if let Some((mtime, size)) = stamp {
+                self.conn
+                    .lock()
+                    .unwrap()
+                    .execute(
+                        "INSERT OR REPLACE INTO scanned_files (path, mtime, size) VALUES (?1, ?2, ?3)",
+                        params![path, mtime, size],
+                    )
+                    .map_err(|e| format!("Failed to update project library: {}", e))?;
+            }
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT path FROM projects UNION SELECT path FROM scanned_files")
+            .map_err(|e| format!("Failed to query project library: {}", e))?;
+        let paths = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to query project library: {}", e))?;
+        drop(statement);
+
+        for path in paths.iter().filter(|p| !Path::new(p).exists()) {
+            conn.execute("DELETE FROM projects WHERE path = ?1", params![path])
+                .and_then(|_| conn.execute("DELETE FROM scanned_files WHERE path = ?1", params![path]))
+                .map_err(|e| format!("Failed to update project library: {}", e))?;
+        }
+
+        Ok(indexed)
+    }
+
+    /// Modification times and sizes of the files seen by the last scans, by path
+    fn scanned_files(&self) -> Result<HashMap<String, (i64, i64)>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT path, mtime, size FROM scanned_files")
+            .map_err(|e| format!("Failed to query project library: {}", e))?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?))))
+            .and_then(|rows| rows.collect::<Result<HashMap<_, _>, _>>())
+            .map_err(|e| format!("Failed to query project library: {}", e))?;
+        Ok(rows)
+    }
+
+    fn read_entry(row: &rusqlite::Row) -> rusqlite::Result<LibraryEntry> {
+        let tags: String = row.get("tags")?;
+        let prompts: String = row.get("prompts")?;
+        let note_count: i64 = row.get("note_count")?;
+
+        Ok(LibraryEntry {
+            path: row.get("path")?,
+            name: row.get("name")?,
+            author: row.get("author")?,
+            description: row.get("description")?,
+            key_signature: row.get("key_signature")?,
+            tempo: row.get("tempo")?,
+            note_count: note_count as usize,
+            tags: serde_json::from_str(&tags).unwrap_or_default(),
+            prompts: serde_json::from_str(&prompts).unwrap_or_default(),
+            created_at: row.get("created_at")?,
+            modified_at: row.get("modified_at")?,
+        })
+    }
+}
+
+/// Modification time (milliseconds since the epoch) and size of a file
+///
+/// Some filesystems only keep mtimes to the second or two, so an edit within
+/// the same tick is told apart by its size.
+fn file_stamp(path: &Path) -> Option<(i64, i64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let since_epoch = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some((i64::try_from(since_epoch.as_millis()).ok()?, i64::try_from(metadata.len()).ok()?))
+}
+
+/// Collect files with a project extension, skipping hidden entries and backup folders
+fn collect_project_files(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || name == BACKUPS_DIR {
+            continue;
+        }
+
+        if path.is_dir() {
+            if depth < MAX_SCAN_DEPTH {
+                collect_project_files(&path, depth + 1, files);
+            }
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| PROJECT_EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(e)))
+        {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn project(name: &str, tempo: u16, tags: &[&str]) -> ProjectData {
+        let mut project = ProjectData {
+            name: name.to_string(),
+            tempo,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            ..ProjectData::default()
+        };
+        project.metadata.tags = tags.iter().map(|t| t.to_string()).collect();
+        project
+    }
+
+    #[test]
+    fn test_index_and_query() {
+        let temp_dir = env::temp_dir().join("piano-app-test-library");
+        fs::create_dir_all(&temp_dir).unwrap();
+        fs::remove_file(temp_dir.join("library.db")).ok();
+        let library = ProjectLibrary::new(temp_dir.clone()).unwrap();
+
+        library.index("/songs/a.json", &project("Blue Night", 90, &["Jazz"])).unwrap();
+        library.index("/songs/b.json", &project("Sunrise", 140, &["pop"])).unwrap();
+
+        let by_tag = library
+            .query(&LibraryQuery { tag: Some("jazz".to_string()), ..LibraryQuery::default() })
+            .unwrap();
+        assert_eq!(by_tag.len(), 1);
+        assert_eq!(by_tag[0].name, "Blue Night");
+
+        let by_tempo = library
+            .query(&LibraryQuery { sort: LibrarySort::Tempo, descending: true, ..LibraryQuery::default() })
+            .unwrap();
+        let names: Vec<&str> = by_tempo.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Sunrise", "Blue Night"]);
+
+        // Re-indexing replaces the entry
+        library.index("/songs/b.json", &project("Sunset", 140, &[])).unwrap();
+        assert_eq!(library.get("/songs/b.json").unwrap().unwrap().name, "Sunset");
+
+        library.remove("/songs/a.json").unwrap();
+        assert!(library.get("/songs/a.json").unwrap().is_none());
+
+        // Cleanup
+        drop(library);
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_refresh_reads_only_changed_files() {
+        let temp_dir = env::temp_dir().join("piano-app-test-library-refresh");
+        fs::remove_dir_all(&temp_dir).ok();
+        let songs = temp_dir.join("songs");
+        fs::create_dir_all(&songs).unwrap();
+        let library = ProjectLibrary::new(temp_dir.clone()).unwrap();
+
+        // Every write gets the same mtime, as on a filesystem with coarse timestamps
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let write = |name: &str, title: &str| {
+            let data = project_file::encode_project(&project(title, 120, &[]), project_file::ProjectFormat::Json).unwrap();
+            fs::write(songs.join(name), data).unwrap();
+            fs::File::options().write(true).open(songs.join(name)).unwrap().set_modified(mtime).unwrap();
+        };
+        write("a.json", "Blue Night");
+        write("b.json", "Sunrise");
+        fs::write(songs.join("broken.json"), "not a project").unwrap();
+
+        assert_eq!(library.refresh(&songs).unwrap(), 2);
+        assert_eq!(library.refresh(&songs).unwrap(), 0);
+
+        // Only the edited file is read again (its size changed); a deleted one is dropped
+        write("a.json", "Blue Morning");
+        fs::remove_file(songs.join("b.json")).unwrap();
+        assert_eq!(library.refresh(&songs).unwrap(), 1);
+        let names: Vec<String> = library.query(&LibraryQuery::default()).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["Blue Morning"]);
+
+        // A full rescan reads everything again
+        assert_eq!(library.rescan(&songs).unwrap(), 1);
+
+        // Cleanup
+        drop(library);
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+
+    #[test]
+    fn test_like_pattern_escapes_wildcards() {
+        assert_eq!(like_pattern("50%_off"), "%50\\%\\_off%");
+    }
+}
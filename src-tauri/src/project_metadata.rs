@@ -0,0 +1,100 @@
+use crate::ai_models::ScaleMode;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+/// Root names accepted in key signatures
+const KEY_ROOTS: [&str; 17] = [
+    "C", "C#", "Db", "D", "D#", "Eb", "E", "F", "F#", "Gb", "G", "G#", "Ab", "A", "A#", "Bb", "B",
+];
+
+/// Organizational metadata of a project
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct ProjectMetadata {
+    #[validate(length(max = 100))]
+    pub author: Option<String>,
+
+    #[validate(length(max = 2000))]
+    pub description: Option<String>,
+
+    /// Key signature, e.g. "C major", "F# minor" or "D harmonic minor"
+    #[validate(custom(function = "validate_key_signature"))]
+    pub key_signature: Option<String>,
+
+    /// Genre/mood tags (at most 20, each up to 32 characters)
+    #[serde(default)]
+    #[validate(length(max = 20), custom(function = "validate_tags"))]
+    pub tags: Vec<String>,
+
+    /// Last save time (RFC 3339), set by the backend on save
+    pub modified_at: Option<String>,
+}
+
+impl ProjectMetadata {
+    /// Trim text fields, drop empty ones and remove duplicate tags (case-insensitive)
+    pub fn normalize(&mut self) {
+        for field in [&mut self.author, &mut self.description, &mut self.key_signature] {
+            *field = field
+                .take()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty());
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        self.tags = self
+            .tags
+            .iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty() && seen.insert(tag.to_lowercase()))
+            .collect();
+    }
+}
+
+fn validate_key_signature(key: &str) -> Result<(), ValidationError> {
+    let (root, mode) = key.trim().split_once(char::is_whitespace).unwrap_or((key, ""));
+
+    let valid = KEY_ROOTS.iter().any(|r| r.eq_ignore_ascii_case(root)) && mode.parse::<ScaleMode>().is_ok();
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("key_signature must look like \"C major\", \"F# minor\" or \"D dorian\""))
+    }
+}
+
+fn validate_tags(tags: &[String]) -> Result<(), ValidationError> {
+    if tags.iter().any(|tag| tag.chars().count() > 32) {
+        return Err(ValidationError::new("tags must be at most 32 characters"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_signature_validation() {
+        assert!(validate_key_signature("C major").is_ok());
+        assert!(validate_key_signature("f# Minor").is_ok());
+        assert!(validate_key_signature("Bb minor").is_ok());
+        assert!(validate_key_signature("H major").is_err());
+        assert!(validate_key_signature("C dorian").is_ok());
+        assert!(validate_key_signature("D harmonic minor").is_ok());
+        assert!(validate_key_signature("C bebop").is_err());
+        assert!(validate_key_signature("C").is_err());
+    }
+
+    #[test]
+    fn test_normalize_dedupes_tags() {
+        let mut metadata = ProjectMetadata {
+            author: Some("  ".to_string()),
+            tags: vec!["Jazz ".to_string(), "jazz".to_string(), "".to_string(), "lofi".to_string()],
+            ..ProjectMetadata::default()
+        };
+        metadata.normalize();
+
+        assert_eq!(metadata.author, None);
+        assert_eq!(metadata.tags, vec!["Jazz".to_string(), "lofi".to_string()]);
+        assert!(metadata.validate().is_ok());
+    }
+}
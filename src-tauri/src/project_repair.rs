@@ -0,0 +1,267 @@
+use crate::ProjectData;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Highest valid MIDI pitch/velocity
+const MIDI_MAX: u8 = 127;
+
+/// Notes of the same pitch and track starting closer than this (in beats) are duplicates
+const DUPLICATE_EPSILON: f32 = 1e-3;
+
+/// Kind of problem found in a project file
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    PitchOutOfRange,
+    VelocityOutOfRange,
+    NegativeStart,
+    InvalidTiming,
+    OrphanTrack,
+    DuplicateId,
+    DuplicateNote,
+}
+
+/// A problem found in a project, and what repair did about it
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub note_id: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Describe `problem`, followed by the `fix` only if it was applied
+    fn new(kind: DiagnosticKind, note_id: &str, problem: String, fix: &str, repaired: bool) -> Self {
+        Self {
+            kind,
+            note_id: note_id.to_string(),
+            message: if repaired { format!("{}; {}", problem, fix) } else { problem },
+        }
+    }
+}
+
+/// Check the notes of a project, fixing them in place when `repair` is set
+///
+/// - Pitch above 127 is clamped; velocity is clamped to 1-127
+/// - Negative start times are moved to 0
+/// - Notes with non-finite times or a non-positive duration are removed
+/// - Notes on unknown tracks are moved to the first track (legacy notes
+///   without a track are left alone)
+/// - Duplicate ids get fresh ones; exact duplicates (same track, pitch and
+///   start) are merged, keeping the longest
+pub fn check(project: &mut ProjectData, repair: bool) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let first_track = project.tracks.first().map(|track| track.id.clone());
+    let track_ids: HashSet<String> = project.tracks.iter().map(|track| track.id.clone()).collect();
+
+    let mut keep = vec![true; project.notes.len()];
+    let mut seen_ids = HashSet::new();
+
+    for (index, note) in project.notes.iter_mut().enumerate() {
+        if !note.start_time.is_finite() || !note.duration.is_finite() || note.duration <= 0.0 {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::InvalidTiming,
+                &note.id,
+                format!("Invalid timing (start {}, duration {})", note.start_time, note.duration),
+                "note removed",
+                repair,
+            ));
+            keep[index] = false;
+            continue;
+        }
+
+        if note.pitch > MIDI_MAX {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::PitchOutOfRange,
+                &note.id,
+                format!("Pitch {} is out of range (0-{})", note.pitch, MIDI_MAX),
+                "clamped",
+                repair,
+            ));
+            if repair {
+                note.pitch = MIDI_MAX;
+            }
+        }
+
+        if note.velocity == 0 || note.velocity > MIDI_MAX {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::VelocityOutOfRange,
+                &note.id,
+                format!("Velocity {} is out of range (1-{})", note.velocity, MIDI_MAX),
+                "clamped",
+                repair,
+            ));
+            if repair {
+                note.velocity = note.velocity.clamp(1, MIDI_MAX);
+            }
+        }
+
+        if note.start_time < 0.0 {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::NegativeStart,
+                &note.id,
+                format!("Start time {} is negative", note.start_time),
+                "moved to 0",
+                repair,
+            ));
+            if repair {
+                note.start_time = 0.0;
+            }
+        }
+
+        if let Some(first_track) = &first_track {
+            if !note.track_id.is_empty() && !track_ids.contains(&note.track_id) {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::OrphanTrack,
+                    &note.id,
+                    format!("Unknown track '{}'", note.track_id),
+                    &format!("moved to '{}'", first_track),
+                    repair,
+                ));
+                if repair {
+                    note.track_id = first_track.clone();
+                }
+            }
+        }
+
+        if !seen_ids.insert(note.id.clone()) {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::DuplicateId,
+                &note.id,
+                "Duplicate note id".to_string(),
+                "a new id was assigned",
+                repair,
+            ));
+            if repair {
+                note.id = uuid::Uuid::new_v4().to_string();
+                seen_ids.insert(note.id.clone());
+            }
+        }
+    }
+
+    // Exact duplicates: group by (track, pitch), sorted by start
+    let mut order: Vec<usize> = (0..project.notes.len()).filter(|&i| keep[i]).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&project.notes[a], &project.notes[b]);
+        (&a.track_id, a.pitch)
+            .cmp(&(&b.track_id, b.pitch))
+            .then(a.start_time.total_cmp(&b.start_time))
+    });
+
+    for pair in order.windows(2) {
+        let (previous, current) = (pair[0], pair[1]);
+        let (a, b) = (&project.notes[previous], &project.notes[current]);
+        if a.track_id != b.track_id || a.pitch != b.pitch || (b.start_time - a.start_time).abs() > DUPLICATE_EPSILON {
+            continue;
+        }
+
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::DuplicateNote,
+            &b.id,
+            format!("Duplicate of note {} at beat {}", a.id, a.start_time),
+            "merged",
+            repair,
+        ));
+        if repair {
+            // Carry the longer duration forward so a chain of duplicates keeps the longest
+            let longest = a.duration.max(b.duration);
+            project.notes[current].duration = longest;
+            keep[previous] = false;
+        }
+    }
+
+    if repair {
+        let mut index = 0;
+        project.notes.retain(|_| {
+            index += 1;
+            keep[index - 1]
+        });
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Note, Track};
+
+    fn note(id: &str, pitch: u8, start_time: f32, duration: f32, track_id: &str) -> Note {
+        Note {
+            id: id.to_string(),
+            pitch,
+            start_time,
+            duration,
+            velocity: 100,
+            track_id: track_id.to_string(),
+        }
+    }
+
+    fn project(notes: Vec<Note>) -> ProjectData {
+        ProjectData {
+            notes,
+            tracks: vec![Track {
+                id: "t1".to_string(),
+                name: "Piano".to_string(),
+                volume: 1.0,
+                pan: 0.0,
+                muted: false,
+                solo: false,
+            }],
+            ..ProjectData::default()
+        }
+    }
+
+    #[test]
+    fn test_repairs_invalid_notes() {
+        let mut project = project(vec![
+            note("a", 200, -1.0, 1.0, "t1"),
+            note("b", 60, 0.0, f32::NAN, "t1"),
+            note("c", 62, 2.0, 1.0, "gone"),
+            note("c", 64, 3.0, 1.0, "t1"),
+        ]);
+
+        let diagnostics = check(&mut project, true);
+        let kinds: Vec<DiagnosticKind> = diagnostics.iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::PitchOutOfRange,
+                DiagnosticKind::NegativeStart,
+                DiagnosticKind::InvalidTiming,
+                DiagnosticKind::OrphanTrack,
+                DiagnosticKind::DuplicateId,
+            ]
+        );
+        assert_eq!(diagnostics[0].message, "Pitch 200 is out of range (0-127); clamped");
+
+        assert_eq!(project.notes.len(), 3);
+        assert_eq!(project.notes[0].pitch, 127);
+        assert_eq!(project.notes[0].start_time, 0.0);
+        assert_eq!(project.notes[1].track_id, "t1");
+        assert_ne!(project.notes[2].id, "c");
+
+        // A repaired project is clean
+        assert!(check(&mut project, true).is_empty());
+    }
+
+    #[test]
+    fn test_merges_duplicates_keeping_longest() {
+        let mut project = project(vec![
+            note("a", 60, 1.0, 0.5, "t1"),
+            note("b", 60, 1.0, 2.0, "t1"),
+            note("c", 60, 1.0, 1.0, "t1"),
+            note("d", 60, 1.0, 1.0, ""),
+        ]);
+
+        let diagnostics = check(&mut project, false);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].message, "Duplicate of note a at beat 1"); // No fix claimed without repair
+        assert_eq!(project.notes.len(), 4); // Untouched without repair
+
+        check(&mut project, true);
+        assert_eq!(project.notes.len(), 2);
+        let merged = project.notes.iter().find(|n| n.track_id == "t1").unwrap();
+        assert_eq!(merged.duration, 2.0);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::project_library::LibraryEntry;
+use serde::Serialize;
+
+/// Maximum number of results returned by a search
+pub const MAX_RESULTS: usize = 50;
+
+/// Weight of a term matching each field; names count most, prompts least
+const NAME_WEIGHT: f32 = 10.0;
+const TAG_WEIGHT: f32 = 6.0;
+const KEY_WEIGHT: f32 = 4.0;
+const AUTHOR_WEIGHT: f32 = 3.0;
+const DESCRIPTION_WEIGHT: f32 = 3.0;
+const PROMPT_WEIGHT: f32 = 2.0;
+
+/// A project matching a search, best first
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub entry: LibraryEntry,
+    pub score: f32,
+    /// Fields that matched ("name", "tags", "key", "author", "description", "prompts")
+    pub matched: Vec<&'static str>,
+}
+
+/// Score how well `text` matches `term` (both lowercase): whole word 1.0, word prefix 0.75, substring 0.5
+fn match_strength(text: &str, term: &str) -> f32 {
+    let words = text.split(|c: char| !c.is_alphanumeric() && c != '#');
+    let mut best: f32 = 0.0;
+
+    for word in words {
+        if word == term {
+            return 1.0;
+        }
+        if word.starts_with(term) {
+            best = best.max(0.75);
+        }
+    }
+
+    if best == 0.0 && text.contains(term) {
+        best = 0.5;
+    }
+    best
+}
+
+/// Score a project against lowercase search terms
+///
+/// Every term must match some field; the score sums the best weighted match
+/// of each term, so projects matching in their name rank above those that
+/// only mention the words in an old AI prompt.
+pub fn score(entry: &LibraryEntry, terms: &[String]) -> Option<SearchResult> {
+    let name = entry.name.to_lowercase();
+    let tags = entry.tags.join(" ").to_lowercase();
+    let key = entry.key_signature.clone().unwrap_or_default().to_lowercase();
+    let author = entry.author.clone().unwrap_or_default().to_lowercase();
+    let description = entry.description.clone().unwrap_or_default().to_lowercase();
+    let prompts = entry.prompts.join("\n").to_lowercase();
+
+    let fields: [(&'static str, &str, f32); 6] = [
+        ("name", &name, NAME_WEIGHT),
+        ("tags", &tags, TAG_WEIGHT),
+        ("key", &key, KEY_WEIGHT),
+        ("author", &author, AUTHOR_WEIGHT),
+        ("description", &description, DESCRIPTION_WEIGHT),
+        ("prompts", &prompts, PROMPT_WEIGHT),
+    ];
+
+    let mut total = 0.0;
+    let mut matched = Vec::new();
+
+    for term in terms {
+        let mut best = 0.0;
+        for (field, text, weight) in fields {
+            let strength = match_strength(text, term);
+            if strength > 0.0 && !matched.contains(&field) {
+                matched.push(field);
+            }
+            best = f32::max(best, strength * weight);
+        }
+
+        if best == 0.0 {
+            return None;
+        }
+        total += best;
+    }
+
+    Some(SearchResult {
+        entry: entry.clone(),
+        score: total,
+        matched,
+    })
+}
+
+/// Split a query into lowercase terms
+pub fn terms(query: &str) -> Vec<String> {
+    query.split_whitespace().map(|t| t.to_lowercase()).collect()
+}
+
+/// Rank entries against a query, best first (newest first on ties)
+pub fn search(entries: &[LibraryEntry], query: &str) -> Vec<SearchResult> {
+    let terms = terms(query);
+    if terms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut results: Vec<SearchResult> = entries.iter().filter_map(|e| score(e, &terms)).collect();
+    results.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.entry.modified_at.cmp(&a.entry.modified_at))
+    });
+    results.truncate(MAX_RESULTS);
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, tags: &[&str], prompts: &[&str]) -> LibraryEntry {
+        LibraryEntry {
+            path: format!("/songs/{}.json", name),
+            name: name.to_string(),
+            author: None,
+            description: None,
+            key_signature: None,
+            tempo: 120,
+            note_count: 0,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            prompts: prompts.iter().map(|p| p.to_string()).collect(),
+            created_at: String::new(),
+            modified_at: None,
+        }
+    }
+
+    #[test]
+    fn test_match_strength() {
+        assert_eq!(match_strength("blue night", "night"), 1.0);
+        assert_eq!(match_strength("blue nightfall", "night"), 0.75);
+        assert_eq!(match_strength("midnight", "night"), 0.5);
+        assert_eq!(match_strength("sunrise", "night"), 0.0);
+    }
+
+    #[test]
+    fn test_search_ranks_name_above_prompt() {
+        let entries = vec![
+            entry("Idea 3", &[], &["a jazzy walking bass line"]),
+            entry("Jazzy Waltz", &[], &[]),
+            entry("Sunrise", &["pop"], &[]),
+        ];
+
+        let results = search(&entries, "Jazzy");
+        let names: Vec<&str> = results.iter().map(|r| r.entry.name.as_str()).collect();
+        assert_eq!(names, vec!["Jazzy Waltz", "Idea 3"]);
+        assert_eq!(results[1].matched, vec!["prompts"]);
+
+        // Every term has to match
+        assert!(search(&entries, "jazzy pop").is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Emitted when the open project changes on disk without going through the app
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ProjectChangeEvent {
+    pub path: String,
+    pub deleted: bool,
+}
+
+/// Contents we last saw for the watched file, to tell real changes from our own saves
+struct WatchedFile {
+    path: PathBuf,
+    known_hash: Option<u64>,
+}
+
+impl WatchedFile {
+    /// Record new file contents; returns the event to emit if they differ from the known ones
+    fn update(&mut self, contents: Option<&[u8]>) -> Option<ProjectChangeEvent> {
+        let hash = contents.map(content_hash);
+        if hash == self.known_hash {
+            return None;
+        }
+
+        self.known_hash = hash;
+        Some(ProjectChangeEvent {
+            path: self.path.to_string_lossy().to_string(),
+            deleted: hash.is_none(),
+        })
+    }
+}
+
+fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Watches the currently open project file for external changes (cloud sync, another instance)
+///
+/// The parent folder is watched rather than the file, since atomic saves
+/// replace the file. Events are only reported when the contents differ from
+/// what the app itself last loaded or saved.
+pub struct ProjectWatcher {
+    watched: Arc<Mutex<Option<WatchedFile>>>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl ProjectWatcher {
+    pub fn new() -> Self {
+        Self {
+            watched: Arc::new(Mutex::new(None)),
+            watcher: Mutex::new(None),
+        }
+    }
+
+    /// Start watching `path`, replacing any previous watch
+    pub fn watch<F>(&self, path: &Path, on_change: F) -> Result<(), String>
+    where
+        F: Fn(ProjectChangeEvent) + Send + 'static,
+    {
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("Cannot watch {}: {}", path.display(), e))?;
+        let folder = path.parent().ok_or("Project path has no parent directory")?.to_path_buf();
+
+        *self.watched.lock().unwrap() = Some(WatchedFile {
+            known_hash: fs::read(&path).ok().as_deref().map(content_hash),
+            path: path.clone(),
+        });
+
+        let watched = self.watched.clone();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+            let Ok(event) = result else { return };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+            if !event.paths.iter().any(|p| p.file_name() == path.file_name()) {
+                return;
+            }
+
+            let mut watched = watched.lock().unwrap();
+            let Some(file) = watched.as_mut().filter(|file| file.path == path) else { return };
+            if let Some(change) = file.update(fs::read(&path).ok().as_deref()) {
+                on_change(change);
+            }
+        })
+        .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+
+        watcher
+            .watch(&folder, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {}", folder.display(), e))?;
+
+        *self.watcher.lock().unwrap() = Some(watcher);
+        Ok(())
+    }
+
+    /// Stop watching
+    pub fn unwatch(&self) {
+        *self.watcher.lock().unwrap() = None;
+        *self.watched.lock().unwrap() = None;
+    }
+
+    /// Note contents the app is about to write to `path`, so the save isn't reported as external
+    pub fn expect_write(&self, path: &Path, data: &[u8]) {
+        let Ok(path) = path.canonicalize() else { return };
+        if let Some(file) = self.watched.lock().unwrap().as_mut().filter(|file| file.path == path) {
+            file.known_hash = Some(content_hash(data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_new_contents_are_reported() {
+        let mut file = WatchedFile {
+            path: PathBuf::from("/songs/song.json"),
+            known_hash: Some(content_hash(b"v1")),
+        };
+
+        assert_eq!(file.update(Some(b"v1")), None);
+        assert_eq!(
+            file.update(Some(b"v2")),
+            Some(ProjectChangeEvent { path: "/songs/song.json".to_string(), deleted: false })
+        );
+        // Several events for the same write are reported once
+        assert_eq!(file.update(Some(b"v2")), None);
+        assert!(file.update(None).unwrap().deleted);
+    }
+}
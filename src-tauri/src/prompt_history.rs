@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Distinct prompts kept; beyond this the least used (then oldest) are dropped
+pub const MAX_PROMPTS: usize = 300;
+
+/// Suggestions returned when the caller doesn't give a limit
+pub const DEFAULT_SUGGESTIONS: usize = 8;
+
+/// A prompt the user has generated with, and how often
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PromptEntry {
+    prompt: String,
+    count: u32,
+    /// Last use (RFC 3339)
+    last_used: String,
+}
+
+/// Prompts the user has used before, for autocomplete, persisted in the app data directory
+pub struct PromptHistory {
+    storage_path: PathBuf,
+    entries: Mutex<Vec<PromptEntry>>,
+}
+
+/// Form prompts are compared in: lowercase, with whitespace collapsed
+fn normalize(prompt: &str) -> String {
+    prompt.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+impl PromptHistory {
+    /// Load the saved history
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let storage_path = app_data_dir.join("prompt_history.json");
+
+        let entries = if storage_path.exists() {
+            let data = fs::read_to_string(&storage_path)
+                .map_err(|e| format!("Failed to read prompt history: {}", e))?;
+            serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse prompt history: {}", e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            storage_path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    fn save(&self, entries: &[PromptEntry]) -> Result<(), String> {
+        let data = serde_json::to_string(entries)
+            .map_err(|e| format!("Failed to serialize prompt history: {}", e))?;
+        fs::write(&self.storage_path, data)
+            .map_err(|e| format!("Failed to write prompt history: {}", e))
+    }
+
+    /// Count a use of `prompt`
+    ///
+    /// Prompts differing only in case or spacing are one entry, shown as last typed.
+    pub fn record(&self, prompt: &str) -> Result<(), String> {
+        let prompt = prompt.split_whitespace().collect::<Vec<_>>().join(" ");
+        if prompt.is_empty() {
+            return Ok(());
+        }
+        let key = normalize(&prompt);
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let mut entries = self.entries.lock().unwrap();
+        match entries.iter_mut().find(|entry| normalize(&entry.prompt) == key) {
+            Some(entry) => {
+                entry.prompt = prompt;
+                entry.count += 1;
+                entry.last_used = now;
+            }
+            None => entries.push(PromptEntry { prompt, count: 1, last_used: now }),
+        }
+
+        if entries.len() > MAX_PROMPTS {
+            entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| b.last_used.cmp(&a.last_used)));
+            entries.truncate(MAX_PROMPTS);
+        }
+        self.save(&entries)
+    }
+
+    /// Up to `limit` past prompts starting with `prefix` (ignoring case and spacing),
+    /// most used first, then most recent
+    ///
+    /// Prompts with a word starting with `prefix` follow those that start with it.
+    pub fn suggestions(&self, prefix: &str, limit: usize) -> Vec<String> {
+        let prefix = normalize(prefix);
+        let entries = self.entries.lock().unwrap();
+
+        let mut matches: Vec<(bool, &PromptEntry)> = entries
+            .iter()
+            .filter_map(|entry| {
+                let prompt = normalize(&entry.prompt);
+                if prompt.starts_with(&prefix) {
+                    Some((true, entry))
+                } else if prompt.split(' ').any(|word| word.starts_with(&prefix)) {
+                    Some((false, entry))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        matches.sort_by(|(a_start, a), (b_start, b)| {
+            b_start
+                .cmp(a_start)
+                .then_with(|| b.count.cmp(&a.count))
+                .then_with(|| b.last_used.cmp(&a.last_used))
+        });
+        matches.into_iter().take(limit).map(|(_, entry)| entry.prompt.clone()).collect()
+    }
+
+    /// Forget every prompt
+    pub fn clear(&self) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.save(&entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_suggestions_ranked_by_use() {
+        let temp_dir = env::temp_dir().join("piano-app-test-prompt-history");
+        fs::create_dir_all(&temp_dir).unwrap();
+        fs::remove_file(temp_dir.join("prompt_history.json")).ok();
+
+        let history = PromptHistory::new(temp_dir.clone()).unwrap();
+        history.record("Jazzy piano riff").unwrap();
+        history.record("jazz waltz in 3/4").unwrap();
+        history.record("jazz  WALTZ in 3/4").unwrap();
+        history.record("A slow jazz ballad").unwrap();
+        history.record("   ").unwrap();
+
+        let reloaded = PromptHistory::new(temp_dir.clone()).unwrap();
+        assert_eq!(
+            reloaded.suggestions("Jaz", DEFAULT_SUGGESTIONS),
+            vec!["jazz WALTZ in 3/4", "Jazzy piano riff", "A slow jazz ballad"]
+        );
+        assert_eq!(reloaded.suggestions("jazz", 1), vec!["jazz WALTZ in 3/4"]);
+        assert!(reloaded.suggestions("blues", DEFAULT_SUGGESTIONS).is_empty());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use validator::{Validate, ValidationError};
+
+/// Placeholders a template may use, written `{genre}`, `{mood}` and `{tempo}`
+pub const PLACEHOLDERS: &[&str] = &["genre", "mood", "tempo"];
+
+/// Tempos (BPM) a `{tempo}` value may have
+const TEMPO_RANGE: std::ops::RangeInclusive<u16> = 20..=300;
+
+/// A named generation prompt with placeholders filled in when it's used
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+pub struct PromptTemplate {
+    pub id: String,
+
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+
+    /// The prompt, e.g. "A {mood} {genre} melody at {tempo} BPM"
+    #[validate(length(min = 1, max = 1000), custom(function = "validate_placeholders"))]
+    pub template: String,
+
+    /// Values of placeholders the user doesn't fill in
+    #[serde(default)]
+    pub defaults: HashMap<String, String>,
+
+    /// Shipped with the app; can be used but not changed or deleted
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+/// Names of the `{placeholders}` in a template, in order
+fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let after = &rest[open + 1..];
+        let close = after
+            .find('}')
+            .ok_or_else(|| "A placeholder is missing its closing }".to_string())?;
+        names.push(&after[..close]);
+        rest = &after[close + 1..];
+    }
+    if rest.contains('}') {
+        return Err("A } has no matching {".to_string());
+    }
+    Ok(names)
+}
+
+fn validate_placeholders(template: &str) -> Result<(), ValidationError> {
+    let names = placeholders(template).map_err(|_| ValidationError::new("unbalanced_placeholder"))?;
+    if names.iter().any(|name| !PLACEHOLDERS.contains(name)) {
+        return Err(ValidationError::new("unknown_placeholder"));
+    }
+    Ok(())
+}
+
+impl PromptTemplate {
+    /// The prompt with its placeholders replaced by `values`, or the template's defaults
+    pub fn render(&self, values: &HashMap<String, String>) -> Result<String, String> {
+        let mut prompt = self.template.clone();
+        for name in placeholders(&self.template)? {
+            let value = values
+                .get(name)
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .or_else(|| self.defaults.get(name).map(String::as_str))
+                .ok_or_else(|| format!("No value for {{{}}} in \"{}\"", name, self.name))?;
+            if name == "tempo" && !value.parse().is_ok_and(|bpm: u16| TEMPO_RANGE.contains(&bpm)) {
+                return Err(format!(
+                    "Tempo must be {} to {} BPM, not \"{}\"",
+                    TEMPO_RANGE.start(),
+                    TEMPO_RANGE.end(),
+                    value
+                ));
+            }
+            prompt = prompt.replace(&format!("{{{}}}", name), value);
+        }
+        Ok(prompt)
+    }
+}
+
+fn builtin(id: &str, name: &str, template: &str, defaults: [(&str, &str); 3]) -> PromptTemplate {
+    PromptTemplate {
+        id: id.to_string(),
+        name: name.to_string(),
+        template: template.to_string(),
+        defaults: defaults.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        builtin: true,
+    }
+}
+
+/// Presets shipped with the app
+pub fn builtin_templates() -> Vec<PromptTemplate> {
+    vec![
+        builtin(
+            "builtin-film-theme",
+            "Film theme",
+            "A sweeping, {mood} film theme in {genre} style at {tempo} BPM that builds to an emotional climax",
+            [("mood", "heroic"), ("genre", "orchestral"), ("tempo", "90")],
+        ),
+        builtin(
+            "builtin-jazz-ballad",
+            "Jazz ballad",
+            "A {mood} {genre} ballad melody at {tempo} BPM with swung eighths and bluesy passing tones",
+            [("mood", "melancholic"), ("genre", "jazz"), ("tempo", "70")],
+        ),
+        builtin(
+            "builtin-pop-hook",
+            "Pop hook",
+            "A catchy, {mood} {genre} hook at {tempo} BPM built on a short repeated motif",
+            [("mood", "upbeat"), ("genre", "pop"), ("tempo", "120")],
+        ),
+        builtin(
+            "builtin-lullaby",
+            "Lullaby",
+            "A {mood}, gentle lullaby at {tempo} BPM with a simple, singable {genre} melody",
+            [("mood", "calm"), ("genre", "folk"), ("tempo", "60")],
+        ),
+    ]
+}
+
+/// Prompt templates: the shipped presets plus the user's own, persisted in the app data directory
+pub struct PromptTemplates {
+    storage_path: PathBuf,
+    templates: Mutex<Vec<PromptTemplate>>,
+}
+
+impl PromptTemplates {
+    /// Load the user's saved templates
+    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+        let storage_path = app_data_dir.join("prompt_templates.json");
+
+        let templates = if storage_path.exists() {
+            let data = fs::read_to_string(&storage_path)
+                .map_err(|e| format!("Failed to read prompt templates: {}", e))?;
+            serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse prompt templates: {}", e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            storage_path,
+            templates: Mutex::new(templates),
+        })
+    }
+
+    fn save(&self, templates: &[PromptTemplate]) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(templates)
+            .map_err(|e| format!("Failed to serialize prompt templates: {}", e))?;
+        fs::write(&self.storage_path, data)
+            .map_err(|e| format!("Failed to write prompt templates: {}", e))
+    }
+
+    /// Every template, presets first
+    pub fn list(&self) -> Vec<PromptTemplate> {
+        let mut templates = builtin_templates();
+        templates.extend(self.templates.lock().unwrap().iter().cloned());
+        templates
+    }
+
+    pub fn get(&self, id: &str) -> Option<PromptTemplate> {
+        self.list().into_iter().find(|template| template.id == id)
+    }
+
+    /// Save a new template of the user's
+    pub fn create(&self, name: String, template: String, defaults: HashMap<String, String>) -> Result<PromptTemplate, String> {
+        let template = PromptTemplate {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.trim().to_string(),
+            template: template.trim().to_string(),
+            defaults,
+            builtin: false,
+        };
+        template.validate()
+            .map_err(|e| format!("Invalid prompt template: {}", e))?;
+
+        let mut templates = self.templates.lock().unwrap();
+        templates.push(template.clone());
+        self.save(&templates)?;
+        Ok(template)
+    }
+
+    /// Replace one of the user's templates (presets can't be changed)
+    pub fn update(&self, template: PromptTemplate) -> Result<(), String> {
+        template.validate()
+            .map_err(|e| format!("Invalid prompt template: {}", e))?;
+
+        let mut templates = self.templates.lock().unwrap();
+        let existing = templates
+            .iter_mut()
+            .find(|existing| existing.id == template.id)
+            .ok_or_else(|| format!("No editable prompt template with id {}", template.id))?;
+        *existing = PromptTemplate { builtin: false, ..template };
+        self.save(&templates)
+    }
+
+    /// Delete one of the user's templates (presets can't be deleted)
+    pub fn delete(&self, id: &str) -> Result<(), String> {
+        let mut templates = self.templates.lock().unwrap();
+        let count = templates.len();
+        templates.retain(|template| template.id != id);
+        if templates.len() == count {
+            return Err(format!("No deletable prompt template with id {}", id));
+        }
+        self.save(&templates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        let lullaby = builtin_templates().into_iter().find(|t| t.id == "builtin-lullaby").unwrap();
+        assert_eq!(
+            lullaby.render(&HashMap::new()).unwrap(),
+            "A calm, gentle lullaby at 60 BPM with a simple, singable folk melody"
+        );
+
+        let values = HashMap::from([("genre".to_string(), "Celtic".to_string()), ("tempo".to_string(), "72".to_string())]);
+        assert!(lullaby.render(&values).unwrap().ends_with("at 72 BPM with a simple, singable Celtic melody"));
+
+        let values = HashMap::from([("tempo".to_string(), "fast".to_string())]);
+        assert!(lullaby.render(&values).unwrap_err().contains("Tempo must be"));
+
+        for template in builtin_templates() {
+            assert!(template.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_user_templates_persist() {
+        let temp_dir = env::temp_dir().join("piano-app-test-prompt-templates");
+        fs::create_dir_all(&temp_dir).unwrap();
+        fs::remove_file(temp_dir.join("prompt_templates.json")).ok();
+
+        let store = PromptTemplates::new(temp_dir.clone()).unwrap();
+        let created = store
+            .create("Chase".to_string(), "A {mood} chase at {tempo} BPM".to_string(), HashMap::new())
+            .unwrap();
+        assert!(store.create("Bad".to_string(), "A {key} melody".to_string(), HashMap::new()).is_err());
+        assert!(store.create("Bad".to_string(), "A {mood melody".to_string(), HashMap::new()).is_err());
+        assert!(created
+            .render(&HashMap::new())
+            .unwrap_err()
+            .contains("No value for {mood}"));
+
+        let reloaded = PromptTemplates::new(temp_dir.clone()).unwrap();
+        assert_eq!(reloaded.list().len(), builtin_templates().len() + 1);
+        reloaded
+            .update(PromptTemplate { name: "Car chase".to_string(), ..created.clone() })
+            .unwrap();
+        assert_eq!(reloaded.get(&created.id).unwrap().name, "Car chase");
+
+        assert!(reloaded.delete("builtin-lullaby").is_err());
+        reloaded.delete(&created.id).unwrap();
+        assert!(reloaded.get(&created.id).is_none());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).ok();
+    }
+}
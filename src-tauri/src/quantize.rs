@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Grid quantization settings
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Validate)]
+pub struct QuantizeSettings {
+    /// Grid resolution (4 = quarter notes, 8 = eighth notes, 16 = sixteenths, ...)
+    #[validate(range(min = 1, max = 64))]
+    pub grid_division: u32,
+
+    /// How far notes move towards the grid (0.0 = untouched, 1.0 = fully snapped)
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub strength: f64,
+
+    /// Delay of every second grid line (0.0 = straight, 1.0 = full triplet swing)
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub swing: f64,
+}
+
+impl QuantizeSettings {
+    /// Grid step in beats
+    pub fn step(&self) -> f64 {
+        4.0 / self.grid_division as f64
+    }
+
+    /// Position of the `index`-th grid line, with swing applied to odd lines
+    ///
+    /// Full swing moves the off-beat from 1/2 to 2/3 of a pair of steps, i.e. a
+    /// delay of one third of a step.
+    fn grid_line(&self, index: i64) -> f64 {
+        let step = self.step();
+        let swing_offset = if index % 2 != 0 { self.swing * step / 3.0 } else { 0.0 };
+        index as f64 * step + swing_offset
+    }
+
+    /// Quantize a time in beats
+    pub fn quantize(&self, time: f64) -> f64 {
+        let base = (time / self.step()).floor() as i64;
+
+        // With swing the nearest line isn't always the rounded one, so compare neighbours
+        let target = (base - 1..=base + 1)
+            .map(|index| self.grid_line(index))
+            .filter(|&line| line >= 0.0)
+            .min_by(|a, b| (a - time).abs().partial_cmp(&(b - time).abs()).unwrap())
+            .unwrap_or(0.0);
+
+        (target * self.strength + time * (1.0 - self.strength)).max(0.0)
+    }
+}
+
+/// Timing and dynamics of a performance relative to the grid, to apply to other material
+///
+/// Holds one entry per grid step of a measure: how early or late notes on
+/// that step were played, and how loud compared to the rest.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct GrooveTemplate {
+    /// Grid resolution the template was measured on (16 = sixteenths)
+    #[validate(range(min = 1, max = 64))]
+    pub grid_division: u32,
+    /// Timing of each step as a fraction of a step (positive = late)
+    #[validate(length(min = 1, max = 256))]
+    pub offsets: Vec<f64>,
+    /// Velocity of each step relative to the performance's average (1.0 = average)
+    #[validate(length(min = 1, max = 256))]
+    pub accents: Vec<f64>,
+}
+
+impl GrooveTemplate {
+    fn step(&self) -> f64 {
+        4.0 / self.grid_division as f64
+    }
+
+    /// Measure the groove of notes given as (start in beats, velocity)
+    ///
+    /// Each note counts towards the grid step nearest to it. Steps nobody
+    /// played are straight and at average velocity. None without notes.
+    pub fn extract(notes: &[(f64, u8)], grid_division: u32, beats_per_measure: f64) -> Option<Self> {
+        if notes.is_empty() {
+            return None;
+        }
+        let step = 4.0 / grid_division as f64;
+        let steps = ((beats_per_measure / step).round() as usize).max(1);
+        let average = notes.iter().map(|&(_, velocity)| velocity as f64).sum::<f64>() / notes.len() as f64;
+
+        // (offset sum, velocity sum, count) per step
+        let mut sums = vec![(0.0, 0.0, 0usize); steps];
+        for &(start, velocity) in notes {
+            let index = (start / step).round();
+            let slot = &mut sums[index as usize % steps];
+            slot.0 += (start - index * step) / step;
+            slot.1 += velocity as f64;
+            slot.2 += 1;
+        }
+
+        let mean = |sum: f64, count: usize, empty: f64| if count == 0 { empty } else { sum / count as f64 };
+        Some(Self {
+            grid_division,
+            offsets: sums.iter().map(|&(offset, _, count)| mean(offset, count, 0.0)).collect(),
+            accents: sums
+                .iter()
+                .map(|&(_, velocity, count)| mean(velocity, count, average) / average.max(1.0))
+                .collect(),
+        })
+    }
+
+    /// Move a note onto the template's grid and timing, and scale its velocity
+    ///
+    /// `strength` blends from the note as played (0.0) to the full groove (1.0).
+    pub fn apply(&self, start: f64, velocity: u8, strength: f64) -> (f64, u8) {
+        let step = self.step();
+        let index = (start / step).round();
+        let slot = index as usize % self.offsets.len();
+        let target = (index + self.offsets[slot]) * step;
+        let accent = self.accents.get(slot).copied().unwrap_or(1.0);
+
+        let start = (start + (target - start) * strength).max(0.0);
+        let velocity = (velocity as f64 * (1.0 + (accent - 1.0) * strength)).round().clamp(1.0, 127.0);
+        (start, velocity as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(grid_division: u32, strength: f64, swing: f64) -> QuantizeSettings {
+        QuantizeSettings {
+            grid_division,
+            strength,
+            swing,
+        }
+    }
+
+    #[test]
+    fn test_full_strength_snaps_to_grid() {
+        let eighths = settings(8, 1.0, 0.0);
+        assert_eq!(eighths.quantize(0.1), 0.0);
+        assert_eq!(eighths.quantize(0.4), 0.5);
+        assert_eq!(eighths.quantize(1.76), 2.0);
+    }
+
+    #[test]
+    fn test_partial_strength() {
+        let quarters = settings(4, 0.5, 0.0);
+        assert!((quarters.quantize(1.2) - 1.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_swing_delays_off_beats() {
+        let swung = settings(8, 1.0, 1.0);
+        // Off-beat eighth moves from 0.5 to the triplet position 2/3
+        assert!((swung.quantize(0.6) - 2.0 / 3.0).abs() < 1e-9);
+        // Down-beats are unaffected
+        assert_eq!(swung.quantize(1.05), 1.0);
+    }
+
+    #[test]
+    fn test_groove_template() {
+        // Laid-back, accented off-beat eighths: every second eighth 0.05 beats late and louder
+        let played: Vec<(f64, u8)> = (0..16)
+            .map(|i| if i % 2 == 0 { (i as f64 * 0.5, 80) } else { (i as f64 * 0.5 + 0.05, 120) })
+            .collect();
+        let groove = GrooveTemplate::extract(&played, 8, 4.0).unwrap();
+        assert_eq!(groove.offsets.len(), 8);
+        assert!((groove.offsets[1] - 0.1).abs() < 1e-9 && groove.offsets[0].abs() < 1e-9);
+        assert!((groove.accents[1] - 1.2).abs() < 1e-9);
+
+        // Straight material picks up the feel
+        let (start, velocity) = groove.apply(2.5, 100, 1.0);
+        assert!((start - 2.55).abs() < 1e-9);
+        assert_eq!(velocity, 120);
+        assert_eq!(groove.apply(2.5, 100, 0.0), (2.5, 100));
+        assert!(GrooveTemplate::extract(&[], 8, 4.0).is_none());
+    }
+}
@@ -0,0 +1,234 @@
+use crate::quantize::QuantizeSettings;
+use crate::{Note, PedalEvent};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Shortest note a recording can produce, in beats (guards against zero-length taps)
+const MIN_RECORDED_DURATION: f64 = 0.01;
+
+/// Notes and pedal events captured during one recording pass
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RecordedTake {
+    pub notes: Vec<Note>,
+    pub pedal_events: Vec<PedalEvent>,
+}
+
+/// A key that is down and not yet turned into a note
+struct HeldNote {
+    start_time: f64,     // Quantized start (where the note is placed)
+    raw_start_time: f64, // Played start (used for the duration)
+    velocity: u8,
+}
+
+/// Capture state for the current take
+#[derive(Default)]
+struct Take {
+    track_id: String,
+    held: HashMap<u8, HeldNote>, // pitch -> held key
+    notes: Vec<Note>,
+    pedal_events: Vec<PedalEvent>,
+}
+
+/// Captures performance data from MIDI input, timed against the transport
+pub struct Recorder {
+    recording: AtomicBool,
+    started_transport: AtomicBool, // The take started the transport (rather than joining playback)
+    take: Mutex<Take>,
+    quantize: Mutex<Option<QuantizeSettings>>, // Input quantization applied to note starts
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            recording: AtomicBool::new(false),
+            started_transport: AtomicBool::new(false),
+            take: Mutex::new(Take::default()),
+            quantize: Mutex::new(None),
+        }
+    }
+
+    /// Set input quantization for recorded note starts (None records unquantized)
+    pub fn set_quantize(&self, settings: Option<QuantizeSettings>) {
+        *self.quantize.lock().unwrap() = settings;
+    }
+
+    /// Arm recording into the given track, discarding any previous take
+    ///
+    /// `started_transport` tells whether the take started the transport, so
+    /// stopping it can leave playback that was already running alone.
+    pub fn start(&self, track_id: String, started_transport: bool) {
+        *self.take.lock().unwrap() = Take {
+            track_id,
+            ..Take::default()
+        };
+        self.started_transport.store(started_transport, Ordering::SeqCst);
+        self.recording.store(true, Ordering::SeqCst);
+    }
+
+    /// Stop recording at the given beat and return the take, and whether it started the transport
+    ///
+    /// Keys still held when recording stops are closed at `beat`. The flag is
+    /// only true once per take, so a repeated stop can't halt later playback.
+    pub fn stop(&self, beat: f64) -> (RecordedTake, bool) {
+        let was_recording = self.recording.swap(false, Ordering::SeqCst);
+        let started_transport = self.started_transport.swap(false, Ordering::SeqCst) && was_recording;
+
+        let mut take = std::mem::take(&mut *self.take.lock().unwrap());
+        let held: Vec<u8> = take.held.keys().copied().collect();
+        for pitch in held {
+            Self::close_note(&mut take, pitch, beat);
+        }
+
+        take.notes
+            .sort_by(|a, b| a.start_time.partial_cmp(&b.start_time).unwrap_or(std::cmp::Ordering::Equal));
+
+        let take = RecordedTake {
+            notes: take.notes,
+            pedal_events: take.pedal_events,
+        };
+        (take, started_transport)
+    }
+
+    /// Check whether a take is being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::SeqCst)
+    }
+
+    /// Record a key press at the given beat position
+    pub fn note_on(&self, beat: f64, pitch: u8, velocity: u8) {
+        if !self.is_recording() {
+            return;
+        }
+
+        let start_time = match *self.quantize.lock().unwrap() {
+            Some(settings) => settings.quantize(beat),
+            None => beat,
+        };
+
+        let mut take = self.take.lock().unwrap();
+        // A retrigger without note-off ends the previous note first
+        Self::close_note(&mut take, pitch, beat);
+        take.held.insert(
+            pitch,
+            HeldNote {
+                start_time,
+                raw_start_time: beat,
+                velocity,
+            },
+        );
+    }
+
+    /// Record a key release at the given beat position
+    pub fn note_off(&self, beat: f64, pitch: u8) {
+        if !self.is_recording() {
+            return;
+        }
+
+        Self::close_note(&mut self.take.lock().unwrap(), pitch, beat);
+    }
+
+    /// Record a sustain pedal change at the given beat position
+    ///
+    /// Pedals send a stream of CC64 values; only changes of the down/up state
+    /// are kept so the lane stays compact.
+    pub fn record_pedal(&self, beat: f64, down: bool) {
+        if !self.is_recording() {
+            return;
+        }
+
+        let mut take = self.take.lock().unwrap();
+
+        let last_down = take.pedal_events.last().map(|e| e.down).unwrap_or(false);
+        if last_down == down {
+            return;
+        }
+
+        take.pedal_events.push(PedalEvent {
+            time: beat as f32,
+            down,
+        });
+    }
+
+    /// Turn a held key into a finished note released at `beat`
+    ///
+    /// The duration is what was actually played, so quantizing the start
+    /// doesn't stretch or clip the note.
+    fn close_note(take: &mut Take, pitch: u8, beat: f64) {
+        if let Some(held) = take.held.remove(&pitch) {
+            take.notes.push(Note {
+                id: uuid::Uuid::new_v4().to_string(),
+                pitch,
+                start_time: held.start_time as f32,
+                duration: (beat - held.raw_start_time).max(MIN_RECORDED_DURATION) as f32,
+                velocity: held.velocity,
+                track_id: take.track_id.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_notes_into_track() {
+        let recorder = Recorder::new();
+        recorder.start("track_1".to_string(), false);
+
+        recorder.note_on(0.0, 60, 100);
+        recorder.note_on(0.5, 64, 80);
+        recorder.note_off(1.0, 60);
+        recorder.record_pedal(1.0, true);
+        recorder.record_pedal(1.2, true); // Repeated CC value is ignored
+        recorder.record_pedal(2.0, false);
+
+        // 64 is still held and gets closed at the stop position
+        let (take, started_transport) = recorder.stop(2.5);
+
+        assert_eq!(take.notes.len(), 2);
+        assert_eq!(take.notes[0].pitch, 60);
+        assert_eq!(take.notes[0].duration, 1.0);
+        assert_eq!(take.notes[1].pitch, 64);
+        assert_eq!(take.notes[1].duration, 2.0);
+        assert!(take.notes.iter().all(|n| n.track_id == "track_1"));
+        assert_eq!(take.pedal_events.len(), 2);
+
+        // Recording over running playback leaves the transport to the user
+        assert!(!started_transport);
+
+        // A take that started the transport reports it once; a second stop doesn't
+        recorder.start("track_1".to_string(), true);
+        assert!(recorder.stop(1.0).1);
+        assert!(!recorder.stop(1.0).1);
+    }
+
+    #[test]
+    fn test_quantizes_note_starts() {
+        let recorder = Recorder::new();
+        recorder.set_quantize(Some(QuantizeSettings {
+            grid_division: 4,
+            strength: 1.0,
+            swing: 0.0,
+        }));
+        recorder.start("track_1".to_string(), false);
+
+        recorder.note_on(0.9, 60, 100);
+        recorder.note_off(1.9, 60);
+        let (take, _) = recorder.stop(2.0);
+
+        assert_eq!(take.notes[0].start_time, 1.0);
+        assert!((take.notes[0].duration - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ignores_input_when_not_recording() {
+        let recorder = Recorder::new();
+        recorder.note_on(0.0, 60, 100);
+        recorder.note_off(1.0, 60);
+
+        assert!(recorder.stop(1.0).0.notes.is_empty());
+    }
+}
@@ -0,0 +1,781 @@
+use crate::encoders::{AudioFormat, EncodeSettings, DEFAULT_MP3_BITRATE, DEFAULT_OGG_QUALITY};
+use crate::sample_player::{PreparedVoice, SamplePlayer};
+use crate::ai_models::{self, TimeSignature};
+use crate::{Note, PedalEvent, ProjectData, Track};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+use validator::{Validate, ValidationError};
+
+/// Renders are always stereo
+const RENDER_CHANNELS: u16 = 2;
+
+/// Fade applied when a voice is released, in seconds (playback cuts instantly, which clicks in a file)
+const RELEASE_FADE: f64 = 0.01;
+
+/// Fade applied when a fixed tail cuts audio off, in seconds
+const TAIL_FADE: f64 = 0.05;
+
+/// Level below which trailing audio counts as silence for automatic tails (-80 dBFS)
+const SILENCE_THRESHOLD: f32 = 1e-4;
+
+/// Highest true peak loudness normalization may raise the audio to (dBTP)
+///
+/// Piano has a high crest factor, so reaching a typical LUFS target would
+/// otherwise push its attacks past full scale, where they'd be clipped.
+const LOUDNESS_PEAK_CEILING_DB: f64 = -1.0;
+
+/// Output level normalization
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum Normalization {
+    /// Scale so the highest sample peak reaches `target_db` dBFS
+    Peak { target_db: f64 },
+    /// Scale so the integrated loudness (EBU R128) reaches `target_lufs`,
+    /// or less if the true peak would go past `LOUDNESS_PEAK_CEILING_DB`
+    Loudness { target_lufs: f64 },
+}
+
+/// Offline render settings
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RenderOptions {
+    /// Output sample rate in Hz (e.g. 44100, 48000, 96000)
+    #[validate(range(min = 22050, max = 192000))]
+    pub sample_rate: u32,
+
+    /// Sample size for WAV and FLAC: 16 or 24 bits
+    #[validate(custom(function = "validate_bit_depth"))]
+    pub bit_depth: u16,
+
+    /// Output file format (defaults to WAV)
+    #[serde(default)]
+    pub format: AudioFormat,
+
+    /// MP3 bitrate in kbps (defaults to 192)
+    #[validate(range(min = 96, max = 320))]
+    pub bitrate: Option<u32>,
+
+    /// OGG Vorbis quality, 0.0 (smallest) to 1.0 (best), defaults to 0.6
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub quality: Option<f32>,
+
+    /// Level normalization applied after mixing (None keeps the mix level)
+    #[serde(default)]
+    pub normalize: Option<Normalization>,
+
+    /// Add TPDF dither when reducing to integer samples
+    #[serde(default)]
+    pub dither: bool,
+
+    /// Seconds kept after the last note ends; None keeps everything until the
+    /// release and sustain have died away
+    #[validate(range(min = 0.0, max = 60.0))]
+    pub tail: Option<f64>,
+
+    /// Render a seamless loop: audio ringing past the loop end is wrapped
+    /// around onto the loop start (the tail option is ignored)
+    #[serde(default)]
+    pub as_loop: bool,
+
+    /// Loop length in beats (defaults to the project length rounded up to whole bars)
+    #[validate(range(min = 0.25, max = 4096.0))]
+    pub loop_length: Option<f64>,
+}
+
+impl Default for RenderOptions {
+    /// CD quality WAV
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            bit_depth: 16,
+            format: AudioFormat::Wav,
+            bitrate: None,
+            quality: None,
+            normalize: None,
+            dither: false,
+            tail: None,
+            as_loop: false,
+            loop_length: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Encoder settings for these options
+    pub fn encode_settings(&self) -> EncodeSettings {
+        EncodeSettings {
+            format: self.format,
+            bit_depth: self.bit_depth,
+            bitrate: self.bitrate.unwrap_or(DEFAULT_MP3_BITRATE),
+            quality: self.quality.unwrap_or(DEFAULT_OGG_QUALITY),
+            dither: self.dither,
+        }
+    }
+}
+
+fn validate_bit_depth(bit_depth: &u16) -> Result<(), ValidationError> {
+    match bit_depth {
+        16 | 24 => Ok(()),
+        _ => Err(ValidationError::new("bit_depth must be 16 or 24")),
+    }
+}
+
+/// Mixed audio, interleaved float samples
+pub struct RenderedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl RenderedAudio {
+    /// Length in seconds
+    pub fn duration(&self) -> f64 {
+        self.samples.len() as f64 / self.channels as f64 / self.sample_rate as f64
+    }
+}
+
+/// Beat at which a note ending at `end` is actually released, honoring the sustain pedal
+///
+/// `pedal_events` must be sorted by time. Returns infinity if the pedal is
+/// never lifted (the sample rings out).
+fn release_beat(end: f64, pedal_events: &[PedalEvent]) -> f64 {
+    let pedal_down = pedal_events
+        .iter()
+        .rev()
+        .find(|e| e.time as f64 <= end)
+        .map(|e| e.down)
+        .unwrap_or(false);
+
+    if !pedal_down {
+        return end;
+    }
+
+    pedal_events
+        .iter()
+        .find(|e| e.time as f64 > end && !e.down)
+        .map(|e| e.time as f64)
+        .unwrap_or(f64::INFINITY)
+}
+
+/// Left/right gains for a track: volume with a balance pan law (center keeps unity gain)
+fn track_gains(volume: f32, pan: f32) -> [f32; 2] {
+    let pan = pan.max(-1.0).min(1.0);
+    [volume * (1.0 - pan).min(1.0), volume * (1.0 + pan).min(1.0)]
+}
+
+/// Check whether a track is heard in the mix, given whether any track is soloed
+fn is_audible(track: &Track, any_solo: bool) -> bool {
+    !track.muted && (!any_solo || track.solo)
+}
+
+/// Add a voice starting at `start` seconds and released at `release` seconds into `buffer`
+///
+/// The voice is resampled to the output rate with linear interpolation; the
+/// buffer grows as needed.
+fn mix_voice(
+    buffer: &mut Vec<f32>,
+    sample_rate: u32,
+    voice: &PreparedVoice,
+    start: f64,
+    release: f64,
+    gains: [f32; 2],
+) {
+    let channels = RENDER_CHANNELS as usize;
+    let start_frame = (start * sample_rate as f64).round() as usize;
+    let release_frame = (release - start) * sample_rate as f64;
+    let fade_frames = RELEASE_FADE * sample_rate as f64;
+    let step = voice.sample_rate as f64 / sample_rate as f64;
+
+    let mut frame = 0usize;
+    loop {
+        let position = frame as f64 * step;
+        let index = position as usize;
+        if index + 1 >= voice.samples.len() {
+            break;
+        }
+
+        let gain = if (frame as f64) < release_frame {
+            1.0
+        } else {
+            1.0 - (frame as f64 - release_frame) / fade_frames
+        };
+        if gain <= 0.0 {
+            break;
+        }
+
+        let fraction = (position - index as f64) as f32;
+        let sample = voice.samples[index] * (1.0 - fraction) + voice.samples[index + 1] * fraction;
+
+        let offset = (start_frame + frame) * channels;
+        if buffer.len() < offset + channels {
+            buffer.resize(offset + channels, 0.0);
+        }
+        for (channel, channel_gain) in gains.iter().enumerate() {
+            buffer[offset + channel] += sample * gain as f32 * channel_gain;
+        }
+
+        frame += 1;
+    }
+}
+
+/// Set the length of a mix: `tail` seconds after `end`, or up to the point
+/// where the audio has decayed to silence when `tail` is None
+fn apply_tail(buffer: &mut Vec<f32>, sample_rate: u32, end: f64, tail: Option<f64>) {
+    let channels = RENDER_CHANNELS as usize;
+
+    match tail {
+        Some(tail) => {
+            let length = ((end + tail) * sample_rate as f64).round() as usize * channels;
+            if length < buffer.len() {
+                // Fade out instead of cutting the ringing notes off
+                let fade = ((TAIL_FADE * sample_rate as f64) as usize * channels).min(length).max(1);
+                for (i, sample) in buffer[length - fade..length].iter_mut().enumerate() {
+                    *sample *= 1.0 - (i / channels) as f32 / (fade / channels).max(1) as f32;
+                }
+            }
+            buffer.resize(length, 0.0);
+        }
+        None => {
+            let end_frame = (end * sample_rate as f64).round() as usize;
+            let last_audible = buffer
+                .iter()
+                .rposition(|s| s.abs() > SILENCE_THRESHOLD)
+                .map(|i| i / channels + 1)
+                .unwrap_or(0);
+            buffer.truncate(last_audible.max(end_frame).min(buffer.len() / channels) * channels);
+        }
+    }
+}
+
+/// Length in beats of a loop over notes ending at `last_end`: whole bars, at least one
+fn loop_beats(last_end: f64, time_signature: &TimeSignature) -> f64 {
+    let bar = time_signature.beats_per_measure();
+    ((last_end / bar).ceil() * bar).max(bar)
+}
+
+/// Fold everything past `loop_end` seconds back onto the start so the audio loops seamlessly
+///
+/// Release tails ringing over the loop point then sound under the first beats
+/// of the next repetition, exactly as when the pattern is played in a loop.
+fn wrap_loop(buffer: &mut Vec<f32>, sample_rate: u32, loop_end: f64) {
+    let length = (loop_end * sample_rate as f64).round() as usize * RENDER_CHANNELS as usize;
+    if length == 0 {
+        buffer.clear();
+        return;
+    }
+
+    if buffer.len() > length {
+        let overflow = buffer.split_off(length);
+        for (i, sample) in overflow.into_iter().enumerate() {
+            buffer[i % length] += sample;
+        }
+    }
+    buffer.resize(length, 0.0);
+}
+
+/// Gain that brings `audio` to the normalization target
+fn normalization_gain(audio: &RenderedAudio, normalization: Normalization) -> Result<f32, String> {
+    let gain_db = match normalization {
+        Normalization::Peak { target_db } => {
+            let peak = audio.samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            if peak <= 0.0 {
+                return Ok(1.0);
+            }
+            target_db - 20.0 * (peak as f64).log10()
+        }
+        Normalization::Loudness { target_lufs } => {
+            let mode = ebur128::Mode::I | ebur128::Mode::TRUE_PEAK;
+            let mut meter = ebur128::EbuR128::new(audio.channels as u32, audio.sample_rate, mode)
+                .map_err(|e| format!("Failed to measure loudness: {}", e))?;
+            meter
+                .add_frames_f32(&audio.samples)
+                .map_err(|e| format!("Failed to measure loudness: {}", e))?;
+            let loudness = meter
+                .loudness_global()
+                .map_err(|e| format!("Failed to measure loudness: {}", e))?;
+            if !loudness.is_finite() {
+                return Ok(1.0); // Silence
+            }
+
+            let mut true_peak = 0.0f64;
+            for channel in 0..audio.channels as u32 {
+                let peak = meter
+                    .true_peak(channel)
+                    .map_err(|e| format!("Failed to measure loudness: {}", e))?;
+                true_peak = true_peak.max(peak);
+            }
+            let headroom = LOUDNESS_PEAK_CEILING_DB - 20.0 * true_peak.log10();
+            (target_lufs - loudness).min(headroom)
+        }
+    };
+
+    Ok(10.0_f64.powf(gain_db / 20.0) as f32)
+}
+
+/// Scale samples by `gain`
+fn apply_gain(audio: &mut RenderedAudio, gain: f32) {
+    for sample in audio.samples.iter_mut() {
+        *sample *= gain;
+    }
+}
+
+/// Error returned when a render is cancelled
+pub const RENDER_CANCELLED: &str = "Render cancelled";
+
+/// Payload of `render-progress` events
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderProgressEvent {
+    pub percent: f64,
+    pub elapsed: f64, // Seconds since the render started
+}
+
+/// Progress reporting and cancellation for a render
+///
+/// Reports whole-percent steps only, so long renders don't flood the UI.
+/// 100% is reserved for `finish`, once the file has been written.
+pub struct RenderProgress<'a> {
+    cancel: &'a CancellationToken,
+    report: Box<dyn FnMut(RenderProgressEvent) + 'a>,
+    started: Instant,
+    total: usize, // Units of work (notes to mix)
+    done: usize,
+    last_percent: Option<usize>,
+}
+
+impl<'a> RenderProgress<'a> {
+    pub fn new<F>(cancel: &'a CancellationToken, report: F) -> Self
+    where
+        F: FnMut(RenderProgressEvent) + 'a,
+    {
+        Self {
+            cancel,
+            report: Box::new(report),
+            started: Instant::now(),
+            total: 0,
+            done: 0,
+            last_percent: None,
+        }
+    }
+
+    /// Add units of work to the total
+    fn add_work(&mut self, units: usize) {
+        self.total += units;
+    }
+
+    /// Complete one unit of work, failing if the render was cancelled
+    fn advance(&mut self) -> Result<(), String> {
+        if self.cancel.is_cancelled() {
+            return Err(RENDER_CANCELLED.to_string());
+        }
+
+        self.done += 1;
+        let percent = (self.done * 99 / self.total.max(1)).min(99);
+        if self.last_percent != Some(percent) {
+            self.last_percent = Some(percent);
+            self.emit(percent as f64);
+        }
+        Ok(())
+    }
+
+    /// Report completion
+    pub fn finish(&mut self) {
+        self.emit(100.0);
+    }
+
+    fn emit(&mut self, percent: f64) {
+        let event = RenderProgressEvent {
+            percent,
+            elapsed: self.started.elapsed().as_secs_f64(),
+        };
+        (self.report)(event);
+    }
+}
+
+/// Mixes notes of a project with the playback sample engine
+struct Mixer<'a> {
+    player: &'a SamplePlayer,
+    project: &'a ProjectData,
+    sample_rate: u32,
+    tail: Option<f64>,
+    loop_end: Option<f64>,                    // Loop length in seconds when rendering a loop
+    pedal_events: Vec<PedalEvent>,            // Sorted by time
+    voices: HashMap<(u8, u8), PreparedVoice>, // Notes often repeat, so each pitch/velocity is prepared once
+}
+
+impl<'a> Mixer<'a> {
+    fn new(player: &'a SamplePlayer, project: &'a ProjectData, options: &RenderOptions) -> Result<Self, String> {
+        if project.notes.is_empty() {
+            return Err("Project has no notes to render".to_string());
+        }
+        if project.tempo == 0 {
+            return Err("Project tempo must be greater than 0".to_string());
+        }
+        ai_models::validate_time_signature(&project.time_signature)
+            .map_err(|_| format!("Invalid time signature {}", project.time_signature))?;
+
+        let mut pedal_events = project.pedal_events.clone();
+        pedal_events.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+
+        // Loop length is shared by every stem so they stay aligned
+        let loop_end = options.as_loop.then(|| {
+            let beats = options.loop_length.unwrap_or_else(|| {
+                let last_end = project
+                    .notes
+                    .iter()
+                    .map(|n| (n.start_time + n.duration) as f64)
+                    .fold(0.0, f64::max);
+                loop_beats(last_end, &project.time_signature)
+            });
+            beats * 60.0 / project.tempo as f64
+        });
+
+        Ok(Self {
+            player,
+            project,
+            sample_rate: options.sample_rate,
+            tail: options.tail,
+            loop_end,
+            pedal_events,
+            voices: HashMap::new(),
+        })
+    }
+
+    /// Track a note belongs to (None for notes of unknown tracks, which play at unity gain)
+    fn track_of(&self, note: &Note) -> Option<&'a Track> {
+        self.project.tracks.iter().find(|t| t.id == note.track_id)
+    }
+
+    /// Mix the notes accepted by `include` into a new buffer
+    ///
+    /// Every project note counts as one unit of progress, included or not.
+    fn mix<F>(&mut self, include: F, progress: &mut RenderProgress) -> Result<RenderedAudio, String>
+    where
+        F: Fn(Option<&Track>) -> bool,
+    {
+        let project = self.project;
+        let seconds_per_beat = 60.0 / project.tempo as f64;
+        let mut buffer = Vec::new();
+        let mut end: f64 = 0.0; // Latest note end in seconds
+
+        for note in &project.notes {
+            progress.advance()?;
+
+            let track = self.track_of(note);
+            if !include(track) {
+                continue;
+            }
+
+            let key = (note.pitch, note.velocity);
+            if !self.voices.contains_key(&key) {
+                self.voices.insert(key, self.player.prepare_voice(note.pitch, note.velocity)?);
+            }
+
+            let gains = track
+                .map(|t| track_gains(t.volume, t.pan))
+                .unwrap_or([1.0, 1.0]);
+            let start = note.start_time as f64;
+            let note_end = start + note.duration as f64;
+            let release = release_beat(note_end, &self.pedal_events);
+            end = end.max(note_end * seconds_per_beat);
+
+            mix_voice(
+                &mut buffer,
+                self.sample_rate,
+                &self.voices[&key],
+                start * seconds_per_beat,
+                release * seconds_per_beat,
+                gains,
+            );
+        }
+
+        match self.loop_end {
+            Some(loop_end) => wrap_loop(&mut buffer, self.sample_rate, loop_end),
+            None => apply_tail(&mut buffer, self.sample_rate, end, self.tail),
+        }
+
+        Ok(RenderedAudio {
+            samples: buffer,
+            sample_rate: self.sample_rate,
+            channels: RENDER_CHANNELS,
+        })
+    }
+}
+
+/// Render the full mix of a project offline, honoring track mute/solo, gain and pan
+pub fn render_project(
+    player: &SamplePlayer,
+    project: &ProjectData,
+    options: &RenderOptions,
+    progress: &mut RenderProgress,
+) -> Result<RenderedAudio, String> {
+    let any_solo = project.tracks.iter().any(|t| t.solo);
+    let mut mixer = Mixer::new(player, project, options)?;
+    progress.add_work(project.notes.len());
+
+    // Notes of unknown tracks are heard unless a track is soloed
+    let mut audio = mixer.mix(|track| track.map(|t| is_audible(t, any_solo)).unwrap_or(!any_solo), progress)?;
+
+    if let Some(normalization) = options.normalize {
+        let gain = normalization_gain(&audio, normalization)?;
+        apply_gain(&mut audio, gain);
+    }
+
+    Ok(audio)
+}
+
+/// Render one stem per audible track, as (track name, audio) pairs
+///
+/// Notes that don't belong to a known track are rendered as an extra stem.
+/// Stems are padded to the same length, and normalization applies one gain
+/// (measured on their sum) to all of them so the balance between them is kept.
+pub fn render_stems(
+    player: &SamplePlayer,
+    project: &ProjectData,
+    options: &RenderOptions,
+    progress: &mut RenderProgress,
+) -> Result<Vec<(String, RenderedAudio)>, String> {
+    let any_solo = project.tracks.iter().any(|t| t.solo);
+    let mut mixer = Mixer::new(player, project, options)?;
+    let mut stems = Vec::new();
+
+    let stem_tracks: Vec<&Track> = project
+        .tracks
+        .iter()
+        .filter(|t| is_audible(t, any_solo) && project.notes.iter().any(|n| n.track_id == t.id))
+        .collect();
+    let has_orphans = !any_solo && project.notes.iter().any(|n| mixer.track_of(n).is_none());
+
+    progress.add_work((stem_tracks.len() + has_orphans as usize) * project.notes.len());
+
+    for track in stem_tracks {
+        let audio = mixer.mix(|t| t.map(|t| t.id == track.id).unwrap_or(false), progress)?;
+        stems.push((track.name.clone(), audio));
+    }
+
+    if has_orphans {
+        stems.push(("Untitled".to_string(), mixer.mix(|t| t.is_none(), progress)?));
+    }
+
+    let length = stems.iter().map(|(_, audio)| audio.samples.len()).max().unwrap_or(0);
+    for (_, audio) in stems.iter_mut() {
+        audio.samples.resize(length, 0.0);
+    }
+
+    if let (Some(normalization), Some((_, first))) = (options.normalize, stems.first()) {
+        let mut sum = RenderedAudio {
+            samples: vec![0.0; length],
+            sample_rate: first.sample_rate,
+            channels: first.channels,
+        };
+        for (_, audio) in &stems {
+            for (total, sample) in sum.samples.iter_mut().zip(&audio.samples) {
+                *total += sample;
+            }
+        }
+
+        let gain = normalization_gain(&sum, normalization)?;
+        for (_, audio) in stems.iter_mut() {
+            apply_gain(audio, gain);
+        }
+    }
+
+    Ok(stems)
+}
+
+/// Make a track name safe to use as a file name
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' { c } else { '_' })
+        .collect();
+
+    if sanitized.is_empty() {
+        "track".to_string()
+    } else {
+        sanitized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pedal(time: f32, down: bool) -> PedalEvent {
+        PedalEvent { time, down }
+    }
+
+    #[test]
+    fn test_release_beat_follows_sustain_pedal() {
+        let events = vec![pedal(1.0, true), pedal(4.0, false), pedal(6.0, true)];
+
+        assert_eq!(release_beat(0.5, &events), 0.5);
+        assert_eq!(release_beat(2.0, &events), 4.0);
+        assert_eq!(release_beat(5.0, &events), 5.0);
+        assert!(release_beat(7.0, &events).is_infinite());
+    }
+
+    #[test]
+    fn test_mix_voice_places_and_releases_voice() {
+        let voice = PreparedVoice {
+            samples: vec![0.5; 1000],
+            sample_rate: 100,
+        };
+        let mut buffer = Vec::new();
+
+        // Starts at frame 10, released at frame 30 and faded out within one frame
+        mix_voice(&mut buffer, 100, &voice, 0.1, 0.3, [1.0, 1.0]);
+
+        assert_eq!(buffer[9 * 2], 0.0);
+        assert_eq!(buffer[10 * 2], 0.5);
+        assert_eq!(buffer[10 * 2 + 1], 0.5);
+        assert_eq!(buffer.len(), 31 * 2);
+    }
+
+    #[test]
+    fn test_track_gains_and_audibility() {
+        assert_eq!(track_gains(0.5, 0.0), [0.5, 0.5]);
+        assert_eq!(track_gains(1.0, -1.0), [1.0, 0.0]);
+        assert_eq!(track_gains(1.0, 0.5), [0.5, 1.0]);
+
+        let track = Track {
+            id: "track_1".to_string(),
+            name: "Lead".to_string(),
+            volume: 1.0,
+            pan: 0.0,
+            muted: false,
+            solo: false,
+        };
+        assert!(is_audible(&track, false));
+        assert!(!is_audible(&track, true));
+        assert!(!is_audible(&Track { muted: true, ..track.clone() }, false));
+        assert!(is_audible(&Track { solo: true, ..track }, true));
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("Left Hand"), "Left Hand");
+        assert_eq!(sanitize_file_name("Bass/Synth:1"), "Bass_Synth_1");
+        assert_eq!(sanitize_file_name("  "), "track");
+    }
+
+    #[test]
+    fn test_fixed_tail_pads_or_trims() {
+        let mut buffer = vec![0.5; 200];
+        apply_tail(&mut buffer, 100, 0.5, Some(0.25));
+        assert_eq!(buffer.len(), 75 * 2);
+        assert_eq!(buffer[0], 0.5);
+        assert!(buffer[74 * 2].abs() < 0.5); // Faded out at the cut
+
+        let mut buffer = vec![0.5; 20];
+        apply_tail(&mut buffer, 100, 0.1, Some(0.1));
+        assert_eq!(buffer.len(), 20 * 2);
+    }
+
+    #[test]
+    fn test_automatic_tail_trims_trailing_silence() {
+        let mut buffer = vec![0.5; 40];
+        buffer.extend(vec![0.0; 60]);
+
+        apply_tail(&mut buffer, 100, 0.1, None);
+        assert_eq!(buffer.len(), 20 * 2);
+
+        // Never shorter than the notes themselves
+        apply_tail(&mut buffer, 100, 0.3, None);
+        assert_eq!(buffer.len(), 20 * 2);
+    }
+
+    #[test]
+    fn test_wrap_loop_folds_tail_onto_start() {
+        // 4 frames of audio, loop of 3 frames
+        let mut buffer = vec![1.0, 1.0, 0.5, 0.5, 0.25, 0.25, 0.1, 0.1];
+        wrap_loop(&mut buffer, 100, 0.03);
+        assert_eq!(buffer, vec![1.1, 1.1, 0.5, 0.5, 0.25, 0.25]);
+
+        // Shorter audio is padded to the loop length
+        let mut buffer = vec![1.0, 1.0];
+        wrap_loop(&mut buffer, 100, 0.02);
+        assert_eq!(buffer, vec![1.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_loop_beats_rounds_to_whole_bars() {
+        let four_four = TimeSignature::default();
+        assert_eq!(loop_beats(5.0, &four_four), 8.0);
+        assert_eq!(loop_beats(0.5, &four_four), 4.0);
+
+        let three_four = TimeSignature { numerator: 3, denominator: 4 };
+        assert_eq!(loop_beats(5.0, &three_four), 6.0);
+        assert_eq!(loop_beats(6.0, &three_four), 6.0);
+
+        let six_eight = TimeSignature { numerator: 6, denominator: 8 };
+        assert_eq!(loop_beats(3.5, &six_eight), 6.0);
+    }
+
+    #[test]
+    fn test_progress_reports_percent_steps_and_cancels() {
+        let cancel = CancellationToken::new();
+        let mut reported = Vec::new();
+
+        {
+            let mut progress = RenderProgress::new(&cancel, |event| reported.push(event.percent));
+            progress.add_work(200);
+            for _ in 0..100 {
+                progress.advance().unwrap();
+            }
+
+            cancel.cancel();
+            assert_eq!(progress.advance(), Err(RENDER_CANCELLED.to_string()));
+        }
+
+        // Two notes per percent: one event per step, stopping at the halfway point
+        assert_eq!(reported.len(), 50);
+        assert_eq!(reported.last(), Some(&49.0));
+    }
+
+    #[test]
+    fn test_peak_normalization_gain() {
+        let audio = RenderedAudio {
+            samples: vec![0.25, -0.5, 0.1, 0.0],
+            sample_rate: 48000,
+            channels: 2,
+        };
+
+        let gain = normalization_gain(&audio, Normalization::Peak { target_db: 0.0 }).unwrap();
+        assert!((gain - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_loudness_normalization_keeps_peaks_below_ceiling() {
+        // Short clicks in silence: very quiet overall, but with high peaks
+        let mut samples = vec![0.0f32; 48000 * 2 * 4];
+        for frame in (0..48000 * 4).step_by(12000) {
+            samples[frame * 2] = 0.5;
+            samples[frame * 2 + 1] = 0.5;
+        }
+        let audio = RenderedAudio { samples, sample_rate: 48000, channels: 2 };
+
+        let gain = normalization_gain(&audio, Normalization::Loudness { target_lufs: -14.0 }).unwrap();
+        let ceiling = 10.0_f32.powf(LOUDNESS_PEAK_CEILING_DB as f32 / 20.0);
+        assert!(0.5 * gain <= ceiling + 1e-4);
+    }
+
+    #[test]
+    fn test_bit_depth_validation() {
+        let mut options = RenderOptions {
+            sample_rate: 48000,
+            bit_depth: 24,
+            format: AudioFormat::Flac,
+            ..RenderOptions::default()
+        };
+        assert!(options.validate().is_ok());
+
+        options.bit_depth = 20;
+        assert!(options.validate().is_err());
+
+        options.bit_depth = 16;
+        options.quality = Some(1.5);
+        assert!(options.validate().is_err());
+    }
+}
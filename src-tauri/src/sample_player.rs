@@ -1,19 +1,171 @@
+use crate::drum_kit::GM_DRUM_MAP;
 use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
 use lru::LruCache;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::num::NonZeroUsize;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval at which finished or released voices are reaped
+const VOICE_REAP_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Drum hits are one-shots; they ring out for at most this long
+const DRUM_RING_TIME: Duration = Duration::from_secs(5);
+
+/// A sounding note, kept so it can be released late (sustain pedal) or stopped
+struct Voice {
+    sink: Sink,
+    release_at: Instant,    // When the key is released (sound continues while sustained)
+    held_pitch: Option<u8>, // Set while a live key is down and its release time is unknown
+    channel: Option<u8>,    // MIDI channel of live notes (per-note channel under MPE)
+    loading: Option<Loading>, // Set until the sample of an uncached note has arrived
+}
+
+/// A voice waiting for its sample from the loader thread
+///
+/// Its release is put off by the time spent decoding, so the note still
+/// sounds for its whole duration.
+struct Loading {
+    queued_at: Instant,
+    started: Arc<OnceLock<Instant>>, // Set by the source on its first real sample
+}
+
+/// Per-channel expression from MIDI input (pitch bend in semitones, pressure 0.0-1.0)
+#[derive(Default)]
+struct ChannelExpression {
+    bend: [f32; 16],
+    pressure: [f32; 16],
+    global_bend: f32, // MPE master channel bend, applied to every voice
+}
+
+impl ChannelExpression {
+    /// Playback speed ratio for a voice on `channel`
+    fn speed(&self, channel: Option<u8>) -> f32 {
+        let channel_bend = channel.map(|c| self.bend[c as usize & 0x0F]).unwrap_or(0.0);
+        2.0_f32.powf((channel_bend + self.global_bend) / 12.0)
+    }
+
+    /// Gain for a voice on `channel`: pressure swells the note by up to +3.5 dB
+    fn gain(&self, channel: Option<u8>) -> f32 {
+        1.0 + 0.5 * channel.map(|c| self.pressure[c as usize & 0x0F]).unwrap_or(0.0)
+    }
+}
+
+/// A note's sample after pitch shift and velocity scaling
+pub struct PreparedVoice {
+    pub samples: Vec<f32>, // Mono
+    pub sample_rate: u32,  // Playback rate (the pitch shift is baked in)
+}
+
+/// A sample file to decode on the loader thread
+struct LoadRequest {
+    key: (u8, u8),
+    path: PathBuf,
+    reply: Sender<Arc<Vec<f32>>>, // Dropped without a reply if decoding fails
+}
+
+/// A mono sample streamed to the output with a fixed gain
+///
+/// The gain is applied as samples are pulled by the audio thread, so
+/// starting a note shares the cached sample instead of copying it.
+struct VoiceSource {
+    samples: Arc<Vec<f32>>,
+    pending: Option<Receiver<Arc<Vec<f32>>>>, // Set while the sample is still being decoded
+    started: Arc<OnceLock<Instant>>, // When the sample arrived, for voices that had to wait
+    position: usize,
+    gain: f32,
+    sample_rate: u32,
+}
+
+impl VoiceSource {
+    fn new(samples: Arc<Vec<f32>>, gain: f32, sample_rate: u32) -> Self {
+        Self { samples, pending: None, started: Arc::new(OnceLock::new()), position: 0, gain, sample_rate }
+    }
+
+    /// A voice whose sample arrives from the loader thread; it plays silence until then
+    fn loading(pending: Receiver<Arc<Vec<f32>>>, gain: f32, sample_rate: u32) -> Self {
+        Self {
+            samples: Arc::new(Vec::new()),
+            pending: Some(pending),
+            started: Arc::new(OnceLock::new()),
+            position: 0,
+            gain,
+            sample_rate,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.samples.len().saturating_sub(self.position)
+    }
+}
+
+impl Iterator for VoiceSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(pending) = &self.pending {
+            match pending.try_recv() {
+                Ok(samples) => {
+                    self.samples = samples;
+                    self.pending = None;
+                    let _ = self.started.set(Instant::now());
+                }
+                Err(TryRecvError::Empty) => return Some(0.0),
+                Err(TryRecvError::Disconnected) => return None,
+            }
+        }
+        let sample = *self.samples.get(self.position)?;
+        self.position += 1;
+        Some(sample * self.gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining(), Some(self.remaining()))
+    }
+}
+
+impl Source for VoiceSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.pending.is_some() {
+            return None;
+        }
+        Some(self.remaining())
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.pending.is_some() {
+            return None;
+        }
+        Some(Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate as f64))
+    }
+}
 
 /// Sample-based piano player using real piano recordings with lazy loading
 pub struct SamplePlayer {
     stream_handle: Arc<OutputStreamHandle>,
     sample_paths: HashMap<(u8, u8), PathBuf>, // (MIDI pitch, velocity 1-16) -> file path
-    sample_cache: Arc<Mutex<LruCache<(u8, u8), Vec<f32>>>>, // LRU cache for loaded samples
+    sample_cache: Arc<Mutex<LruCache<(u8, u8), Arc<Vec<f32>>>>>, // LRU cache for loaded samples
+    drum_samples: HashMap<u8, Arc<Vec<f32>>>, // GM drum note -> decoded sample (loaded up front)
     sample_rate: u32,
-    volume: f32,
+    volume: Mutex<f32>, // Master volume (0.0-1.0)
+    voices: Arc<Mutex<Vec<Voice>>>,
+    sustain: Arc<AtomicBool>, // Sustain pedal (CC64) state
+    expression: Mutex<ChannelExpression>,
+    loader: Sender<LoadRequest>, // Decodes uncached samples off the calling thread
 }
 
 unsafe impl Send for SamplePlayer {}
@@ -22,17 +174,27 @@ impl SamplePlayer {
     pub fn new() -> Result<(Self, OutputStream), String> {
         let (stream, stream_handle) = OutputStream::try_default()
             .map_err(|e| format!("Failed to create audio stream: {}", e))?;
+        let (loader, load_requests) = mpsc::channel();
 
         let mut player = Self {
             stream_handle: Arc::new(stream_handle),
             sample_paths: HashMap::new(),
             sample_cache: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(100).unwrap()))), // Cache up to 100 samples
+            drum_samples: HashMap::new(),
             sample_rate: 48000,
-            volume: 0.8,
+            volume: Mutex::new(0.8),
+            voices: Arc::new(Mutex::new(Vec::new())),
+            sustain: Arc::new(AtomicBool::new(false)),
+            expression: Mutex::new(ChannelExpression::default()),
+            loader,
         };
 
         // Index sample files from the samples directory (no loading yet)
         player.index_samples()?;
+        player.load_drum_samples();
+
+        player.spawn_voice_reaper()?;
+        player.spawn_sample_loader(load_requests)?;
 
         Ok((player, stream))
     }
@@ -103,6 +265,34 @@ impl SamplePlayer {
         Ok(())
     }
 
+    /// Load the basic drum set from `samples/drums`
+    ///
+    /// The set is optional: without it, drum notes are silent.
+    fn load_drum_samples(&mut self) {
+        let Ok(drums_dir) = self.get_samples_dir().map(|dir| dir.join("drums")) else {
+            return;
+        };
+
+        let mut decoded: HashMap<&str, Arc<Vec<f32>>> = HashMap::new();
+        for sound in GM_DRUM_MAP {
+            if !decoded.contains_key(sound.sample) {
+                match Self::decode_file(&drums_dir.join(sound.sample)) {
+                    Ok(samples) => {
+                        decoded.insert(sound.sample, Arc::new(samples));
+                    }
+                    Err(_) => continue,
+                }
+            }
+            self.drum_samples.insert(sound.pitch, decoded[sound.sample].clone());
+        }
+
+        if decoded.is_empty() {
+            println!("No drum samples found in {} (drum notes will be silent)", drums_dir.display());
+        } else {
+            println!("Loaded {} drum samples", decoded.len());
+        }
+    }
+
     /// Get the samples directory path
     fn get_samples_dir(&self) -> Result<PathBuf, String> {
         // Try to find the samples directory relative to the executable
@@ -138,7 +328,7 @@ impl SamplePlayer {
     }
 
     /// Load a single sample file on-demand and cache it
-    fn load_sample_on_demand(&self, key: (u8, u8)) -> Result<Vec<f32>, String> {
+    fn load_sample_on_demand(&self, key: (u8, u8)) -> Result<Arc<Vec<f32>>, String> {
         // Check if already in cache
         {
             let mut cache = self.sample_cache.lock().unwrap();
@@ -151,6 +341,19 @@ impl SamplePlayer {
         let path = self.sample_paths.get(&key)
             .ok_or_else(|| format!("Sample not found for pitch {} velocity {}", key.0, key.1))?;
 
+        let samples = Arc::new(Self::decode_file(path)?);
+
+        // Cache the loaded sample
+        {
+            let mut cache = self.sample_cache.lock().unwrap();
+            cache.put(key, samples.clone());
+        }
+
+        Ok(samples)
+    }
+
+    /// Decode a sample file
+    fn decode_file(path: &Path) -> Result<Vec<f32>, String> {
         let file = File::open(path)
             .map_err(|e| format!("Failed to open file: {}", e))?;
 
@@ -159,63 +362,282 @@ impl SamplePlayer {
             .map_err(|e| format!("Failed to decode audio file: {}", e))?;
 
         // Convert to mono and collect samples
-        let samples: Vec<f32> = source
-            .convert_samples()
-            .collect();
+        Ok(source.convert_samples().collect())
+    }
 
-        // Cache the loaded sample
-        {
-            let mut cache = self.sample_cache.lock().unwrap();
-            cache.put(key, samples.clone());
+    /// Play a note using samples with pitch shifting
+    pub fn play_note(&self, pitch: u8, duration: f32, velocity: u8) -> Result<(), String> {
+        let release_at = Instant::now() + Duration::from_secs_f32(duration.max(0.0));
+        let source = self.voice_source(pitch, velocity)?;
+        self.start_voice(source, release_at, None, None)
+    }
+
+    /// Play a General MIDI drum sound (channel 10 semantics: the pitch picks the sound)
+    ///
+    /// Drum hits ignore note length and ring out their sample; notes outside
+    /// the map, or without a loaded sample, are silent.
+    pub fn play_drum(&self, pitch: u8, velocity: u8) -> Result<(), String> {
+        let Some(sample) = self.drum_samples.get(&pitch) else {
+            return Ok(());
+        };
+
+        let gain = self.volume() * velocity.min(127) as f32 / 127.0;
+        let source = VoiceSource::new(sample.clone(), gain, self.sample_rate);
+        self.start_voice(source, Instant::now() + DRUM_RING_TIME, None, None)
+    }
+
+    /// Start a live note (e.g. from MIDI input) on `channel` that sounds until `note_off`
+    pub fn note_on(&self, pitch: u8, velocity: u8, channel: u8) -> Result<(), String> {
+        // Samples decay naturally, so a lost note-off can't ring forever
+        let release_at = Instant::now() + Duration::from_secs(3600);
+        let source = self.voice_source(pitch, velocity)?;
+        self.start_voice(source, release_at, Some(pitch), Some(channel))
+    }
+
+    /// Release a live note started with `note_on`
+    pub fn note_off(&self, pitch: u8, channel: u8) {
+        let now = Instant::now();
+        for voice in self.voices.lock().unwrap().iter_mut() {
+            if voice.held_pitch == Some(pitch) && voice.channel == Some(channel) {
+                voice.held_pitch = None;
+                voice.release_at = now;
+            }
         }
+    }
 
-        Ok(samples)
+    /// Set the pitch bend of a channel in semitones, re-tuning its sounding voices
+    ///
+    /// Under MPE every note has its own channel, so this bends a single note.
+    pub fn set_channel_bend(&self, channel: u8, semitones: f32) {
+        let mut expression = self.expression.lock().unwrap();
+        expression.bend[channel as usize & 0x0F] = semitones;
+        self.apply_expression(&expression, Some(channel));
     }
 
-    /// Play a note using samples with pitch shifting
-    pub fn play_note(&self, pitch: u8, duration: f32, velocity: u8) -> Result<(), String> {
+    /// Set a bend applied to every voice (MPE master channel)
+    pub fn set_global_bend(&self, semitones: f32) {
+        let mut expression = self.expression.lock().unwrap();
+        expression.global_bend = semitones;
+        self.apply_expression(&expression, None);
+    }
+
+    /// Set the pressure (aftertouch) of a channel, 0.0-1.0
+    pub fn set_channel_pressure(&self, channel: u8, pressure: f32) {
+        let mut expression = self.expression.lock().unwrap();
+        expression.pressure[channel as usize & 0x0F] = pressure.max(0.0).min(1.0);
+        self.apply_expression(&expression, Some(channel));
+    }
+
+    /// Update speed and gain of the voices on `channel` (all voices if None)
+    fn apply_expression(&self, expression: &ChannelExpression, channel: Option<u8>) {
+        for voice in self.voices.lock().unwrap().iter() {
+            if channel.is_none() || voice.channel == channel {
+                voice.sink.set_speed(expression.speed(voice.channel));
+                voice.sink.set_volume(expression.gain(voice.channel));
+            }
+        }
+    }
+
+    /// Load and adjust the sample for a note: closest sample layer, pitch shift and velocity scaling
+    ///
+    /// Offline rendering mixes the adjusted samples itself; live playback
+    /// streams the same voice through `voice_source`, so both sound the same.
+    pub fn prepare_voice(&self, pitch: u8, velocity: u8) -> Result<PreparedVoice, String> {
+        let (key, gain, sample_rate) = self.voice_layer(pitch, velocity)?;
+        let sample_data = self.load_sample_on_demand(key)?;
+        Ok(PreparedVoice {
+            samples: sample_data.iter().map(|&s| s * gain).collect(),
+            sample_rate,
+        })
+    }
+
+    /// Streamed voice for a note
+    ///
+    /// An uncached sample is decoded on the loader thread, so playing a note
+    /// never waits on the disk; the voice starts once its sample is ready.
+    fn voice_source(&self, pitch: u8, velocity: u8) -> Result<VoiceSource, String> {
+        let (key, gain, sample_rate) = self.voice_layer(pitch, velocity)?;
+
+        let cached = self.sample_cache.lock().unwrap().get(&key).cloned();
+        if let Some(sample_data) = cached {
+            return Ok(VoiceSource::new(sample_data, gain, sample_rate));
+        }
+
+        let path = self.sample_paths.get(&key)
+            .ok_or_else(|| format!("Sample not found for pitch {} velocity {}", key.0, key.1))?;
+        let (reply, pending) = mpsc::channel();
+        self.loader
+            .send(LoadRequest { key, path: path.clone(), reply })
+            .map_err(|_| "Sample loader has stopped".to_string())?;
+        Ok(VoiceSource::loading(pending, gain, sample_rate))
+    }
+
+    /// Sample key of a note with its velocity gain and pitch-shifted playback rate
+    fn voice_layer(&self, pitch: u8, velocity: u8) -> Result<((u8, u8), f32, u32), String> {
         // Map MIDI velocity to sample velocity layer
         let target_velocity = Self::velocity_to_sample_layer(velocity);
 
         // Find the closest sample key (pitch and velocity)
         let (closest_pitch, closest_velocity) = self.find_closest_sample_key(pitch, target_velocity)?;
 
-        // Load the sample on-demand (with caching)
-        let sample_data = self.load_sample_on_demand((closest_pitch, closest_velocity))?;
-
         // Calculate pitch shift ratio (minimize shifting by using exact notes when possible)
         let semitone_diff = pitch as f32 - closest_pitch as f32;
         let pitch_ratio = 2.0_f32.powf(semitone_diff / 12.0);
 
         // Apply velocity scaling only if we don't have the exact velocity layer
         // If we have the right velocity layer, let the sample speak for itself
+        let volume = self.volume();
         let velocity_factor = if closest_velocity == target_velocity {
-            self.volume
+            volume
         } else {
             // Light adjustment if we're using a different velocity layer
             let velocity_diff = (target_velocity as f32 - closest_velocity as f32) / 16.0;
-            (self.volume * (1.0 + velocity_diff * 0.3)).max(0.1).min(1.0)
+            (volume * (1.0 + velocity_diff * 0.3)).max(0.1).min(1.0)
         };
 
-        // Create a velocity-adjusted source
-        let adjusted_samples: Vec<f32> = sample_data
-            .iter()
-            .map(|&s| s * velocity_factor)
-            .collect();
-
-        // Create source with pitch shifting via sample rate manipulation
-        let adjusted_sample_rate = (self.sample_rate as f32 * pitch_ratio) as u32;
-        let source = rodio::buffer::SamplesBuffer::new(1, adjusted_sample_rate, adjusted_samples);
-
-        // Limit duration by taking only the needed samples
-        let limited_source = source.take_duration(std::time::Duration::from_secs_f32(duration));
+        // Pitch shifting via sample rate manipulation
+        let sample_rate = (self.sample_rate as f32 * pitch_ratio) as u32;
+        Ok(((closest_pitch, closest_velocity), velocity_factor, sample_rate))
+    }
 
-        // Create a sink and play
+    /// Start streaming a voice that is released at `release_at`
+    fn start_voice(
+        &self,
+        source: VoiceSource,
+        release_at: Instant,
+        held_pitch: Option<u8>,
+        channel: Option<u8>,
+    ) -> Result<(), String> {
+        // Create a sink and play the full sample; the reaper cuts it off at release time
+        // (or later, while the sustain pedal is held)
         let sink = Sink::try_new(&*self.stream_handle)
             .map_err(|e| format!("Failed to create sink: {}", e))?;
 
-        sink.append(limited_source);
-        sink.detach();
+        // Controllers send bend/pressure before the note-on, so apply the channel's current state
+        {
+            let expression = self.expression.lock().unwrap();
+            sink.set_speed(expression.speed(channel));
+            sink.set_volume(expression.gain(channel));
+        }
+
+        let loading = source.pending.is_some().then(|| Loading {
+            queued_at: Instant::now(),
+            started: source.started.clone(),
+        });
+        sink.append(source);
+
+        self.voices.lock().unwrap().push(Voice {
+            sink,
+            release_at,
+            held_pitch,
+            channel,
+            loading,
+        });
+
+        Ok(())
+    }
+
+    /// Set the master volume (0.0 to 1.0), applied to notes started afterwards
+    pub fn set_volume(&self, volume: f32) {
+        *self.volume.lock().unwrap() = volume.max(0.0).min(1.0);
+    }
+
+    /// Get the master volume
+    pub fn volume(&self) -> f32 {
+        *self.volume.lock().unwrap()
+    }
+
+    /// Set the sustain pedal state
+    ///
+    /// While the pedal is down, notes keep sounding past their release time;
+    /// lifting the pedal releases every note whose key is already up.
+    pub fn set_sustain(&self, down: bool) {
+        self.sustain.store(down, Ordering::SeqCst);
+    }
+
+    /// Get the sustain pedal state
+    pub fn is_sustained(&self) -> bool {
+        self.sustain.load(Ordering::SeqCst)
+    }
+
+    /// Immediately silence every sounding voice and release the sustain pedal
+    pub fn stop_all_voices(&self) {
+        self.sustain.store(false, Ordering::SeqCst);
+
+        for voice in self.voices.lock().unwrap().drain(..) {
+            voice.sink.stop();
+        }
+    }
+
+    /// Spawn the background thread that releases voices once their time is up
+    fn spawn_voice_reaper(&self) -> Result<(), String> {
+        let voices = self.voices.clone();
+        let sustain = self.sustain.clone();
+
+        thread::Builder::new()
+            .name("voice-reaper".to_string())
+            .spawn(move || loop {
+                {
+                    let now = Instant::now();
+                    let sustained = sustain.load(Ordering::SeqCst);
+                    let mut voices = voices.lock().unwrap();
+
+                    voices.retain_mut(|voice| {
+                        if voice.sink.empty() {
+                            return false;
+                        }
+                        if let Some(loading) = &voice.loading {
+                            // The note hasn't sounded yet; its duration counts from its first sample
+                            let Some(&started) = loading.started.get() else {
+                                return true;
+                            };
+                            voice.release_at += started.saturating_duration_since(loading.queued_at);
+                            voice.loading = None;
+                        }
+                        if !sustained && voice.release_at <= now {
+                            voice.sink.stop();
+                            return false;
+                        }
+                        true
+                    });
+                }
+
+                thread::sleep(VOICE_REAP_INTERVAL);
+            })
+            .map_err(|e| format!("Failed to start voice thread: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Spawn the background thread that decodes and caches samples for `voice_source`
+    fn spawn_sample_loader(&self, requests: Receiver<LoadRequest>) -> Result<(), String> {
+        let cache = self.sample_cache.clone();
+
+        thread::Builder::new()
+            .name("sample-loader".to_string())
+            .spawn(move || {
+                for request in requests {
+                    // An earlier request may already have loaded the same sample
+                    let cached = cache.lock().unwrap().get(&request.key).cloned();
+                    let samples = match cached {
+                        Some(samples) => samples,
+                        None => match Self::decode_file(&request.path) {
+                            Ok(samples) => {
+                                let samples = Arc::new(samples);
+                                cache.lock().unwrap().put(request.key, samples.clone());
+                                samples
+                            }
+                            Err(e) => {
+                                eprintln!("⚠ Failed to load sample {}: {}", request.path.display(), e);
+                                continue;
+                            }
+                        },
+                    };
+                    // The voice may have been stopped meanwhile
+                    let _ = request.reply.send(samples);
+                }
+            })
+            .map_err(|e| format!("Failed to start sample loader: {}", e))?;
 
         Ok(())
     }
@@ -256,4 +678,46 @@ impl SamplePlayer {
     pub fn sample_count(&self) -> usize {
         self.sample_paths.len()
     }
+
+    /// Get the number of drum notes with a sample
+    pub fn drum_count(&self) -> usize {
+        self.drum_samples.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_source_streams_shared_sample() {
+        let samples = Arc::new(vec![0.5, -1.0, 0.25]);
+        let source = VoiceSource::new(samples.clone(), 0.5, 24000);
+        assert_eq!(source.total_duration(), Some(Duration::from_secs_f64(3.0 / 24000.0)));
+        assert_eq!(source.collect::<Vec<f32>>(), vec![0.25, -0.5, 0.125]);
+
+        // The cached sample itself is left untouched
+        assert_eq!(*samples, vec![0.5, -1.0, 0.25]);
+    }
+
+    #[test]
+    fn test_loading_voice_plays_silence_until_sample_arrives() {
+        let (reply, pending) = mpsc::channel();
+        let mut source = VoiceSource::loading(pending, 0.5, 24000);
+        let started = source.started.clone();
+        assert_eq!(source.next(), Some(0.0));
+        assert_eq!(source.total_duration(), None);
+        assert!(started.get().is_none());
+
+        let sent_at = Instant::now();
+        reply.send(Arc::new(vec![0.5, -1.0])).unwrap();
+        assert_eq!(source.collect::<Vec<f32>>(), vec![0.25, -0.5]);
+        // The note's duration is measured from here, not from when it was queued
+        assert!(started.get().is_some_and(|&started| started >= sent_at));
+
+        // A sample that fails to decode ends the voice
+        let (reply, pending) = mpsc::channel::<Arc<Vec<f32>>>();
+        drop(reply);
+        assert_eq!(VoiceSource::loading(pending, 0.5, 24000).next(), None);
+    }
 }
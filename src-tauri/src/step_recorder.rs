@@ -0,0 +1,200 @@
+use crate::ai_models::Note;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Step-record session state
+struct StepSession {
+    track_id: String,
+    cursor: f64,      // Beat where the next note is placed
+    step_length: f64, // Beats the cursor advances per step
+    duration: f64,    // Duration of placed notes in beats
+    held: HashSet<u8>, // Keys currently down (a chord shares one step)
+    notes: Vec<Note>,
+}
+
+/// Current step-record position, returned to the UI after each entry
+#[derive(Debug, Clone, Serialize)]
+pub struct StepCursor {
+    pub cursor: f64,
+    pub step_length: f64,
+    pub duration: f64,
+}
+
+/// Step-record entry: notes are placed at a cursor that advances automatically
+pub struct StepRecorder {
+    session: Mutex<Option<StepSession>>,
+}
+
+impl StepRecorder {
+    pub fn new() -> Self {
+        Self {
+            session: Mutex::new(None),
+        }
+    }
+
+    fn validate_lengths(step_length: f64, duration: f64) -> Result<(), String> {
+        if !(step_length > 0.0 && step_length <= 16.0) {
+            return Err(format!("Step length must be between 0 and 16 beats (got {})", step_length));
+        }
+        if !(duration > 0.0 && duration <= 16.0) {
+            return Err(format!("Note duration must be between 0 and 16 beats (got {})", duration));
+        }
+        Ok(())
+    }
+
+    /// Start a step-record session at `start_beat`
+    pub fn start(&self, track_id: String, start_beat: f64, step_length: f64, duration: Option<f64>) -> Result<StepCursor, String> {
+        let duration = duration.unwrap_or(step_length);
+        Self::validate_lengths(step_length, duration)?;
+
+        let mut session = self.session.lock().unwrap();
+        *session = Some(StepSession {
+            track_id,
+            cursor: start_beat.max(0.0),
+            step_length,
+            duration,
+            held: HashSet::new(),
+            notes: Vec::new(),
+        });
+
+        Ok(Self::cursor_of(session.as_ref().unwrap()))
+    }
+
+    /// Stop the session and return every note it placed
+    pub fn stop(&self) -> Vec<Note> {
+        self.session
+            .lock()
+            .unwrap()
+            .take()
+            .map(|session| session.notes)
+            .unwrap_or_default()
+    }
+
+    /// Check whether a step-record session is active
+    pub fn is_active(&self) -> bool {
+        self.session.lock().unwrap().is_some()
+    }
+
+    /// Change step length and note duration for subsequent steps
+    pub fn set_lengths(&self, step_length: f64, duration: Option<f64>) -> Result<StepCursor, String> {
+        let duration = duration.unwrap_or(step_length);
+        Self::validate_lengths(step_length, duration)?;
+
+        let mut guard = self.session.lock().unwrap();
+        let session = guard.as_mut().ok_or("Step record is not active")?;
+        session.step_length = step_length;
+        session.duration = duration;
+        Ok(Self::cursor_of(session))
+    }
+
+    /// Enter a single note at the cursor and advance
+    pub fn input_note(&self, pitch: u8, velocity: u8) -> Result<Note, String> {
+        let mut guard = self.session.lock().unwrap();
+        let session = guard.as_mut().ok_or("Step record is not active")?;
+
+        let note = Self::place(session, pitch, velocity)?;
+        session.cursor += session.step_length;
+        Ok(note)
+    }
+
+    /// Advance the cursor by one step without placing a note
+    pub fn rest(&self) -> Result<StepCursor, String> {
+        let mut guard = self.session.lock().unwrap();
+        let session = guard.as_mut().ok_or("Step record is not active")?;
+
+        session.cursor += session.step_length;
+        Ok(Self::cursor_of(session))
+    }
+
+    /// Live key press: keys pressed together form a chord on the same step
+    pub fn note_on(&self, pitch: u8, velocity: u8) {
+        let mut guard = self.session.lock().unwrap();
+        if let Some(session) = guard.as_mut() {
+            if Self::place(session, pitch, velocity).is_ok() {
+                session.held.insert(pitch);
+            }
+        }
+    }
+
+    /// Live key release: the cursor advances once every key is up
+    pub fn note_off(&self, pitch: u8) {
+        let mut guard = self.session.lock().unwrap();
+        if let Some(session) = guard.as_mut() {
+            if session.held.remove(&pitch) && session.held.is_empty() {
+                session.cursor += session.step_length;
+            }
+        }
+    }
+
+    fn place(session: &mut StepSession, pitch: u8, velocity: u8) -> Result<Note, String> {
+        if pitch > 127 || velocity > 127 {
+            return Err(format!("Invalid note (pitch {}, velocity {})", pitch, velocity));
+        }
+
+        let note = Note {
+            id: uuid::Uuid::new_v4().to_string(),
+            pitch,
+            start_time: session.cursor,
+            duration: session.duration,
+            velocity,
+            track_id: session.track_id.clone(),
+        };
+        session.notes.push(note.clone());
+        Ok(note)
+    }
+
+    fn cursor_of(session: &StepSession) -> StepCursor {
+        StepCursor {
+            cursor: session.cursor,
+            step_length: session.step_length,
+            duration: session.duration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_input_advances_cursor() {
+        let recorder = StepRecorder::new();
+        recorder.start("track_1".to_string(), 0.0, 0.5, Some(0.25)).unwrap();
+
+        let first = recorder.input_note(60, 100).unwrap();
+        recorder.rest().unwrap();
+        let second = recorder.input_note(62, 100).unwrap();
+
+        assert_eq!(first.start_time, 0.0);
+        assert_eq!(first.duration, 0.25);
+        assert_eq!(second.start_time, 1.0);
+        assert_eq!(recorder.stop().len(), 2);
+        assert!(!recorder.is_active());
+    }
+
+    #[test]
+    fn test_held_chord_shares_one_step() {
+        let recorder = StepRecorder::new();
+        recorder.start("track_1".to_string(), 4.0, 1.0, None).unwrap();
+
+        recorder.note_on(60, 90);
+        recorder.note_on(64, 90);
+        recorder.note_off(60);
+        recorder.note_on(67, 90); // Still part of the chord: 64 is held
+        recorder.note_off(64);
+        recorder.note_off(67);
+        recorder.note_on(72, 90);
+        recorder.note_off(72);
+
+        let notes = recorder.stop();
+        let starts: Vec<f64> = notes.iter().map(|n| n.start_time).collect();
+        assert_eq!(starts, vec![4.0, 4.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_input_requires_session() {
+        let recorder = StepRecorder::new();
+        assert!(recorder.input_note(60, 100).is_err());
+    }
+}
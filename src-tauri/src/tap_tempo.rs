@@ -0,0 +1,83 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A pause longer than this starts a new tap sequence
+const TAP_RESET_GAP: Duration = Duration::from_secs(2);
+
+/// Number of most recent taps averaged
+const MAX_TAPS: usize = 8;
+
+/// Tap tempo: averages the intervals between recent taps into a BPM
+pub struct TapTempo {
+    taps: Mutex<Vec<Instant>>,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        Self {
+            taps: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Register a tap now; returns the averaged BPM once two or more taps exist
+    pub fn tap(&self) -> Option<f64> {
+        self.tap_at(Instant::now())
+    }
+
+    /// Register a tap at `now` (separate from `tap` for testing)
+    fn tap_at(&self, now: Instant) -> Option<f64> {
+        let mut taps = self.taps.lock().unwrap();
+
+        if let Some(&last) = taps.last() {
+            if now.duration_since(last) > TAP_RESET_GAP {
+                taps.clear();
+            }
+        }
+
+        taps.push(now);
+        if taps.len() > MAX_TAPS {
+            let excess = taps.len() - MAX_TAPS;
+            taps.drain(..excess);
+        }
+
+        if taps.len() < 2 {
+            return None;
+        }
+
+        // Average interval = span / number of intervals
+        let span = taps.last()?.duration_since(*taps.first()?).as_secs_f64();
+        let average_interval = span / (taps.len() - 1) as f64;
+        if average_interval <= 0.0 {
+            return None;
+        }
+
+        // Round to one decimal so the value is friendly to display and store
+        Some((600.0 / average_interval).round() / 10.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_averages_tap_intervals() {
+        let tapper = TapTempo::new();
+        let start = Instant::now();
+
+        assert_eq!(tapper.tap_at(start), None);
+        assert_eq!(tapper.tap_at(start + Duration::from_millis(500)), Some(120.0));
+        // Intervals of 500ms and 600ms average to 550ms (~109.1 BPM)
+        assert_eq!(tapper.tap_at(start + Duration::from_millis(1100)), Some(109.1));
+    }
+
+    #[test]
+    fn test_long_pause_restarts_sequence() {
+        let tapper = TapTempo::new();
+        let start = Instant::now();
+
+        tapper.tap_at(start);
+        tapper.tap_at(start + Duration::from_millis(1000));
+        assert_eq!(tapper.tap_at(start + Duration::from_millis(4000)), None);
+    }
+}
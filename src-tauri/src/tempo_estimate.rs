@@ -0,0 +1,107 @@
+use serde::Serialize;
+
+/// Tempos considered (BPM)
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+const BPM_STEP: f64 = 0.5;
+
+/// Onsets closer than this are one event (a chord), in seconds
+const CHORD_WINDOW: f64 = 0.03;
+
+/// Intervals between onsets further apart than this are ignored, in seconds
+const MAX_INTERVAL: f64 = 2.0;
+
+/// How far (in beats) an interval may be from a whole number of beats and still mostly fit
+const TOLERANCE: f64 = 0.08;
+
+/// Fewest distinct onsets needed for an estimate
+const MIN_ONSETS: usize = 4;
+
+/// A tempo guessed from note timing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TempoEstimate {
+    /// Beats per minute, to one decimal
+    pub bpm: f64,
+    /// Share of the intervals between notes that fall on whole beats at this tempo (0 to 1)
+    pub confidence: f64,
+}
+
+/// Estimate the tempo of notes starting at `onsets` (seconds)
+///
+/// Every interval between notes up to two seconds apart votes for the
+/// tempos it is a whole number of beats at; tempos near 120 BPM are
+/// preferred, since half and double tempos fit the same intervals. None with
+/// fewer than four distinct onsets.
+pub fn estimate(onsets: &[f64]) -> Option<TempoEstimate> {
+    let mut sorted = onsets.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mut events: Vec<f64> = Vec::new();
+    for onset in sorted {
+        if !events.last().is_some_and(|&last| onset - last <= CHORD_WINDOW) {
+            events.push(onset);
+        }
+    }
+    if events.len() < MIN_ONSETS {
+        return None;
+    }
+
+    let intervals: Vec<f64> = events
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &start)| events[i + 1..].iter().map(move |&end| end - start).take_while(|&d| d <= MAX_INTERVAL))
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(f64, f64, f64)> = None; // (weighted score, bpm, fit)
+    let steps = ((MAX_BPM - MIN_BPM) / BPM_STEP) as usize;
+    for step in 0..=steps {
+        let bpm = MIN_BPM + step as f64 * BPM_STEP;
+        let beat = 60.0 / bpm;
+        let fit = intervals
+            .iter()
+            .filter_map(|&interval| {
+                let beats = interval / beat;
+                let whole = beats.round();
+                (whole >= 1.0).then(|| (-((beats - whole) / TOLERANCE).powi(2) / 2.0).exp())
+            })
+            .sum::<f64>()
+            / intervals.len() as f64;
+
+        // Log-normal preference for moderate tempos, an octave wide
+        let preference = (-(bpm / 120.0).log2().powi(2) / 0.5).exp();
+        if !best.is_some_and(|(score, ..)| fit * preference <= score) {
+            best = Some((fit * preference, bpm, fit));
+        }
+    }
+
+    best.map(|(_, bpm, fit)| TempoEstimate {
+        bpm: (bpm * 10.0).round() / 10.0,
+        confidence: (fit * 100.0).round() / 100.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates_tempo_from_onsets() {
+        // Quarters and eighths at 96 BPM
+        let rhythm = [1.0, 0.5, 0.5, 1.0, 1.0, 0.5, 0.5, 0.5, 0.5, 2.0, 1.0, 1.0, 0.5, 0.5, 1.0];
+        let beat = 60.0 / 96.0;
+        let mut time = 0.0;
+        let mut onsets = Vec::new();
+        for beats in rhythm.iter().chain(&rhythm) {
+            onsets.push(time);
+            onsets.push(time + 0.01); // chord tones count once
+            time += beats * beat;
+        }
+
+        let estimate = estimate(&onsets).unwrap();
+        assert_eq!(estimate.bpm, 96.0);
+        assert!(estimate.confidence > 0.5);
+        assert!(super::estimate(&[0.0, 0.5, 1.0]).is_none());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::midi::MidiManager;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use validator::Validate;
+
+/// MIDI real-time status bytes
+const MIDI_TIMING_CLOCK: u8 = 0xF8;
+const MIDI_START: u8 = 0xFA;
+const MIDI_STOP: u8 = 0xFC;
+const MIDI_SONG_POSITION: u8 = 0xF2;
+
+/// MIDI clock resolution (pulses per quarter note, fixed by the MIDI spec)
+const CLOCK_PPQN: f64 = 24.0;
+
+/// Interval at which the transport thread checks for due clock pulses
+const TICK_INTERVAL: Duration = Duration::from_micros(500);
+
+/// Allowed tempo range in BPM
+const MIN_TEMPO: f64 = 20.0;
+const MAX_TEMPO: f64 = 300.0;
+
+/// Named position in the arrangement (intro, verse, chorus...)
+///
+/// A marker starts a section that runs until the next marker.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct Marker {
+    pub id: String,
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+    /// Position in beats
+    #[validate(range(min = 0.0))]
+    pub beat: f64,
+}
+
+/// Musical clock: converts wall time to beats at the current tempo
+#[derive(Debug, Clone, Copy)]
+struct Clock {
+    tempo: f64,       // BPM
+    anchor: Instant,  // Wall time of the last tempo change or start
+    anchor_beat: f64, // Beat position at `anchor`
+    running: bool,
+}
+
+impl Clock {
+    /// Current position in beats
+    fn position(&self) -> f64 {
+        if self.running {
+            self.anchor_beat + self.anchor.elapsed().as_secs_f64() * self.tempo / 60.0
+        } else {
+            self.anchor_beat
+        }
+    }
+}
+
+/// Backend transport driving tempo-synced timing (MIDI clock master)
+pub struct Transport {
+    clock: Arc<Mutex<Clock>>,
+    midi: Arc<MidiManager>,
+    worker: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>>, // (keep-running flag, thread)
+    resync: Arc<AtomicBool>, // Set after a seek so the clock thread realigns its pulse count
+    markers: Mutex<Vec<Marker>>,
+}
+
+impl Transport {
+    pub fn new(midi: Arc<MidiManager>) -> Self {
+        Self {
+            clock: Arc::new(Mutex::new(Clock {
+                tempo: 120.0,
+                anchor: Instant::now(),
+                anchor_beat: 0.0,
+                running: false,
+            })),
+            midi,
+            worker: Mutex::new(None),
+            resync: Arc::new(AtomicBool::new(false)),
+            markers: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn validate_tempo(tempo: f64) -> Result<(), String> {
+        if !tempo.is_finite() || tempo < MIN_TEMPO || tempo > MAX_TEMPO {
+            return Err(format!(
+                "Tempo must be between {} and {} BPM (got {})",
+                MIN_TEMPO, MAX_TEMPO, tempo
+            ));
+        }
+        Ok(())
+    }
+
+    /// Start the transport from beat 0 at the given tempo
+    pub fn start(&self, tempo: f64) -> Result<(), String> {
+        Self::validate_tempo(tempo)?;
+
+        // Restart cleanly if already running
+        self.stop()?;
+
+        {
+            let mut clock = self.clock.lock().unwrap();
+            clock.tempo = tempo;
+            clock.anchor = Instant::now();
+            clock.anchor_beat = 0.0;
+            clock.running = true;
+        }
+
+        self.midi.send_clock_message(&[MIDI_START]);
+
+        let keep_running = Arc::new(AtomicBool::new(true));
+        let handle = {
+            let keep_running = keep_running.clone();
+            let clock = self.clock.clone();
+            let midi = self.midi.clone();
+            let resync = self.resync.clone();
+            thread::Builder::new()
+                .name("transport".to_string())
+                .spawn(move || Self::run_clock(clock, midi, keep_running, resync))
+                .map_err(|e| format!("Failed to start transport thread: {}", e))?
+        };
+
+        *self.worker.lock().unwrap() = Some((keep_running, handle));
+        Ok(())
+    }
+
+    /// Stop the transport (no-op if not running)
+    pub fn stop(&self) -> Result<(), String> {
+        let worker = self.worker.lock().unwrap().take();
+
+        if let Some((keep_running, handle)) = worker {
+            keep_running.store(false, Ordering::SeqCst);
+            handle
+                .join()
+                .map_err(|_| "Transport thread panicked".to_string())?;
+
+            {
+                let mut clock = self.clock.lock().unwrap();
+                clock.anchor_beat = clock.position();
+                clock.running = false;
+            }
+
+            self.midi.send_clock_message(&[MIDI_STOP]);
+        }
+
+        Ok(())
+    }
+
+    /// Change tempo without losing the current beat position
+    pub fn set_tempo(&self, tempo: f64) -> Result<(), String> {
+        Self::validate_tempo(tempo)?;
+
+        let mut clock = self.clock.lock().unwrap();
+        clock.anchor_beat = clock.position();
+        clock.anchor = Instant::now();
+        clock.tempo = tempo;
+        Ok(())
+    }
+
+    /// Move the playhead to `beat`, keeping the transport running if it is
+    ///
+    /// Sends a MIDI Song Position Pointer so clock followers jump too.
+    pub fn seek(&self, beat: f64) -> Result<(), String> {
+        if !beat.is_finite() || beat < 0.0 {
+            return Err(format!("Invalid transport position: {}", beat));
+        }
+
+        {
+            let mut clock = self.clock.lock().unwrap();
+            clock.anchor = Instant::now();
+            clock.anchor_beat = beat;
+        }
+        self.resync.store(true, Ordering::SeqCst);
+
+        // Song position is counted in sixteenth notes (14 bits)
+        let sixteenths = ((beat * 4.0).round() as u32).min(0x3FFF);
+        self.midi.send_clock_message(&[
+            MIDI_SONG_POSITION,
+            (sixteenths & 0x7F) as u8,
+            (sixteenths >> 7) as u8,
+        ]);
+        Ok(())
+    }
+
+    /// Replace the arrangement markers (sorted by position)
+    pub fn set_markers(&self, mut markers: Vec<Marker>) {
+        markers.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap_or(std::cmp::Ordering::Equal));
+        *self.markers.lock().unwrap() = markers;
+    }
+
+    /// Get the arrangement markers
+    pub fn markers(&self) -> Vec<Marker> {
+        self.markers.lock().unwrap().clone()
+    }
+
+    /// Seek to the marker with the given id, returning its position
+    pub fn jump_to_marker(&self, id: &str) -> Result<f64, String> {
+        let beat = self
+            .markers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|marker| marker.id == id)
+            .map(|marker| marker.beat)
+            .ok_or_else(|| format!("Marker not found: {}", id))?;
+
+        self.seek(beat)?;
+        Ok(beat)
+    }
+
+    /// Current tempo in BPM
+    pub fn tempo(&self) -> f64 {
+        self.clock.lock().unwrap().tempo
+    }
+
+    /// Current position in beats
+    pub fn position(&self) -> f64 {
+        self.clock.lock().unwrap().position()
+    }
+
+    /// Check whether the transport is running
+    pub fn is_running(&self) -> bool {
+        self.clock.lock().unwrap().running
+    }
+
+    /// Transport thread: emits 24 clock pulses per beat to the clock output
+    ///
+    /// Pulse times are derived from the beat position rather than a fixed sleep,
+    /// so tempo changes take effect on the next pulse and sleep jitter doesn't
+    /// accumulate into drift.
+    fn run_clock(
+        clock: Arc<Mutex<Clock>>,
+        midi: Arc<MidiManager>,
+        keep_running: Arc<AtomicBool>,
+        resync: Arc<AtomicBool>,
+    ) {
+        let mut next_pulse: u64 = 0;
+
+        while keep_running.load(Ordering::SeqCst) {
+            let position = clock.lock().unwrap().position();
+
+            // After a seek, continue from the new position instead of bursting or stalling
+            if resync.swap(false, Ordering::SeqCst) {
+                next_pulse = (position * CLOCK_PPQN).ceil() as u64;
+            }
+
+            while (next_pulse as f64) / CLOCK_PPQN <= position {
+                midi.send_clock_message(&[MIDI_TIMING_CLOCK]);
+                next_pulse += 1;
+            }
+
+            thread::sleep(TICK_INTERVAL);
+        }
+    }
+}
+
+impl Drop for Transport {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(id: &str, beat: f64) -> Marker {
+        Marker {
+            id: id.to_string(),
+            name: id.to_string(),
+            beat,
+        }
+    }
+
+    #[test]
+    fn test_jump_to_marker_moves_playhead() {
+        let transport = Transport::new(Arc::new(MidiManager::new()));
+        transport.set_markers(vec![marker("chorus", 16.0), marker("verse", 8.0)]);
+
+        assert_eq!(transport.markers()[0].id, "verse");
+        assert_eq!(transport.jump_to_marker("chorus"), Ok(16.0));
+        assert_eq!(transport.position(), 16.0);
+        assert!(transport.jump_to_marker("bridge").is_err());
+    }
+
+    #[test]
+    fn test_seek_rejects_negative_position() {
+        let transport = Transport::new(Arc::new(MidiManager::new()));
+
+        assert!(transport.seek(-1.0).is_err());
+        assert!(transport.seek(4.5).is_ok());
+        assert_eq!(transport.position(), 4.5);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::ai_models::Note;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Widest leap within a voice that isn't flagged, in semitones (an octave)
+pub const MAX_LEAP: i32 = 12;
+
+/// Start times closer than this count as simultaneous (beats)
+const SIMULTANEOUS: f64 = 0.01;
+
+/// Kind of voice-leading problem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceIssue {
+    /// Two voices moving in parallel perfect fifths
+    ParallelFifths,
+    /// Two voices moving in parallel octaves or unisons
+    ParallelOctaves,
+    /// A leap wider than an octave, or an augmented fourth, within a voice
+    AwkwardLeap,
+    /// A voice moving below the voice under it (or above the one over it)
+    VoiceCrossing,
+}
+
+/// A voice-leading problem, with the notes to highlight
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceWarning {
+    pub issue: VoiceIssue,
+    /// Notes involved, in time order
+    pub note_ids: Vec<String>,
+    /// Where the problem is heard (beats)
+    pub start_time: f64,
+    pub message: String,
+}
+
+/// Split notes into monophonic voices, highest first
+///
+/// Each track is a voice of its own when it plays one note at a time. A
+/// track playing chords is split by rank: the top note of each chord goes to
+/// its first voice, the next to the second, and the bass note always to its
+/// lowest voice. Voices are then ordered by average pitch.
+fn voices(notes: &[Note]) -> Vec<Vec<&Note>> {
+    let mut tracks: BTreeMap<&str, Vec<&Note>> = BTreeMap::new();
+    for note in notes {
+        tracks.entry(note.track_id.as_str()).or_default().push(note);
+    }
+
+    let mut voices: Vec<Vec<&Note>> = Vec::new();
+    for mut track in tracks.into_values() {
+        track.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then(b.pitch.cmp(&a.pitch)));
+
+        let mut chords: Vec<Vec<&Note>> = Vec::new();
+        for note in track {
+            match chords.last_mut() {
+                Some(chord) if (chord[0].start_time - note.start_time).abs() < SIMULTANEOUS => chord.push(note),
+                _ => chords.push(vec![note]),
+            }
+        }
+
+        let count = chords.iter().map(Vec::len).max().unwrap_or(0);
+        let mut split = vec![Vec::new(); count];
+        for chord in chords {
+            let (bass, upper) = chord.split_last().expect("chords are never empty");
+            for (rank, &note) in upper.iter().enumerate() {
+                split[rank].push(note);
+            }
+            split[count - 1].push(*bass);
+        }
+        voices.extend(split);
+    }
+
+    let mean = |voice: &Vec<&Note>| voice.iter().map(|n| n.pitch as f64).sum::<f64>() / voice.len() as f64;
+    voices.sort_by(|a, b| mean(b).total_cmp(&mean(a)));
+    voices
+}
+
+/// Note of `voice` sounding at `time`
+fn sounding<'a>(voice: &[&'a Note], time: f64) -> Option<&'a Note> {
+    voice
+        .iter()
+        .rev()
+        .find(|n| n.start_time <= time + SIMULTANEOUS && time < n.start_time + n.duration - SIMULTANEOUS)
+        .copied()
+}
+
+fn leaps(voice: &[&Note], warnings: &mut Vec<VoiceWarning>) {
+    for pair in voice.windows(2) {
+        let leap = (pair[1].pitch as i32 - pair[0].pitch as i32).abs();
+        let message = if leap > MAX_LEAP {
+            format!("Leap of {} semitones, wider than an octave", leap)
+        } else if leap == 6 {
+            "Leap of an augmented fourth".to_string()
+        } else {
+            continue;
+        };
+        warnings.push(VoiceWarning {
+            issue: VoiceIssue::AwkwardLeap,
+            note_ids: vec![pair[0].id.clone(), pair[1].id.clone()],
+            start_time: pair[1].start_time,
+            message,
+        });
+    }
+}
+
+/// Parallel perfect intervals between an upper and a lower voice, and
+/// crossings when `neighbors`
+fn between(upper: &[&Note], lower: &[&Note], neighbors: bool, warnings: &mut Vec<VoiceWarning>) {
+    let mut onsets: Vec<f64> = upper.iter().chain(lower).map(|n| n.start_time).collect();
+    onsets.sort_by(f64::total_cmp);
+    onsets.dedup_by(|a, b| (*a - *b).abs() < SIMULTANEOUS);
+
+    let mut previous: Option<(&Note, &Note)> = None;
+    for time in onsets {
+        let (Some(high), Some(low)) = (sounding(upper, time), sounding(lower, time)) else {
+            previous = None;
+            continue;
+        };
+
+        if let Some((prev_high, prev_low)) = previous {
+            let high_move = high.pitch as i32 - prev_high.pitch as i32;
+            let low_move = low.pitch as i32 - prev_low.pitch as i32;
+            let before = (prev_high.pitch as i32 - prev_low.pitch as i32).rem_euclid(12);
+            let after = (high.pitch as i32 - low.pitch as i32).rem_euclid(12);
+            let parallel = high_move != 0 && high_move.signum() == low_move.signum() && before == after;
+
+            let issue = match after {
+                7 if parallel => Some((VoiceIssue::ParallelFifths, "Parallel fifths")),
+                0 if parallel => Some((VoiceIssue::ParallelOctaves, "Parallel octaves")),
+                _ => None,
+            };
+            if let Some((issue, message)) = issue {
+                warnings.push(VoiceWarning {
+                    issue,
+                    note_ids: vec![prev_high.id.clone(), high.id.clone(), prev_low.id.clone(), low.id.clone()],
+                    start_time: time,
+                    message: message.to_string(),
+                });
+            }
+        }
+
+        // Flag a crossing where it begins, not for as long as it lasts
+        let crossed = |(high, low): (&Note, &Note)| high.pitch < low.pitch;
+        if neighbors && crossed((high, low)) && !previous.is_some_and(crossed) {
+            warnings.push(VoiceWarning {
+                issue: VoiceIssue::VoiceCrossing,
+                note_ids: vec![high.id.clone(), low.id.clone()],
+                start_time: time,
+                message: "Voices cross: the upper voice moves below the lower one".to_string(),
+            });
+        }
+        previous = Some((high, low));
+    }
+}
+
+/// Check multi-voice material for parallel fifths and octaves, awkward leaps and voice crossings
+///
+/// Voices come from tracks, with chords split top to bottom (see `voices`).
+/// Parallels are checked between every pair of voices; crossings between
+/// neighboring ones. Warnings are in time order.
+pub fn check(notes: &[Note]) -> Vec<VoiceWarning> {
+    let voices = voices(notes);
+    let mut warnings = Vec::new();
+
+    for (i, voice) in voices.iter().enumerate() {
+        leaps(voice, &mut warnings);
+        for (j, lower) in voices.iter().enumerate().skip(i + 1) {
+            between(voice, lower, j == i + 1, &mut warnings);
+        }
+    }
+
+    warnings.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(track_id: &str, pitches: &[u8]) -> Vec<Note> {
+        pitches
+            .iter()
+            .enumerate()
+            .map(|(i, &pitch)| Note {
+                id: format!("{}{}", track_id, i),
+                pitch,
+                start_time: i as f64,
+                duration: 1.0,
+                velocity: 80,
+                track_id: track_id.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_voice_leading_warnings() {
+        let notes = [voice("u", &[67, 69, 71, 63, 80]), voice("l", &[60, 62, 65, 67, 68])].concat();
+        let warnings = check(&notes);
+        let found: Vec<(VoiceIssue, f64)> = warnings.iter().map(|w| (w.issue, w.start_time)).collect();
+        assert_eq!(
+            found,
+            vec![(VoiceIssue::ParallelFifths, 1.0), (VoiceIssue::VoiceCrossing, 3.0), (VoiceIssue::AwkwardLeap, 4.0)]
+        );
+        assert_eq!(warnings[0].note_ids, vec!["u0", "u1", "l0", "l1"]);
+
+        // A chord track splits into voices: C-G moving to D-A is parallel fifths
+        let chords = [voice("c", &[60, 62]), voice("c", &[67, 69])].concat();
+        assert_eq!(check(&chords)[0].issue, VoiceIssue::ParallelFifths);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::ai_models::{ChordSymbol, Note};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// How a chord's tones are spread over the keyboard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoicingStyle {
+    /// Tones stacked as tightly as possible
+    #[default]
+    Close,
+    /// Every other tone of the close voicing raised an octave (root, fifth, third, seventh)
+    Open,
+    /// The second-highest tone of the close voicing dropped an octave, as in jazz guitar and piano
+    Drop2,
+}
+
+/// How to voice chord symbols as notes
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default, rename_all = "camelCase")]
+pub struct VoicingOptions {
+    pub style: VoicingStyle,
+    /// Which chord tone is lowest: 0 for the root, 1 for the third (first inversion), ...
+    #[validate(range(max = 4))]
+    pub inversion: u8,
+    /// The voicing is built upwards from the first chord tone at or above this pitch
+    #[validate(range(max = 108))]
+    pub lowest: u8,
+    #[validate(range(min = 1, max = 127))]
+    pub velocity: u8,
+    pub track_id: String,
+}
+
+impl Default for VoicingOptions {
+    fn default() -> Self {
+        Self {
+            style: VoicingStyle::Close,
+            inversion: 0,
+            lowest: 48,
+            velocity: 80,
+            track_id: "track_chords".to_string(),
+        }
+    }
+}
+
+/// Pitches of a chord symbol voiced as `options` ask, lowest first
+///
+/// A slash chord's bass note goes below the voicing, unless the voicing
+/// already starts on it.
+pub fn voice(symbol: &str, options: &VoicingOptions) -> Result<Vec<u8>, String> {
+    let chord = ChordSymbol { symbol: symbol.to_string(), start_time: 0.0, duration: 0.0 };
+    let (root, intervals, slash) = chord.structure().ok_or_else(|| format!("Unknown chord symbol: {}", symbol))?;
+    let inversion = options.inversion as usize;
+    if inversion >= intervals.len() {
+        return Err(format!("{} has only {} tones to invert", symbol, intervals.len()));
+    }
+
+    // Close position, then the lowest tones moved up an octave for the inversion
+    let mut tones: Vec<i32> = intervals.iter().map(|interval| root + interval).collect();
+    tones.rotate_left(inversion);
+    for i in 1..tones.len() {
+        while tones[i] <= tones[i - 1] {
+            tones[i] += 12;
+        }
+    }
+
+    match options.style {
+        VoicingStyle::Close => {}
+        VoicingStyle::Open => {
+            for tone in tones.iter_mut().skip(1).step_by(2) {
+                *tone += 12;
+            }
+        }
+        VoicingStyle::Drop2 if tones.len() >= 3 => {
+            let second_highest = tones.len() - 2;
+            tones[second_highest] -= 12;
+        }
+        VoicingStyle::Drop2 => {}
+    }
+    tones.sort_unstable();
+
+    // Place the voicing from the lowest pitch asked for
+    let lowest = options.lowest as i32;
+    let shift = lowest + (tones[0] - lowest).rem_euclid(12) - tones[0];
+    let mut pitches: Vec<i32> = tones.iter().map(|tone| tone + shift).collect();
+    if let Some(bass) = slash.filter(|&bass| bass != pitches[0].rem_euclid(12)) {
+        pitches.insert(0, pitches[0] - 1 - (pitches[0] - 1 - bass).rem_euclid(12));
+    }
+
+    if pitches.iter().any(|pitch| !(0..=127).contains(pitch)) {
+        return Err(format!("{} voiced from {} goes beyond the MIDI range", symbol, options.lowest));
+    }
+    Ok(pitches.into_iter().map(|pitch| pitch as u8).collect())
+}
+
+/// Notes playing each chord at its place on the timeline
+pub fn voice_chords(chords: &[ChordSymbol], options: &VoicingOptions) -> Result<Vec<Note>, String> {
+    let mut notes = Vec::new();
+    for chord in chords {
+        for pitch in voice(&chord.symbol, options)? {
+            notes.push(Note {
+                id: uuid::Uuid::new_v4().to_string(),
+                pitch,
+                start_time: chord.start_time,
+                duration: chord.duration,
+                velocity: options.velocity,
+                track_id: options.track_id.clone(),
+            });
+        }
+    }
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voiced(symbol: &str, style: VoicingStyle, inversion: u8) -> Vec<u8> {
+        let options = VoicingOptions { style, inversion, lowest: 48, ..VoicingOptions::default() };
+        voice(symbol, &options).unwrap()
+    }
+
+    #[test]
+    fn test_voicings_and_inversions() {
+        assert_eq!(voiced("C", VoicingStyle::Close, 0), vec![48, 52, 55]);
+        assert_eq!(voiced("C", VoicingStyle::Close, 1), vec![52, 55, 60]);
+        assert_eq!(voiced("C", VoicingStyle::Open, 0), vec![48, 55, 64]);
+        assert_eq!(voiced("Cmaj7", VoicingStyle::Drop2, 0), vec![55, 60, 64, 71]);
+        assert_eq!(voiced("Am7", VoicingStyle::Close, 2), vec![52, 55, 57, 60]);
+
+        // The bass of a slash chord sits below the voicing
+        assert_eq!(voiced("F/G", VoicingStyle::Close, 0), vec![43, 53, 57, 60]);
+
+        let too_far = VoicingOptions { inversion: 3, ..VoicingOptions::default() };
+        assert!(voice("C", &too_far).is_err());
+        assert!(voice("H7", &VoicingOptions::default()).is_err());
+    }
+}